@@ -16,6 +16,7 @@ use crate::binemit::{
 use crate::dce::do_dce;
 use crate::dominator_tree::DominatorTree;
 use crate::flowgraph::ControlFlowGraph;
+use crate::heap_checks::do_merge_heap_checks;
 use crate::ir::Function;
 use crate::isa::TargetIsa;
 use crate::legalize_function;
@@ -159,6 +160,7 @@ impl Context {
         self.compute_cfg();
         if opt_level != OptLevel::None {
             self.preopt(isa)?;
+            self.merge_heap_checks(isa)?;
         }
         if isa.flags().enable_nan_canonicalization() {
             self.canonicalize_nans(isa)?;
@@ -357,6 +359,15 @@ impl Context {
         self.verify_if(fisa)
     }
 
+    /// Merge redundant heap bounds checks within each block of the function.
+    pub fn merge_heap_checks<'a, FOI: Into<FlagsOrIsa<'a>>>(
+        &mut self,
+        fisa: FOI,
+    ) -> CodegenResult<()> {
+        do_merge_heap_checks(&mut self.func);
+        self.verify_if(fisa)
+    }
+
     /// Perform LICM on the function.
     pub fn licm(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
         do_licm(
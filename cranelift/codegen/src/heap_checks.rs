@@ -0,0 +1,102 @@
+//! Merging of redundant heap bounds checks.
+//!
+//! Every `heap_addr` instruction checks that an access is in bounds before
+//! the legalizer turns it into a bounds check and an address computation. For
+//! heaps without enough offset-guard pages, WebAssembly accesses to the same
+//! index with different offsets each get their own check, and since the
+//! checks can trap they are never merged by GVN.
+//!
+//! This pass looks for `heap_addr` instructions within a block which access
+//! the same heap at the same index:
+//!
+//! - If an earlier check already covers the later access, the later one is
+//!   redundant and is replaced with the earlier address.
+//! - Otherwise, if nothing observable can happen between the two checks, the
+//!   earlier check is widened to cover the later access as well. An access
+//!   which would have trapped at the later check then traps at the earlier
+//!   one instead, with the same trap code and nothing observable in between.
+//!
+//! The heap's base can move when it's grown, which can only happen in a call,
+//! so no address is reused across calls.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::fx::FxHashMap;
+use crate::ir::immediates::Uimm32;
+use crate::ir::{Function, Heap, Inst, InstructionData, Opcode, Value};
+use crate::timing;
+
+/// An earlier `heap_addr` instruction in the current block.
+struct Check {
+    inst: Inst,
+    /// The number of bytes past the index which the check covers.
+    size: u32,
+    /// Whether nothing observable happened since the check, so it can still
+    /// be widened.
+    widenable: bool,
+}
+
+/// Test whether the given opcode can have any effect which is observable
+/// after a trap.
+fn is_observable(opcode: Opcode) -> bool {
+    opcode.is_call() || opcode.can_trap() || opcode.can_store() || opcode.other_side_effects()
+}
+
+/// Merge the redundant heap bounds checks within each block of `func`.
+pub fn do_merge_heap_checks(func: &mut Function) {
+    let _tt = timing::heap_checks();
+    let mut checks: FxHashMap<(Heap, Value), Check> = FxHashMap();
+    let mut pos = FuncCursor::new(func);
+
+    while let Some(_block) = pos.next_block() {
+        checks.clear();
+        while let Some(inst) = pos.next_inst() {
+            // Resolve aliases, particularly aliases we created earlier.
+            pos.func.dfg.resolve_aliases_in_arguments(inst);
+
+            let (heap, index, size): (Heap, Value, u32) = match pos.func.dfg[inst] {
+                InstructionData::HeapAddr {
+                    opcode: Opcode::HeapAddr,
+                    heap,
+                    arg,
+                    imm,
+                } => (heap, arg, imm.into()),
+                ref data => {
+                    let opcode = data.opcode();
+                    if opcode.is_call() {
+                        checks.clear();
+                    } else if is_observable(opcode) {
+                        for check in checks.values_mut() {
+                            check.widenable = false;
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let earlier = match checks.get_mut(&(heap, index)) {
+                Some(check) if check.size >= size => check.inst,
+                Some(check) if check.widenable => {
+                    if let InstructionData::HeapAddr { ref mut imm, .. } = pos.func.dfg[check.inst]
+                    {
+                        *imm = Uimm32::from(size);
+                    }
+                    check.size = size;
+                    check.inst
+                }
+                _ => {
+                    checks.insert(
+                        (heap, index),
+                        Check {
+                            inst,
+                            size,
+                            widenable: true,
+                        },
+                    );
+                    continue;
+                }
+            };
+            pos.func.dfg.replace_with_aliases(inst, earlier);
+            pos.remove_inst_and_step_back();
+        }
+    }
+}
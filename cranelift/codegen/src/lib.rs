@@ -94,6 +94,7 @@ mod context;
 mod dce;
 mod divconst_magic_numbers;
 mod fx;
+mod heap_checks;
 mod inst_predicates;
 mod iterators;
 mod legalizer;
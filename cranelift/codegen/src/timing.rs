@@ -59,6 +59,7 @@ define_passes! {
     preopt: "Pre-legalization rewriting",
     dce: "Dead code elimination",
     legalize: "Legalization",
+    heap_checks: "Heap bounds check merging",
     gvn: "Global value numbering",
     licm: "Loop invariant code motion",
     unreachable_code: "Remove unreachable blocks",
@@ -147,6 +148,20 @@ mod details {
         }
     }
 
+    impl PassTimes {
+        /// Returns the description, total time and self time of every pass that has run.
+        pub fn passes(&self) -> impl Iterator<Item = (&'static str, Duration, Duration)> + '_ {
+            self.pass
+                .iter()
+                .zip(&DESCRIPTIONS[..])
+                .filter(|(time, _)| time.total != Duration::default())
+                .map(|(time, desc)| {
+                    let self_time = time.total.checked_sub(time.child).unwrap_or_default();
+                    (*desc, time.total, self_time)
+                })
+        }
+    }
+
     impl fmt::Display for PassTimes {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             writeln!(f, "======== ========  ==================================")?;
@@ -236,6 +251,15 @@ mod details {
     pub struct TimingToken;
     /// Dummy `PassTimes`
     pub struct PassTimes;
+    impl PassTimes {
+        /// Returns no passes
+        pub fn passes(
+            &self,
+        ) -> impl Iterator<Item = (&'static str, core::time::Duration, core::time::Duration)>
+        {
+            core::iter::empty()
+        }
+    }
     /// Returns dummy `PassTimes`
     pub fn take_current() -> PassTimes {
         PassTimes
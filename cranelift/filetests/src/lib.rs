@@ -44,6 +44,7 @@ mod test_dce;
 mod test_domtree;
 mod test_legalizer;
 mod test_licm;
+mod test_merge_heap_checks;
 mod test_postopt;
 mod test_preopt;
 mod test_print_cfg;
@@ -127,6 +128,7 @@ fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<dyn subtest::
         "domtree" => test_domtree::subtest(parsed),
         "legalizer" => test_legalizer::subtest(parsed),
         "licm" => test_licm::subtest(parsed),
+        "merge-heap-checks" => test_merge_heap_checks::subtest(parsed),
         "postopt" => test_postopt::subtest(parsed),
         "simple_preopt" => test_simple_preopt::subtest(parsed),
         "print-cfg" => test_print_cfg::subtest(parsed),
@@ -222,6 +222,12 @@ impl FuncTranslationState {
     pub fn reachable(&self) -> bool {
         self.reachable
     }
+
+    /// The values currently on the wasm operand stack, bottom first.
+    #[inline]
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
 }
 
 impl FuncTranslationState {
@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+use std::path::Path;
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::{CoverageMap, Module};
+use wasmtime_jit::{CompiledModule, SourceLine};
+
+/// Hit counts of the lines and functions of one source file.
+#[derive(Default)]
+struct FileCoverage {
+    /// How often each line was executed, keyed by line number.
+    lines: BTreeMap<u64, u64>,
+    /// The first line, name and call count of each function.
+    functions: Vec<(u64, String, u64)>,
+}
+
+/// Formats the coverage `counters` of an instance of `compiled` as an LCOV
+/// tracefile.
+///
+/// Each line is reported with the highest count of the basic blocks it's part
+/// of, so a line which is only partially executed still counts as hit.
+pub(crate) fn lcov(compiled: &CompiledModule, coverage: &CoverageMap, counters: &[u64]) -> String {
+    let module = compiled.module_ref();
+    let rows = compiled.source_lines();
+    let mut files = BTreeMap::<&Path, FileCoverage>::new();
+
+    for i in 0..coverage.num_functions() {
+        let func = DefinedFuncIndex::new(i);
+        let body = coverage.body(func);
+        let mut first_line = None;
+        for (counter, range) in coverage.blocks(func) {
+            let count = counters[counter];
+            for row in rows_in(rows, &body, range) {
+                first_line = first_line.or(Some(row));
+                let hits = files
+                    .entry(row.file.as_path())
+                    .or_default()
+                    .lines
+                    .entry(row.line)
+                    .or_insert(0);
+                *hits = (*hits).max(count);
+            }
+        }
+        if let Some(row) = first_line {
+            let entered = counters[coverage.first_counter(func) as usize];
+            files
+                .entry(row.file.as_path())
+                .or_default()
+                .functions
+                .push((row.line, function_name(module, func), entered));
+        }
+    }
+
+    let mut out = String::new();
+    for (path, file) in files {
+        writeln!(out, "TN:").unwrap();
+        writeln!(out, "SF:{}", path.display()).unwrap();
+        for (line, name, _) in &file.functions {
+            writeln!(out, "FN:{},{}", line, name).unwrap();
+        }
+        for (_, name, count) in &file.functions {
+            writeln!(out, "FNDA:{},{}", count, name).unwrap();
+        }
+        writeln!(out, "FNF:{}", file.functions.len()).unwrap();
+        let functions_hit = file.functions.iter().filter(|f| f.2 > 0).count();
+        writeln!(out, "FNH:{}", functions_hit).unwrap();
+        for (line, count) in &file.lines {
+            writeln!(out, "DA:{},{}", line, count).unwrap();
+        }
+        writeln!(out, "LF:{}", file.lines.len()).unwrap();
+        let lines_hit = file.lines.values().filter(|&&c| c > 0).count();
+        writeln!(out, "LH:{}", lines_hit).unwrap();
+        writeln!(out, "end_of_record").unwrap();
+    }
+    out
+}
+
+/// Returns the line table rows which apply to the instructions at `range`
+/// within the function whose body is at `body`: the row in effect at the
+/// start of the range, if it belongs to the function, and those starting
+/// within the range.
+fn rows_in<'a>(
+    rows: &'a [SourceLine],
+    body: &Range<usize>,
+    range: Range<usize>,
+) -> &'a [SourceLine] {
+    // The number of rows starting at or before `range.start`.
+    let after = rows
+        .binary_search_by(|row| {
+            if row.module_offset as usize <= range.start {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        })
+        .unwrap_or_else(|i| i);
+    let first = if after > 0 && rows[after - 1].module_offset as usize >= body.start {
+        after - 1
+    } else {
+        after
+    };
+    let end = after
+        + rows[after..]
+            .iter()
+            .take_while(|row| (row.module_offset as usize) < range.end)
+            .count();
+    &rows[first..end]
+}
+
+fn function_name(module: &Module, func: DefinedFuncIndex) -> String {
+    let index = module.local.func_index(func);
+    match module.func_names.get(&index) {
+        Some(name) => name.clone(),
+        None => format!("wasm-function[{}]", index.index()),
+    }
+}
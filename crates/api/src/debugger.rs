@@ -0,0 +1,332 @@
+use crate::externals::{Extern, Global, Memory};
+use crate::module::Module;
+use crate::runtime::Store;
+use crate::trap::Trap;
+use crate::values::Val;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use wasmtime_environ::wasm::{GlobalIndex, MemoryIndex};
+use wasmtime_environ::{DebugValueType, EntityIndex};
+use wasmtime_runtime::{InstanceHandle, VMDebugFrame, VMDebugValue, VMInterrupts};
+
+/// A debugger for the WebAssembly code running in a [`Store`].
+///
+/// This is created with [`Store::debugger`], which requires
+/// [`Config::debug_instrumentation`](crate::Config::debug_instrumentation) to
+/// be enabled. Breakpoints are set at byte offsets of instructions within a
+/// module's binary, and whenever execution reaches one of them, or the next
+/// instruction after a [`DebugAction::Step`], the callback registered with
+/// [`Debugger::on_event`] is called with a [`DebugEvent`] to inspect the paused
+/// wasm frames.
+///
+/// All clones of a `Debugger` control the same debugger.
+#[derive(Clone)]
+pub struct Debugger {
+    state: Rc<DebuggerState>,
+}
+
+type EventCallback = dyn Fn(&DebugEvent<'_>) -> Result<DebugAction, Trap>;
+
+pub(crate) struct DebuggerState {
+    interrupts: Arc<VMInterrupts>,
+    /// Breakpoint offsets, keyed by the address of the module they're in.
+    /// The module is kept alive so its address can't be reused.
+    breakpoints: RefCell<HashMap<usize, (Arc<wasmtime_environ::Module>, HashSet<usize>)>>,
+    stepping: Cell<bool>,
+    callback: RefCell<Option<Rc<EventCallback>>>,
+}
+
+/// The reason execution was paused, see [`DebugEvent::kind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugEventKind {
+    /// Execution reached a breakpoint.
+    Breakpoint,
+    /// Execution reached the next instruction after a [`DebugAction::Step`].
+    Step,
+}
+
+/// What to do after a debug event was handled, returned by the callback
+/// registered with [`Debugger::on_event`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Resume execution until the next breakpoint.
+    Continue,
+    /// Resume execution and pause again before the next instruction, which
+    /// may be in a called or returned-to function.
+    Step,
+}
+
+/// Paused wasm execution, passed to the callback registered with
+/// [`Debugger::on_event`].
+pub struct DebugEvent<'a> {
+    kind: DebugEventKind,
+    frame: &'a VMDebugFrame,
+    store: &'a Store,
+}
+
+/// A paused wasm frame, see [`DebugEvent::frames`].
+pub struct DebugFrame<'a> {
+    frame: &'a VMDebugFrame,
+    store: &'a Store,
+}
+
+impl Debugger {
+    pub(crate) fn new(state: Rc<DebuggerState>) -> Debugger {
+        Debugger { state }
+    }
+
+    /// Sets a breakpoint at the instruction at `offset` bytes from the start
+    /// of `module`'s binary.
+    ///
+    /// Offsets which don't point at the start of an instruction in a function
+    /// body are never hit.
+    pub fn set_breakpoint(&self, module: &Module, offset: usize) {
+        let module = module.compiled_module().module();
+        self.state
+            .breakpoints
+            .borrow_mut()
+            .entry(module_key(module))
+            .or_insert_with(|| (module.clone(), HashSet::new()))
+            .1
+            .insert(offset);
+        self.state.arm();
+    }
+
+    /// Removes a breakpoint previously set with
+    /// [`Debugger::set_breakpoint`], returning whether it existed.
+    pub fn remove_breakpoint(&self, module: &Module, offset: usize) -> bool {
+        let key = module_key(module.compiled_module().module());
+        let mut breakpoints = self.state.breakpoints.borrow_mut();
+        let removed = match breakpoints.get_mut(&key) {
+            Some((_, offsets)) => offsets.remove(&offset),
+            None => false,
+        };
+        if breakpoints.get(&key).map_or(false, |(_, o)| o.is_empty()) {
+            breakpoints.remove(&key);
+        }
+        drop(breakpoints);
+        self.state.arm();
+        removed
+    }
+
+    /// Removes all breakpoints.
+    pub fn clear_breakpoints(&self) {
+        self.state.breakpoints.borrow_mut().clear();
+        self.state.arm();
+    }
+
+    /// Pauses execution before the next wasm instruction which is executed in
+    /// this store.
+    pub fn step(&self) {
+        self.state.stepping.set(true);
+        self.state.arm();
+    }
+
+    /// Sets the callback which is called whenever execution is paused.
+    ///
+    /// The callback can inspect the paused frames through the
+    /// [`DebugEvent`] and then decides how to resume. Returning an error
+    /// aborts execution with that trap.
+    ///
+    /// No events are reported until a callback is set.
+    pub fn on_event(
+        &self,
+        callback: impl Fn(&DebugEvent<'_>) -> Result<DebugAction, Trap> + 'static,
+    ) {
+        *self.state.callback.borrow_mut() = Some(Rc::new(callback));
+        self.state.arm();
+    }
+}
+
+impl DebuggerState {
+    pub(crate) fn new(interrupts: Arc<VMInterrupts>) -> DebuggerState {
+        DebuggerState {
+            interrupts,
+            breakpoints: Default::default(),
+            stepping: Cell::new(false),
+            callback: RefCell::new(None),
+        }
+    }
+
+    /// Makes instrumented code call into `handle_event` only while there's
+    /// something to pause at.
+    ///
+    /// Frames don't record their state while disarmed, so disarming forgets
+    /// the state recorded by all the frames on the stack until they execute
+    /// an instruction while armed again.
+    fn arm(&self) {
+        let armed = self.callback.borrow().is_some()
+            && (self.stepping.get() || !self.breakpoints.borrow().is_empty());
+        self.interrupts.debug_armed.store(armed as usize, SeqCst);
+        if !armed {
+            let mut frame = self.interrupts.debug_frame.load(SeqCst) as *const VMDebugFrame;
+            // The list only contains frames which are live on the stack.
+            while let Some(live) = unsafe { frame.as_ref() } {
+                live.invalidate();
+                frame = live.parent().map_or(std::ptr::null(), |parent| parent);
+            }
+        }
+    }
+
+    /// Called by instrumented code before each instruction while armed.
+    pub(crate) fn handle_event(&self, store: &Store, frame: &VMDebugFrame) -> Result<(), Trap> {
+        let kind = if self.stepping.get() {
+            DebugEventKind::Step
+        } else if self.is_breakpoint(frame) {
+            DebugEventKind::Breakpoint
+        } else {
+            return Ok(());
+        };
+        let callback = match self.callback.borrow().clone() {
+            Some(callback) => callback,
+            None => return Ok(()),
+        };
+
+        self.stepping.set(false);
+        let result = callback(&DebugEvent { kind, frame, store });
+        if let Ok(DebugAction::Step) = result {
+            self.stepping.set(true);
+        }
+        // Resuming without anything left to pause at disarms the debugger,
+        // which forgets the recorded state of the frames on the stack.
+        self.arm();
+        result.map(drop)
+    }
+
+    fn is_breakpoint(&self, frame: &VMDebugFrame) -> bool {
+        let instance = unsafe { InstanceHandle::from_vmctx(frame.vmctx()) };
+        let key = module_key(instance.module());
+        match self.breakpoints.borrow().get(&key) {
+            Some((_, offsets)) => offsets.contains(&frame.offset()),
+            None => false,
+        }
+    }
+}
+
+impl<'a> DebugEvent<'a> {
+    /// Returns why execution was paused.
+    pub fn kind(&self) -> DebugEventKind {
+        self.kind
+    }
+
+    /// Returns the wasm frames on the stack, starting with the innermost one
+    /// which is about to execute the instruction execution was paused at.
+    ///
+    /// Frames of host functions are skipped.
+    pub fn frames(&self) -> impl Iterator<Item = DebugFrame<'a>> + 'a {
+        let store = self.store;
+        let mut next = Some(self.frame);
+        std::iter::from_fn(move || {
+            let frame = next?;
+            next = frame.parent();
+            Some(DebugFrame { frame, store })
+        })
+    }
+
+    /// Returns the [`Store`] execution was paused in.
+    pub fn store(&self) -> &Store {
+        self.store
+    }
+}
+
+impl DebugFrame<'_> {
+    /// Returns the index of the frame's function within its module.
+    pub fn func_index(&self) -> u32 {
+        self.frame.func_index().as_u32()
+    }
+
+    /// Returns whether the frame's function belongs to `module`.
+    pub fn is_in(&self, module: &Module) -> bool {
+        module_key(self.instance().module()) == module_key(module.compiled_module().module())
+    }
+
+    /// Returns the name of the module the frame's function belongs to, if it
+    /// has one.
+    pub fn module_name(&self) -> Option<String> {
+        self.instance().module().name.clone()
+    }
+
+    /// Returns the byte offset within the module of the instruction the frame
+    /// is paused at, which for frames other than the innermost one is the call
+    /// they're in.
+    ///
+    /// This is `None` for frames which were entered before debugging was
+    /// armed and haven't executed an instruction since, in which case their
+    /// locals and operand stack aren't available either.
+    pub fn offset(&self) -> Option<usize> {
+        if self.frame.is_valid() {
+            Some(self.frame.offset())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the values of the frame's locals, starting with the function's
+    /// parameters.
+    ///
+    /// Values which can't be represented, such as references, are `None`.
+    pub fn locals(&self) -> Vec<Option<Val>> {
+        self.frame.locals().iter().map(to_val).collect()
+    }
+
+    /// Returns the frame's operand stack, with the bottom of the stack first.
+    ///
+    /// Values which can't be represented, such as references, are `None`.
+    pub fn stack(&self) -> Vec<Option<Val>> {
+        self.frame.stack().iter().map(to_val).collect()
+    }
+
+    /// Returns the global with the given index in the frame's module, which
+    /// can be used to read or write its value.
+    pub fn global(&self, index: u32) -> Option<Global> {
+        let instance = self.instance();
+        if index as usize >= instance.module().local.globals.len() {
+            return None;
+        }
+        let export =
+            instance.lookup_by_declaration(&EntityIndex::Global(GlobalIndex::from_u32(index)));
+        match Extern::from_wasmtime_export(export, self.store, instance) {
+            Extern::Global(global) => Some(global),
+            _ => None,
+        }
+    }
+
+    /// Returns the memory with the given index in the frame's module, which
+    /// can be used to read or write its contents.
+    pub fn memory(&self, index: u32) -> Option<Memory> {
+        let instance = self.instance();
+        if index as usize >= instance.module().local.memory_plans.len() {
+            return None;
+        }
+        let export =
+            instance.lookup_by_declaration(&EntityIndex::Memory(MemoryIndex::from_u32(index)));
+        match Extern::from_wasmtime_export(export, self.store, instance) {
+            Extern::Memory(memory) => Some(memory),
+            _ => None,
+        }
+    }
+
+    fn instance(&self) -> InstanceHandle {
+        // The instance is alive since one of its functions is on the stack.
+        unsafe { InstanceHandle::from_vmctx(self.frame.vmctx()) }
+    }
+}
+
+fn module_key(module: &Arc<wasmtime_environ::Module>) -> usize {
+    &**module as *const wasmtime_environ::Module as usize
+}
+
+fn to_val(value: &VMDebugValue) -> Option<Val> {
+    let bits = value.bits();
+    Some(match value.ty() {
+        DebugValueType::I32 => Val::I32(bits as i32),
+        DebugValueType::I64 => Val::I64(bits as i64),
+        DebugValueType::F32 => Val::F32(bits as u32),
+        DebugValueType::F64 => Val::F64(bits as u64),
+        DebugValueType::V128 => Val::V128(bits),
+        DebugValueType::Unavailable => return None,
+    })
+}
@@ -4,6 +4,7 @@ use crate::Mutability;
 use crate::{ExternType, GlobalType, MemoryType, TableType, ValType};
 use crate::{Func, Store, Trap};
 use anyhow::{anyhow, bail, Result};
+use std::mem;
 use std::slice;
 use wasmtime_environ::wasm;
 use wasmtime_runtime::{self as runtime, InstanceHandle};
@@ -499,6 +500,13 @@ impl Table {
 /// lot to keep in mind! It's hopefully though sort of setting the stage as to
 /// what you can safely do with memories.
 ///
+/// The easiest way to stay safe is to never borrow memory at all and instead
+/// copy data in and out with [`Memory::read`] and [`Memory::write`], or the
+/// typed helpers like [`Memory::read_u32`]. These check bounds on every access.
+/// With the `wiggle` feature of this crate enabled, `Memory` also implements
+/// `wiggle::GuestMemory`, so that host functions can use `GuestPtr` to check
+/// bounds and alignment and to track borrows of guest memory.
+///
 /// Let's run through a few safe examples first of how you can use a `Memory`.
 ///
 /// ```rust
@@ -791,6 +799,62 @@ impl Memory {
         (self.data_size() / wasmtime_environ::WASM_PAGE_SIZE as usize) as u32
     }
 
+    /// Copies `buffer.len()` bytes starting at `offset` in this memory into
+    /// `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes aren't all within this memory, in which
+    /// case `buffer` isn't modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(
+    ///     &store,
+    ///     r#"(module (memory (export "mem") 1) (data (i32.const 8) "hello"))"#,
+    /// )?;
+    /// let instance = Instance::new(&module, &[])?;
+    /// let memory = instance.get_memory("mem").unwrap();
+    ///
+    /// let mut buffer = [0; 5];
+    /// memory.read(8, &mut buffer)?;
+    /// assert_eq!(&buffer, b"hello");
+    /// assert!(memory.read(65534, &mut buffer).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<()> {
+        // The borrow of the memory's contents doesn't outlive this function,
+        // which doesn't run any code that could modify the memory.
+        let data = unsafe { self.data_unchecked() };
+        let bytes = offset
+            .checked_add(buffer.len())
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(|| anyhow!("out of bounds memory access"))?;
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Copies `buffer` into this memory, starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes wouldn't all be within this memory, in
+    /// which case the memory isn't modified.
+    pub fn write(&self, offset: usize, buffer: &[u8]) -> Result<()> {
+        let data = unsafe { self.data_unchecked_mut() };
+        let bytes = offset
+            .checked_add(buffer.len())
+            .and_then(|end| data.get_mut(offset..end))
+            .ok_or_else(|| anyhow!("out of bounds memory access"))?;
+        bytes.copy_from_slice(buffer);
+        Ok(())
+    }
+
     /// Grows this WebAssembly memory by `delta` pages.
     ///
     /// This will attempt to add `delta` more pages of memory on to the end of
@@ -848,6 +912,55 @@ impl Memory {
     }
 }
 
+macro_rules! typed_accessors {
+    ($(($read:ident $write:ident $ty:ident))*) => (
+        impl Memory {
+            $(
+                /// Reads an integer of this method's type at `offset`, stored
+                /// in little-endian byte order like wasm's loads expect.
+                ///
+                /// # Errors
+                ///
+                /// Returns an error if the integer isn't within this memory.
+                pub fn $read(&self, offset: usize) -> Result<$ty> {
+                    let mut bytes = [0; mem::size_of::<$ty>()];
+                    self.read(offset, &mut bytes)?;
+                    Ok($ty::from_le_bytes(bytes))
+                }
+
+                /// Writes `value` at `offset`, in little-endian byte order
+                /// like wasm's stores.
+                ///
+                /// # Errors
+                ///
+                /// Returns an error if the integer wouldn't be within this
+                /// memory.
+                pub fn $write(&self, offset: usize, value: $ty) -> Result<()> {
+                    self.write(offset, &value.to_le_bytes())
+                }
+            )*
+        }
+    )
+}
+
+typed_accessors! {
+    (read_u8 write_u8 u8)
+    (read_u16 write_u16 u16)
+    (read_u32 write_u32 u32)
+    (read_u64 write_u64 u64)
+}
+
+#[cfg(feature = "wiggle")]
+unsafe impl wiggle::GuestMemory for Memory {
+    fn base(&self) -> (*mut u8, u32) {
+        use std::convert::TryFrom;
+        // The size of a 4GiB memory doesn't fit, which leaves its last byte
+        // inaccessible through `GuestPtr`.
+        let len = u32::try_from(self.data_size()).unwrap_or(u32::max_value());
+        (self.data_ptr(), len)
+    }
+}
+
 /// A linear memory. This trait provides an interface for raw memory buffers which are used
 /// by wasmtime, e.g. inside ['Memory']. Such buffers are in principle not thread safe.
 /// By implementing this trait together with MemoryCreator,
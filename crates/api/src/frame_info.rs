@@ -1,3 +1,4 @@
+use backtrace::Backtrace;
 use std::cmp;
 use std::collections::BTreeMap;
 use std::sync::{Arc, RwLock};
@@ -109,6 +110,31 @@ impl GlobalFrameInfo {
         })
     }
 
+    /// Fetches frame information about each wasm frame of `backtrace`,
+    /// starting with the innermost one.
+    ///
+    /// Return addresses point at the instruction *after* a call, so the
+    /// previous instruction is looked up for every frame except the one at
+    /// `trap_pc`, which is the exact pc of a trapping instruction.
+    pub fn lookup_backtrace(
+        &self,
+        backtrace: &Backtrace,
+        trap_pc: Option<usize>,
+    ) -> Vec<FrameInfo> {
+        let mut wasm_trace = Vec::new();
+        for frame in backtrace.frames() {
+            let pc = frame.ip() as usize;
+            if pc == 0 {
+                continue;
+            }
+            let pc_to_lookup = if Some(pc) == trap_pc { pc } else { pc - 1 };
+            if let Some(info) = self.lookup_frame_info(pc_to_lookup) {
+                wasm_trace.push(info);
+            }
+        }
+        wasm_trace
+    }
+
     /// Fetches trap information about a program counter in a backtrace.
     pub fn lookup_trap_info(&self, pc: usize) -> Option<&TrapInformation> {
         let (_module, func) = self.func(pc)?;
@@ -1,5 +1,7 @@
-use crate::{Extern, FuncType, Memory, Store, Trap, Val, ValType};
+use crate::frame_info::FRAME_INFO;
+use crate::{CallHook, Extern, FrameInfo, FuncType, Memory, Store, Trap, Val, ValType};
 use anyhow::{bail, ensure, Context as _, Result};
+use backtrace::Backtrace;
 use std::cmp::max;
 use std::fmt;
 use std::mem;
@@ -176,7 +178,8 @@ macro_rules! getters {
             // of the closure. Pass the export in so that we can call it.
             let instance = self.instance.clone();
             let export = self.export.clone();
-            let max_wasm_stack = self.store.engine().config().max_wasm_stack;
+            let store = self.store.clone();
+            let max_wasm_stack = store.engine().config().max_wasm_stack;
 
             // ... and then once we've passed the typechecks we can hand out our
             // object since our `transmute` below should be safe!
@@ -192,9 +195,13 @@ macro_rules! getters {
                     >(export.address);
                     let mut ret = None;
                     $(let $args = $args.into_abi();)*
-                    wasmtime_runtime::catch_traps(export.vmctx, max_wasm_stack, || {
+                    store.invoke_call_hook(CallHook::CallingWasm)?;
+                    let result = wasmtime_runtime::catch_traps(export.vmctx, max_wasm_stack, || {
                         ret = Some(fnptr(export.vmctx, ptr::null_mut(), $($args,)*));
-                    }).map_err(Trap::from_jit)?;
+                    });
+                    let hook = store.invoke_call_hook(CallHook::ReturningFromWasm);
+                    result.map_err(Trap::from_jit)?;
+                    hook?;
 
                     // We're holding this handle just to ensure that the instance stays
                     // live while we call into it.
@@ -253,14 +260,17 @@ impl Func {
                 }
             }
             let mut returns = vec![Val::null(); ty_clone.results().len()];
-            func(
+            store_clone.invoke_call_hook(CallHook::CallingHost)?;
+            let result = func(
                 Caller {
                     store: &store_clone,
                     caller_vmctx,
                 },
                 &args,
                 &mut returns,
-            )?;
+            );
+            store_clone.invoke_call_hook(CallHook::ReturningFromHost)?;
+            result?;
 
             // Unlike our arguments we need to dynamically check that the return
             // values produced are correct. There could be a bug in `func` that
@@ -558,7 +568,8 @@ impl Func {
         }
 
         // Call the trampoline.
-        if let Err(error) = unsafe {
+        self.store.invoke_call_hook(CallHook::CallingWasm)?;
+        let result = unsafe {
             wasmtime_runtime::catch_traps(
                 self.export.vmctx,
                 self.store.engine().config().max_wasm_stack,
@@ -571,9 +582,12 @@ impl Func {
                     )
                 },
             )
-        } {
+        };
+        let hook = self.store.invoke_call_hook(CallHook::ReturningFromWasm);
+        if let Err(error) = result {
             return Err(Trap::from_jit(error).into());
         }
+        hook?;
 
         // Load the return values out of `values_vec`.
         let mut results = Vec::with_capacity(my_ty.results().len());
@@ -1008,6 +1022,17 @@ impl Caller<'_> {
             Some(Extern::Memory(mem))
         }
     }
+
+    /// Returns the wasm frames on the stack at the point this host function
+    /// was called, starting with the innermost one which made the call.
+    ///
+    /// This is the same trace a [`Trap`] created here would carry in
+    /// [`Trap::trace`], without having to create one. Frames of host
+    /// functions are skipped.
+    pub fn backtrace(&self) -> Vec<FrameInfo> {
+        let info = FRAME_INFO.read().unwrap();
+        info.lookup_backtrace(&Backtrace::new_unresolved(), None)
+    }
 }
 
 macro_rules! impl_into_func {
@@ -1059,15 +1084,19 @@ macro_rules! impl_into_func {
                         // work.
                         debug_assert!(state.is::<(F, Store)>());
                         let (func, store) = &*(state as *const _ as *const (F, Store));
-                        panic::catch_unwind(AssertUnwindSafe(|| {
-                            func(
+                        panic::catch_unwind(AssertUnwindSafe(|| -> Result<R, Trap> {
+                            store.invoke_call_hook(CallHook::CallingHost)?;
+                            let ret = func(
                                 Caller { store, caller_vmctx },
                                 $($args,)*
-                            )
+                            );
+                            store.invoke_call_hook(CallHook::ReturningFromHost)?;
+                            Ok(ret)
                         }))
                     };
                     match ret {
-                        Ok(ret) => ret.into_abi(),
+                        Ok(Ok(ret)) => ret.into_abi(),
+                        Ok(Err(trap)) => wasmtime_runtime::raise_user_trap(Box::new(trap)),
                         Err(panic) => wasmtime_runtime::resume_panic(panic),
                     }
                 }
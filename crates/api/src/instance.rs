@@ -2,11 +2,13 @@ use crate::externals::{Export, Extern, Global, Memory, Table};
 use crate::func::Func;
 use crate::module::Module;
 use crate::runtime::{Config, Store};
+use crate::snapshot::InstanceSnapshot;
 use crate::trap::Trap;
 use anyhow::{bail, Error, Result};
 use std::any::Any;
+use std::rc::Rc;
 use wasmtime_jit::{CompiledModule, Resolver};
-use wasmtime_runtime::{InstanceHandle, InstantiationError, SignatureRegistry};
+use wasmtime_runtime::{DebugHandler, InstanceHandle, InstantiationError, SignatureRegistry};
 
 struct SimpleResolver<'a> {
     imports: &'a [Extern],
@@ -26,6 +28,7 @@ fn instantiate(
     imports: &[Extern],
     sig_registry: &SignatureRegistry,
     host: Box<dyn Any>,
+    debug_handler: Option<Rc<DebugHandler>>,
 ) -> Result<InstanceHandle, Error> {
     let mut resolver = SimpleResolver { imports };
     unsafe {
@@ -37,6 +40,7 @@ fn instantiate(
                 config.memory_creator.as_ref().map(|a| a as _),
                 config.max_wasm_stack,
                 host,
+                debug_handler,
             )
             .map_err(|e| -> Error {
                 match e {
@@ -137,14 +141,19 @@ impl Instance {
         }
 
         let info = module.register_frame_info();
+        let registration = store.instance_registration();
+        let registration_id = registration.id();
         let config = store.engine().config();
         let instance_handle = instantiate(
             config,
             module.compiled_module(),
             imports,
             store.compiler().signatures(),
-            Box::new(info),
+            Box::new((info, registration)),
+            store.debug_handler(),
         )?;
+        store.register_instance(registration_id, &instance_handle);
+        store.engine().counters().on_instantiated();
 
         Ok(Instance {
             instance_handle,
@@ -222,6 +231,60 @@ impl Instance {
         self.get_export(name)?.into_global()
     }
 
+    /// Returns an [LCOV] tracefile of which source lines of this instance
+    /// were executed so far, and how often.
+    ///
+    /// This requires the module to be compiled with
+    /// [`Config::coverage`] enabled, and to contain DWARF line information
+    /// which maps its code back to source lines.
+    ///
+    /// [LCOV]: http://ltp.sourceforge.net/coverage/lcov/geninfo.1.php
+    pub fn coverage_report(&self) -> Result<String> {
+        let compiled = self.module.compiled_module();
+        let coverage = match &compiled.module_ref().coverage {
+            Some(coverage) => coverage,
+            None => bail!("coverage isn't enabled for this module"),
+        };
+        if compiled.source_lines().is_empty() {
+            bail!("the module has no DWARF line information to report coverage with");
+        }
+        let counters = self.instance_handle.coverage_counters();
+        Ok(crate::coverage::lcov(compiled, coverage, &counters))
+    }
+
+    /// Captures the current contents of the linear memories, globals and
+    /// tables defined by this instance, so that it can later be rolled back
+    /// to them with [`Instance::restore`].
+    ///
+    /// Only the pages of linear memory which differ from their contents right
+    /// after instantiation are copied into the snapshot.
+    pub fn snapshot(&self) -> InstanceSnapshot {
+        crate::snapshot::snapshot(&self.instance_handle, self.module.compiled_module())
+    }
+
+    /// Rolls this instance back in place to the state captured in `snapshot`.
+    ///
+    /// Only the pages of linear memory which differ from the snapshot are
+    /// written, which makes this cheaper than creating a new instance if
+    /// little memory was modified in between. Memories populated lazily, see
+    /// [`Config::memory_lazy_init`](crate::Config::memory_lazy_init), are
+    /// discarded at once instead, after which only the pages the snapshot
+    /// changed are written.
+    ///
+    /// Memories and tables which grew since the snapshot was taken are shrunk
+    /// back to their old sizes. Passive data and element segments dropped
+    /// since then stay dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `snapshot` was taken of a different instance, in
+    /// which case this instance isn't modified. Memories created by a
+    /// [`MemoryCreator`](crate::MemoryCreator) can't be shrunk, so restoring
+    /// also fails if one of those grew since then.
+    pub fn restore(&self, snapshot: &InstanceSnapshot) -> Result<()> {
+        crate::snapshot::restore(&self.instance_handle, snapshot)
+    }
+
     #[doc(hidden)]
     pub fn handle(&self) -> &InstanceHandle {
         &self.instance_handle
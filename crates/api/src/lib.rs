@@ -10,27 +10,36 @@
 #![doc(test(attr(deny(warnings))))]
 #![doc(test(attr(allow(dead_code, unused_variables, unused_mut))))]
 
+mod coverage;
+mod debugger;
 mod externals;
 mod frame_info;
 mod func;
 mod instance;
 mod linker;
 mod module;
+mod profile;
 mod r#ref;
 mod runtime;
+mod snapshot;
+mod stats;
 mod trampoline;
 mod trap;
 mod types;
 mod values;
 
+pub use crate::debugger::{DebugAction, DebugEvent, DebugEventKind, DebugFrame, Debugger};
 pub use crate::externals::*;
 pub use crate::frame_info::FrameInfo;
 pub use crate::func::*;
 pub use crate::instance::Instance;
 pub use crate::linker::*;
 pub use crate::module::Module;
+pub use crate::profile::GuestProfile;
 pub use crate::r#ref::{AnyRef, HostRef};
 pub use crate::runtime::*;
+pub use crate::snapshot::InstanceSnapshot;
+pub use crate::stats::{EngineStats, MemoryUsage, ModuleCompileStats, PassTime};
 pub use crate::trap::Trap;
 pub use crate::types::*;
 pub use crate::values::*;
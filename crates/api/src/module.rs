@@ -1,9 +1,11 @@
 use crate::frame_info::GlobalFrameInfoRegistration;
 use crate::runtime::Store;
+use crate::stats::ModuleCompileStats;
 use crate::types::{EntityType, ExportType, ImportType};
 use anyhow::{Error, Result};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use wasmparser::validate;
 use wasmtime_jit::CompiledModule;
 
@@ -81,6 +83,7 @@ pub struct Module {
 struct ModuleInner {
     store: Store,
     compiled: CompiledModule,
+    compile_stats: ModuleCompileStats,
     frame_info_registration: Mutex<Option<Option<Arc<GlobalFrameInfoRegistration>>>>,
 }
 
@@ -305,16 +308,20 @@ impl Module {
     }
 
     unsafe fn compile(store: &Store, binary: &[u8]) -> Result<Self> {
-        let compiled = CompiledModule::new(
-            &mut store.compiler_mut(),
-            binary,
-            &*store.engine().config().profiler,
-        )?;
+        let start = Instant::now();
+        let config = store.engine().config();
+        let compiled = CompiledModule::new(&mut store.compiler_mut(), binary, &*config.profiler)?;
+        if config.tunables.deterministic {
+            config.check_deterministic(compiled.module_ref())?;
+        }
+        let compile_stats = ModuleCompileStats::new(&compiled, start.elapsed());
+        store.engine().counters().on_module_compiled(&compile_stats);
 
         Ok(Module {
             inner: Arc::new(ModuleInner {
                 store: store.clone(),
                 compiled,
+                compile_stats,
                 frame_info_registration: Mutex::new(None),
             }),
         })
@@ -324,6 +331,26 @@ impl Module {
         &self.inner.compiled
     }
 
+    /// Returns statistics about the compilation of this module, such as the
+    /// time spent in each compiler pass and the size of each function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let module = Module::new(&store, "(module (func) (func))")?;
+    /// let stats = module.compile_stats();
+    /// assert_eq!(stats.function_sizes.len(), 2);
+    /// assert!(stats.compiled_bytes > 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compile_stats(&self) -> &ModuleCompileStats {
+        &self.inner.compile_stats
+    }
+
     /// Returns identifier/name that this [`Module`] has. This name
     /// is used in traps/backtrace details.
     ///
@@ -0,0 +1,230 @@
+use crate::frame_info::FRAME_INFO;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::time::Duration;
+use wasmtime_profiling::RawSamples;
+
+/// Samples of the wasm stacks of running threads, collected by
+/// [`ProfilingStrategy::Sampling`](crate::ProfilingStrategy::Sampling).
+///
+/// This is returned by
+/// [`Engine::take_guest_profile`](crate::Engine::take_guest_profile) and can
+/// be written out in formats understood by common profile viewers.
+#[derive(Debug, Clone)]
+pub struct GuestProfile {
+    interval: Duration,
+    samples: Vec<Sample>,
+    dropped: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    thread: u32,
+    time: Duration,
+    /// Names of the wasm functions on the stack, outermost first.
+    stack: Vec<String>,
+}
+
+impl GuestProfile {
+    /// Symbolizes raw samples with the frame information of the modules which
+    /// are currently alive. Frames which don't belong to any of them, such as
+    /// host functions, are skipped.
+    pub(crate) fn new(raw: RawSamples, interval: Duration) -> GuestProfile {
+        let info = FRAME_INFO.read().unwrap();
+        let mut samples = raw
+            .samples
+            .into_iter()
+            .filter_map(|sample| {
+                let mut stack = sample
+                    .frames
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, pc)| {
+                        // All frames but the innermost one are return
+                        // addresses, which point just past the call.
+                        let pc = if i == 0 { *pc } else { pc.wrapping_sub(1) };
+                        let frame = info.lookup_frame_info(pc)?;
+                        let module = frame.module_name().unwrap_or("<unknown>");
+                        Some(match frame.func_name() {
+                            Some(name) => match rustc_demangle::try_demangle(name) {
+                                Ok(name) => format!("{}!{}", module, name),
+                                Err(_) => format!("{}!{}", module, name),
+                            },
+                            None => format!("{}!<wasm function {}>", module, frame.func_index()),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                if stack.is_empty() {
+                    return None;
+                }
+                stack.reverse();
+                Some(Sample {
+                    thread: sample.thread,
+                    time: sample.time,
+                    stack,
+                })
+            })
+            .collect::<Vec<_>>();
+        samples.sort_by_key(|s| (s.thread, s.time));
+        GuestProfile {
+            interval,
+            samples,
+            dropped: raw.dropped,
+        }
+    }
+
+    /// Returns the number of samples which hit wasm code.
+    pub fn samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns the number of samples which were lost because too many were
+    /// taken between two calls to `Engine::take_guest_profile`.
+    pub fn dropped_samples(&self) -> usize {
+        self.dropped
+    }
+
+    /// Writes the profile in the "collapsed stacks" format, with one line per
+    /// distinct stack followed by the number of samples which hit it.
+    ///
+    /// This is the input format of `flamegraph.pl` and `inferno`.
+    pub fn write_collapsed(&self, mut out: impl Write) -> io::Result<()> {
+        let mut counts = BTreeMap::new();
+        for sample in self.samples.iter() {
+            *counts.entry(sample.stack.join(";")).or_insert(0usize) += 1;
+        }
+        for (stack, count) in counts {
+            writeln!(out, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the profile as Gecko profile JSON, which can be loaded into the
+    /// Firefox profiler at <https://profiler.firefox.com>.
+    pub fn write_firefox_json(&self, mut out: impl Write) -> io::Result<()> {
+        let interval = self.interval.as_secs_f64() * 1000.0;
+        write!(
+            out,
+            "{{\"meta\":{{\"version\":24,\"startTime\":0,\"shutdownTime\":null,\
+             \"interval\":{},\"stackwalk\":1,\"debug\":0,\"gcpoison\":0,\
+             \"asyncstack\":0,\"processType\":0,\"presymbolicated\":true,\
+             \"product\":\"wasmtime\",\"pid\":{},\
+             \"categories\":[{{\"name\":\"Wasm\",\"color\":\"yellow\",\
+             \"subcategories\":[\"Other\"]}}],\"markerSchema\":[]}},\
+             \"libs\":[],\"pausedRanges\":[],\"processes\":[],\"threads\":[",
+            interval,
+            std::process::id()
+        )?;
+        let mut rest = &self.samples[..];
+        let mut first = true;
+        while let Some(sample) = rest.first() {
+            let len = rest
+                .iter()
+                .position(|s| s.thread != sample.thread)
+                .unwrap_or(rest.len());
+            if !first {
+                write!(out, ",")?;
+            }
+            first = false;
+            write_thread(&mut out, sample.thread, &rest[..len])?;
+            rest = &rest[len..];
+        }
+        write!(out, "]}}")
+    }
+}
+
+/// Writes the samples of a single thread, deduplicating the function names,
+/// frames and stack prefixes into the tables the Gecko format expects.
+fn write_thread(out: &mut impl Write, thread: u32, samples: &[Sample]) -> io::Result<()> {
+    let mut strings = Vec::new();
+    let mut string_ids = HashMap::new();
+    let mut stacks = Vec::new();
+    let mut stack_ids = HashMap::new();
+    let mut sample_stacks = Vec::with_capacity(samples.len());
+    for sample in samples {
+        let mut prefix = None;
+        for name in sample.stack.iter() {
+            let frame = *string_ids.entry(name.as_str()).or_insert_with(|| {
+                strings.push(name.as_str());
+                strings.len() - 1
+            });
+            let key = (prefix, frame);
+            prefix = Some(*stack_ids.entry(key).or_insert_with(|| {
+                stacks.push(key);
+                stacks.len() - 1
+            }));
+        }
+        sample_stacks.push(prefix.unwrap());
+    }
+
+    write!(
+        out,
+        "{{\"name\":\"thread {0}\",\"processType\":\"default\",\"tid\":{0},\
+         \"pid\":{1},\"registerTime\":0,\"unregisterTime\":null,",
+        thread,
+        std::process::id()
+    )?;
+    write!(
+        out,
+        "\"markers\":{{\"schema\":{{\"name\":0,\"startTime\":1,\"endTime\":2,\
+         \"phase\":3,\"category\":4,\"data\":5}},\"data\":[]}},"
+    )?;
+    write!(
+        out,
+        "\"samples\":{{\"schema\":{{\"stack\":0,\"time\":1,\"eventDelay\":2}},\"data\":["
+    )?;
+    for (i, (sample, stack)) in samples.iter().zip(sample_stacks).enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "[{},{},0]", stack, sample.time.as_secs_f64() * 1000.0)?;
+    }
+    write!(
+        out,
+        "]}},\"stackTable\":{{\"schema\":{{\"prefix\":0,\"frame\":1}},\"data\":["
+    )?;
+    for (i, (prefix, frame)) in stacks.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        match prefix {
+            Some(prefix) => write!(out, "[{},{}]", prefix, frame)?,
+            None => write!(out, "[null,{}]", frame)?,
+        }
+    }
+    // Frames and strings are deduplicated together, so frame `i` is always
+    // named by string `i`.
+    write!(
+        out,
+        "]}},\"frameTable\":{{\"schema\":{{\"location\":0,\"relevantForJS\":1,\
+         \"innerWindowID\":2,\"implementation\":3,\"optimizations\":4,\
+         \"line\":5,\"column\":6,\"category\":7,\"subcategory\":8}},\"data\":["
+    )?;
+    for i in 0..strings.len() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "[{},false,0,null,null,null,null,0,0]", i)?;
+    }
+    write!(out, "]}},\"stringTable\":[")?;
+    for (i, s) in strings.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write_json_string(out, s)?;
+    }
+    write!(out, "]}}")
+}
+
+fn write_json_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
@@ -1,18 +1,31 @@
+use crate::debugger::{Debugger, DebuggerState};
 use crate::externals::MemoryCreator;
+use crate::profile::GuestProfile;
+use crate::stats::{EngineCounters, EngineStats, MemoryUsage};
 use crate::trampoline::MemoryCreatorProxy;
+use crate::trap::Trap;
 use anyhow::{bail, Result};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::atomic::Ordering::SeqCst;
 use std::sync::Arc;
+use std::time::Duration;
 use wasmparser::{OperatorValidatorConfig, ValidatingParserConfig};
 use wasmtime_environ::settings::{self, Configurable};
-use wasmtime_environ::{CacheConfig, Tunables};
+use wasmtime_environ::{CacheConfig, Tunables, WASM_MAX_PAGES, WASM_PAGE_SIZE};
 use wasmtime_jit::{native, CompilationStrategy, Compiler};
-use wasmtime_profiling::{JitDumpAgent, NullProfilerAgent, ProfilingAgent, VTuneAgent};
-use wasmtime_runtime::{debug_builtins, RuntimeMemoryCreator, VMInterrupts};
+use wasmtime_profiling::{
+    JitDumpAgent, NullProfilerAgent, PerfMapAgent, ProfilingAgent, SamplingAgent, VTuneAgent,
+};
+use wasmtime_runtime::{
+    debug_builtins, DebugHandler, InstanceHandle, RuntimeMemoryCreator, VMContext, VMDebugFrame,
+    VMInterrupts,
+};
 
 // Runtime Environment
 
@@ -31,10 +44,24 @@ pub struct Config {
     pub(crate) strategy: CompilationStrategy,
     pub(crate) cache_config: CacheConfig,
     pub(crate) profiler: Arc<dyn ProfilingAgent>,
+    pub(crate) sampler: Option<Arc<SamplingAgent>>,
     pub(crate) memory_creator: Option<MemoryCreatorProxy>,
     pub(crate) max_wasm_stack: usize,
+    pub(crate) deterministic_import_overrides: HashSet<(String, String)>,
 }
 
+/// The module names WASI functions are imported from.
+const WASI_MODULES: &[&str] = &["wasi_snapshot_preview1", "wasi_unstable"];
+
+/// WASI functions whose results can differ between calls with the same
+/// arguments.
+const NONDETERMINISTIC_WASI_FUNCTIONS: &[&str] = &[
+    "clock_res_get",
+    "clock_time_get",
+    "poll_oneoff",
+    "random_get",
+];
+
 impl Config {
     /// Creates a new configuration object with the default configuration
     /// specified.
@@ -86,8 +113,10 @@ impl Config {
             strategy: CompilationStrategy::Auto,
             cache_config: CacheConfig::new_cache_disabled(),
             profiler: Arc::new(NullProfilerAgent),
+            sampler: None,
             memory_creator: None,
             max_wasm_stack: 1 << 20,
+            deterministic_import_overrides: HashSet::new(),
         }
     }
 
@@ -112,6 +141,132 @@ impl Config {
         self
     }
 
+    /// Configures whether compiled code consumes fuel as it executes, so that
+    /// it can be interrupted deterministically once it has run a given number
+    /// of instructions.
+    ///
+    /// Each wasm instruction consumes one unit of fuel. The fuel available
+    /// to a [`Store`] is set with [`Store::set_fuel`], and once it runs out
+    /// execution traps as if it had been interrupted. Fuel is taken at the
+    /// end of each basic block and before calls, so code may run past the
+    /// point where fuel ran out until then.
+    ///
+    /// By default this option is `false`.
+    pub fn consume_fuel(&mut self, enable: bool) -> &mut Self {
+        self.tunables.consume_fuel = enable;
+        self
+    }
+
+    /// Configures whether compiled code will be instrumented so it can be
+    /// paused and inspected with the [`Debugger`] returned by
+    /// [`Store::debugger`].
+    ///
+    /// Instrumented code checks before each wasm instruction whether a
+    /// debugger wants to pause there, which makes it significantly larger and
+    /// slower even while no breakpoints are set. This is only supported by the
+    /// Cranelift backend.
+    ///
+    /// By default this option is `false`.
+    pub fn debug_instrumentation(&mut self, enable: bool) -> &mut Self {
+        self.tunables.debug_instrumentation = enable;
+        self
+    }
+
+    /// Configures whether compiled code will count how often each of its
+    /// basic blocks is entered, so that
+    /// [`Instance::coverage_report`](crate::Instance::coverage_report) can
+    /// report which source lines were executed.
+    ///
+    /// The counters are kept per instance. This is only supported by the
+    /// Cranelift backend.
+    ///
+    /// By default this option is `false`.
+    pub fn coverage(&mut self, enable: bool) -> &mut Self {
+        self.tunables.coverage = enable;
+        self
+    }
+
+    /// Configures whether the pages of linear memories are populated lazily,
+    /// the first time they're accessed, instead of when they're created.
+    ///
+    /// With this enabled, instantiating a module doesn't copy its data
+    /// segments into memory. Instead a handler thread fills in each page from
+    /// the data segments, or with zeros, when wasm or the host first touches
+    /// it. Instances which only use a small part of a large memory image then
+    /// only pay for that part, which is reflected in
+    /// [`MemoryUsage::resident_linear_memory`](crate::MemoryUsage::resident_linear_memory).
+    /// Restoring an [`InstanceSnapshot`](crate::InstanceSnapshot) also
+    /// discards such memories at once instead of comparing every page.
+    ///
+    /// This uses `userfaultfd` and so only works on Linux, where it can also
+    /// be disabled for unprivileged processes. It's also only used for
+    /// memories which are reserved up front rather than moved as they grow,
+    /// and which are created by wasmtime itself rather than by a
+    /// [`MemoryCreator`](crate::MemoryCreator). Data segments are only served
+    /// lazily when all of a module's segments have constant offsets.
+    /// Everywhere else memories are populated as usual.
+    ///
+    /// By default this option is `false`.
+    pub fn memory_lazy_init(&mut self, enable: bool) -> &mut Self {
+        self.tunables.userfaultfd = enable;
+        self
+    }
+
+    /// Configures the maximum size, in bytes, of linear memories which are
+    /// "static": reserved up front and never moved.
+    ///
+    /// A memory whose declared maximum size is at most `max_size` reserves
+    /// `max_size` bytes of address space, plus the guard configured with
+    /// [`Config::static_memory_guard_size`], when it's created. It can then
+    /// grow in place and, with large enough reservations and guards, compiled
+    /// code doesn't need to check the bounds of its accesses at all. All
+    /// other memories are "dynamic": they only reserve their current size and
+    /// are moved when they grow, and every access is explicitly checked.
+    ///
+    /// The default reserves 4 GiB, which covers every 32-bit memory, on
+    /// 64-bit hosts. Where address space is limited, for example with
+    /// `ulimit -v`, lowering this (down to 0 to make all memories dynamic)
+    /// allows many more instances to exist at once at the cost of some
+    /// performance.
+    ///
+    /// `max_size` is rounded down to a multiple of the wasm page size, and
+    /// sizes beyond 4 GiB are treated as 4 GiB.
+    pub fn static_memory_maximum_size(&mut self, max_size: u64) -> &mut Self {
+        let max_pages = min(
+            max_size / u64::from(WASM_PAGE_SIZE),
+            u64::from(WASM_MAX_PAGES),
+        );
+        self.tunables.static_memory_bound = max_pages as u32;
+        self
+    }
+
+    /// Configures the size, in bytes, of the guard region reserved after
+    /// static memories, see [`Config::static_memory_maximum_size`].
+    ///
+    /// Accesses whose offset falls within the guard are caught by the guard
+    /// pages instead of explicit bounds checks. The default on 64-bit hosts
+    /// is 2 GiB, which with the default maximum size covers every possible
+    /// access.
+    ///
+    /// `guard_size` is rounded up to a multiple of the host page size.
+    pub fn static_memory_guard_size(&mut self, guard_size: u64) -> &mut Self {
+        self.tunables.static_memory_offset_guard_size = round_up_to_pages(guard_size);
+        self
+    }
+
+    /// Configures the size, in bytes, of the guard region reserved after
+    /// dynamic memories, see [`Config::static_memory_maximum_size`].
+    ///
+    /// Bounds checks of accesses to the same address with offsets that fall
+    /// within the same multiple of the guard size can share a single check,
+    /// so a larger guard makes dynamic memories faster. The default is 64 KiB.
+    ///
+    /// `guard_size` is rounded up to a multiple of the host page size.
+    pub fn dynamic_memory_guard_size(&mut self, guard_size: u64) -> &mut Self {
+        self.tunables.dynamic_memory_offset_guard_size = round_up_to_pages(guard_size);
+        self
+    }
+
     /// Configures the maximum amount of native stack space available to
     /// executing WebAssembly code.
     ///
@@ -278,14 +433,36 @@ impl Config {
     /// Profiler creation calls the type's default initializer where the purpose is
     /// really just to put in place the type used for profiling.
     pub fn profiler(&mut self, profile: ProfilingStrategy) -> Result<&mut Self> {
+        // Drop any previous sampler first, since only one can run at a time.
+        self.sampler = None;
+        self.profiler = Arc::new(NullProfilerAgent);
         self.profiler = match profile {
             ProfilingStrategy::JitDump => Arc::new(JitDumpAgent::new()?) as Arc<dyn ProfilingAgent>,
             ProfilingStrategy::VTune => Arc::new(VTuneAgent::new()?) as Arc<dyn ProfilingAgent>,
+            ProfilingStrategy::PerfMap => Arc::new(PerfMapAgent::new()?) as Arc<dyn ProfilingAgent>,
+            ProfilingStrategy::Sampling => {
+                return self.sampling_profiler(Duration::from_millis(1));
+            }
             ProfilingStrategy::None => Arc::new(NullProfilerAgent),
         };
         Ok(self)
     }
 
+    /// Enables the sampling profiler like [`ProfilingStrategy::Sampling`],
+    /// but samples every `interval` of CPU time instead of every millisecond.
+    ///
+    /// Shorter intervals give more detailed profiles, at the cost of slowing
+    /// the guest down more. This fails if `interval` is zero.
+    pub fn sampling_profiler(&mut self, interval: Duration) -> Result<&mut Self> {
+        // Drop any previous sampler first, since only one can run at a time.
+        self.sampler = None;
+        self.profiler = Arc::new(NullProfilerAgent);
+        let sampler = Arc::new(SamplingAgent::new(interval)?);
+        self.sampler = Some(sampler.clone());
+        self.profiler = sampler as Arc<dyn ProfilingAgent>;
+        Ok(self)
+    }
+
     /// Configures whether the debug verifier of Cranelift is enabled or not.
     ///
     /// When Cranelift is used as a code generation backend this will configure
@@ -337,6 +514,88 @@ impl Config {
         self
     }
 
+    /// Configures whether wasm code will be compiled and run such that it
+    /// gives bit-identical results on every host, for replayable or
+    /// consensus-critical computation.
+    ///
+    /// Enabling this:
+    ///
+    /// * turns on [`Config::cranelift_nan_canonicalization`], so that NaNs
+    ///   have the same bits everywhere,
+    /// * turns off the [threads](Config::wasm_threads) and
+    ///   [SIMD](Config::wasm_simd) proposals, as the results of some of their
+    ///   instructions depend on the host,
+    /// * turns off [`Config::interruptable`], as interrupts depend on timing,
+    ///   and turns on [`Config::consume_fuel`] instead, so that execution can
+    ///   be interrupted after the same number of instructions everywhere,
+    /// * makes `memory.grow` fail only if the memory would exceed its limits.
+    ///   If the host can't provide the memory, execution traps instead.
+    ///
+    /// Creating a [`Module`](crate::Module) fails if any of these settings
+    /// were changed back afterwards, or if the module imports a WASI function
+    /// such as `clock_time_get` or `random_get` whose results differ between
+    /// calls. Embedders which provide a deterministic implementation of such
+    /// an import can allow it with
+    /// [`Config::override_nondeterministic_import`].
+    ///
+    /// This is `false` by default.
+    pub fn deterministic(&mut self, enable: bool) -> &mut Self {
+        self.tunables.deterministic = enable;
+        if enable {
+            self.cranelift_nan_canonicalization(true);
+            self.wasm_threads(false);
+            self.wasm_simd(false);
+            self.interruptable(false);
+            self.consume_fuel(true);
+        }
+        self
+    }
+
+    /// Declares that the embedder provides a deterministic implementation of
+    /// the import `name` from `module`, so that modules importing it can be
+    /// created with [`Config::deterministic`] enabled even if it's a WASI
+    /// function which isn't deterministic otherwise.
+    pub fn override_nondeterministic_import(&mut self, module: &str, name: &str) -> &mut Self {
+        self.deterministic_import_overrides
+            .insert((module.to_string(), name.to_string()));
+        self
+    }
+
+    /// Checks that code compiled with this configuration, which has
+    /// [`Config::deterministic`] enabled, behaves the same on every host,
+    /// and that `module` doesn't import known non-deterministic functions.
+    pub(crate) fn check_deterministic(&self, module: &wasmtime_environ::Module) -> Result<()> {
+        let operators = &self.validating_config.operator_config;
+        if operators.enable_threads || operators.enable_simd {
+            bail!("the threads and SIMD proposals can't be enabled in deterministic mode");
+        }
+        if !settings::Flags::new(self.flags.clone()).enable_nan_canonicalization() {
+            bail!("NaN canonicalization can't be disabled in deterministic mode");
+        }
+        if self.tunables.interruptable {
+            bail!("interruptable code can't be used in deterministic mode");
+        }
+        if !self.tunables.consume_fuel {
+            bail!("fuel consumption can't be disabled in deterministic mode");
+        }
+        for (module_name, name, _) in &module.imports {
+            if WASI_MODULES.contains(&module_name.as_str())
+                && NONDETERMINISTIC_WASI_FUNCTIONS.contains(&name.as_str())
+                && !self
+                    .deterministic_import_overrides
+                    .contains(&(module_name.clone(), name.clone()))
+            {
+                bail!(
+                    "import `{}::{}` isn't deterministic, see \
+                     `Config::override_nondeterministic_import`",
+                    module_name,
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Loads cache configuration specified at `path`.
     ///
     /// This method will read the file specified by `path` on the filesystem and
@@ -393,6 +652,12 @@ impl Default for Config {
     }
 }
 
+fn round_up_to_pages(val: u64) -> u64 {
+    let page_size = region::page::size() as u64;
+    // Page sizes are always powers of two.
+    val.saturating_add(page_size - 1) & !(page_size - 1)
+}
+
 impl fmt::Debug for Config {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let features = &self.validating_config.operator_config;
@@ -404,6 +669,20 @@ impl fmt::Debug for Config {
             .field("wasm_bulk_memory", &features.enable_bulk_memory)
             .field("wasm_simd", &features.enable_simd)
             .field("wasm_multi_value", &features.enable_multi_value)
+            .field("deterministic", &self.tunables.deterministic)
+            .field("memory_lazy_init", &self.tunables.userfaultfd)
+            .field(
+                "static_memory_maximum_size",
+                &(u64::from(self.tunables.static_memory_bound) * u64::from(WASM_PAGE_SIZE)),
+            )
+            .field(
+                "static_memory_guard_size",
+                &self.tunables.static_memory_offset_guard_size,
+            )
+            .field(
+                "dynamic_memory_guard_size",
+                &self.tunables.dynamic_memory_offset_guard_size,
+            )
             .field(
                 "flags",
                 &settings::Flags::new(self.flags.clone()).to_string(),
@@ -415,6 +694,12 @@ impl fmt::Debug for Config {
 /// Possible Compilation strategies for a wasm module.
 ///
 /// This is used as an argument to the [`Config::strategy`] method.
+///
+/// Note that every function in a module is compiled with the same strategy,
+/// and code is never recompiled with a different backend after a module has
+/// been created. Calls between functions of a module are resolved to absolute
+/// addresses when the module is linked, so there's currently no way to switch
+/// a function over to new code once the module has been instantiated.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
 pub enum Strategy {
@@ -467,6 +752,18 @@ pub enum ProfilingStrategy {
 
     /// Collect profiling info using the "ittapi", used with `VTune` on Linux.
     VTune,
+
+    /// Write the address of every compiled function to `/tmp/perf-<pid>.map`,
+    /// which `perf` on Linux reads without needing `perf inject`.
+    PerfMap,
+
+    /// Periodically sample the wasm stacks of running threads with a timer
+    /// signal, without relying on external tools. The samples are retrieved
+    /// with [`Engine::take_guest_profile`].
+    ///
+    /// This is currently only supported on x86_64 Linux, and only one
+    /// configuration in a process may use it at a time.
+    Sampling,
 }
 
 // Engine
@@ -496,6 +793,7 @@ pub enum ProfilingStrategy {
 #[derive(Default, Clone)]
 pub struct Engine {
     config: Arc<Config>,
+    counters: Arc<EngineCounters>,
 }
 
 impl Engine {
@@ -505,6 +803,7 @@ impl Engine {
         debug_builtins::ensure_exported();
         Engine {
             config: Arc::new(config.clone()),
+            counters: Default::default(),
         }
     }
 
@@ -512,6 +811,33 @@ impl Engine {
     pub fn config(&self) -> &Config {
         &self.config
     }
+
+    /// Returns statistics about the work done by this engine so far, such as
+    /// the number of modules compiled and cache usage.
+    ///
+    /// For statistics about an individual module see
+    /// [`Module::compile_stats`](crate::Module::compile_stats).
+    pub fn stats(&self) -> EngineStats {
+        self.counters.snapshot(&self.config.cache_config)
+    }
+
+    pub(crate) fn counters(&self) -> &EngineCounters {
+        &self.counters
+    }
+
+    /// Returns the samples collected since the last call to this method, if
+    /// this engine was configured with [`ProfilingStrategy::Sampling`].
+    ///
+    /// Samples are symbolized when this is called, so it should be called
+    /// while the modules being profiled are still alive. Samples which only
+    /// hit host code are discarded.
+    pub fn take_guest_profile(&self) -> Option<GuestProfile> {
+        let sampler = self.config.sampler.as_ref()?;
+        Some(GuestProfile::new(
+            sampler.take_samples(),
+            sampler.interval(),
+        ))
+    }
 }
 
 // Store
@@ -542,6 +868,56 @@ pub struct Store {
 struct StoreInner {
     engine: Engine,
     compiler: RefCell<Compiler>,
+    instances: Rc<LiveInstances>,
+    debugger: Option<Rc<DebuggerState>>,
+    call_hook: RefCell<Option<Rc<CallHookCallback>>>,
+}
+
+type CallHookCallback = dyn Fn(CallHook) -> Result<(), Trap>;
+
+/// A transition between host and wasm code, reported to the hook registered
+/// with [`Store::call_hook`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CallHook {
+    /// The host is about to call into wasm.
+    CallingWasm,
+    /// A call from the host into wasm has returned, or trapped.
+    ReturningFromWasm,
+    /// Wasm is about to call a host function.
+    CallingHost,
+    /// A host function called by wasm is about to return.
+    ReturningFromHost,
+}
+
+/// The instances currently alive within a `Store`.
+///
+/// Instances are tracked by their `vmctx` without holding on to a handle, so
+/// the store doesn't keep them alive. Instead each tracked instance carries an
+/// `InstanceRegistration` in its host state which removes the entry again
+/// once the instance is deallocated.
+#[derive(Default)]
+struct LiveInstances {
+    next_id: Cell<usize>,
+    vmctx: RefCell<HashMap<usize, *mut VMContext>>,
+}
+
+/// Keeps an instance registered with its `Store` for as long as the instance
+/// is alive. See `Store::register_instance`.
+pub(crate) struct InstanceRegistration {
+    instances: Rc<LiveInstances>,
+    id: usize,
+}
+
+impl InstanceRegistration {
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl Drop for InstanceRegistration {
+    fn drop(&mut self) {
+        self.instances.vmctx.borrow_mut().remove(&self.id);
+    }
 }
 
 impl Store {
@@ -554,10 +930,18 @@ impl Store {
             engine.config.cache_config.clone(),
             engine.config.tunables.clone(),
         );
+        let debugger = if engine.config.tunables.debug_instrumentation {
+            Some(Rc::new(DebuggerState::new(compiler.interrupts().clone())))
+        } else {
+            None
+        };
         Store {
             inner: Rc::new(StoreInner {
                 engine: engine.clone(),
                 compiler: RefCell::new(compiler),
+                instances: Default::default(),
+                debugger,
+                call_hook: RefCell::new(None),
             }),
         }
     }
@@ -580,6 +964,74 @@ impl Store {
         self.inner.compiler.borrow_mut()
     }
 
+    /// Creates a registration which must be stored in the host state of a
+    /// new instance, which is then passed to `register_instance`.
+    pub(crate) fn instance_registration(&self) -> InstanceRegistration {
+        let instances = &self.inner.instances;
+        let id = instances.next_id.get();
+        instances.next_id.set(id + 1);
+        InstanceRegistration {
+            instances: instances.clone(),
+            id,
+        }
+    }
+
+    /// Tracks `handle` in this store until the `InstanceRegistration` with the
+    /// id `registration` is dropped along with the instance's host state.
+    ///
+    /// The registration is created separately since it needs to be part of
+    /// the host state when the instance is created, before `handle` exists.
+    pub(crate) fn register_instance(&self, registration: usize, handle: &InstanceHandle) {
+        self.inner
+            .instances
+            .vmctx
+            .borrow_mut()
+            .insert(registration, handle.vmctx_ptr());
+    }
+
+    /// Returns the handler which debug-instrumented instances of this store
+    /// should call, if debug instrumentation is enabled.
+    pub(crate) fn debug_handler(&self) -> Option<Rc<DebugHandler>> {
+        let state = self.inner.debugger.clone()?;
+        // Instances don't keep their store alive, so don't create a cycle
+        // through their handlers either.
+        let store = Rc::downgrade(&self.inner);
+        let handler: Rc<DebugHandler> = Rc::new(move |frame: &VMDebugFrame| {
+            let store = match store.upgrade() {
+                Some(inner) => Store { inner },
+                None => return Ok(()),
+            };
+            state
+                .handle_event(&store, frame)
+                .map_err(|trap| trap.into())
+        });
+        Some(handler)
+    }
+
+    /// Returns the amount of memory currently used by the linear memories and
+    /// tables of all instances in this store.
+    ///
+    /// This includes memories and tables created with [`Memory::new`] and
+    /// [`Table::new`], and only counts each memory or table once even if it's
+    /// imported by other instances. Only memory which is accessible to wasm is
+    /// counted; address space reserved for guard regions or future growth is
+    /// not.
+    ///
+    /// [`Memory::new`]: crate::Memory::new
+    /// [`Table::new`]: crate::Table::new
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for vmctx in self.inner.instances.vmctx.borrow().values() {
+            // Safety: entries are removed before their instance is
+            // deallocated, so `vmctx` points to a live instance.
+            let handle = unsafe { InstanceHandle::from_vmctx(*vmctx) };
+            usage.linear_memory += handle.defined_memories_size();
+            usage.resident_linear_memory += handle.defined_memories_resident_size();
+            usage.tables += handle.defined_tables_size();
+        }
+        usage
+    }
+
     /// Returns whether the stores `a` and `b` refer to the same underlying
     /// `Store`.
     ///
@@ -680,6 +1132,110 @@ impl Store {
             bail!("interrupts aren't enabled for this `Store`")
         }
     }
+
+    /// Sets the amount of fuel left to wasm code running in this `Store`,
+    /// where each instruction consumes one unit.
+    ///
+    /// Once the fuel runs out execution traps as if it had been interrupted.
+    /// Stores start out with an unlimited amount of fuel.
+    ///
+    /// Returns an error if [`Config::consume_fuel`] isn't enabled, as code
+    /// doesn't consume fuel then.
+    pub fn set_fuel(&self, fuel: u64) -> Result<()> {
+        if !self.engine().config.tunables.consume_fuel {
+            bail!("fuel consumption isn't enabled for this `Store`");
+        }
+        let fuel = i64::try_from(fuel).unwrap_or(i64::max_value());
+        self.compiler()
+            .interrupts()
+            .fuel_remaining
+            .store(fuel, SeqCst);
+        Ok(())
+    }
+
+    /// Returns the amount of fuel left to wasm code running in this `Store`,
+    /// or `None` if [`Config::consume_fuel`] isn't enabled.
+    pub fn fuel_remaining(&self) -> Option<u64> {
+        if !self.engine().config.tunables.consume_fuel {
+            return None;
+        }
+        let fuel = self.compiler().interrupts().fuel_remaining.load(SeqCst);
+        Some(u64::try_from(fuel).unwrap_or(0))
+    }
+
+    /// Returns the [`Debugger`] of this store, which can pause wasm code at
+    /// breakpoints and inspect its state.
+    ///
+    /// Only code compiled with [`Config::debug_instrumentation`] enabled can
+    /// be debugged, so this returns an error if it isn't.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use anyhow::Result;
+    /// # use wasmtime::*;
+    /// # fn main() -> Result<()> {
+    /// let engine = Engine::new(Config::new().debug_instrumentation(true));
+    /// let store = Store::new(&engine);
+    /// let debugger = store.debugger()?;
+    ///
+    /// let module = Module::new(&store, r#"
+    ///     (func (export "add") (param i32 i32) (result i32)
+    ///         local.get 0
+    ///         local.get 1
+    ///         i32.add)
+    /// "#)?;
+    /// let instance = Instance::new(&module, &[])?;
+    /// let add = instance
+    ///     .get_func("add")
+    ///     .ok_or(anyhow::format_err!("failed to find `add` function export"))?
+    ///     .get2::<i32, i32, i32>()?;
+    ///
+    /// // Print the operand stack before each instruction of the call.
+    /// debugger.on_event(|event| {
+    ///     let frame = event.frames().next().unwrap();
+    ///     println!("{:?}: {:?}", frame.offset(), frame.stack());
+    ///     Ok(DebugAction::Step)
+    /// });
+    /// debugger.step();
+    /// assert_eq!(add(1, 2)?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn debugger(&self) -> Result<Debugger> {
+        match &self.inner.debugger {
+            Some(state) => Ok(Debugger::new(state.clone())),
+            None => bail!("debug instrumentation isn't enabled for this `Store`"),
+        }
+    }
+
+    /// Sets a hook which is called whenever execution in this store crosses
+    /// between host and wasm code, replacing any previous hook.
+    ///
+    /// The hook is called when the host calls into wasm through
+    /// [`Func::call`](crate::Func::call) or the closures returned by
+    /// [`Func::get1`](crate::Func::get1) and friends, when that call
+    /// returns, and when wasm calls a host function defined with
+    /// [`Func::new`](crate::Func::new) or [`Func::wrap`](crate::Func::wrap)
+    /// and that function returns. This can be used to account for the time
+    /// spent in wasm, for example.
+    ///
+    /// If the hook returns an error then the wasm code involved traps with
+    /// that error. An error on [`CallHook::CallingWasm`] prevents the call
+    /// from happening at all.
+    pub fn call_hook(&self, hook: impl Fn(CallHook) -> Result<(), Trap> + 'static) {
+        *self.inner.call_hook.borrow_mut() = Some(Rc::new(hook));
+    }
+
+    /// Runs the hook registered with [`Store::call_hook`], if any.
+    pub(crate) fn invoke_call_hook(&self, kind: CallHook) -> Result<(), Trap> {
+        // The hook is cloned out so that it can replace itself.
+        let hook = self.inner.call_hook.borrow().clone();
+        match hook {
+            Some(hook) => hook(kind),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Default for Store {
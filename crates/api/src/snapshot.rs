@@ -0,0 +1,212 @@
+use anyhow::{bail, ensure, Result};
+use std::collections::BTreeMap;
+use std::slice;
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::wasm::{DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex};
+use wasmtime_environ::WASM_PAGE_SIZE;
+use wasmtime_jit::CompiledModule;
+use wasmtime_runtime::{
+    InstanceHandle, VMCallerCheckedAnyfunc, VMGlobalDefinition, VMMemoryDefinition,
+};
+
+const PAGE_SIZE: usize = WASM_PAGE_SIZE as usize;
+
+/// The state of an [`Instance`] at some point, which it can be rolled back to
+/// with [`Instance::restore`].
+///
+/// This is created with [`Instance::snapshot`] and captures the linear
+/// memories, globals and tables the instance defines itself. Items it imports
+/// belong to other instances and aren't part of the snapshot.
+///
+/// [`Instance`]: crate::Instance
+/// [`Instance::restore`]: crate::Instance::restore
+/// [`Instance::snapshot`]: crate::Instance::snapshot
+pub struct InstanceSnapshot {
+    /// The id of the instance the snapshot was taken of, which it can only be
+    /// restored into. Only the id is kept, so that the snapshot doesn't keep
+    /// the instance alive.
+    instance_id: usize,
+    memories: Vec<MemorySnapshot>,
+    globals: Vec<VMGlobalDefinition>,
+    tables: Vec<Vec<VMCallerCheckedAnyfunc>>,
+}
+
+struct MemorySnapshot {
+    /// The size of the memory in wasm pages.
+    pages: usize,
+    /// The memory's initial image, which is built once when the snapshot is
+    /// taken so that restoring it doesn't need to go over the data segments.
+    image: InitialImage,
+    /// The contents of the wasm pages which differ from the memory's initial
+    /// image, sorted by page index. All other pages match the image.
+    dirty: Vec<(usize, Box<[u8]>)>,
+}
+
+/// The contents a defined memory had right after instantiation: zeros, with
+/// the module's active data segments for the memory copied over them.
+struct InitialImage {
+    /// The contents of the wasm pages which data segments were copied into,
+    /// by page index. All other pages are zeros.
+    pages: BTreeMap<usize, Box<[u8]>>,
+}
+
+/// A wasm page of zeros, which pages outside of data segments are compared
+/// with.
+static ZERO_PAGE: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+/// Takes a snapshot of `handle`, an instance of `compiled`.
+pub(crate) fn snapshot(handle: &InstanceHandle, compiled: &CompiledModule) -> InstanceSnapshot {
+    let module = handle.module_ref();
+    let data_initializers = compiled.data_initializers();
+
+    let memories = (module.local.num_imported_memories..module.local.memory_plans.len())
+        .map(|i| {
+            let index = DefinedMemoryIndex::new(i - module.local.num_imported_memories);
+            let image = InitialImage::new(handle, index, &data_initializers);
+            let data = unsafe { memory_data(handle.defined_memory(index)) };
+            let dirty = data
+                .chunks(PAGE_SIZE)
+                .enumerate()
+                .filter(|(page, contents)| contents[..] != *image.page(*page))
+                .map(|(page, contents)| (page, contents.into()))
+                .collect();
+            MemorySnapshot {
+                pages: data.len() / PAGE_SIZE,
+                image,
+                dirty,
+            }
+        })
+        .collect();
+
+    let globals = (module.local.num_imported_globals..module.local.globals.len())
+        .map(|i| {
+            handle.defined_global(DefinedGlobalIndex::new(
+                i - module.local.num_imported_globals,
+            ))
+        })
+        .collect();
+
+    let tables = (module.local.num_imported_tables..module.local.table_plans.len())
+        .map(|i| {
+            let table = handle
+                .get_defined_table(DefinedTableIndex::new(i - module.local.num_imported_tables));
+            (0..table.size()).filter_map(|j| table.get(j)).collect()
+        })
+        .collect();
+
+    InstanceSnapshot {
+        instance_id: handle.id(),
+        memories,
+        globals,
+        tables,
+    }
+}
+
+/// Rolls `handle` back to `snapshot`.
+pub(crate) fn restore(handle: &InstanceHandle, snapshot: &InstanceSnapshot) -> Result<()> {
+    ensure!(
+        handle.id() == snapshot.instance_id,
+        "the snapshot was taken of a different instance"
+    );
+
+    // Memories and tables which grew since the snapshot go back to their old
+    // sizes first, which discards everything past them.
+    for (i, memory) in snapshot.memories.iter().enumerate() {
+        let index = DefinedMemoryIndex::new(i);
+        if handle.defined_memory(index).current_length != memory.pages * PAGE_SIZE
+            && !handle.shrink_defined_memory(index, memory.pages as u32)
+        {
+            bail!(
+                "memory {} has grown since the snapshot was taken and can't be shrunk",
+                i
+            );
+        }
+    }
+    for (i, elements) in snapshot.tables.iter().enumerate() {
+        let shrunk = handle.shrink_defined_table(DefinedTableIndex::new(i), elements.len() as u32);
+        // Tables never shrink on their own.
+        debug_assert!(shrunk);
+    }
+
+    for (i, memory) in snapshot.memories.iter().enumerate() {
+        let index = DefinedMemoryIndex::new(i);
+        let data = unsafe { memory_data(handle.defined_memory(index)) };
+        // A memory which is populated lazily can go back to its initial image
+        // without touching any of its pages, which leaves only the pages
+        // which differ from the image to write.
+        if handle.reset_defined_memory(index) {
+            for (page, contents) in &memory.dirty {
+                data[page * PAGE_SIZE..][..PAGE_SIZE].copy_from_slice(contents);
+            }
+            continue;
+        }
+        let mut dirty = memory.dirty.iter().peekable();
+        for (page, contents) in data.chunks_mut(PAGE_SIZE).enumerate() {
+            let target = if dirty.peek().map_or(false, |(p, _)| *p == page) {
+                &dirty.next().unwrap().1[..]
+            } else {
+                memory.image.page(page)
+            };
+            // Only write pages which were modified, so that untouched pages
+            // stay shared with the zero page.
+            if contents[..] != target[..] {
+                contents.copy_from_slice(target);
+            }
+        }
+    }
+
+    for (i, global) in snapshot.globals.iter().enumerate() {
+        handle.set_defined_global(DefinedGlobalIndex::new(i), *global);
+    }
+
+    for (i, elements) in snapshot.tables.iter().enumerate() {
+        let table = handle.get_defined_table(DefinedTableIndex::new(i));
+        for (j, element) in elements.iter().enumerate() {
+            table.set(j as u32, element.clone()).unwrap();
+        }
+    }
+    Ok(())
+}
+
+impl InitialImage {
+    fn new(
+        handle: &InstanceHandle,
+        index: DefinedMemoryIndex,
+        data_initializers: &[wasmtime_environ::DataInitializer<'_>],
+    ) -> InitialImage {
+        let memory_index = handle.module_ref().local.memory_index(index);
+        let mut pages = BTreeMap::new();
+        for init in data_initializers
+            .iter()
+            .filter(|init| init.location.memory_index == memory_index)
+        {
+            // Instantiation succeeded, so every segment is in bounds.
+            let mut offset = handle.memory_init_start(init);
+            let mut data = init.data;
+            while !data.is_empty() {
+                let page = pages
+                    .entry(offset / PAGE_SIZE)
+                    .or_insert_with(|| ZERO_PAGE.to_vec().into_boxed_slice());
+                let start = offset % PAGE_SIZE;
+                let len = data.len().min(PAGE_SIZE - start);
+                page[start..start + len].copy_from_slice(&data[..len]);
+                offset += len;
+                data = &data[len..];
+            }
+        }
+        InitialImage { pages }
+    }
+
+    /// Returns the initial contents of wasm page `page`.
+    fn page(&self, page: usize) -> &[u8] {
+        self.pages
+            .get(&page)
+            .map_or(&ZERO_PAGE[..], |page| &page[..])
+    }
+}
+
+/// Returns the contents of `memory`, which must stay alive and not be grown
+/// while they're borrowed.
+unsafe fn memory_data<'a>(memory: VMMemoryDefinition) -> &'a mut [u8] {
+    slice::from_raw_parts_mut(memory.base, memory.current_length)
+}
@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::time::Duration;
+use wasmtime_environ::CacheConfig;
+use wasmtime_jit::CompiledModule;
+
+/// Statistics collected by an [`Engine`](crate::Engine) since it was created.
+///
+/// This is returned by [`Engine::stats`](crate::Engine::stats). All numbers
+/// are cumulative, so to report rates take the difference between two
+/// snapshots.
+///
+/// Note that the cache numbers are shared by all engines created from the
+/// same [`Config`](crate::Config).
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct EngineStats {
+    /// Number of modules compiled (or loaded from the cache).
+    pub modules_compiled: usize,
+    /// Number of functions compiled (or loaded from the cache).
+    pub functions_compiled: usize,
+    /// Total size in bytes of the machine code of all compiled functions.
+    pub compiled_bytes: usize,
+    /// Number of trampolines generated for calling into wasm from the host.
+    pub trampolines: usize,
+    /// Number of instances created from wasm modules.
+    pub instances: usize,
+    /// Number of modules found in the compilation cache.
+    pub cache_hits: usize,
+    /// Number of modules which weren't in the compilation cache.
+    pub cache_misses: usize,
+    /// Number of individual functions found in the compilation cache.
+    pub function_cache_hits: usize,
+    /// Number of individual functions which weren't in the compilation cache.
+    pub function_cache_misses: usize,
+    /// Number of entries removed from the compilation cache by its cleanup
+    /// task.
+    pub cache_evictions: usize,
+}
+
+/// Statistics about the compilation of a single [`Module`](crate::Module).
+///
+/// This is returned by [`Module::compile_stats`](crate::Module::compile_stats).
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ModuleCompileStats {
+    /// Wall-clock time it took to compile the module, including translation,
+    /// code generation and linking.
+    pub compile_time: Duration,
+    /// Time spent in each Cranelift pass.
+    ///
+    /// This is empty if the module was loaded from the cache or compiled with
+    /// a different backend.
+    pub pass_times: Vec<PassTime>,
+    /// Size in bytes of the machine code of each function defined in the
+    /// module, as pairs of function index and size.
+    pub function_sizes: Vec<(u32, usize)>,
+    /// Total size in bytes of the machine code of all functions.
+    pub compiled_bytes: usize,
+    /// Number of trampolines generated for this module.
+    pub trampolines: usize,
+}
+
+/// Time spent in a single Cranelift compilation pass.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PassTime {
+    /// Human readable description of the pass.
+    pub pass: &'static str,
+    /// Total time spent in this pass, including nested passes.
+    pub total: Duration,
+    /// Time spent in this pass, excluding nested passes.
+    pub self_time: Duration,
+}
+
+/// Amount of memory used by the instances of a [`Store`](crate::Store).
+///
+/// This is returned by [`Store::memory_usage`](crate::Store::memory_usage).
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct MemoryUsage {
+    /// Number of bytes currently accessible in all linear memories.
+    pub linear_memory: usize,
+    /// Number of bytes of all linear memories which are backed by physical
+    /// pages.
+    ///
+    /// This is only smaller than `linear_memory` for memories whose pages are
+    /// populated on first access, see
+    /// [`Config::memory_lazy_init`](crate::Config::memory_lazy_init).
+    pub resident_linear_memory: usize,
+    /// Number of bytes used by the elements of all tables.
+    pub tables: usize,
+}
+
+impl ModuleCompileStats {
+    pub(crate) fn new(compiled: &CompiledModule, compile_time: Duration) -> ModuleCompileStats {
+        let stats = compiled.compilation_stats();
+        let local = &compiled.module_ref().local;
+        let function_sizes = stats
+            .function_sizes
+            .iter()
+            .map(|(index, size)| (local.func_index(index).as_u32(), *size))
+            .collect::<Vec<_>>();
+        ModuleCompileStats {
+            compile_time,
+            pass_times: stats
+                .pass_times
+                .iter()
+                .map(|&(pass, total, self_time)| PassTime {
+                    pass,
+                    total,
+                    self_time,
+                })
+                .collect(),
+            compiled_bytes: function_sizes.iter().map(|(_, size)| size).sum(),
+            function_sizes,
+            trampolines: stats.trampolines,
+        }
+    }
+}
+
+/// Counters behind [`EngineStats`], shared by all clones of an `Engine`.
+#[derive(Default)]
+pub(crate) struct EngineCounters {
+    modules_compiled: AtomicUsize,
+    functions_compiled: AtomicUsize,
+    compiled_bytes: AtomicUsize,
+    trampolines: AtomicUsize,
+    instances: AtomicUsize,
+}
+
+impl EngineCounters {
+    pub(crate) fn on_module_compiled(&self, stats: &ModuleCompileStats) {
+        self.modules_compiled.fetch_add(1, SeqCst);
+        self.functions_compiled
+            .fetch_add(stats.function_sizes.len(), SeqCst);
+        self.compiled_bytes.fetch_add(stats.compiled_bytes, SeqCst);
+        self.trampolines.fetch_add(stats.trampolines, SeqCst);
+    }
+
+    pub(crate) fn on_instantiated(&self) {
+        self.instances.fetch_add(1, SeqCst);
+    }
+
+    pub(crate) fn snapshot(&self, cache_config: &CacheConfig) -> EngineStats {
+        EngineStats {
+            modules_compiled: self.modules_compiled.load(SeqCst),
+            functions_compiled: self.functions_compiled.load(SeqCst),
+            compiled_bytes: self.compiled_bytes.load(SeqCst),
+            trampolines: self.trampolines.load(SeqCst),
+            instances: self.instances.load(SeqCst),
+            cache_hits: cache_config.cache_hits(),
+            cache_misses: cache_config.cache_misses(),
+            function_cache_hits: cache_config.function_cache_hits(),
+            function_cache_misses: cache_config.function_cache_misses(),
+            cache_evictions: cache_config.cache_evictions(),
+        }
+    }
+}
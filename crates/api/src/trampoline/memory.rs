@@ -27,13 +27,17 @@ pub fn create_handle_with_memory(store: &Store, memory: &MemoryType) -> Result<I
         .exports
         .insert("memory".to_string(), EntityIndex::Memory(memory_id));
 
-    create_handle(
+    let registration = store.instance_registration();
+    let registration_id = registration.id();
+    let handle = create_handle(
         module,
         store,
         PrimaryMap::new(),
         Default::default(),
-        Box::new(()),
-    )
+        Box::new(registration),
+    )?;
+    store.register_instance(registration_id, &handle);
+    Ok(handle)
 }
 
 struct LinearMemoryProxy {
@@ -25,11 +25,15 @@ pub fn create_handle_with_table(store: &Store, table: &TableType) -> Result<Inst
         .exports
         .insert("table".to_string(), EntityIndex::Table(table_id));
 
-    create_handle(
+    let registration = store.instance_registration();
+    let registration_id = registration.id();
+    let handle = create_handle(
         module,
         store,
         PrimaryMap::new(),
         Default::default(),
-        Box::new(()),
-    )
+        Box::new(registration),
+    )?;
+    store.register_instance(registration_id, &handle);
+    Ok(handle)
 }
@@ -14,6 +14,7 @@ pub struct Trap {
 
 struct TrapInner {
     message: String,
+    i32_exit_status: Option<i32>,
     wasm_trace: Vec<FrameInfo>,
     native_trace: Backtrace,
 }
@@ -34,6 +35,25 @@ impl Trap {
         Trap::new_with_trace(&info, None, message.into(), Backtrace::new_unresolved())
     }
 
+    /// Creates a new `Trap` representing an explicit program exit with a
+    /// classic `i32` exit status value.
+    ///
+    /// This is how WASI's `proc_exit` stops the program: instead of exiting
+    /// the host process it unwinds back to the caller of
+    /// [`Func::call`](crate::Func::call), which can then use
+    /// [`Trap::i32_exit_status`] to find out how the program exited.
+    pub fn i32_exit(status: i32) -> Self {
+        let info = FRAME_INFO.read().unwrap();
+        let mut trap = Trap::new_with_trace(
+            &info,
+            None,
+            format!("Exited with i32 exit status {}", status),
+            Backtrace::new_unresolved(),
+        );
+        Arc::get_mut(&mut trap.inner).unwrap().i32_exit_status = Some(status);
+        trap
+    }
+
     pub(crate) fn from_jit(jit: wasmtime_runtime::Trap) -> Self {
         let info = FRAME_INFO.read().unwrap();
         match jit {
@@ -105,30 +125,11 @@ impl Trap {
         message: String,
         native_trace: Backtrace,
     ) -> Self {
-        let mut wasm_trace = Vec::new();
-        for frame in native_trace.frames() {
-            let pc = frame.ip() as usize;
-            if pc == 0 {
-                continue;
-            }
-            // Note that we need to be careful about the pc we pass in here to
-            // lookup frame information. This program counter is used to
-            // translate back to an original source location in the origin wasm
-            // module. If this pc is the exact pc that the trap happened at,
-            // then we look up that pc precisely. Otherwise backtrace
-            // information typically points at the pc *after* the call
-            // instruction (because otherwise it's likely a call instruction on
-            // the stack). In that case we want to lookup information for the
-            // previous instruction (the call instruction) so we subtract one as
-            // the lookup.
-            let pc_to_lookup = if Some(pc) == trap_pc { pc } else { pc - 1 };
-            if let Some(info) = info.lookup_frame_info(pc_to_lookup) {
-                wasm_trace.push(info);
-            }
-        }
+        let wasm_trace = info.lookup_backtrace(&native_trace, trap_pc);
         Trap {
             inner: Arc::new(TrapInner {
                 message,
+                i32_exit_status: None,
                 wasm_trace,
                 native_trace,
             }),
@@ -140,6 +141,12 @@ impl Trap {
         &self.inner.message
     }
 
+    /// If the trap was created with [`Trap::i32_exit`], returns the exit
+    /// status it carries.
+    pub fn i32_exit_status(&self) -> Option<i32> {
+        self.inner.i32_exit_status
+    }
+
     /// Returns a list of function frames in WebAssembly code that led to this
     /// trap happening.
     pub fn trace(&self) -> &[FrameInfo] {
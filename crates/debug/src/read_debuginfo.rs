@@ -248,3 +248,63 @@ pub fn read_debuginfo(data: &[u8]) -> Result<DebugInfoData> {
         },
     })
 }
+
+/// A row of the DWARF line table of a module.
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    /// The offset within the module of the first instruction of the row.
+    pub module_offset: u64,
+    /// The path of the source file, relative to the compilation directory if
+    /// it wasn't absolute.
+    pub file: PathBuf,
+    /// The line within the source file, starting at 1.
+    pub line: u64,
+}
+
+/// Reads the DWARF line tables of the module in `data`, returning their rows
+/// ordered by offset. Rows without a line are skipped.
+pub fn read_source_lines(data: &[u8]) -> Result<Vec<SourceLine>> {
+    let debuginfo = read_debuginfo(data)?;
+    let dwarf = &debuginfo.dwarf;
+    let code_section_offset = debuginfo.wasm_file.code_section_offset;
+
+    let mut lines = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+        let comp_dir = match &unit.comp_dir {
+            Some(dir) => PathBuf::from(dir.to_string_lossy().into_owned()),
+            None => PathBuf::new(),
+        };
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            if row.end_sequence() {
+                continue;
+            }
+            let (line, file) = match (row.line(), row.file(header)) {
+                (Some(line), Some(file)) if line > 0 => (line, file),
+                _ => continue,
+            };
+            let mut path = comp_dir.clone();
+            if file.directory_index() != 0 {
+                if let Some(dir) = file.directory(header) {
+                    let dir = dwarf.attr_string(&unit, dir)?;
+                    path.push(&*dir.to_string_lossy());
+                }
+            }
+            let name = dwarf.attr_string(&unit, file.path_name())?;
+            path.push(&*name.to_string_lossy());
+            lines.push(SourceLine {
+                module_offset: code_section_offset + row.address(),
+                file: path,
+                line,
+            });
+        }
+    }
+    lines.sort_by_key(|line| line.module_offset);
+    Ok(lines)
+}
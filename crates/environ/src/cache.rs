@@ -1,9 +1,12 @@
-use crate::address_map::{ModuleAddressMap, ValueLabelsRanges};
-use crate::compilation::{Compilation, Relocations, Traps};
+use crate::address_map::{FunctionAddressMap, ModuleAddressMap, ValueLabelsRanges};
+use crate::compilation::{
+    Compilation, CompiledFunction, Relocation, Relocations, TrapInformation, Traps,
+};
 use cranelift_codegen::ir;
 use cranelift_entity::PrimaryMap;
 use cranelift_wasm::DefinedFuncIndex;
 use log::{debug, trace, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
@@ -21,6 +24,14 @@ use worker::Worker;
 
 pub struct ModuleCacheEntry<'config>(Option<ModuleCacheEntryInner<'config>>);
 
+/// Cache of individually compiled functions.
+///
+/// Entries live next to the module entries (in a `functions` directory
+/// instead of `modules`) so they're managed by the same worker, but they're
+/// looked up one function at a time. This means that a module in which only a
+/// few function bodies changed can still reuse the code of all the other ones.
+pub struct FunctionCacheEntry<'config>(Option<ModuleCacheEntryInner<'config>>);
+
 struct ModuleCacheEntryInner<'config> {
     root_path: PathBuf,
     cache_config: &'config CacheConfig,
@@ -47,12 +58,42 @@ pub type ModuleCacheDataTupleType = (
     Traps,
 );
 
+/// Cached compilation data of a single Wasm function.
+///
+/// Source locations are stored relative to the start of the function body in
+/// the module, so that entries stay valid when the function moves around in
+/// the wasm binary (e.g. because a preceding function changed).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FunctionCacheData {
+    /// The compiled function body.
+    pub function: CompiledFunction,
+    /// Relocations to apply to the function body.
+    pub relocations: Vec<Relocation>,
+    /// Mapping from wasm offsets to native code offsets.
+    pub address_transform: FunctionAddressMap,
+    /// Value ranges, only present with debug info enabled.
+    pub value_ranges: cranelift_codegen::ValueLabelsRanges,
+    /// Stack slots of the function.
+    pub stack_slots: ir::StackSlots,
+    /// Trap information of the function.
+    pub traps: Vec<TrapInformation>,
+}
+
 struct Sha256Hasher(Sha256);
 
+fn hash_state<T: Hash>(state: &T) -> String {
+    let mut hasher = Sha256Hasher(Sha256::new());
+    state.hash(&mut hasher);
+    let hash: [u8; 32] = hasher.0.result().into();
+    // standard encoding uses '/' which can't be used for filename
+    base64::encode_config(&hash, base64::URL_SAFE_NO_PAD)
+}
+
 impl<'config> ModuleCacheEntry<'config> {
     pub fn new<'data>(compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
         if cache_config.enabled() {
             Self(Some(ModuleCacheEntryInner::new(
+                "modules",
                 compiler_name,
                 cache_config,
             )))
@@ -71,11 +112,7 @@ impl<'config> ModuleCacheEntry<'config> {
         state: T,
         compute: fn(T) -> Result<ModuleCacheDataTupleType, E>,
     ) -> Result<ModuleCacheData, E> {
-        let mut hasher = Sha256Hasher(Sha256::new());
-        state.hash(&mut hasher);
-        let hash: [u8; 32] = hasher.0.result().into();
-        // standard encoding uses '/' which can't be used for filename
-        let hash = base64::encode_config(&hash, base64::URL_SAFE_NO_PAD);
+        let hash = hash_state(&state);
 
         let inner = match &self.0 {
             Some(inner) => inner,
@@ -96,8 +133,66 @@ impl<'config> ModuleCacheEntry<'config> {
     }
 }
 
+impl<'config> FunctionCacheEntry<'config> {
+    pub fn new(compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
+        if cache_config.enabled() {
+            Self(Some(ModuleCacheEntryInner::new(
+                "functions",
+                compiler_name,
+                cache_config,
+            )))
+        } else {
+            Self(None)
+        }
+    }
+
+    #[cfg(test)]
+    fn from_inner(inner: ModuleCacheEntryInner<'config>) -> Self {
+        Self(Some(inner))
+    }
+
+    /// Looks up the function compiled from `state`, or compiles it with
+    /// `compute` and stores the result.
+    ///
+    /// `module_offset` is the offset of the function body in the wasm module,
+    /// which all source locations of the returned data are relative to.
+    pub fn get_data<T: Hash, E>(
+        &self,
+        state: T,
+        module_offset: usize,
+        compute: impl FnOnce(T) -> Result<FunctionCacheData, E>,
+    ) -> Result<FunctionCacheData, E> {
+        let inner = match &self.0 {
+            Some(inner) => inner,
+            None => return compute(state),
+        };
+        let hash = hash_state(&state);
+        let module_offset = module_offset as u32;
+
+        if let Some(mut cached_val) = inner.get_data::<FunctionCacheData>(&hash) {
+            cached_val.rebase_srclocs(0, module_offset);
+            let func_cache_path = inner.root_path.join(&hash);
+            inner
+                .cache_config
+                .on_function_cache_get_async(&func_cache_path); // call on success
+            return Ok(cached_val);
+        }
+        let mut val_to_cache = compute(state)?;
+        val_to_cache.rebase_srclocs(module_offset, 0);
+        let updated = inner.update_data(&hash, &val_to_cache).is_some();
+        val_to_cache.rebase_srclocs(0, module_offset);
+        if updated {
+            let func_cache_path = inner.root_path.join(&hash);
+            inner
+                .cache_config
+                .on_function_cache_update_async(&func_cache_path); // call on success
+        }
+        Ok(val_to_cache)
+    }
+}
+
 impl<'config> ModuleCacheEntryInner<'config> {
-    fn new<'data>(compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
+    fn new<'data>(kind: &str, compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
         // If debug assertions are enabled then assume that we're some sort of
         // local build. We don't want local builds to stomp over caches between
         // builds, so just use a separate cache directory based on the mtime of
@@ -131,7 +226,7 @@ impl<'config> ModuleCacheEntryInner<'config> {
                 comp_ver = env!("GIT_REV"),
             )
         };
-        let root_path = cache_config.directory().join("modules").join(compiler_dir);
+        let root_path = cache_config.directory().join(kind).join(compiler_dir);
 
         Self {
             root_path,
@@ -139,7 +234,7 @@ impl<'config> ModuleCacheEntryInner<'config> {
         }
     }
 
-    fn get_data(&self, hash: &str) -> Option<ModuleCacheData> {
+    fn get_data<T: DeserializeOwned>(&self, hash: &str) -> Option<T> {
         let mod_cache_path = self.root_path.join(hash);
         trace!("get_data() for path: {}", mod_cache_path.display());
         let compressed_cache_bytes = fs::read(&mod_cache_path).ok()?;
@@ -151,7 +246,7 @@ impl<'config> ModuleCacheEntryInner<'config> {
             .ok()
     }
 
-    fn update_data(&self, hash: &str, data: &ModuleCacheData) -> Option<()> {
+    fn update_data<T: Serialize>(&self, hash: &str, data: &T) -> Option<()> {
         let mod_cache_path = self.root_path.join(hash);
         trace!("update_data() for path: {}", mod_cache_path.display());
         let serialized_data = bincode::serialize(&data)
@@ -219,6 +314,26 @@ impl ModuleCacheData {
     }
 }
 
+impl FunctionCacheData {
+    /// Moves all valid source locations from being relative to `from` to being
+    /// relative to `to`.
+    fn rebase_srclocs(&mut self, from: u32, to: u32) {
+        let rebase = |loc: &mut ir::SourceLoc| {
+            if !loc.is_default() {
+                *loc = ir::SourceLoc::new(loc.bits().wrapping_sub(from).wrapping_add(to));
+            }
+        };
+        for inst in self.address_transform.instructions.iter_mut() {
+            rebase(&mut inst.srcloc);
+        }
+        rebase(&mut self.address_transform.start_srcloc);
+        rebase(&mut self.address_transform.end_srcloc);
+        for trap in self.traps.iter_mut() {
+            rebase(&mut trap.source_loc);
+        }
+    }
+}
+
 impl Hasher for Sha256Hasher {
     fn finish(&self) -> u64 {
         panic!("Sha256Hasher doesn't support finish!");
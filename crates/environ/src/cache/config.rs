@@ -98,6 +98,9 @@ pub struct CacheConfig {
 struct CacheState {
     hits: AtomicUsize,
     misses: AtomicUsize,
+    function_hits: AtomicUsize,
+    function_misses: AtomicUsize,
+    evictions: AtomicUsize,
 }
 
 /// Creates a new configuration file at specified path, or default path if None is passed.
@@ -385,6 +388,21 @@ impl CacheConfig {
         self.state.misses.load(SeqCst)
     }
 
+    /// Returns the number of individual functions found in the cache so far
+    pub fn function_cache_hits(&self) -> usize {
+        self.state.function_hits.load(SeqCst)
+    }
+
+    /// Returns the number of individual functions missing from the cache so far
+    pub fn function_cache_misses(&self) -> usize {
+        self.state.function_misses.load(SeqCst)
+    }
+
+    /// Returns the number of cache entries removed by the cleanup task so far
+    pub fn cache_evictions(&self) -> usize {
+        self.state.evictions.load(SeqCst)
+    }
+
     pub(crate) fn on_cache_get_async(&self, path: impl AsRef<Path>) {
         self.state.hits.fetch_add(1, SeqCst);
         self.worker().on_cache_get_async(path)
@@ -395,6 +413,20 @@ impl CacheConfig {
         self.worker().on_cache_update_async(path)
     }
 
+    pub(crate) fn on_function_cache_get_async(&self, path: impl AsRef<Path>) {
+        self.state.function_hits.fetch_add(1, SeqCst);
+        self.worker().on_cache_get_async(path)
+    }
+
+    pub(crate) fn on_function_cache_update_async(&self, path: impl AsRef<Path>) {
+        self.state.function_misses.fetch_add(1, SeqCst);
+        self.worker().on_cache_update_async(path)
+    }
+
+    pub(super) fn on_cache_evicted(&self) {
+        self.state.evictions.fetch_add(1, SeqCst);
+    }
+
     fn load_and_parse_file(config_file: Option<&Path>) -> Result<Self> {
         // get config file path
         let (config_file, user_custom_file) = match config_file {
@@ -63,8 +63,16 @@ fn test_write_read_cache() {
     let compiler1 = "test-1";
     let compiler2 = "test-2";
 
-    let entry1 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(compiler1, &cache_config));
-    let entry2 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(compiler2, &cache_config));
+    let entry1 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(
+        "modules",
+        compiler1,
+        &cache_config,
+    ));
+    let entry2 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(
+        "modules",
+        compiler2,
+        &cache_config,
+    ));
 
     entry1.get_data(1, |_| new_module_cache_data()).unwrap();
     entry1.get_data::<_, i32>(1, |_| panic!()).unwrap();
@@ -92,6 +100,82 @@ fn test_write_read_cache() {
     entry2.get_data::<_, i32>(1, |_| panic!()).unwrap();
 }
 
+#[test]
+fn test_write_read_function_cache() {
+    let (_tempdir, cache_dir, config_path) = test_prolog();
+    let cache_config = load_config!(
+        config_path,
+        "[cache]\n\
+         enabled = true\n\
+         directory = {cache_dir}\n\
+         baseline-compression-level = 3\n",
+        cache_dir
+    );
+    assert!(cache_config.enabled());
+
+    let entry = FunctionCacheEntry::from_inner(ModuleCacheEntryInner::new(
+        "functions",
+        "test-1",
+        &cache_config,
+    ));
+
+    let data = entry
+        .get_data(1, 100, |_| new_function_cache_data::<()>(100))
+        .unwrap();
+    assert_eq!(data, new_function_cache_data::<()>(100).unwrap());
+    assert_eq!(cache_config.function_cache_hits(), 0);
+    assert_eq!(cache_config.function_cache_misses(), 1);
+
+    // The same function at a different offset in the module should be found
+    // in the cache, with its source locations moved along with it.
+    let data: Result<_, ()> = entry.get_data(1, 300, |_| panic!());
+    assert_eq!(data.unwrap(), new_function_cache_data::<()>(300).unwrap());
+    assert_eq!(cache_config.function_cache_hits(), 1);
+    assert_eq!(cache_config.function_cache_misses(), 1);
+
+    entry
+        .get_data(2, 100, |_| new_function_cache_data::<()>(100))
+        .unwrap();
+    let data: Result<_, ()> = entry.get_data(2, 100, |_| panic!());
+    data.unwrap();
+    assert_eq!(cache_config.function_cache_hits(), 2);
+    assert_eq!(cache_config.function_cache_misses(), 2);
+
+    // module-level statistics are kept separately
+    assert_eq!(cache_config.cache_hits(), 0);
+    assert_eq!(cache_config.cache_misses(), 0);
+}
+
+fn new_function_cache_data<E>(module_offset: u32) -> Result<FunctionCacheData, E> {
+    let srcloc = |offset| ir::SourceLoc::new(module_offset + offset);
+    Ok(FunctionCacheData {
+        function: CompiledFunction {
+            body: vec![0x90, 0xc3],
+            jt_offsets: Default::default(),
+            unwind_info: None,
+        },
+        relocations: Vec::new(),
+        address_transform: FunctionAddressMap {
+            instructions: vec![crate::address_map::InstructionAddressMap {
+                srcloc: srcloc(1),
+                code_offset: 0,
+                code_len: 2,
+            }],
+            start_srcloc: srcloc(0),
+            end_srcloc: srcloc(3),
+            body_offset: 0,
+            body_len: 2,
+        },
+        value_ranges: Default::default(),
+        stack_slots: ir::StackSlots::new(),
+        traps: vec![TrapInformation {
+            code_offset: 1,
+            source_loc: srcloc(2),
+            trap_code: ir::TrapCode::UnreachableCodeReached,
+        }],
+    })
+}
+
 fn new_module_cache_data() -> Result<ModuleCacheDataTupleType, ()> {
     Ok((
         Compilation::new(PrimaryMap::new()),
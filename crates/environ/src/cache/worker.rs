@@ -523,13 +523,18 @@ impl WorkerThread {
                         (fs::remove_dir_all(path), path, "directory")
                     }
                 };
-                if let Err(err) = result {
-                    warn!(
+                match result {
+                    Ok(()) => {
+                        if let CacheEntry::Recognized { .. } = item {
+                            self.cache_config.on_cache_evicted();
+                        }
+                    }
+                    Err(err) => warn!(
                         "Failed to remove {} during cleanup, path: {}, err: {}",
                         entity,
                         path.display(),
                         err
-                    );
+                    ),
                 }
             }
         }
@@ -0,0 +1,115 @@
+//! The basic blocks counted by coverage instrumentation.
+//!
+//! When `Tunables::coverage` is enabled every function is split into basic
+//! blocks while its module is translated, and each block gets a counter in
+//! the instance's counter region which compiled code increments whenever the
+//! block is entered. The same split is made again by `FuncEnvironment` when
+//! the function is compiled, so the counters of a function are laid out in
+//! the order of its blocks.
+
+use cranelift_entity::EntityRef;
+use cranelift_wasm::{DefinedFuncIndex, WasmError, WasmResult};
+use std::convert::TryFrom;
+use std::ops::Range;
+use wasmparser::Operator;
+
+/// The basic blocks of the functions of a module, and the counter of each.
+#[derive(Debug, Default, Clone, Hash)]
+pub struct CoverageMap {
+    /// The index of the first counter of each defined function.
+    first_counters: Vec<u32>,
+    /// The module offset of the first instruction of each counted block,
+    /// indexed by counter.
+    block_starts: Vec<u32>,
+    /// The range of module offsets of each defined function's body.
+    bodies: Vec<Range<u32>>,
+}
+
+impl CoverageMap {
+    /// Adds the blocks of the next defined function, whose body is `data` at
+    /// `module_offset` in the module.
+    pub fn add_function(&mut self, data: &[u8], module_offset: usize) -> WasmResult<()> {
+        let first_counter =
+            u32::try_from(self.block_starts.len()).map_err(|_| WasmError::ImplLimitExceeded)?;
+        let body = wasmparser::FunctionBody::new(module_offset, data);
+        let mut reader = body.get_operators_reader()?;
+        let mut block_start = true;
+        while !reader.eof() {
+            let (op, offset) = reader.read_with_offset()?;
+            if block_start {
+                self.block_starts.push(offset as u32);
+            }
+            block_start = ends_block(&op);
+        }
+        self.first_counters.push(first_counter);
+        self.bodies
+            .push(module_offset as u32..(module_offset + data.len()) as u32);
+        Ok(())
+    }
+
+    /// Returns the index of the counter of the first block of `func`.
+    pub fn first_counter(&self, func: DefinedFuncIndex) -> u32 {
+        self.first_counters[func.index()]
+    }
+
+    /// Returns the total number of counters of the module.
+    pub fn num_counters(&self) -> usize {
+        self.block_starts.len()
+    }
+
+    /// Returns the range of module offsets of the body of `func`.
+    pub fn body(&self, func: DefinedFuncIndex) -> Range<usize> {
+        let body = &self.bodies[func.index()];
+        body.start as usize..body.end as usize
+    }
+
+    /// Returns the counters of the blocks of `func` along with the range of
+    /// module offsets each block covers. The first block also covers the
+    /// declarations of the function's locals.
+    pub fn blocks(
+        &self,
+        func: DefinedFuncIndex,
+    ) -> impl Iterator<Item = (usize, Range<usize>)> + '_ {
+        let first = self.first_counters[func.index()] as usize;
+        let last = self
+            .first_counters
+            .get(func.index() + 1)
+            .map_or(self.block_starts.len(), |&c| c as usize);
+        let body = self.body(func);
+        (first..last).map(move |counter| {
+            let start = if counter == first {
+                body.start
+            } else {
+                self.block_starts[counter] as usize
+            };
+            let end = if counter + 1 < last {
+                self.block_starts[counter + 1] as usize
+            } else {
+                body.end
+            };
+            (counter, start..end)
+        })
+    }
+
+    /// Returns the number of defined functions of the module.
+    pub fn num_functions(&self) -> usize {
+        self.first_counters.len()
+    }
+}
+
+/// Returns whether the operator after `op` starts a new basic block, because
+/// `op` branches or is the target of a branch.
+pub(crate) fn ends_block(op: &Operator) -> bool {
+    match op {
+        Operator::If { .. }
+        | Operator::Else
+        | Operator::Loop { .. }
+        | Operator::End
+        | Operator::Br { .. }
+        | Operator::BrIf { .. }
+        | Operator::BrTable { .. }
+        | Operator::Return
+        | Operator::Unreachable => true,
+        _ => false,
+    }
+}
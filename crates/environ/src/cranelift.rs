@@ -86,15 +86,17 @@
 // assume no valid stack pointer will ever be `usize::max_value() - 32k`.
 
 use crate::address_map::{FunctionAddressMap, InstructionAddressMap};
-use crate::cache::{ModuleCacheDataTupleType, ModuleCacheEntry};
+use crate::cache::{
+    FunctionCacheData, FunctionCacheEntry, ModuleCacheDataTupleType, ModuleCacheEntry,
+};
 use crate::compilation::{
     Compilation, CompileError, CompiledFunction, Relocation, RelocationTarget, TrapInformation,
 };
 use crate::func_environ::{get_func_name, FuncEnvironment};
-use crate::{CacheConfig, FunctionBodyData, ModuleLocal, ModuleTranslation, Tunables};
+use crate::{CacheConfig, CoverageMap, FunctionBodyData, ModuleLocal, ModuleTranslation, Tunables};
 use cranelift_codegen::ir::{self, ExternalName};
 use cranelift_codegen::print_errors::pretty_error;
-use cranelift_codegen::{binemit, isa, Context};
+use cranelift_codegen::{binemit, isa, timing, Context};
 use cranelift_entity::PrimaryMap;
 use cranelift_wasm::{DefinedFuncIndex, FuncIndex, FuncTranslator, ModuleTranslationState};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
@@ -255,6 +257,7 @@ impl crate::compilation::Compiler for Cranelift {
         cache_config: &CacheConfig,
     ) -> Result<ModuleCacheDataTupleType, CompileError> {
         let cache_entry = ModuleCacheEntry::new("cranelift", cache_config);
+        let function_cache = FunctionCacheEntry::new("cranelift", cache_config);
 
         let data = cache_entry.get_data(
             CompileEnv {
@@ -265,6 +268,8 @@ impl crate::compilation::Compiler for Cranelift {
                 function_body_inputs: &translation.function_body_inputs,
                 isa: Isa(isa),
                 tunables: &translation.tunables,
+                coverage: translation.module.coverage.as_ref(),
+                function_cache: UnhashedFunctionCache(&function_cache),
             },
             compile,
         )?;
@@ -286,124 +291,41 @@ fn compile(env: CompileEnv<'_>) -> Result<ModuleCacheDataTupleType, CompileError
         .collect::<Vec<(DefinedFuncIndex, &FunctionBodyData<'_>)>>()
         .par_iter()
         .map_init(FuncTranslator::new, |func_translator, (i, input)| {
-            let func_index = env.local.func_index(*i);
-            let mut context = Context::new();
-            context.func.name = get_func_name(func_index);
-            context.func.signature = env.local.func_signature(func_index).clone();
-            if env.tunables.debug_info {
-                context.func.collect_debug_info();
-            }
-
-            let mut func_env = FuncEnvironment::new(isa.frontend_config(), env.local, env.tunables);
-
-            // We use these as constant offsets below in
-            // `stack_limit_from_arguments`, so assert their values here. This
-            // allows the closure below to get coerced to a function pointer, as
-            // needed by `ir::Function`.
-            //
-            // Otherwise our stack limit is specially calculated from the vmctx
-            // argument, where we need to load the `*const VMInterrupts`
-            // pointer, and then from that pointer we need to load the stack
-            // limit itself. Note that manual register allocation is needed here
-            // too due to how late in the process this codegen happens.
-            //
-            // For more information about interrupts and stack checks, see the
-            // top of this file.
-            let vmctx = context
-                .func
-                .create_global_value(ir::GlobalValueData::VMContext);
-            let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
-                base: vmctx,
-                offset: i32::try_from(func_env.offsets.vmctx_interrupts())
-                    .unwrap()
-                    .into(),
-                global_type: isa.pointer_type(),
-                readonly: true,
-            });
-            let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
-                base: interrupts_ptr,
-                offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
-                    .unwrap()
-                    .into(),
-                global_type: isa.pointer_type(),
-                readonly: false,
-            });
-            context.func.stack_limit = Some(stack_limit);
-            func_translator.translate(
-                env.module_translation.0,
-                input.data,
-                input.module_offset,
-                &mut context.func,
-                &mut func_env,
-            )?;
-
-            let mut code_buf: Vec<u8> = Vec::new();
-            let mut reloc_sink = RelocSink::new(func_index);
-            let mut trap_sink = TrapSink::new();
-            let mut stackmap_sink = binemit::NullStackmapSink {};
-            context
-                .compile_and_emit(
-                    isa,
-                    &mut code_buf,
-                    &mut reloc_sink,
-                    &mut trap_sink,
-                    &mut stackmap_sink,
-                )
-                .map_err(|error| {
-                    CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-                })?;
-
-            let unwind_info = context.create_unwind_info(isa).map_err(|error| {
-                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-            })?;
-
-            let address_transform = get_function_address_map(&context, input, code_buf.len(), isa);
-
-            let ranges = if env.tunables.debug_info {
-                let ranges = context.build_value_labels_ranges(isa).map_err(|error| {
-                    CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-                })?;
-                Some(ranges)
+            let key = FunctionCompileEnv {
+                local: env.local,
+                index: *i,
+                body: input.data,
+                isa: Isa(isa),
+                tunables: env.tunables,
+            };
+            // Debug instrumentation embeds the module offset of each
+            // instruction in the code itself, and coverage instrumentation
+            // the index of each block's counter within the module, so neither
+            // can be shared between modules containing the same function.
+            let data = if env.tunables.debug_instrumentation || env.tunables.coverage {
+                compile_function(func_translator, &env, *i, input)?
             } else {
-                None
+                env.function_cache
+                    .0
+                    .get_data(key, input.module_offset, |key| {
+                        compile_function(func_translator, &env, key.index, input)
+                    })?
             };
-
-            Ok((
-                code_buf,
-                context.func.jt_offsets,
-                reloc_sink.func_relocs,
-                address_transform,
-                ranges,
-                context.func.stack_slots,
-                trap_sink.traps,
-                unwind_info,
-            ))
+            // Pass timings are accumulated per thread, so hand them over to
+            // the thread driving the compilation.
+            Ok((data, timing::take_current()))
         })
         .collect::<Result<Vec<_>, CompileError>>()?
         .into_iter()
-        .for_each(
-            |(
-                function,
-                func_jt_offsets,
-                relocs,
-                address_transform,
-                ranges,
-                sss,
-                function_traps,
-                unwind_info,
-            )| {
-                functions.push(CompiledFunction {
-                    body: function,
-                    jt_offsets: func_jt_offsets,
-                    unwind_info,
-                });
-                relocations.push(relocs);
-                address_transforms.push(address_transform);
-                value_ranges.push(ranges.unwrap_or_default());
-                stack_slots.push(sss);
-                traps.push(function_traps);
-            },
-        );
+        .for_each(|(data, pass_times)| {
+            timing::add_to_current(&pass_times);
+            functions.push(data.function);
+            relocations.push(data.relocations);
+            address_transforms.push(data.address_transform);
+            value_ranges.push(data.value_ranges);
+            stack_slots.push(data.stack_slots);
+            traps.push(data.traps);
+        });
 
     // TODO: Reorganize where we create the Vec for the resolved imports.
 
@@ -417,6 +339,132 @@ fn compile(env: CompileEnv<'_>) -> Result<ModuleCacheDataTupleType, CompileError
     ))
 }
 
+/// Returns the number of wasm locals of a function, including its parameters.
+fn count_locals(
+    env: &CompileEnv<'_>,
+    func_index: FuncIndex,
+    input: &FunctionBodyData<'_>,
+) -> Result<u32, CompileError> {
+    // The first two parameters are the callee and caller vmctx.
+    let mut count = env.local.func_signature(func_index).params.len() as u32 - 2;
+    let body = wasmparser::FunctionBody::new(input.module_offset, input.data);
+    let mut reader = body
+        .get_locals_reader()
+        .map_err(|e| CompileError::Wasm(e.into()))?;
+    for _ in 0..reader.get_count() {
+        let (n, _) = reader.read().map_err(|e| CompileError::Wasm(e.into()))?;
+        count += n;
+    }
+    Ok(count)
+}
+
+fn compile_function(
+    func_translator: &mut FuncTranslator,
+    env: &CompileEnv<'_>,
+    i: DefinedFuncIndex,
+    input: &FunctionBodyData<'_>,
+) -> Result<FunctionCacheData, CompileError> {
+    let Isa(isa) = env.isa;
+    let func_index = env.local.func_index(i);
+    let mut context = Context::new();
+    context.func.name = get_func_name(func_index);
+    context.func.signature = env.local.func_signature(func_index).clone();
+    if env.tunables.debug_info {
+        context.func.collect_debug_info();
+    }
+
+    let mut func_env = FuncEnvironment::new(isa.frontend_config(), env.local, env.tunables);
+    if env.tunables.debug_instrumentation {
+        func_env.enable_debug_instrumentation(func_index, count_locals(env, func_index, input)?);
+    }
+    if let Some(coverage) = env.coverage {
+        func_env.enable_coverage(coverage.first_counter(i));
+    }
+
+    // We use these as constant offsets below in
+    // `stack_limit_from_arguments`, so assert their values here. This
+    // allows the closure below to get coerced to a function pointer, as
+    // needed by `ir::Function`.
+    //
+    // Otherwise our stack limit is specially calculated from the vmctx
+    // argument, where we need to load the `*const VMInterrupts`
+    // pointer, and then from that pointer we need to load the stack
+    // limit itself. Note that manual register allocation is needed here
+    // too due to how late in the process this codegen happens.
+    //
+    // For more information about interrupts and stack checks, see the
+    // top of this file.
+    let vmctx = context
+        .func
+        .create_global_value(ir::GlobalValueData::VMContext);
+    let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
+        base: vmctx,
+        offset: i32::try_from(func_env.offsets.vmctx_interrupts())
+            .unwrap()
+            .into(),
+        global_type: isa.pointer_type(),
+        readonly: true,
+    });
+    let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
+        base: interrupts_ptr,
+        offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
+            .unwrap()
+            .into(),
+        global_type: isa.pointer_type(),
+        readonly: false,
+    });
+    context.func.stack_limit = Some(stack_limit);
+    func_translator.translate(
+        env.module_translation.0,
+        input.data,
+        input.module_offset,
+        &mut context.func,
+        &mut func_env,
+    )?;
+
+    let mut code_buf: Vec<u8> = Vec::new();
+    let mut reloc_sink = RelocSink::new(func_index);
+    let mut trap_sink = TrapSink::new();
+    let mut stackmap_sink = binemit::NullStackmapSink {};
+    context
+        .compile_and_emit(
+            isa,
+            &mut code_buf,
+            &mut reloc_sink,
+            &mut trap_sink,
+            &mut stackmap_sink,
+        )
+        .map_err(|error| CompileError::Codegen(pretty_error(&context.func, Some(isa), error)))?;
+
+    let unwind_info = context
+        .create_unwind_info(isa)
+        .map_err(|error| CompileError::Codegen(pretty_error(&context.func, Some(isa), error)))?;
+
+    let address_transform = get_function_address_map(&context, input, code_buf.len(), isa);
+
+    let ranges = if env.tunables.debug_info {
+        let ranges = context.build_value_labels_ranges(isa).map_err(|error| {
+            CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
+        })?;
+        Some(ranges)
+    } else {
+        None
+    };
+
+    Ok(FunctionCacheData {
+        function: CompiledFunction {
+            body: code_buf,
+            jt_offsets: context.func.jt_offsets,
+            unwind_info,
+        },
+        relocations: reloc_sink.func_relocs,
+        address_transform,
+        value_ranges: ranges.unwrap_or_default(),
+        stack_slots: context.func.stack_slots,
+        traps: trap_sink.traps,
+    })
+}
+
 #[derive(Hash)]
 struct CompileEnv<'a> {
     local: &'a ModuleLocal,
@@ -424,6 +472,31 @@ struct CompileEnv<'a> {
     function_body_inputs: &'a PrimaryMap<DefinedFuncIndex, FunctionBodyData<'a>>,
     isa: Isa<'a, 'a>,
     tunables: &'a Tunables,
+    coverage: Option<&'a CoverageMap>,
+    function_cache: UnhashedFunctionCache<'a>,
+}
+
+/// Everything the compiled code of a single function depends on, used as the
+/// key of the function cache.
+///
+/// The module environment (vmctx layout, signatures of callees, imports) is
+/// covered by `local`. The offset of the body within the module isn't part of
+/// the key since the cache stores source locations relative to the function.
+#[derive(Hash)]
+struct FunctionCompileEnv<'a> {
+    local: &'a ModuleLocal,
+    index: DefinedFuncIndex,
+    body: &'a [u8],
+    isa: Isa<'a, 'a>,
+    tunables: &'a Tunables,
+}
+
+/// The function cache used while compiling a module. It doesn't influence the
+/// compiled code, so it's excluded from the module's hash.
+struct UnhashedFunctionCache<'a>(&'a FunctionCacheEntry<'a>);
+
+impl Hash for UnhashedFunctionCache<'_> {
+    fn hash<H: Hasher>(&self, _hasher: &mut H) {}
 }
 
 /// This is a wrapper struct to hash the specific bits of `TargetIsa` that
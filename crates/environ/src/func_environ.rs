@@ -1,5 +1,6 @@
+use crate::coverage;
 use crate::module::{MemoryPlan, MemoryStyle, ModuleLocal, TableStyle};
-use crate::vmoffsets::VMOffsets;
+use crate::vmoffsets::{DebugValueType, VMOffsets};
 use crate::{Tunables, INTERRUPTED, WASM_PAGE_SIZE};
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::ir;
@@ -9,13 +10,15 @@ use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::{AbiParam, ArgumentPurpose, Function, InstBuilder, Signature};
 use cranelift_codegen::isa::TargetFrontendConfig;
 use cranelift_entity::EntityRef;
+use cranelift_frontend::{FunctionBuilder, Variable};
 use cranelift_wasm::{
-    self, FuncIndex, GlobalIndex, GlobalVariable, MemoryIndex, SignatureIndex, TableIndex,
-    TargetEnvironment, WasmError, WasmResult,
+    self, FuncIndex, FuncTranslationState, GlobalIndex, GlobalVariable, MemoryIndex,
+    SignatureIndex, TableIndex, TargetEnvironment, WasmError, WasmResult,
 };
 #[cfg(feature = "lightbeam")]
 use cranelift_wasm::{DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex};
 use std::convert::TryFrom;
+use wasmparser::Operator;
 
 /// Compute an `ir::ExternalName` for a given wasm function index.
 pub fn get_func_name(func_index: FuncIndex) -> ir::ExternalName {
@@ -80,9 +83,13 @@ impl BuiltinFunctionIndex {
     pub const fn get_data_drop_index() -> Self {
         Self(12)
     }
+    /// Returns an index for the hook called by debug instrumentation.
+    pub const fn get_debug_hook_index() -> Self {
+        Self(13)
+    }
     /// Returns the total number of builtin functions.
     pub const fn builtin_functions_total_number() -> u32 {
-        13
+        14
     }
 
     /// Return the index as an u32 number.
@@ -134,12 +141,54 @@ pub struct FuncEnvironment<'module_environment> {
     /// The external function signature for implementing wasm's `data.drop`.
     data_drop_sig: Option<ir::SigRef>,
 
+    /// The external function signature of the hook called by debug
+    /// instrumentation.
+    debug_hook_sig: Option<ir::SigRef>,
+
+    /// State of the debug instrumentation of the function being translated,
+    /// if it's enabled.
+    debug: Option<DebugInstrumentation>,
+
+    /// State of the coverage instrumentation of the function being
+    /// translated, if it's enabled.
+    coverage: Option<CoverageInstrumentation>,
+
+    /// The fuel consumed by the instructions translated since the last time
+    /// it was taken from `VMInterrupts`, if fuel consumption is enabled.
+    fuel_pending: i64,
+
     /// Offsets to struct fields accessed by JIT code.
     pub(crate) offsets: VMOffsets,
 
     tunables: &'module_environment Tunables,
 }
 
+/// Debug instrumentation state of the function being translated.
+struct DebugInstrumentation {
+    func_index: FuncIndex,
+    num_locals: u32,
+    frame: Option<DebugFrame>,
+}
+
+/// Coverage instrumentation state of the function being translated.
+struct CoverageInstrumentation {
+    /// The counter of the next basic block.
+    next_counter: u32,
+    /// Whether the next operator starts a basic block.
+    block_start: bool,
+    /// The Cranelift global holding the address of the instance's counters.
+    counters: Option<ir::GlobalValue>,
+}
+
+/// The debug frame of the function being translated, allocated in its entry
+/// block.
+#[derive(Copy, Clone)]
+struct DebugFrame {
+    slot: ir::StackSlot,
+    addr: ir::Value,
+    interrupts: ir::Value,
+}
+
 impl<'module_environment> FuncEnvironment<'module_environment> {
     pub fn new(
         target_config: TargetFrontendConfig,
@@ -159,11 +208,42 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             memory_fill_sig: None,
             memory_init_sig: None,
             data_drop_sig: None,
+            debug_hook_sig: None,
+            debug: None,
+            coverage: None,
+            fuel_pending: 0,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             tunables,
         }
     }
 
+    /// Instruments the function being translated so that its state is
+    /// recorded in a `VMDebugFrame` and the debug hook is called before each
+    /// instruction while debugging is armed.
+    ///
+    /// `num_locals` is the number of wasm locals of the function, including
+    /// its parameters.
+    pub fn enable_debug_instrumentation(&mut self, func_index: FuncIndex, num_locals: u32) {
+        self.debug = Some(DebugInstrumentation {
+            func_index,
+            num_locals,
+            frame: None,
+        });
+    }
+
+    /// Instruments the function being translated so that it counts how
+    /// often each of its basic blocks is entered, using the instance's
+    /// counters starting at `first_counter`.
+    ///
+    /// The blocks are split the same way as in `CoverageMap`.
+    pub fn enable_coverage(&mut self, first_counter: u32) {
+        self.coverage = Some(CoverageInstrumentation {
+            next_counter: first_counter,
+            block_start: true,
+            counters: None,
+        });
+    }
+
     fn pointer_type(&self) -> ir::Type {
         self.target_config.pointer_type()
     }
@@ -485,6 +565,236 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         (sig, BuiltinFunctionIndex::get_data_drop_index())
     }
 
+    fn get_debug_hook_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.debug_hook_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Debug frame.
+                    AbiParam::new(self.pointer_type()),
+                ],
+                returns: vec![],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.debug_hook_sig = Some(sig);
+        sig
+    }
+
+    /// Increments the coverage counter with the given index.
+    fn increment_coverage_counter(&mut self, builder: &mut FunctionBuilder, counter: u32) {
+        let pointer_type = self.pointer_type();
+        let vmctx = self.vmctx(builder.func);
+        let coverage = self.coverage.as_mut().unwrap();
+        let offsets = &self.offsets;
+        let counters = *coverage.counters.get_or_insert_with(|| {
+            builder.func.create_global_value(ir::GlobalValueData::Load {
+                base: vmctx,
+                offset: i32::try_from(offsets.vmctx_coverage_counters())
+                    .unwrap()
+                    .into(),
+                global_type: pointer_type,
+                readonly: true,
+            })
+        });
+        let base = builder.ins().global_value(pointer_type, counters);
+        let addr = builder.ins().iadd_imm(base, i64::from(counter) * 8);
+        let count = builder.ins().load(I64, ir::MemFlags::trusted(), addr, 0);
+        let count = builder.ins().iadd_imm(count, 1);
+        builder.ins().store(ir::MemFlags::trusted(), count, addr, 0);
+    }
+
+    /// Emits code which takes the fuel consumed since the last call to this
+    /// from `VMInterrupts`, trapping with an interrupt if that leaves less
+    /// than zero.
+    fn consume_pending_fuel(&mut self, builder: &mut FunctionBuilder) {
+        let pointer_type = self.pointer_type();
+        let flags = ir::MemFlags::trusted();
+        let vmctx = self.vmctx(builder.func);
+        let base = builder.ins().global_value(pointer_type, vmctx);
+        let interrupts = builder.ins().load(
+            pointer_type,
+            flags,
+            base,
+            i32::try_from(self.offsets.vmctx_interrupts()).unwrap(),
+        );
+        let offset = i32::from(self.offsets.vminterrupts_fuel_remaining());
+        let fuel = builder.ins().load(I64, flags, interrupts, offset);
+        let fuel = builder.ins().iadd_imm(fuel, -self.fuel_pending);
+        builder.ins().store(flags, fuel, interrupts, offset);
+        let exhausted = builder.ins().icmp_imm(IntCC::SignedLessThan, fuel, 0);
+        builder.ins().trapnz(exhausted, ir::TrapCode::Interrupt);
+        self.fuel_pending = 0;
+    }
+
+    /// Returns the address of the debug frame of the function and of its
+    /// `VMInterrupts`, emitting the code which links the frame into the
+    /// `debug_frame` list the first time this is called.
+    fn debug_frame(&mut self, builder: &mut FunctionBuilder) -> DebugFrame {
+        let debug = self.debug.as_ref().unwrap();
+        if let Some(frame) = debug.frame {
+            return frame;
+        }
+        let func_index = debug.func_index;
+        let num_locals = debug.num_locals;
+        let pointer_type = self.pointer_type();
+        let flags = ir::MemFlags::trusted();
+
+        let slot = builder.func.create_stack_slot(ir::StackSlotData::new(
+            ir::StackSlotKind::ExplicitSlot,
+            u32::from(self.offsets.size_of_vmdebug_frame()),
+        ));
+        let addr = builder.ins().stack_addr(pointer_type, slot, 0);
+        let vmctx = self.vmctx(builder.func);
+        let vmctx = builder.ins().global_value(pointer_type, vmctx);
+        let mut readonly = flags;
+        readonly.set_readonly();
+        let interrupts = builder.ins().load(
+            pointer_type,
+            readonly,
+            vmctx,
+            i32::try_from(self.offsets.vmctx_interrupts()).unwrap(),
+        );
+
+        let debug_frame_offset = i32::from(self.offsets.vminterrupts_debug_frame());
+        let parent = builder
+            .ins()
+            .load(pointer_type, flags, interrupts, debug_frame_offset);
+        builder.ins().store(
+            flags,
+            parent,
+            addr,
+            i32::from(self.offsets.vmdebug_frame_parent()),
+        );
+        builder.ins().store(
+            flags,
+            vmctx,
+            addr,
+            i32::from(self.offsets.vmdebug_frame_vmctx()),
+        );
+        let func_index = builder.ins().iconst(I32, i64::from(func_index.as_u32()));
+        builder.ins().store(
+            flags,
+            func_index,
+            addr,
+            i32::from(self.offsets.vmdebug_frame_func_index()),
+        );
+        let num_locals = builder.ins().iconst(I32, i64::from(num_locals));
+        builder.ins().store(
+            flags,
+            num_locals,
+            addr,
+            i32::from(self.offsets.vmdebug_frame_num_locals()),
+        );
+        let invalid = builder.ins().iconst(I32, 0);
+        builder.ins().store(
+            flags,
+            invalid,
+            addr,
+            i32::from(self.offsets.vmdebug_frame_valid()),
+        );
+        builder
+            .ins()
+            .store(flags, addr, interrupts, debug_frame_offset);
+
+        let frame = DebugFrame {
+            slot,
+            addr,
+            interrupts,
+        };
+        self.debug.as_mut().unwrap().frame = Some(frame);
+        frame
+    }
+
+    /// Records the current offset, locals and operand stack of the function
+    /// in its debug frame.
+    fn spill_debug_frame(&mut self, builder: &mut FunctionBuilder, stack: &[ir::Value]) {
+        let debug = self.debug.as_ref().unwrap();
+        let frame = debug.frame.unwrap();
+        let num_locals = debug.num_locals;
+        let flags = ir::MemFlags::trusted();
+
+        let offset = builder
+            .ins()
+            .iconst(I32, i64::from(builder.srcloc().bits()));
+        builder.ins().store(
+            flags,
+            offset,
+            frame.addr,
+            i32::from(self.offsets.vmdebug_frame_offset()),
+        );
+        let stack_len = builder.ins().iconst(I32, stack.len() as i64);
+        builder.ins().store(
+            flags,
+            stack_len,
+            frame.addr,
+            i32::from(self.offsets.vmdebug_frame_stack_len()),
+        );
+        let valid = builder.ins().iconst(I32, 1);
+        builder.ins().store(
+            flags,
+            valid,
+            frame.addr,
+            i32::from(self.offsets.vmdebug_frame_valid()),
+        );
+
+        for i in 0..num_locals {
+            let value = builder.use_var(Variable::with_u32(i));
+            self.spill_debug_value(builder, frame.addr, i, value);
+        }
+        for (i, value) in stack.iter().enumerate() {
+            self.spill_debug_value(builder, frame.addr, num_locals + i as u32, *value);
+        }
+
+        let size = u32::from(self.offsets.size_of_vmdebug_frame())
+            + (num_locals + stack.len() as u32) * u32::from(self.offsets.size_of_vmdebug_value());
+        let slot = &mut builder.func.stack_slots[frame.slot];
+        slot.size = slot.size.max(size);
+    }
+
+    fn spill_debug_value(
+        &self,
+        builder: &mut FunctionBuilder,
+        frame: ir::Value,
+        index: u32,
+        value: ir::Value,
+    ) {
+        let flags = ir::MemFlags::trusted();
+        let ty = builder.func.dfg.value_type(value);
+        let debug_ty = match ty {
+            I32 => DebugValueType::I32,
+            I64 => DebugValueType::I64,
+            F32 => DebugValueType::F32,
+            F64 => DebugValueType::F64,
+            ty if ty.is_vector() && ty.bits() == 128 => DebugValueType::V128,
+            _ => DebugValueType::Unavailable,
+        };
+        let base = i32::try_from(
+            u32::from(self.offsets.size_of_vmdebug_frame())
+                + index * u32::from(self.offsets.size_of_vmdebug_value()),
+        )
+        .unwrap();
+        let tag = builder.ins().iconst(I32, debug_ty as i64);
+        builder.ins().store(
+            flags,
+            tag,
+            frame,
+            base + i32::from(self.offsets.vmdebug_value_ty()),
+        );
+        if debug_ty != DebugValueType::Unavailable {
+            // Debug frames are only 8-byte aligned, which isn't enough for
+            // vectors, so don't claim that the value is aligned.
+            let mut flags = ir::MemFlags::new();
+            flags.set_notrap();
+            builder.ins().store(
+                flags,
+                value,
+                frame,
+                base + i32::from(self.offsets.vmdebug_value_bits()),
+            );
+        }
+    }
+
     /// Translates load of builtin function and returns a pair of values `vmctx`
     /// and address of the loaded function.
     fn translate_load_builtin_function_address(
@@ -822,6 +1132,8 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 style: MemoryStyle::Dynamic,
                 offset_guard_size,
                 memory: _,
+                grow_failure_traps: _,
+                userfaultfd: _,
             } => {
                 let heap_bound = func.create_global_value(ir::GlobalValueData::Load {
                     base: ptr,
@@ -841,6 +1153,8 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 style: MemoryStyle::Static { bound },
                 offset_guard_size,
                 memory: _,
+                grow_failure_traps: _,
+                userfaultfd: _,
             } => (
                 Uimm64::new(offset_guard_size),
                 ir::HeapStyle::Static {
@@ -1283,4 +1597,107 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         pos.ins().trapnz(cmp, ir::TrapCode::Interrupt);
         Ok(())
     }
+
+    fn before_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if let Some(coverage) = &mut self.coverage {
+            if coverage.block_start {
+                // Counters are assigned to unreachable blocks too, so that
+                // they line up with the blocks of the `CoverageMap`.
+                let counter = coverage.next_counter;
+                coverage.next_counter += 1;
+                if state.reachable() {
+                    self.increment_coverage_counter(builder, counter);
+                }
+            }
+        }
+
+        if self.tunables.consume_fuel && state.reachable() {
+            // Fuel is only taken before control leaves the current basic
+            // block, which covers loops and function exits, and before calls,
+            // so that callees see how much fuel is left.
+            self.fuel_pending += 1;
+            match op {
+                Operator::Call { .. } | Operator::CallIndirect { .. } => {
+                    self.consume_pending_fuel(builder)
+                }
+                _ if coverage::ends_block(op) => self.consume_pending_fuel(builder),
+                _ => {}
+            }
+        }
+
+        if self.debug.is_none() || !state.reachable() {
+            return Ok(());
+        }
+
+        // Before each instruction check whether a debugger is armed, and if
+        // so record the state of the frame and let the runtime decide whether
+        // to pause here. Keeping the check inline keeps the cost of
+        // instrumentation low while nobody is debugging.
+        let frame = self.debug_frame(builder);
+        let pointer_type = self.pointer_type();
+        let armed = builder.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted(),
+            frame.interrupts,
+            i32::from(self.offsets.vminterrupts_debug_armed()),
+        );
+        let hook_block = builder.create_block();
+        let continue_block = builder.create_block();
+        builder.ins().brnz(armed, hook_block, &[]);
+        builder.ins().jump(continue_block, &[]);
+        builder.seal_block(hook_block);
+
+        builder.switch_to_block(hook_block);
+        self.spill_debug_frame(builder, state.stack());
+        let sig = self.get_debug_hook_sig(builder.func);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            &mut builder.cursor(),
+            BuiltinFunctionIndex::get_debug_hook_index(),
+        );
+        builder
+            .ins()
+            .call_indirect(sig, func_addr, &[vmctx, frame.addr]);
+        builder.ins().jump(continue_block, &[]);
+        builder.seal_block(continue_block);
+
+        builder.switch_to_block(continue_block);
+        Ok(())
+    }
+
+    fn after_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.block_start = coverage::ends_block(op);
+        }
+
+        let frame = match &self.debug {
+            Some(debug) => debug.frame,
+            None => return Ok(()),
+        };
+        match (op, frame) {
+            (Operator::Call { .. }, Some(frame)) | (Operator::CallIndirect { .. }, Some(frame))
+                if state.reachable() =>
+            {
+                // Callees link their own frames in front of ours, so make our
+                // frame the innermost one again once they return.
+                builder.ins().store(
+                    ir::MemFlags::trusted(),
+                    frame.addr,
+                    frame.interrupts,
+                    i32::from(self.offsets.vminterrupts_debug_frame()),
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 }
@@ -26,6 +26,7 @@
 
 mod address_map;
 mod compilation;
+mod coverage;
 mod data_structures;
 mod func_environ;
 mod module;
@@ -49,6 +50,7 @@ pub use crate::compilation::{
     Compilation, CompileError, CompiledFunction, Compiler, Relocation, RelocationTarget,
     Relocations, TrapInformation, Traps,
 };
+pub use crate::coverage::CoverageMap;
 pub use crate::cranelift::Cranelift;
 pub use crate::data_structures::*;
 pub use crate::func_environ::BuiltinFunctionIndex;
@@ -62,7 +64,7 @@ pub use crate::module_environ::{
     ModuleEnvironment, ModuleTranslation,
 };
 pub use crate::tunables::Tunables;
-pub use crate::vmoffsets::{TargetSharedSignatureIndex, VMOffsets, INTERRUPTED};
+pub use crate::vmoffsets::{DebugValueType, TargetSharedSignatureIndex, VMOffsets, INTERRUPTED};
 
 /// WebAssembly page sizes are defined to be 64KiB.
 pub const WASM_PAGE_SIZE: u32 = 0x10000;
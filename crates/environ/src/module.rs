@@ -1,5 +1,6 @@
 //! Data structures for representing decoded wasm modules.
 
+use crate::coverage::CoverageMap;
 use crate::tunables::Tunables;
 use crate::WASM_MAX_PAGES;
 use cranelift_codegen::ir;
@@ -88,6 +89,16 @@ pub struct MemoryPlan {
     pub style: MemoryStyle,
     /// Our chosen offset-guard size.
     pub offset_guard_size: u64,
+    /// Whether a `memory.grow` which stays within the memory's limits, but
+    /// fails because the host can't provide the memory, traps instead of
+    /// returning -1. This way the result of `memory.grow` never depends on
+    /// the host.
+    pub grow_failure_traps: bool,
+    /// Whether the memory's pages are populated lazily, on first access, by
+    /// a userfaultfd handler instead of eagerly at instantiation. This is
+    /// only done for static memories, since dynamic memories are copied
+    /// when they move, and only where userfaultfd is available.
+    pub userfaultfd: bool,
 }
 
 impl MemoryPlan {
@@ -98,6 +109,12 @@ impl MemoryPlan {
             memory,
             style,
             offset_guard_size,
+            grow_failure_traps: tunables.deterministic,
+            userfaultfd: tunables.userfaultfd
+                && match style {
+                    MemoryStyle::Static { .. } => true,
+                    MemoryStyle::Dynamic => false,
+                },
         }
     }
 }
@@ -168,8 +185,15 @@ pub struct Module {
     /// WebAssembly passive data segments.
     pub passive_data: HashMap<DataIndex, Arc<[u8]>>,
 
+    /// The data of the active data segments, in the order of the module's
+    /// `DataInitializer`s, which instances can share.
+    pub active_data: Vec<Arc<[u8]>>,
+
     /// WebAssembly table initializers.
     pub func_names: HashMap<FuncIndex, String>,
+
+    /// The basic blocks counted by coverage instrumentation, if it's enabled.
+    pub coverage: Option<CoverageMap>,
 }
 
 /// Local information known about a wasm module, the bare minimum necessary to
@@ -222,7 +246,9 @@ impl Module {
             table_elements: Vec::new(),
             passive_elements: HashMap::new(),
             passive_data: HashMap::new(),
+            active_data: Vec::new(),
             func_names: HashMap::new(),
+            coverage: None,
             local: ModuleLocal {
                 num_imported_funcs: 0,
                 num_imported_tables: 0,
@@ -1,3 +1,4 @@
+use crate::coverage::CoverageMap;
 use crate::module::{EntityIndex, MemoryPlan, Module, TableElements, TablePlan};
 use crate::tunables::Tunables;
 use cranelift_codegen::ir;
@@ -54,10 +55,14 @@ pub struct ModuleEnvironment<'data> {
 impl<'data> ModuleEnvironment<'data> {
     /// Allocates the environment data structures.
     pub fn new(target_config: TargetFrontendConfig, tunables: &Tunables) -> Self {
+        let mut module = Module::new();
+        if tunables.coverage {
+            module.coverage = Some(CoverageMap::default());
+        }
         Self {
             result: ModuleTranslation {
                 target_config,
-                module: Module::new(),
+                module,
                 function_body_inputs: PrimaryMap::new(),
                 data_initializers: Vec::new(),
                 tunables: tunables.clone(),
@@ -347,6 +352,9 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()> {
+        if let Some(coverage) = &mut self.result.module.coverage {
+            coverage.add_function(body_bytes, body_offset)?;
+        }
         self.result.function_body_inputs.push(FunctionBodyData {
             data: body_bytes,
             module_offset: body_offset,
@@ -376,6 +384,7 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
             },
             data,
         });
+        self.result.module.active_data.push(Arc::from(data));
         Ok(())
     }
 
@@ -20,6 +20,37 @@ pub struct Tunables {
     /// calls and interrupts are implemented through the `VMInterrupts`
     /// structure, or `InterruptHandle` in the `wasmtime` crate.
     pub interruptable: bool,
+
+    /// Whether or not to instrument compiled code so that it can be paused
+    /// and inspected at wasm instruction boundaries.
+    ///
+    /// See `VMDebugFrame` in the runtime crate for how the state of each
+    /// instrumented frame is made available to the host.
+    pub debug_instrumentation: bool,
+
+    /// Whether or not to count how often each basic block of wasm code is
+    /// entered, for code coverage.
+    ///
+    /// See `CoverageMap` for how the blocks are assigned counters.
+    pub coverage: bool,
+
+    /// Whether or not compiled code consumes fuel as it executes, and traps
+    /// with an interrupt once it runs out.
+    ///
+    /// Each wasm instruction consumes one unit of fuel, which is taken from
+    /// the `fuel_remaining` field of `VMInterrupts` at the end of each basic
+    /// block and before calls.
+    pub consume_fuel: bool,
+
+    /// Whether or not compiled code must behave the same on every host.
+    ///
+    /// This makes `memory.grow` trap instead of failing when the host can't
+    /// provide the memory, see `MemoryPlan::grow_failure_traps`.
+    pub deterministic: bool,
+
+    /// Whether or not the pages of static linear memories are populated on
+    /// first access by a userfaultfd handler, see `MemoryPlan::userfaultfd`.
+    pub userfaultfd: bool,
 }
 
 impl Default for Tunables {
@@ -53,6 +84,11 @@ impl Default for Tunables {
 
             debug_info: false,
             interruptable: false,
+            debug_instrumentation: false,
+            coverage: false,
+            consume_fuel: false,
+            deterministic: false,
+            userfaultfd: false,
         }
     }
 }
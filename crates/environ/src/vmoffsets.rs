@@ -252,6 +252,87 @@ impl VMOffsets {
     pub fn vminterrupts_stack_limit(&self) -> u8 {
         0
     }
+
+    /// Return the offset of the `debug_armed` field of `VMInterrupts`
+    #[allow(clippy::identity_op)]
+    pub fn vminterrupts_debug_armed(&self) -> u8 {
+        1 * self.pointer_size
+    }
+
+    /// Return the offset of the `debug_frame` field of `VMInterrupts`
+    pub fn vminterrupts_debug_frame(&self) -> u8 {
+        2 * self.pointer_size
+    }
+
+    /// Return the offset of the `fuel_remaining` field of `VMInterrupts`,
+    /// which is aligned to 8 bytes.
+    pub fn vminterrupts_fuel_remaining(&self) -> u8 {
+        (3 * self.pointer_size + 7) / 8 * 8
+    }
+}
+
+/// Offsets for `VMDebugFrame`.
+impl VMOffsets {
+    /// The offset of the `parent` field.
+    #[allow(clippy::erasing_op)]
+    pub fn vmdebug_frame_parent(&self) -> u8 {
+        0 * self.pointer_size
+    }
+
+    /// The offset of the `vmctx` field.
+    #[allow(clippy::identity_op)]
+    pub fn vmdebug_frame_vmctx(&self) -> u8 {
+        1 * self.pointer_size
+    }
+
+    /// The offset of the `func_index` field.
+    pub fn vmdebug_frame_func_index(&self) -> u8 {
+        2 * self.pointer_size
+    }
+
+    /// The offset of the `offset` field.
+    pub fn vmdebug_frame_offset(&self) -> u8 {
+        self.vmdebug_frame_func_index() + 4
+    }
+
+    /// The offset of the `num_locals` field.
+    pub fn vmdebug_frame_num_locals(&self) -> u8 {
+        self.vmdebug_frame_offset() + 4
+    }
+
+    /// The offset of the `stack_len` field.
+    pub fn vmdebug_frame_stack_len(&self) -> u8 {
+        self.vmdebug_frame_num_locals() + 4
+    }
+
+    /// The offset of the `valid` field.
+    pub fn vmdebug_frame_valid(&self) -> u8 {
+        self.vmdebug_frame_stack_len() + 4
+    }
+
+    /// Return the size of the `VMDebugFrame` header, which is followed by the
+    /// locals and then the operand stack of the frame as `VMDebugValue`s.
+    pub fn size_of_vmdebug_frame(&self) -> u8 {
+        (self.vmdebug_frame_valid() + 4 + 15) & !15
+    }
+}
+
+/// Offsets for `VMDebugValue`.
+impl VMOffsets {
+    /// The offset of the `ty` field.
+    pub fn vmdebug_value_ty(&self) -> u8 {
+        0
+    }
+
+    /// The offset of the `bits` field.
+    pub fn vmdebug_value_bits(&self) -> u8 {
+        16
+    }
+
+    /// Return the size of `VMDebugValue`.
+    pub fn size_of_vmdebug_value(&self) -> u8 {
+        32
+    }
 }
 
 /// Offsets for `VMCallerCheckedAnyfunc`.
@@ -286,9 +367,16 @@ impl VMOffsets {
         0
     }
 
+    /// Return the offset to the pointer to the instance's coverage counters.
+    pub fn vmctx_coverage_counters(&self) -> u32 {
+        self.vmctx_interrupts()
+            .checked_add(u32::from(self.pointer_size))
+            .unwrap()
+    }
+
     /// The offset of the `signature_ids` array.
     pub fn vmctx_signature_ids_begin(&self) -> u32 {
-        self.vmctx_interrupts()
+        self.vmctx_coverage_counters()
             .checked_add(u32::from(self.pointer_size))
             .unwrap()
     }
@@ -599,6 +687,39 @@ impl TargetSharedSignatureIndex {
     }
 }
 
+/// The type of a value stored in a `VMDebugValue`, recorded in its `ty`
+/// field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DebugValueType {
+    /// The value couldn't be recorded, for example because it's a reference.
+    Unavailable = 0,
+    /// A 32-bit integer.
+    I32 = 1,
+    /// A 64-bit integer.
+    I64 = 2,
+    /// A 32-bit float.
+    F32 = 3,
+    /// A 64-bit float.
+    F64 = 4,
+    /// A 128-bit vector.
+    V128 = 5,
+}
+
+impl DebugValueType {
+    /// Converts the raw `ty` field of a `VMDebugValue`.
+    pub fn from_u32(ty: u32) -> Self {
+        match ty {
+            1 => DebugValueType::I32,
+            2 => DebugValueType::I64,
+            3 => DebugValueType::F32,
+            4 => DebugValueType::F64,
+            5 => DebugValueType::V128,
+            _ => DebugValueType::Unavailable,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::vmoffsets::align;
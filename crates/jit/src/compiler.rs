@@ -6,14 +6,15 @@ use cranelift_codegen::ir::ExternalName;
 use cranelift_codegen::ir::InstBuilder;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::Context;
-use cranelift_codegen::{binemit, ir};
+use cranelift_codegen::{binemit, ir, timing};
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use wasmtime_debug::{emit_debugsections_image, DebugInfoData};
 use wasmtime_environ::entity::{EntityRef, PrimaryMap};
 use wasmtime_environ::isa::{TargetFrontendConfig, TargetIsa};
-use wasmtime_environ::wasm::{DefinedFuncIndex, DefinedMemoryIndex, MemoryIndex};
+use wasmtime_environ::wasm::{DefinedFuncIndex, DefinedMemoryIndex, MemoryIndex, SignatureIndex};
 use wasmtime_environ::{
     CacheConfig, CompileError, CompiledFunction, Compiler as _C, ModuleAddressMap,
     ModuleMemoryOffset, ModuleTranslation, ModuleVmctxInfo, Relocation, RelocationTarget,
@@ -82,10 +83,29 @@ pub struct Compilation {
     pub relocations: Relocations,
     pub trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
     pub trampoline_relocations: HashMap<VMSharedSignatureIndex, Vec<Relocation>>,
+    pub trampoline_bodies: Vec<(SignatureIndex, *mut [VMFunctionBody])>,
     pub jt_offsets: PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
     pub dbg_image: Option<Vec<u8>>,
     pub traps: Traps,
     pub address_transform: ModuleAddressMap,
+    pub stats: CompilationStats,
+}
+
+/// Statistics gathered while compiling a module.
+#[derive(Debug, Clone, Default)]
+pub struct CompilationStats {
+    /// Cranelift passes that ran, as the pass description, the total time
+    /// spent in the pass and the time spent in the pass itself (excluding
+    /// nested passes).
+    ///
+    /// This is empty if the module was loaded from the cache.
+    pub pass_times: Vec<(&'static str, Duration, Duration)>,
+
+    /// Size in bytes of the machine code of each defined function.
+    pub function_sizes: PrimaryMap<DefinedFuncIndex, usize>,
+
+    /// Number of trampolines generated for the module's signatures.
+    pub trampolines: usize,
 }
 
 impl Compiler {
@@ -110,27 +130,33 @@ impl Compiler {
         translation: &ModuleTranslation,
         debug_data: Option<DebugInfoData>,
     ) -> Result<Compilation, SetupError> {
-        let (compilation, relocations, address_transform, value_ranges, stack_slots, traps) =
-            match self.strategy {
-                // For now, interpret `Auto` as `Cranelift` since that's the most stable
-                // implementation.
-                CompilationStrategy::Auto | CompilationStrategy::Cranelift => {
-                    wasmtime_environ::cranelift::Cranelift::compile_module(
-                        translation,
-                        &*self.isa,
-                        &self.cache_config,
-                    )
-                }
-                #[cfg(feature = "lightbeam")]
-                CompilationStrategy::Lightbeam => {
-                    wasmtime_environ::lightbeam::Lightbeam::compile_module(
-                        translation,
-                        &*self.isa,
-                        &self.cache_config,
-                    )
-                }
+        // Cranelift accumulates pass timings per thread, so set aside whatever
+        // was recorded on this thread before to only report this module's.
+        let previous_pass_times = timing::take_current();
+        let result = match self.strategy {
+            // For now, interpret `Auto` as `Cranelift` since that's the most stable
+            // implementation.
+            CompilationStrategy::Auto | CompilationStrategy::Cranelift => {
+                wasmtime_environ::cranelift::Cranelift::compile_module(
+                    translation,
+                    &*self.isa,
+                    &self.cache_config,
+                )
             }
-            .map_err(SetupError::Compile)?;
+            #[cfg(feature = "lightbeam")]
+            CompilationStrategy::Lightbeam => {
+                wasmtime_environ::lightbeam::Lightbeam::compile_module(
+                    translation,
+                    &*self.isa,
+                    &self.cache_config,
+                )
+            }
+        };
+        let pass_times = timing::take_current();
+        timing::add_to_current(&previous_pass_times);
+        timing::add_to_current(&pass_times);
+        let (compilation, relocations, address_transform, value_ranges, stack_slots, traps) =
+            result.map_err(SetupError::Compile)?;
 
         // Allocate all of the compiled functions into executable memory,
         // copying over their contents.
@@ -149,19 +175,21 @@ impl Compiler {
         let mut cx = FunctionBuilderContext::new();
         let mut trampolines = HashMap::new();
         let mut trampoline_relocations = HashMap::new();
-        for sig in translation.module.local.signatures.values() {
+        let mut trampoline_bodies = Vec::new();
+        for (sig_index, sig) in translation.module.local.signatures.iter() {
             let index = self.signatures.register(sig);
             if trampolines.contains_key(&index) {
                 continue;
             }
-            let (trampoline, relocations) = make_trampoline(
+            let (body, relocations) = compile_trampoline(
                 &*self.isa,
                 &mut self.code_memory,
                 &mut cx,
                 sig,
                 std::mem::size_of::<u128>(),
             )?;
-            trampolines.insert(index, trampoline);
+            trampolines.insert(index, body_to_trampoline(body));
+            trampoline_bodies.push((sig_index, body));
 
             // Typically trampolines do not have relocations, so if one does
             // show up be sure to log it in case anyone's listening and there's
@@ -214,15 +242,23 @@ impl Compiler {
 
         let jt_offsets = compilation.get_jt_offsets();
 
+        let stats = CompilationStats {
+            pass_times: pass_times.passes().collect(),
+            function_sizes: compilation.into_iter().map(|f| f.body.len()).collect(),
+            trampolines: trampolines.len(),
+        };
+
         Ok(Compilation {
             finished_functions,
             relocations,
             trampolines,
             trampoline_relocations,
+            trampoline_bodies,
             jt_offsets,
             dbg_image,
             traps,
             address_transform,
+            stats,
         })
     }
 
@@ -245,6 +281,24 @@ pub fn make_trampoline(
     signature: &ir::Signature,
     value_size: usize,
 ) -> Result<(VMTrampoline, Vec<Relocation>), SetupError> {
+    let (body, relocations) =
+        compile_trampoline(isa, code_memory, fn_builder_ctx, signature, value_size)?;
+    Ok((body_to_trampoline(body), relocations))
+}
+
+fn body_to_trampoline(body: *mut [VMFunctionBody]) -> VMTrampoline {
+    unsafe { std::mem::transmute::<*const VMFunctionBody, VMTrampoline>(body as *const _) }
+}
+
+/// Like `make_trampoline`, but returns the whole body of the trampoline
+/// rather than just its entry point.
+fn compile_trampoline(
+    isa: &dyn TargetIsa,
+    code_memory: &mut CodeMemory,
+    fn_builder_ctx: &mut FunctionBuilderContext,
+    signature: &ir::Signature,
+    value_size: usize,
+) -> Result<(*mut [VMFunctionBody], Vec<Relocation>), SetupError> {
     let pointer_type = isa.pointer_type();
     let mut wrapper_sig = ir::Signature::new(isa.frontend_config().default_call_conv);
 
@@ -351,18 +405,14 @@ pub fn make_trampoline(
         )))
     })?;
 
-    let ptr = code_memory
+    let body = code_memory
         .allocate_for_function(&CompiledFunction {
             body: code_buf,
             jt_offsets: context.func.jt_offsets,
             unwind_info,
         })
-        .map_err(|message| SetupError::Instantiate(InstantiationError::Resource(message)))?
-        .as_ptr();
-    Ok((
-        unsafe { std::mem::transmute::<*const VMFunctionBody, VMTrampoline>(ptr) },
-        reloc_sink.relocs,
-    ))
+        .map_err(|message| SetupError::Instantiate(InstantiationError::Resource(message)))?;
+    Ok((body as *mut [VMFunctionBody], reloc_sink.relocs))
 }
 
 fn allocate_functions(
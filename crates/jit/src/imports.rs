@@ -242,6 +242,8 @@ fn is_memory_compatible(exported: &MemoryPlan, imported: &MemoryPlan) -> bool {
             },
         style: _exported_style,
         offset_guard_size: _exported_offset_guard_size,
+        grow_failure_traps: _exported_grow_failure_traps,
+        userfaultfd: _exported_userfaultfd,
     } = exported;
     let MemoryPlan {
         memory:
@@ -252,6 +254,8 @@ fn is_memory_compatible(exported: &MemoryPlan, imported: &MemoryPlan) -> bool {
             },
         style: _imported_style,
         offset_guard_size: _imported_offset_guard_size,
+        grow_failure_traps: _imported_grow_failure_traps,
+        userfaultfd: _imported_userfaultfd,
     } = imported;
 
     imported_minimum <= exported_minimum
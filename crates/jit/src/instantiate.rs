@@ -3,7 +3,7 @@
 //! `CompiledModule` to allow compiling and instantiating to be done as separate
 //! steps.
 
-use crate::compiler::Compiler;
+use crate::compiler::{CompilationStats, Compiler};
 use crate::imports::resolve_imports;
 use crate::link::link_module;
 use crate::resolver::Resolver;
@@ -13,7 +13,7 @@ use std::io::Write;
 use std::rc::Rc;
 use std::sync::Arc;
 use thiserror::Error;
-use wasmtime_debug::read_debuginfo;
+use wasmtime_debug::{read_debuginfo, read_source_lines, SourceLine};
 use wasmtime_environ::entity::{BoxedSlice, PrimaryMap};
 use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
 use wasmtime_environ::{
@@ -23,8 +23,8 @@ use wasmtime_environ::{
 use wasmtime_profiling::ProfilingAgent;
 use wasmtime_runtime::VMInterrupts;
 use wasmtime_runtime::{
-    GdbJitImageRegistration, InstanceHandle, InstantiationError, RuntimeMemoryCreator,
-    SignatureRegistry, VMFunctionBody, VMSharedSignatureIndex, VMTrampoline,
+    DebugHandler, GdbJitImageRegistration, InstanceHandle, InstantiationError,
+    RuntimeMemoryCreator, SignatureRegistry, VMFunctionBody, VMSharedSignatureIndex, VMTrampoline,
 };
 
 /// An error condition while setting up a wasm instance, be it validation,
@@ -60,6 +60,8 @@ struct RawCompiledModule<'data> {
     dbg_jit_registration: Option<GdbJitImageRegistration>,
     traps: Traps,
     address_transform: ModuleAddressMap,
+    stats: CompilationStats,
+    source_lines: Box<[SourceLine]>,
 }
 
 impl<'data> RawCompiledModule<'data> {
@@ -81,6 +83,13 @@ impl<'data> RawCompiledModule<'data> {
             debug_data = Some(read_debuginfo(&data)?);
         }
 
+        // Coverage counters are reported in terms of source lines, which
+        // can't be recovered once the module's custom sections are gone.
+        let mut source_lines = Vec::new();
+        if compiler.tunables().coverage {
+            source_lines = read_source_lines(&data)?;
+        }
+
         let compilation = compiler.compile(&translation, debug_data)?;
 
         link_module(&translation.module, &compilation);
@@ -106,6 +115,9 @@ impl<'data> RawCompiledModule<'data> {
             &compilation.finished_functions,
             compilation.dbg_image.as_deref(),
         );
+        for (index, body) in compilation.trampoline_bodies.iter() {
+            profiler.trampoline_load(&translation.module, *index, *body);
+        }
 
         let dbg_jit_registration = if let Some(img) = compilation.dbg_image {
             let mut bytes = Vec::new();
@@ -125,6 +137,8 @@ impl<'data> RawCompiledModule<'data> {
             dbg_jit_registration,
             traps: compilation.traps,
             address_transform: compilation.address_transform,
+            stats: compilation.stats,
+            source_lines: source_lines.into_boxed_slice(),
         })
     }
 }
@@ -140,6 +154,8 @@ pub struct CompiledModule {
     traps: Traps,
     address_transform: ModuleAddressMap,
     interrupts: Arc<VMInterrupts>,
+    stats: CompilationStats,
+    source_lines: Box<[SourceLine]>,
 }
 
 impl CompiledModule {
@@ -151,21 +167,27 @@ impl CompiledModule {
     ) -> Result<Self, SetupError> {
         let raw = RawCompiledModule::<'data>::new(compiler, data, profiler)?;
 
-        Ok(Self::from_parts(
+        let data_initializers = raw
+            .data_initializers
+            .iter()
+            .zip(&raw.module.active_data)
+            .map(|(init, data)| OwnedDataInitializer::new(init, data))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        let mut module = Self::from_parts(
             raw.module,
             raw.finished_functions,
             raw.trampolines,
-            raw.data_initializers
-                .iter()
-                .map(OwnedDataInitializer::new)
-                .collect::<Vec<_>>()
-                .into_boxed_slice(),
+            data_initializers,
             raw.signatures.clone(),
             raw.dbg_jit_registration,
             raw.traps,
             raw.address_transform,
             compiler.interrupts().clone(),
-        ))
+        );
+        module.stats = raw.stats;
+        module.source_lines = raw.source_lines;
+        Ok(module)
     }
 
     /// Construct a `CompiledModule` from component parts.
@@ -190,9 +212,23 @@ impl CompiledModule {
             traps,
             address_transform,
             interrupts,
+            stats: CompilationStats::default(),
+            source_lines: Box::new([]),
         }
     }
 
+    /// Returns the active data segments which initialize the linear memories
+    /// of an instance of this module.
+    pub fn data_initializers(&self) -> Vec<DataInitializer<'_>> {
+        self.data_initializers
+            .iter()
+            .map(|init| DataInitializer {
+                location: init.location.clone(),
+                data: &*init.data,
+            })
+            .collect()
+    }
+
     /// Crate an `Instance` from this `CompiledModule`.
     ///
     /// Note that if only one instance of this module is needed, it may be more
@@ -210,15 +246,9 @@ impl CompiledModule {
         mem_creator: Option<&dyn RuntimeMemoryCreator>,
         max_wasm_stack: usize,
         host_state: Box<dyn Any>,
+        debug_handler: Option<Rc<DebugHandler>>,
     ) -> Result<InstanceHandle, InstantiationError> {
-        let data_initializers = self
-            .data_initializers
-            .iter()
-            .map(|init| DataInitializer {
-                location: init.location.clone(),
-                data: &*init.data,
-            })
-            .collect::<Vec<_>>();
+        let data_initializers = self.data_initializers();
         let imports = resolve_imports(&self.module, &sig_registry, resolver)?;
         InstanceHandle::new(
             Arc::clone(&self.module),
@@ -231,6 +261,7 @@ impl CompiledModule {
             self.dbg_jit_registration.as_ref().map(|r| Rc::clone(&r)),
             is_bulk_memory,
             host_state,
+            debug_handler,
             self.interrupts.clone(),
             max_wasm_stack,
         )
@@ -265,23 +296,37 @@ impl CompiledModule {
     pub fn address_transform(&self) -> &ModuleAddressMap {
         &self.address_transform
     }
+
+    /// Returns statistics about the compilation of this module.
+    pub fn compilation_stats(&self) -> &CompilationStats {
+        &self.stats
+    }
+
+    /// Returns the rows of the module's DWARF line table, ordered by offset.
+    ///
+    /// These are only read if the module was compiled with coverage
+    /// instrumentation.
+    pub fn source_lines(&self) -> &[SourceLine] {
+        &self.source_lines
+    }
 }
 
-/// Similar to `DataInitializer`, but owns its own copy of the data rather
-/// than holding a slice of the original module.
+/// Similar to `DataInitializer`, but shares the data of the compiled module
+/// rather than holding a slice of the original module.
 pub struct OwnedDataInitializer {
     /// The location where the initialization is to be performed.
     location: DataInitializerLocation,
 
     /// The initialization data.
-    data: Box<[u8]>,
+    data: Arc<[u8]>,
 }
 
 impl OwnedDataInitializer {
-    fn new(borrowed: &DataInitializer<'_>) -> Self {
+    fn new(borrowed: &DataInitializer<'_>, data: &Arc<[u8]>) -> Self {
+        debug_assert_eq!(borrowed.data, &**data);
         Self {
             location: borrowed.location.clone(),
-            data: borrowed.data.to_vec().into_boxed_slice(),
+            data: Arc::clone(data),
         }
     }
 }
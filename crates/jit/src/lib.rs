@@ -33,10 +33,13 @@ pub mod native;
 pub mod trampoline;
 
 pub use crate::code_memory::CodeMemory;
-pub use crate::compiler::{make_trampoline, Compilation, CompilationStrategy, Compiler};
+pub use crate::compiler::{
+    make_trampoline, Compilation, CompilationStats, CompilationStrategy, Compiler,
+};
 pub use crate::instantiate::{CompiledModule, SetupError};
 pub use crate::link::link_module;
 pub use crate::resolver::{NullResolver, Resolver};
+pub use wasmtime_debug::SourceLine;
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -1,7 +1,8 @@
 use std::error::Error;
 use std::fmt;
+use std::time::Duration;
 use wasmtime_environ::entity::{EntityRef, PrimaryMap};
-use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
 use wasmtime_environ::Module;
 use wasmtime_runtime::VMFunctionBody;
 
@@ -15,6 +16,16 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        #[path = "perfmap_linux.rs"]
+        mod perfmap;
+    } else {
+        #[path = "perfmap_disabled.rs"]
+        mod perfmap;
+    }
+}
+
 cfg_if::cfg_if! {
     if #[cfg(all(feature = "vtune", target_os = "linux"))] {
         #[path = "vtune_linux.rs"]
@@ -25,9 +36,46 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
+        #[path = "sampling_linux.rs"]
+        mod sampling;
+    } else {
+        #[path = "sampling_disabled.rs"]
+        mod sampling;
+    }
+}
+
 pub use crate::jitdump::JitDumpAgent;
+pub use crate::perfmap::PerfMapAgent;
+pub use crate::sampling::SamplingAgent;
 pub use crate::vtune::VTuneAgent;
 
+/// A stack captured by the [`SamplingAgent`].
+#[derive(Debug, Clone)]
+pub struct RawSample {
+    /// OS thread id of the sampled thread.
+    pub thread: u32,
+    /// Time at which the sample was taken, relative to the creation of the
+    /// agent.
+    pub time: Duration,
+    /// Program counters of the stack, innermost frame first. All but the
+    /// first one are return addresses.
+    ///
+    /// Host frames without a frame pointer end the walk, and addresses which
+    /// don't belong to wasm code may be included.
+    pub frames: Vec<usize>,
+}
+
+/// Samples taken from a [`SamplingAgent`].
+#[derive(Debug, Clone, Default)]
+pub struct RawSamples {
+    /// The recorded samples, in no particular order.
+    pub samples: Vec<RawSample>,
+    /// Number of samples which didn't fit in the agent's buffer.
+    pub dropped: usize,
+}
+
 /// Common interface for profiling tools.
 pub trait ProfilingAgent: Send + Sync + 'static {
     /// Notify the profiler of a new module loaded into memory
@@ -37,6 +85,16 @@ pub trait ProfilingAgent: Send + Sync + 'static {
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
         dbg_image: Option<&[u8]>,
     ) -> ();
+
+    /// Notify the profiler of a trampoline generated for calling functions of
+    /// `module` with the signature `index` from the host.
+    fn trampoline_load(
+        &self,
+        _module: &Module,
+        _index: SignatureIndex,
+        _body: *const [VMFunctionBody],
+    ) {
+    }
 }
 
 /// Default agent for unsupported profiling build.
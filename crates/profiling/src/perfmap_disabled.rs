@@ -0,0 +1,29 @@
+use crate::ProfilingAgent;
+use anyhow::{bail, Result};
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::Module;
+use wasmtime_runtime::VMFunctionBody;
+
+/// Interface for driving the creation of `perf` map files.
+#[derive(Debug)]
+pub struct PerfMapAgent {
+    _private: (),
+}
+
+impl PerfMapAgent {
+    /// Open `/tmp/perf-<pid>.map` for appending, creating it if needed.
+    pub fn new() -> Result<Self> {
+        bail!("perf map files are only supported on Linux");
+    }
+}
+
+impl ProfilingAgent for PerfMapAgent {
+    fn module_load(
+        &self,
+        _module: &Module,
+        _functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _dbg_image: Option<&[u8]>,
+    ) {
+    }
+}
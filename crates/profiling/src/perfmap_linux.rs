@@ -0,0 +1,87 @@
+//! Support for `perf` map files, a much simpler alternative to jitdump.
+//!
+//! `perf` looks for `/tmp/perf-<pid>.map` when it finds samples in anonymous
+//! executable memory of process `<pid>`. Each line of the file describes one
+//! function as `START SIZE NAME`, with the address and size in hex.
+//!
+//! Usage Example:
+//!     Record
+//!         perf record target/debug/wasmtime --perfmap test.wasm
+//!     Report
+//!         perf report
+
+use crate::{debug_name, ProfilingAgent};
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::process;
+use std::sync::Mutex;
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
+use wasmtime_environ::Module;
+use wasmtime_runtime::VMFunctionBody;
+
+/// Interface for driving the creation of `perf` map files.
+#[derive(Debug)]
+pub struct PerfMapAgent {
+    file: Mutex<File>,
+}
+
+impl PerfMapAgent {
+    /// Open `/tmp/perf-<pid>.map` for appending, creating it if needed.
+    pub fn new() -> Result<Self> {
+        let path = format!("/tmp/perf-{}.map", process::id());
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open `{}`", path))?;
+        Ok(PerfMapAgent {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends `lines` with a single write, so that modules loaded from
+    /// several threads don't interleave their entries.
+    fn write(&self, lines: &str) {
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(lines.as_bytes()) {
+            log::warn!("Error writing perf map file: {:?}", e);
+        }
+    }
+}
+
+fn entry(body: *const [VMFunctionBody], name: &str) -> String {
+    let (addr, len) = unsafe { ((*body).as_ptr() as usize, (*body).len()) };
+    // Names can't contain newlines, and perf only reads up to the end of the
+    // line anyway.
+    format!("{:x} {:x} {}\n", addr, len, name.replace('\n', " "))
+}
+
+impl ProfilingAgent for PerfMapAgent {
+    fn module_load(
+        &self,
+        module: &Module,
+        functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _dbg_image: Option<&[u8]>,
+    ) {
+        let mut lines = String::new();
+        for (index, body) in functions.iter() {
+            lines.push_str(&entry(*body, &debug_name(module, index)));
+        }
+        self.write(&lines);
+    }
+
+    fn trampoline_load(
+        &self,
+        module: &Module,
+        index: SignatureIndex,
+        body: *const [VMFunctionBody],
+    ) {
+        let name = match &module.name {
+            Some(name) => format!("wasm::{}::trampoline[{}]", name, index.as_u32()),
+            None => format!("wasm::trampoline[{}]", index.as_u32()),
+        };
+        self.write(&entry(body, &name));
+    }
+}
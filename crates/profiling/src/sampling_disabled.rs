@@ -0,0 +1,41 @@
+use crate::{ProfilingAgent, RawSamples};
+use anyhow::{bail, Result};
+use std::time::Duration;
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::Module;
+use wasmtime_runtime::VMFunctionBody;
+
+/// Interface for the built-in sampling profiler.
+#[derive(Debug)]
+pub struct SamplingAgent {
+    _private: (),
+}
+
+impl SamplingAgent {
+    /// Installs the `SIGPROF` handler and starts sampling every `interval` of
+    /// consumed CPU time.
+    pub fn new(_interval: Duration) -> Result<Self> {
+        bail!("the sampling profiler is only supported on x86_64 Linux");
+    }
+
+    /// Returns the sampling interval this agent was created with.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(0)
+    }
+
+    /// Returns the samples recorded since the last call to this method.
+    pub fn take_samples(&self) -> RawSamples {
+        RawSamples::default()
+    }
+}
+
+impl ProfilingAgent for SamplingAgent {
+    fn module_load(
+        &self,
+        _module: &Module,
+        _functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _dbg_image: Option<&[u8]>,
+    ) {
+    }
+}
@@ -0,0 +1,309 @@
+//! A sampling profiler for wasm code which doesn't rely on any external tools.
+//!
+//! A `SIGPROF` interval timer interrupts whichever thread is consuming CPU
+//! time. If that thread is executing wasm, the signal handler walks the frame
+//! pointer chain from the interrupted context and appends the raw return
+//! addresses to a preallocated buffer. Symbolizing the addresses is left to
+//! the embedder, which knows where each module's code lives.
+//!
+//! Everything the signal handler touches is lock-free and allocation-free:
+//! the buffer is reserved with a single atomic increment, and samples which
+//! don't fit are counted and dropped. Two buffers are allocated up front and
+//! swapped on every `SamplingAgent::take_samples`, so sampling carries on into
+//! one while the other is read.
+
+use crate::{ProfilingAgent, RawSample, RawSamples};
+use anyhow::{bail, Result};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering::SeqCst};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::Module;
+use wasmtime_runtime::VMFunctionBody;
+
+/// Size, in words, of the buffer that samples are recorded into between two
+/// calls to `SamplingAgent::take_samples`.
+const BUFFER_WORDS: usize = 1 << 21;
+
+/// The deepest stack recorded for a single sample.
+const MAX_FRAMES: usize = 128;
+
+/// Each sample starts with the number of frames, the thread id and the
+/// timestamp, followed by the return addresses.
+const HEADER_WORDS: usize = 3;
+
+/// Set while at most one `SamplingAgent` exists, since the timer and the
+/// signal handler are process-wide.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the signal handler should record samples into `BUFFER`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Number of signal handlers currently running, used to wait for them to
+/// finish before a buffer is read or freed.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+static BUFFER: AtomicPtr<Buffer> = AtomicPtr::new(ptr::null_mut());
+
+struct Buffer {
+    words: Box<[AtomicUsize]>,
+    cursor: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl Buffer {
+    fn new() -> Buffer {
+        Buffer {
+            words: (0..BUFFER_WORDS).map(|_| AtomicUsize::new(0)).collect(),
+            cursor: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Clears the samples recorded so far, so the buffer can be sampled into
+    /// again.
+    fn reset(&mut self) {
+        let used = (*self.cursor.get_mut()).min(self.words.len());
+        for word in self.words[..used].iter_mut() {
+            *word.get_mut() = 0;
+        }
+        *self.cursor.get_mut() = 0;
+        *self.dropped.get_mut() = 0;
+    }
+}
+
+/// Interface for the built-in sampling profiler.
+#[derive(Debug)]
+pub struct SamplingAgent {
+    interval: Duration,
+    start: u64,
+    /// The buffer which isn't being sampled into. Holding its lock also
+    /// serializes calls to `take_samples`.
+    spare: Mutex<Option<Box<Buffer>>>,
+}
+
+impl SamplingAgent {
+    /// Installs the `SIGPROF` handler and starts sampling every `interval` of
+    /// consumed CPU time.
+    ///
+    /// Only one sampling agent may exist in a process at a time.
+    pub fn new(interval: Duration) -> Result<Self> {
+        if INSTALLED
+            .compare_exchange(false, true, SeqCst, SeqCst)
+            .is_err()
+        {
+            bail!("the sampling profiler is already running in this process");
+        }
+        let start_time = now();
+        match unsafe { start(interval) } {
+            Ok(()) => Ok(SamplingAgent {
+                interval,
+                start: start_time,
+                spare: Mutex::new(Some(Box::new(Buffer::new()))),
+            }),
+            Err(e) => {
+                INSTALLED.store(false, SeqCst);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the sampling interval this agent was created with.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the samples recorded since the last call to this method, and
+    /// keeps sampling into the spare buffer.
+    pub fn take_samples(&self) -> RawSamples {
+        let mut spare = self.spare.lock().unwrap();
+        let fresh = Box::into_raw(spare.take().unwrap());
+        pause();
+        let old = BUFFER.swap(fresh, SeqCst);
+        ACTIVE.store(true, SeqCst);
+        // No handler can still be writing to `old` after the pause, so it
+        // becomes the spare once its samples are read.
+        let old = spare.get_or_insert(unsafe { Box::from_raw(old) });
+
+        let mut samples = Vec::new();
+        let mut pos = 0;
+        while pos + HEADER_WORDS <= old.words.len() {
+            let len = old.words[pos].load(SeqCst);
+            if len == 0 || pos + HEADER_WORDS + len > old.words.len() {
+                break;
+            }
+            let word = |i: usize| old.words[pos + i].load(SeqCst);
+            samples.push(RawSample {
+                thread: word(1) as u32,
+                time: Duration::from_nanos((word(2) as u64).saturating_sub(self.start)),
+                frames: (0..len).map(|i| word(HEADER_WORDS + i)).collect(),
+            });
+            pos += HEADER_WORDS + len;
+        }
+        let dropped = old.dropped.load(SeqCst);
+        old.reset();
+        RawSamples { samples, dropped }
+    }
+}
+
+impl ProfilingAgent for SamplingAgent {
+    fn module_load(
+        &self,
+        _module: &Module,
+        _functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _dbg_image: Option<&[u8]>,
+    ) {
+        // Nothing to do here: samples are symbolized by whoever takes them,
+        // while the modules they point into are still alive.
+    }
+}
+
+impl Drop for SamplingAgent {
+    fn drop(&mut self) {
+        let _ = unsafe { set_timer(Duration::from_secs(0)) };
+        pause();
+        let buffer = BUFFER.swap(ptr::null_mut(), SeqCst);
+        ACTIVE.store(false, SeqCst);
+        drop(unsafe { Box::from_raw(buffer) });
+        INSTALLED.store(false, SeqCst);
+    }
+}
+
+/// Stops the signal handler from recording anything and waits for handlers
+/// which are already running to finish.
+fn pause() {
+    ACTIVE.store(false, SeqCst);
+    while IN_FLIGHT.load(SeqCst) != 0 {
+        thread::yield_now();
+    }
+}
+
+unsafe fn start(interval: Duration) -> Result<()> {
+    if interval == Duration::from_secs(0) {
+        bail!("the sampling interval must not be zero");
+    }
+
+    // The handler is left installed once the agent is dropped: a `SIGPROF`
+    // may still be pending on some thread after the timer is disarmed, and
+    // restoring the default disposition would terminate the process. Without
+    // an agent the handler returns immediately.
+    // `INSTALLED` guarantees that we're the only ones in here.
+    static HANDLER_INSTALLED: AtomicBool = AtomicBool::new(false);
+    if !HANDLER_INSTALLED.load(SeqCst) {
+        let mut handler: libc::sigaction = mem::zeroed();
+        // SA_RESTART keeps the signal from interrupting system calls made by
+        // host functions, which generally don't expect `EINTR`.
+        handler.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_ONSTACK;
+        handler.sa_sigaction = sample_handler as usize;
+        libc::sigemptyset(&mut handler.sa_mask);
+        if libc::sigaction(libc::SIGPROF, &handler, ptr::null_mut()) != 0 {
+            bail!(
+                "unable to install the SIGPROF handler: {}",
+                io::Error::last_os_error()
+            );
+        }
+        HANDLER_INSTALLED.store(true, SeqCst);
+    }
+
+    BUFFER.store(Box::into_raw(Box::new(Buffer::new())), SeqCst);
+    ACTIVE.store(true, SeqCst);
+    if let Err(e) = set_timer(interval) {
+        pause();
+        drop(Box::from_raw(BUFFER.swap(ptr::null_mut(), SeqCst)));
+        bail!("unable to start the profiling timer: {}", e);
+    }
+    Ok(())
+}
+
+unsafe fn set_timer(interval: Duration) -> io::Result<()> {
+    let interval = libc::timeval {
+        tv_sec: interval.as_secs() as libc::time_t,
+        tv_usec: interval.subsec_micros() as libc::suseconds_t,
+    };
+    let timer = libc::itimerval {
+        it_interval: interval,
+        it_value: interval,
+    };
+    if libc::setitimer(libc::ITIMER_PROF, &timer, ptr::null_mut()) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+unsafe extern "C" fn sample_handler(
+    _signum: libc::c_int,
+    _siginfo: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let errno = *libc::__errno_location();
+    IN_FLIGHT.fetch_add(1, SeqCst);
+    if ACTIVE.load(SeqCst) {
+        record_sample(&*(context as *const libc::ucontext_t));
+    }
+    IN_FLIGHT.fetch_sub(1, SeqCst);
+    *libc::__errno_location() = errno;
+}
+
+unsafe fn record_sample(cx: &libc::ucontext_t) {
+    let base = match wasmtime_runtime::wasm_stack_base() {
+        Some(base) => base,
+        None => return,
+    };
+    let gregs = &cx.uc_mcontext.gregs;
+    let pc = gregs[libc::REG_RIP as usize] as usize;
+    let sp = gregs[libc::REG_RSP as usize] as usize;
+    let mut fp = gregs[libc::REG_RBP as usize] as usize;
+
+    // If we interrupted another signal handler running on the alternate stack
+    // then the frame pointers below don't belong to the wasm stack at all.
+    let altstack = cx.uc_stack.ss_sp as usize;
+    if sp >= altstack && sp < altstack + cx.uc_stack.ss_size {
+        return;
+    }
+    if sp >= base {
+        return;
+    }
+
+    // Cranelift establishes a frame pointer in every function it compiles, so
+    // the caller's frame pointer is saved at `fp` and the return address right
+    // above it. Only follow frame pointers which stay within the live part of
+    // this thread's stack and move towards its base: host code which doesn't
+    // maintain frame pointers then ends the walk instead of faulting.
+    let mut frames = [0usize; MAX_FRAMES];
+    frames[0] = pc;
+    let mut len = 1;
+    let mut lower = sp;
+    while len < MAX_FRAMES && fp >= lower && fp % mem::align_of::<usize>() == 0 && fp + 16 <= base {
+        frames[len] = *((fp + 8) as *const usize);
+        len += 1;
+        lower = fp + 16;
+        fp = *(fp as *const usize);
+    }
+
+    let buffer = &*BUFFER.load(SeqCst);
+    let start = buffer.cursor.fetch_add(HEADER_WORDS + len, SeqCst);
+    if start + HEADER_WORDS + len > buffer.words.len() {
+        buffer.dropped.fetch_add(1, SeqCst);
+        return;
+    }
+    let words = &buffer.words[start..start + HEADER_WORDS + len];
+    words[1].store(libc::syscall(libc::SYS_gettid) as usize, SeqCst);
+    words[2].store(now() as usize, SeqCst);
+    for (word, frame) in words[HEADER_WORDS..].iter().zip(&frames[..len]) {
+        word.store(*frame, SeqCst);
+    }
+    words[0].store(len, SeqCst);
+}
@@ -10,9 +10,9 @@ use crate::table::Table;
 use crate::traphandlers;
 use crate::traphandlers::{catch_traps, Trap};
 use crate::vmcontext::{
-    VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMFunctionBody, VMFunctionImport,
-    VMGlobalDefinition, VMGlobalImport, VMInterrupts, VMMemoryDefinition, VMMemoryImport,
-    VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline,
+    VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMDebugFrame, VMFunctionBody,
+    VMFunctionImport, VMGlobalDefinition, VMGlobalImport, VMInterrupts, VMMemoryDefinition,
+    VMMemoryImport, VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline,
 };
 use crate::{ExportFunction, ExportGlobal, ExportMemory, ExportTable};
 use memoffset::offset_of;
@@ -23,6 +23,7 @@ use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
 use std::{mem, ptr, slice};
 use thiserror::Error;
@@ -31,7 +32,10 @@ use wasmtime_environ::wasm::{
     DataIndex, DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex,
     ElemIndex, FuncIndex, GlobalIndex, GlobalInit, MemoryIndex, SignatureIndex, TableIndex,
 };
-use wasmtime_environ::{ir, DataInitializer, EntityIndex, Module, TableElements, VMOffsets};
+use wasmtime_environ::{
+    ir, DataInitializer, EntityIndex, Module, TableElements, VMOffsets, WASM_MAX_PAGES,
+    WASM_PAGE_SIZE,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
@@ -61,6 +65,15 @@ cfg_if::cfg_if! {
     }
 }
 
+/// A callback run by debug-instrumented code before each instruction while
+/// the `debug_armed` flag of `VMInterrupts` is set. It receives the innermost
+/// frame, and returning an error raises it as a trap.
+pub type DebugHandler =
+    dyn Fn(&VMDebugFrame) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// The id of the next instance to be created.
+static NEXT_INSTANCE_ID: AtomicUsize = AtomicUsize::new(0);
+
 /// A WebAssembly instance.
 ///
 /// This is repr(C) to ensure that the vmctx field is last.
@@ -69,6 +82,10 @@ pub(crate) struct Instance {
     /// The number of references to this `Instance`.
     refcount: Cell<usize>,
 
+    /// A number identifying this `Instance`, which is never reused by another
+    /// one in the process.
+    id: usize,
+
     /// `Instance`s from which this `Instance` imports. These won't
     /// create reference cycles because wasm instances can't cyclically
     /// import from each other.
@@ -110,10 +127,18 @@ pub(crate) struct Instance {
     /// Handler run when `SIGBUS`, `SIGFPE`, `SIGILL`, or `SIGSEGV` are caught by the instance thread.
     pub(crate) signal_handler: Cell<Option<Box<SignalHandler>>>,
 
+    /// Handler run by debug-instrumented code before each instruction while
+    /// debugging is armed.
+    pub(crate) debug_handler: Option<Rc<DebugHandler>>,
+
     /// Externally allocated data indicating how this instance will be
     /// interrupted.
     pub(crate) interrupts: Arc<VMInterrupts>,
 
+    /// How often each basic block was entered, incremented by compiled code
+    /// if coverage instrumentation is enabled.
+    coverage_counters: Box<[Cell<u64>]>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -256,7 +281,6 @@ impl Instance {
     }
 
     /// Set the indexed global to `VMGlobalDefinition`.
-    #[allow(dead_code)]
     fn set_global(&self, index: DefinedGlobalIndex, global: VMGlobalDefinition) {
         unsafe {
             *self.global_ptr(index) = global;
@@ -284,6 +308,11 @@ impl Instance {
         unsafe { self.vmctx_plus_offset(self.offsets.vmctx_interrupts()) }
     }
 
+    /// Return a pointer to the pointer to the coverage counters.
+    fn coverage_counters_ptr(&self) -> *mut *const Cell<u64> {
+        unsafe { self.vmctx_plus_offset(self.offsets.vmctx_coverage_counters()) }
+    }
+
     /// Return a reference to the vmctx used by compiled wasm code.
     pub fn vmctx(&self) -> &VMContext {
         &self.vmctx
@@ -513,6 +542,44 @@ impl Instance {
         foreign_instance.memory_grow(foreign_index, delta)
     }
 
+    /// Returns whether compiled code should trap after failing to grow memory
+    /// by `delta` pages, instead of getting -1 as the result of `memory.grow`.
+    ///
+    /// This is the case if the memory's plan asks for it and growing it
+    /// wouldn't have exceeded its limits, so it failed because of the host.
+    pub(crate) fn memory_grow_failure_traps(
+        &self,
+        memory_index: DefinedMemoryIndex,
+        delta: u32,
+    ) -> bool {
+        let plan = &self.module.local.memory_plans[self.module.local.memory_index(memory_index)];
+        let new_pages = u64::from(self.memory_size(memory_index)) + u64::from(delta);
+        plan.grow_failure_traps
+            && new_pages <= u64::from(WASM_MAX_PAGES)
+            && plan
+                .memory
+                .maximum
+                .map_or(true, |maximum| new_pages <= u64::from(maximum))
+    }
+
+    /// Returns whether compiled code should trap after failing to grow an
+    /// imported memory, see `memory_grow_failure_traps`.
+    ///
+    /// # Safety
+    /// This is unsafe for the same reasons as `imported_memory_grow`.
+    pub(crate) unsafe fn imported_memory_grow_failure_traps(
+        &self,
+        memory_index: MemoryIndex,
+        delta: u32,
+    ) -> bool {
+        let import = self.imported_memory(memory_index);
+        let foreign_instance = (&*import.vmctx).instance();
+        let foreign_memory = &*import.from;
+        let foreign_index = foreign_instance.memory_index(foreign_memory);
+
+        foreign_instance.memory_grow_failure_traps(foreign_index, delta)
+    }
+
     /// Returns the number of allocated wasm pages.
     pub(crate) fn memory_size(&self, memory_index: DefinedMemoryIndex) -> u32 {
         self.memories
@@ -883,6 +950,7 @@ impl InstanceHandle {
         dbg_jit_registration: Option<Rc<GdbJitImageRegistration>>,
         is_bulk_memory: bool,
         host_state: Box<dyn Any>,
+        debug_handler: Option<Rc<DebugHandler>>,
         interrupts: Arc<VMInterrupts>,
         max_wasm_stack: usize,
     ) -> Result<Self, InstantiationError> {
@@ -907,9 +975,13 @@ impl InstanceHandle {
 
         let passive_data = RefCell::new(module.passive_data.clone());
 
+        let num_counters = module.coverage.as_ref().map_or(0, |c| c.num_counters());
+        let coverage_counters = vec![Cell::new(0); num_counters].into_boxed_slice();
+
         let handle = {
             let instance = Instance {
                 refcount: Cell::new(1),
+                id: NEXT_INSTANCE_ID.fetch_add(1, SeqCst),
                 dependencies: imports.dependencies,
                 module,
                 offsets,
@@ -922,7 +994,9 @@ impl InstanceHandle {
                 dbg_jit_registration,
                 host_state,
                 signal_handler: Cell::new(None),
+                debug_handler,
                 interrupts,
+                coverage_counters,
                 vmctx: VMContext {},
             };
             let layout = instance.alloc_layout();
@@ -982,6 +1056,7 @@ impl InstanceHandle {
             VMBuiltinFunctionsArray::initialized(),
         );
         *instance.interrupts() = &*instance.interrupts;
+        *instance.coverage_counters_ptr() = instance.coverage_counters.as_ptr();
 
         // Check initializer bounds before initializing anything. Only do this
         // when bulk memory is disabled, since the bulk memory proposal changes
@@ -1033,6 +1108,12 @@ impl InstanceHandle {
         self.instance().vmctx_ptr()
     }
 
+    /// Return a number identifying the instance, which unlike the address of
+    /// its vmctx is never reused by another instance once it's freed.
+    pub fn id(&self) -> usize {
+        self.instance().id
+    }
+
     /// Return a reference-counting pointer to a module.
     pub fn module(&self) -> &Arc<Module> {
         self.instance().module()
@@ -1121,6 +1202,118 @@ impl InstanceHandle {
         self.instance().get_defined_table(index)
     }
 
+    /// Returns the number of bytes currently allocated to the linear memories
+    /// defined by this instance.
+    pub fn defined_memories_size(&self) -> usize {
+        self.instance()
+            .memories
+            .values()
+            .map(|memory| memory.size() as usize * WASM_PAGE_SIZE as usize)
+            .sum()
+    }
+
+    /// Returns the number of bytes of the linear memories defined by this
+    /// instance which are backed by physical pages.
+    ///
+    /// Memories which don't keep track of this, because their pages aren't
+    /// populated lazily, are counted as fully resident.
+    pub fn defined_memories_resident_size(&self) -> usize {
+        self.instance()
+            .memories
+            .values()
+            .map(|memory| {
+                memory
+                    .resident_size()
+                    .unwrap_or(memory.size() as usize * WASM_PAGE_SIZE as usize)
+            })
+            .sum()
+    }
+
+    /// Resets the defined memory `index` to its contents right after
+    /// instantiation, if its pages are populated lazily, by discarding them
+    /// all at once.
+    ///
+    /// Returns `false` if the memory isn't populated lazily, in which case
+    /// it's left unchanged.
+    pub fn reset_defined_memory(&self, index: DefinedMemoryIndex) -> bool {
+        self.instance().memories[index].reset_lazily()
+    }
+
+    /// Shrinks the defined memory `index` back to `pages` wasm pages, see
+    /// `RuntimeLinearMemory::shrink`.
+    ///
+    /// Returns `false` if the memory can't be shrunk, in which case it's left
+    /// unchanged.
+    pub fn shrink_defined_memory(&self, index: DefinedMemoryIndex, pages: u32) -> bool {
+        let instance = self.instance();
+        if !instance.memories[index].shrink(pages) {
+            return false;
+        }
+        // Keep current the VMContext pointers used by compiled wasm code.
+        instance.set_memory(index, instance.memories[index].vmmemory());
+        true
+    }
+
+    /// Shrinks the defined table `index` back to `size` elements, see
+    /// `Table::shrink`.
+    ///
+    /// Returns `false` if the table is already smaller than that, in which
+    /// case it's left unchanged.
+    pub fn shrink_defined_table(&self, index: DefinedTableIndex, size: u32) -> bool {
+        let instance = self.instance();
+        if !instance.tables[index].shrink(size) {
+            return false;
+        }
+        // Keep current the VMContext pointers used by compiled wasm code.
+        instance.set_table(index, instance.tables[index].vmtable());
+        true
+    }
+
+    /// Returns the number of bytes currently allocated to the elements of the
+    /// tables defined by this instance.
+    pub fn defined_tables_size(&self) -> usize {
+        self.instance()
+            .tables
+            .values()
+            .map(|table| table.size() as usize * mem::size_of::<VMCallerCheckedAnyfunc>())
+            .sum()
+    }
+
+    /// Returns the definition of the linear memory `index` defined by this
+    /// instance, which points at its current contents.
+    pub fn defined_memory(&self, index: DefinedMemoryIndex) -> VMMemoryDefinition {
+        self.instance().memory(index)
+    }
+
+    /// Returns the value of the global `index` defined by this instance.
+    pub fn defined_global(&self, index: DefinedGlobalIndex) -> VMGlobalDefinition {
+        self.instance().global(index)
+    }
+
+    /// Sets the value of the global `index` defined by this instance.
+    pub fn set_defined_global(&self, index: DefinedGlobalIndex, global: VMGlobalDefinition) {
+        self.instance().set_global(index, global)
+    }
+
+    /// Returns the offset within its memory at which the data of `init` was
+    /// copied when this instance was created.
+    pub fn memory_init_start(&self, init: &DataInitializer<'_>) -> usize {
+        get_memory_init_start(init, self.instance())
+    }
+
+    /// Returns how often each basic block of the module was entered, indexed
+    /// by the counters of the module's `CoverageMap`.
+    ///
+    /// This is empty unless the module was compiled with coverage
+    /// instrumentation.
+    pub fn coverage_counters(&self) -> Vec<u64> {
+        self.instance()
+            .coverage_counters
+            .iter()
+            .map(Cell::get)
+            .collect()
+    }
+
     /// Gets the trampoline pre-registered for a particular signature
     pub fn trampoline(&self, sig: VMSharedSignatureIndex) -> Option<VMTrampoline> {
         self.instance().trampolines.get(&sig).cloned()
@@ -1339,7 +1532,19 @@ fn initialize_memories(
     instance: &Instance,
     data_initializers: &[DataInitializer<'_>],
 ) -> Result<(), InstantiationError> {
+    let lazy = initialize_memories_lazily(instance, data_initializers);
+
     for init in data_initializers {
+        if let Some(index) = instance
+            .module
+            .local
+            .defined_memory_index(init.location.memory_index)
+        {
+            if lazy.contains(&index) {
+                continue;
+            }
+        }
+
         let memory = instance.get_memory(init.location.memory_index);
 
         let start = get_memory_init_start(init, instance);
@@ -1363,6 +1568,51 @@ fn initialize_memories(
     Ok(())
 }
 
+/// Hands the data segments of the defined memories which can populate their
+/// pages lazily over to them, returning the memories which took them.
+///
+/// This is only done when every segment has a constant offset and is in
+/// bounds, since then initialization can't fail partway through, and the
+/// order in which segments are applied doesn't depend on other memories.
+fn initialize_memories_lazily(
+    instance: &Instance,
+    data_initializers: &[DataInitializer<'_>],
+) -> HashSet<DefinedMemoryIndex> {
+    let mut lazy = HashSet::new();
+    let all_constant_and_in_bounds = data_initializers.iter().all(|init| {
+        init.location.base.is_none()
+            && init
+                .location
+                .offset
+                .checked_add(init.data.len())
+                .map_or(false, |end| {
+                    end <= instance
+                        .get_memory(init.location.memory_index)
+                        .current_length
+                })
+    });
+    // The images share the module's copy of the segments' data, which only
+    // modules which keep one have.
+    let active_data = &instance.module.active_data;
+    if !all_constant_and_in_bounds || active_data.len() != data_initializers.len() {
+        return lazy;
+    }
+
+    for (index, memory) in instance.memories.iter() {
+        let memory_index = instance.module.local.memory_index(index);
+        let image = data_initializers
+            .iter()
+            .zip(active_data)
+            .filter(|(init, _)| init.location.memory_index == memory_index)
+            .map(|(init, data)| (init.location.offset, Arc::clone(data)))
+            .collect();
+        if memory.set_lazy_image(image) {
+            lazy.insert(index);
+        }
+    }
+    lazy
+}
+
 /// Allocate memory for just the globals of the current module,
 /// with initializers applied.
 fn create_globals(module: &Module) -> BoxedSlice<DefinedGlobalIndex, VMGlobalDefinition> {
@@ -33,8 +33,9 @@
 //!   ```
 
 use crate::table::Table;
-use crate::traphandlers::raise_lib_trap;
-use crate::vmcontext::VMContext;
+use crate::traphandlers::{raise_lib_trap, raise_user_trap, resume_panic, Trap};
+use crate::vmcontext::{VMContext, VMDebugFrame};
+use std::panic::{self, AssertUnwindSafe};
 use wasmtime_environ::wasm::{DataIndex, DefinedMemoryIndex, ElemIndex, MemoryIndex, TableIndex};
 
 /// Implementation of f32.ceil
@@ -128,9 +129,13 @@ pub unsafe extern "C" fn wasmtime_memory32_grow(
     let instance = (&mut *vmctx).instance();
     let memory_index = DefinedMemoryIndex::from_u32(memory_index);
 
-    instance
-        .memory_grow(memory_index, delta)
-        .unwrap_or(u32::max_value())
+    match instance.memory_grow(memory_index, delta) {
+        Some(prev) => prev,
+        None if instance.memory_grow_failure_traps(memory_index, delta) => {
+            raise_lib_trap(Trap::oom())
+        }
+        None => u32::max_value(),
+    }
 }
 
 /// Implementation of memory.grow for imported 32-bit memories.
@@ -142,9 +147,13 @@ pub unsafe extern "C" fn wasmtime_imported_memory32_grow(
     let instance = (&mut *vmctx).instance();
     let memory_index = MemoryIndex::from_u32(memory_index);
 
-    instance
-        .imported_memory_grow(memory_index, delta)
-        .unwrap_or(u32::max_value())
+    match instance.imported_memory_grow(memory_index, delta) {
+        Some(prev) => prev,
+        None if instance.imported_memory_grow_failure_traps(memory_index, delta) => {
+            raise_lib_trap(Trap::oom())
+        }
+        None => u32::max_value(),
+    }
 }
 
 /// Implementation of memory.size for locally-defined 32-bit memories.
@@ -313,3 +322,20 @@ pub unsafe extern "C" fn wasmtime_data_drop(vmctx: *mut VMContext, data_index: u
     let instance = (&mut *vmctx).instance();
     instance.data_drop(data_index)
 }
+
+/// Implementation of the hook called by debug-instrumented code before each
+/// instruction while debugging is armed.
+pub unsafe extern "C" fn wasmtime_debug_hook(vmctx: *mut VMContext, frame: *const VMDebugFrame) {
+    let result = {
+        let instance = (&mut *vmctx).instance();
+        match &instance.debug_handler {
+            Some(handler) => panic::catch_unwind(AssertUnwindSafe(|| handler(&*frame))),
+            None => Ok(Ok(())),
+        }
+    };
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => raise_user_trap(err),
+        Err(panic) => resume_panic(panic),
+    }
+}
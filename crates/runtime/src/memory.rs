@@ -3,10 +3,12 @@
 //! `RuntimeLinearMemory` is to WebAssembly linear memories what `Table` is to WebAssembly tables.
 
 use crate::mmap::Mmap;
+use crate::uffd;
 use crate::vmcontext::VMMemoryDefinition;
 use more_asserts::{assert_ge, assert_le};
 use std::cell::RefCell;
 use std::convert::TryFrom;
+use std::sync::Arc;
 use wasmtime_environ::{MemoryPlan, MemoryStyle, WASM_MAX_PAGES, WASM_PAGE_SIZE};
 
 /// A memory allocator
@@ -38,11 +40,54 @@ pub trait RuntimeLinearMemory {
 
     /// Return a `VMMemoryDefinition` for exposing the memory to compiled wasm code.
     fn vmmemory(&self) -> VMMemoryDefinition;
+
+    /// Shrinks the memory back to `pages` wasm pages, discarding the contents
+    /// of the pages past it so that they're zeroed if it grows again. Wasm
+    /// can't do this itself, but it's how an instance is rolled back to a
+    /// snapshot taken before the memory grew.
+    ///
+    /// Returns `false` if the memory can't do that, or is already smaller than
+    /// `pages`, in which case it's left unchanged.
+    fn shrink(&self, _pages: u32) -> bool {
+        false
+    }
+
+    /// Offers the initial contents of the memory, as the start offset and
+    /// data of each data segment, so that the memory can populate its pages
+    /// lazily.
+    ///
+    /// Returns `false` if the memory can't do that, in which case the data
+    /// segments are copied into it instead.
+    fn set_lazy_image(&self, _image: Vec<(usize, Arc<[u8]>)>) -> bool {
+        false
+    }
+
+    /// Returns the number of bytes of the memory backed by physical pages,
+    /// or `None` if the memory doesn't keep track of that.
+    fn resident_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Discards the contents of a memory which populates its pages lazily,
+    /// so that each page is populated with the initial image again on its
+    /// next access. This is much cheaper than writing the image back for
+    /// large memories which are mostly untouched.
+    ///
+    /// Returns `false` if the memory can't do that, in which case it's left
+    /// unchanged.
+    fn reset_lazily(&self) -> bool {
+        false
+    }
 }
 
 /// A linear memory instance.
 #[derive(Debug)]
 pub struct MmapMemory {
+    // The registration of the allocation with the userfaultfd handler, if its
+    // pages are populated lazily. This is declared before `mmap` so that it's
+    // dropped before the allocation is unmapped.
+    uffd: Option<uffd::Registration>,
+
     // The underlying allocation.
     mmap: RefCell<WasmMmap>,
 
@@ -96,12 +141,22 @@ impl MmapMemory {
         let mapped_pages = plan.memory.minimum as usize;
         let mapped_bytes = mapped_pages * WASM_PAGE_SIZE as usize;
 
-        let mmap = WasmMmap {
+        let mut mmap = WasmMmap {
             alloc: Mmap::accessible_reserved(mapped_bytes, request_bytes)?,
             size: plan.memory.minimum,
         };
 
+        // Static memories never move, so the whole reservation can be
+        // registered up front, including pages which become accessible when
+        // the memory grows.
+        let uffd = if plan.userfaultfd {
+            uffd::register(mmap.alloc.as_mut_ptr(), mmap.alloc.len())
+        } else {
+            None
+        };
+
         Ok(Self {
+            uffd,
             mmap: mmap.into(),
             maximum: plan.memory.maximum,
             offset_guard_size: offset_guard_bytes,
@@ -175,6 +230,25 @@ impl RuntimeLinearMemory for MmapMemory {
         Some(prev_pages)
     }
 
+    fn shrink(&self, pages: u32) -> bool {
+        let mut mmap = self.mmap.borrow_mut();
+        if pages > mmap.size {
+            return false;
+        }
+        if pages == mmap.size {
+            return true;
+        }
+
+        let new_bytes = usize::try_from(pages).unwrap() * WASM_PAGE_SIZE as usize;
+        let delta_bytes = usize::try_from(mmap.size - pages).unwrap() * WASM_PAGE_SIZE as usize;
+        if let Err(e) = mmap.alloc.make_inaccessible(new_bytes, delta_bytes) {
+            log::warn!("failed to shrink a linear memory: {}", e);
+            return false;
+        }
+        mmap.size = pages;
+        true
+    }
+
     /// Return a `VMMemoryDefinition` for exposing the memory to compiled wasm code.
     fn vmmemory(&self) -> VMMemoryDefinition {
         let mut mmap = self.mmap.borrow_mut();
@@ -183,4 +257,31 @@ impl RuntimeLinearMemory for MmapMemory {
             current_length: mmap.size as usize * WASM_PAGE_SIZE as usize,
         }
     }
+
+    fn set_lazy_image(&self, image: Vec<(usize, Arc<[u8]>)>) -> bool {
+        match &self.uffd {
+            Some(uffd) => {
+                uffd.set_image(image);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn resident_size(&self) -> Option<usize> {
+        self.uffd.as_ref().map(|uffd| uffd.resident_size())
+    }
+
+    fn reset_lazily(&self) -> bool {
+        match &self.uffd {
+            Some(uffd) => match uffd.reset() {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("failed to discard the pages of a lazy memory: {}", e);
+                    false
+                }
+            },
+            None => false,
+        }
+    }
 }
@@ -218,6 +218,51 @@ impl Mmap {
         Ok(())
     }
 
+    /// Make the memory starting at `start` and extending for `len` bytes inaccessible
+    /// again, discarding its contents so that it's zeroed when it's made accessible
+    /// again. `start` and `len` must be native page-size multiples and describe a range
+    /// within `self`'s reserved memory.
+    #[cfg(not(target_os = "windows"))]
+    pub fn make_inaccessible(&mut self, start: usize, len: usize) -> Result<(), String> {
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        // Private anonymous pages read as zeros after they're discarded.
+        let ptr = self.ptr as *mut u8;
+        if unsafe { libc::madvise(ptr.add(start) as *mut _, len, libc::MADV_DONTNEED) } != 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        unsafe { region::protect(ptr.add(start), len, region::Protection::None) }
+            .map_err(|e| e.to_string())
+    }
+
+    /// Make the memory starting at `start` and extending for `len` bytes inaccessible
+    /// again, discarding its contents so that it's zeroed when it's made accessible
+    /// again. `start` and `len` must be native page-size multiples and describe a range
+    /// within `self`'s reserved memory.
+    #[cfg(target_os = "windows")]
+    pub fn make_inaccessible(&mut self, start: usize, len: usize) -> Result<(), String> {
+        use winapi::ctypes::c_void;
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_DECOMMIT;
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        // Decommitted pages are zeroed when they're committed again.
+        let ptr = self.ptr as *const u8;
+        if unsafe { VirtualFree(ptr.add(start) as *mut c_void, len, MEM_DECOMMIT) } == 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+
+        Ok(())
+    }
+
     /// Return the allocated memory as a slice of u8.
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
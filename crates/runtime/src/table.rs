@@ -66,6 +66,22 @@ impl Table {
         Some(new_len)
     }
 
+    /// Shrinks the table back to `size` elements. Wasm can't do this itself,
+    /// but it's how an instance is rolled back to a snapshot taken before the
+    /// table grew.
+    ///
+    /// Returns `false` if the table is already smaller than `size`, in which
+    /// case it's left unchanged.
+    pub fn shrink(&self, size: u32) -> bool {
+        let mut vec = self.vec.borrow_mut();
+        let size = usize::try_from(size).unwrap();
+        if size > vec.len() {
+            return false;
+        }
+        vec.truncate(size);
+        true
+    }
+
     /// Get reference to the specified element.
     ///
     /// Returns `None` if the index is out of bounds.
@@ -386,6 +386,25 @@ where
     }
 }
 
+/// Returns the highest native stack address which wasm frames on the current
+/// thread can occupy, or `None` if no wasm code is executing on this thread.
+///
+/// This is the location of the state registered by the outermost call to
+/// `catch_traps`, so every wasm frame on this thread lives below it. Stack
+/// samplers can use it to bound a walk of the frame pointer chain. It neither
+/// allocates nor takes locks, so it's safe to call from a signal handler.
+pub fn wasm_stack_base() -> Option<usize> {
+    tls::with(|state| {
+        let mut state = state? as *const CallThreadState;
+        unsafe {
+            while let Some(prev) = (*state).prev {
+                state = prev;
+            }
+        }
+        Some(state as usize)
+    })
+}
+
 /// Temporary state stored on the stack which is registered in the `tls` module
 /// below for calls into wasm.
 pub struct CallThreadState {
@@ -423,7 +442,13 @@ impl CallThreadState {
         tls::with(|prev| {
             self.prev = prev.map(|p| p as *const _);
             let _reset = self.update_stack_limit(max_wasm_stack)?;
+            // Instrumented wasm frames which are unwound by a trap never
+            // unlink themselves from the list of debug frames, so restore it
+            // to what it was when we entered.
+            let interrupts = unsafe { &**(&*self.vmctx).instance().interrupts() };
+            let debug_frame = interrupts.debug_frame.load(SeqCst);
             let ret = tls::set(&self, || closure(&self));
+            interrupts.debug_frame.store(debug_frame, SeqCst);
             match self.unwind.replace(UnwindReason::None) {
                 UnwindReason::None => {
                     debug_assert_eq!(ret, 1);
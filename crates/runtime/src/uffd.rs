@@ -0,0 +1,450 @@
+//! Lazy population of linear memories with userfaultfd.
+//!
+//! Memories registered here start out without any physical pages. The first
+//! access to a wasm page faults, and a handler thread shared by the whole
+//! process fills the page in: with the memory's initial data image if the
+//! page overlaps it, or with zeros otherwise. Instantiating a module with
+//! large data segments therefore doesn't touch any memory it doesn't use, and
+//! the number of pages actually served gives the resident size of the memory.
+//!
+//! Resetting a registered memory discards all of its pages, so that they're
+//! populated from the image again on their next access. This takes a single
+//! `madvise` call however large the memory is.
+//!
+//! userfaultfd is only available on Linux, and may be disabled for
+//! unprivileged processes (see `vm.unprivileged_userfaultfd`), in which case
+//! `register` returns `None` and memories are populated eagerly as usual.
+
+use std::sync::Arc;
+use wasmtime_environ::WASM_PAGE_SIZE;
+
+/// The amount of memory which is populated on a fault.
+///
+/// Faults are served a whole wasm page at a time, which batches the zero
+/// fills of sequential accesses. Linear memories are always accessible in
+/// whole wasm pages, so the rest of the wasm page can always be populated.
+const CHUNK_SIZE: usize = WASM_PAGE_SIZE as usize;
+
+/// The initial contents of a memory: the start offset and data of each data
+/// segment, in initialization order, over zeros. The data is shared by all
+/// instances of a module.
+pub(crate) type Image = Vec<(usize, Arc<[u8]>)>;
+
+cfg_if::cfg_if! {
+    if #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))] {
+        pub(crate) use self::linux::{register, Registration};
+    } else {
+        /// A registered memory, which can't exist on this platform.
+        #[derive(Debug)]
+        pub(crate) enum Registration {}
+
+        impl Registration {
+            pub(crate) fn set_image(&self, _image: Image) {
+                match *self {}
+            }
+
+            pub(crate) fn resident_size(&self) -> usize {
+                match *self {}
+            }
+
+            pub(crate) fn reset(&self) -> std::io::Result<()> {
+                match *self {}
+            }
+        }
+
+        /// userfaultfd isn't supported on this platform.
+        pub(crate) fn register(_ptr: *mut u8, _len: usize) -> Option<Registration> {
+            None
+        }
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod linux {
+    use super::{Image, CHUNK_SIZE};
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::mem;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+    use std::sync::{Arc, Mutex, Once, RwLock};
+    use std::thread;
+
+    // The definitions from `linux/userfaultfd.h` which we need. The ioctl
+    // numbers use the generic encoding, which x86_64 and aarch64 share.
+    const UFFD_API: u64 = 0xaa;
+    const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+    const UFFDIO_REGISTER_MODE_MISSING: u64 = 1;
+    const UFFDIO_API: u64 = 0xc018_aa3f;
+    const UFFDIO_REGISTER: u64 = 0xc020_aa00;
+    const UFFDIO_UNREGISTER: u64 = 0x8010_aa01;
+    const UFFDIO_WAKE: u64 = 0x8010_aa02;
+    const UFFDIO_COPY: u64 = 0xc028_aa03;
+    const UFFDIO_ZEROPAGE: u64 = 0xc020_aa04;
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct UffdioApi {
+        api: u64,
+        features: u64,
+        ioctls: u64,
+    }
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct UffdioRange {
+        start: u64,
+        len: u64,
+    }
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct UffdioRegister {
+        range: UffdioRange,
+        mode: u64,
+        ioctls: u64,
+    }
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct UffdioCopy {
+        dst: u64,
+        src: u64,
+        len: u64,
+        mode: u64,
+        copy: i64,
+    }
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct UffdioZeropage {
+        range: UffdioRange,
+        mode: u64,
+        zeropage: i64,
+    }
+
+    /// A `struct uffd_msg` for a page fault, the only event we ask for.
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct UffdMsg {
+        event: u8,
+        reserved1: u8,
+        reserved2: u16,
+        reserved3: u32,
+        flags: u64,
+        address: u64,
+        ptid: u32,
+        reserved4: u32,
+    }
+
+    /// The userfaultfd shared by all registered memories, and its handler
+    /// thread.
+    struct Handler {
+        fd: libc::c_int,
+        /// The registered memories, by start address.
+        regions: Mutex<BTreeMap<usize, Arc<Region>>>,
+    }
+
+    #[derive(Debug)]
+    struct Region {
+        start: usize,
+        len: usize,
+        image: RwLock<Image>,
+        /// The number of bytes which have been populated.
+        resident: AtomicUsize,
+    }
+
+    /// A memory registered with the handler, which is unregistered when this
+    /// is dropped. This must be dropped before the memory is unmapped.
+    #[derive(Debug)]
+    pub(crate) struct Registration {
+        region: Arc<Region>,
+    }
+
+    static INIT: Once = Once::new();
+    static mut HANDLER: Option<&'static Handler> = None;
+
+    /// Returns the handler, starting it the first time this is called, or
+    /// `None` if userfaultfd isn't available to this process.
+    fn handler() -> Option<&'static Handler> {
+        INIT.call_once(|| unsafe {
+            HANDLER = Handler::new().map(|handler| &*Box::leak(Box::new(handler)));
+            if let Some(handler) = HANDLER {
+                thread::Builder::new()
+                    .name("wasmtime-userfaultfd".to_string())
+                    .spawn(move || handler.run())
+                    .expect("failed to spawn the userfaultfd handler thread");
+            }
+        });
+        unsafe { HANDLER }
+    }
+
+    /// Registers the `len` bytes at `ptr`, a page-aligned anonymous mapping,
+    /// so that its pages are populated lazily.
+    ///
+    /// Until `Registration::set_image` is called all pages are populated with
+    /// zeros. Returns `None` if userfaultfd isn't available.
+    pub(crate) fn register(ptr: *mut u8, len: usize) -> Option<Registration> {
+        if len == 0 {
+            return None;
+        }
+        let handler = handler()?;
+        let mut register = UffdioRegister {
+            range: UffdioRange {
+                start: ptr as u64,
+                len: len as u64,
+            },
+            mode: UFFDIO_REGISTER_MODE_MISSING,
+            ioctls: 0,
+        };
+        unsafe { ioctl(handler.fd, UFFDIO_REGISTER, &mut register) }.ok()?;
+
+        let region = Arc::new(Region {
+            start: ptr as usize,
+            len,
+            image: RwLock::new(Vec::new()),
+            resident: AtomicUsize::new(0),
+        });
+        handler
+            .regions
+            .lock()
+            .unwrap()
+            .insert(region.start, region.clone());
+        Some(Registration { region })
+    }
+
+    impl Registration {
+        /// Sets the initial contents which pages are populated with.
+        ///
+        /// This must be called before any page of the memory is accessed.
+        pub(crate) fn set_image(&self, image: Image) {
+            *self.region.image.write().unwrap() = image;
+        }
+
+        /// Returns the number of bytes of the memory which have been
+        /// populated so far.
+        pub(crate) fn resident_size(&self) -> usize {
+            self.region.resident.load(SeqCst)
+        }
+
+        /// Discards all pages of the memory, so that they're populated from
+        /// the image again on their next access.
+        ///
+        /// The memory must not be accessed while this is running.
+        pub(crate) fn reset(&self) -> io::Result<()> {
+            let ret = unsafe {
+                libc::madvise(
+                    self.region.start as *mut libc::c_void,
+                    self.region.len,
+                    libc::MADV_DONTNEED,
+                )
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.region.resident.store(0, SeqCst);
+            Ok(())
+        }
+    }
+
+    impl Drop for Registration {
+        fn drop(&mut self) {
+            let handler = handler().unwrap();
+            let mut range = UffdioRange {
+                start: self.region.start as u64,
+                len: self.region.len as u64,
+            };
+            // If this fails the handler keeps serving faults in the range
+            // until it's unmapped, which only costs the zero fills.
+            if let Err(e) = unsafe { ioctl(handler.fd, UFFDIO_UNREGISTER, &mut range) } {
+                log::warn!("failed to unregister memory from userfaultfd: {}", e);
+            }
+            handler.regions.lock().unwrap().remove(&self.region.start);
+        }
+    }
+
+    impl Handler {
+        fn new() -> Option<Handler> {
+            let fd = unsafe { libc::syscall(libc::SYS_userfaultfd, libc::O_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            let fd = fd as libc::c_int;
+            let mut api = UffdioApi {
+                api: UFFD_API,
+                features: 0,
+                ioctls: 0,
+            };
+            if unsafe { ioctl(fd, UFFDIO_API, &mut api) }.is_err() {
+                unsafe { libc::close(fd) };
+                return None;
+            }
+            Some(Handler {
+                fd,
+                regions: Mutex::new(BTreeMap::new()),
+            })
+        }
+
+        fn run(&self) {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            loop {
+                let mut msg = UffdMsg::default();
+                let n = unsafe {
+                    libc::read(
+                        self.fd,
+                        &mut msg as *mut UffdMsg as *mut libc::c_void,
+                        mem::size_of::<UffdMsg>(),
+                    )
+                };
+                if n < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    // Reads only fail if the fd is broken, so there are no
+                    // faults to serve anymore.
+                    log::error!("failed to read from userfaultfd: {}", err);
+                    return;
+                }
+                if msg.event == UFFD_EVENT_PAGEFAULT {
+                    self.handle_fault(msg.address as usize, &mut chunk);
+                }
+            }
+        }
+
+        fn handle_fault(&self, address: usize, chunk: &mut [u8]) {
+            let page_size = ::region::page::size();
+            let page = address & !(page_size - 1);
+            let region = match self.regions.lock().unwrap().range(..=address).next_back() {
+                Some((_, region)) if address < region.start + region.len => region.clone(),
+                _ => {
+                    // Only registered memories should fault, but don't leave
+                    // the faulting thread waiting if anything else does.
+                    log::error!("userfaultfd fault at {:#x} outside of a memory", address);
+                    if let Err(e) = self.populate(None, page, None, page_size) {
+                        self.recover(page, page_size, e);
+                    }
+                    return;
+                }
+            };
+
+            let offset = (address - region.start) / CHUNK_SIZE * CHUNK_SIZE;
+            let dst = region.start + offset;
+            let has_data = region.fill(offset, chunk);
+            let src = if has_data { Some(&chunk[..]) } else { None };
+            let result = match self.populate(Some(&region), dst, src, CHUNK_SIZE) {
+                // Part of the chunk was populated already, for example because
+                // another thread faulted on it at the same time. Populate just
+                // the faulting page.
+                Err(ref e) if e.raw_os_error() == Some(libc::EEXIST) => {
+                    let src = src.map(|chunk| &chunk[page - dst..][..page_size]);
+                    self.populate(Some(&region), page, src, page_size)
+                }
+                result => result,
+            };
+            if let Err(e) = result {
+                self.recover(page, page_size, e);
+            }
+        }
+
+        /// Wakes the threads waiting on the `len` bytes at `start` after
+        /// populating them failed with `err`.
+        ///
+        /// If the page exists by now they just continue, and otherwise they
+        /// fault again, so that populating the page is retried. That's what
+        /// transient errors such as `EAGAIN`, which the kernel returns while
+        /// the address space is changing, need.
+        fn recover(&self, start: usize, len: usize, err: io::Error) {
+            if err.raw_os_error() != Some(libc::EEXIST) {
+                log::error!("failed to populate memory at {:#x}: {}", start, err);
+            }
+            let mut range = UffdioRange {
+                start: start as u64,
+                len: len as u64,
+            };
+            if let Err(e) = unsafe { ioctl(self.fd, UFFDIO_WAKE, &mut range) } {
+                log::error!("failed to wake thread faulting at {:#x}: {}", start, e);
+            }
+        }
+
+        /// Populates the `len` bytes at `dst` with `src`, or with zeros, and
+        /// wakes the threads waiting on them. The populated bytes are counted
+        /// towards the resident size of `region`, if they belong to one.
+        fn populate(
+            &self,
+            region: Option<&Region>,
+            dst: usize,
+            src: Option<&[u8]>,
+            len: usize,
+        ) -> io::Result<()> {
+            let (result, populated) = match src {
+                Some(src) => {
+                    let mut copy = UffdioCopy {
+                        dst: dst as u64,
+                        src: src.as_ptr() as u64,
+                        len: len as u64,
+                        mode: 0,
+                        copy: 0,
+                    };
+                    let result = unsafe { ioctl(self.fd, UFFDIO_COPY, &mut copy) };
+                    (result, copy.copy)
+                }
+                None => {
+                    let mut zeropage = UffdioZeropage {
+                        range: UffdioRange {
+                            start: dst as u64,
+                            len: len as u64,
+                        },
+                        mode: 0,
+                        zeropage: 0,
+                    };
+                    let result = unsafe { ioctl(self.fd, UFFDIO_ZEROPAGE, &mut zeropage) };
+                    (result, zeropage.zeropage)
+                }
+            };
+            // On failure the kernel still reports how much it populated
+            // before stopping, as long as that's anything at all.
+            if let (Some(region), true) = (region, populated > 0) {
+                region.resident.fetch_add(populated as usize, SeqCst);
+            }
+            result
+        }
+    }
+
+    impl Region {
+        /// Writes the initial contents of the chunk at `offset` to `chunk`,
+        /// returning whether they're anything other than zeros.
+        fn fill(&self, offset: usize, chunk: &mut [u8]) -> bool {
+            let end = offset + chunk.len();
+            let mut has_data = false;
+            for byte in chunk.iter_mut() {
+                *byte = 0;
+            }
+            for (start, data) in self.image.read().unwrap().iter() {
+                let from = offset.max(*start);
+                let to = end.min(start + data.len());
+                if from < to {
+                    chunk[from - offset..to - offset]
+                        .copy_from_slice(&data[from - start..to - start]);
+                    has_data = true;
+                }
+            }
+            has_data
+        }
+    }
+
+    #[allow(trivial_numeric_casts)]
+    unsafe fn ioctl<T>(fd: libc::c_int, request: u64, arg: &mut T) -> io::Result<()> {
+        if libc::ioctl(fd, request as _, arg as *mut T) < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -3,9 +3,11 @@
 
 use crate::instance::Instance;
 use std::any::Any;
-use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
-use std::{ptr, u32};
-use wasmtime_environ::BuiltinFunctionIndex;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering::SeqCst};
+use std::{mem, ptr, slice, u32};
+use wasmtime_environ::wasm::FuncIndex;
+use wasmtime_environ::{BuiltinFunctionIndex, DebugValueType};
 
 /// An imported function.
 #[derive(Debug, Copy, Clone)]
@@ -570,6 +572,8 @@ impl VMBuiltinFunctionsArray {
             wasmtime_memory_init as usize;
         ptrs[BuiltinFunctionIndex::get_data_drop_index().index() as usize] =
             wasmtime_data_drop as usize;
+        ptrs[BuiltinFunctionIndex::get_debug_hook_index().index() as usize] =
+            wasmtime_debug_hook as usize;
 
         debug_assert!(ptrs.iter().cloned().all(|p| p != 0));
 
@@ -623,6 +627,18 @@ pub struct VMInterrupts {
     /// This is used to control both stack overflow as well as interrupting wasm
     /// modules. For more information see `crates/environ/src/cranelift.rs`.
     pub stack_limit: AtomicUsize,
+
+    /// Non-zero while instrumented code should call the debug hook before
+    /// each instruction, see `Tunables::debug_instrumentation`.
+    pub debug_armed: AtomicUsize,
+
+    /// Address of the innermost `VMDebugFrame` of instrumented code on the
+    /// stack, or zero if there is none.
+    pub debug_frame: AtomicUsize,
+
+    /// The fuel left to code compiled with `Tunables::consume_fuel`, which
+    /// traps with an interrupt once this drops below zero.
+    pub fuel_remaining: AtomicI64,
 }
 
 impl VMInterrupts {
@@ -637,6 +653,9 @@ impl Default for VMInterrupts {
     fn default() -> VMInterrupts {
         VMInterrupts {
             stack_limit: AtomicUsize::new(usize::max_value()),
+            debug_armed: AtomicUsize::new(0),
+            debug_frame: AtomicUsize::new(0),
+            fuel_remaining: AtomicI64::new(i64::max_value()),
         }
     }
 }
@@ -656,6 +675,193 @@ mod test_vminterrupts {
             offset_of!(VMInterrupts, stack_limit),
             usize::from(offsets.vminterrupts_stack_limit())
         );
+        assert_eq!(
+            offset_of!(VMInterrupts, debug_armed),
+            usize::from(offsets.vminterrupts_debug_armed())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, debug_frame),
+            usize::from(offsets.vminterrupts_debug_frame())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, fuel_remaining),
+            usize::from(offsets.vminterrupts_fuel_remaining())
+        );
+    }
+}
+
+/// The state of a wasm frame of code compiled with debug instrumentation.
+///
+/// Each instrumented function keeps one of these in its native stack frame,
+/// linked to the frame of its caller. While debugging is armed the locals and
+/// operand stack of the function are recorded here before each instruction,
+/// as an array of `VMDebugValue`s following this header.
+#[derive(Debug)]
+#[repr(C)]
+pub struct VMDebugFrame {
+    parent: *const VMDebugFrame,
+    vmctx: *mut VMContext,
+    func_index: u32,
+    offset: u32,
+    num_locals: u32,
+    stack_len: u32,
+    valid: Cell<u32>,
+}
+
+impl VMDebugFrame {
+    /// Offset of the values from the start of the frame.
+    const VALUES_OFFSET: usize = (mem::size_of::<Self>() + 15) & !15;
+
+    /// Returns the frame of the wasm function which called this one, if any.
+    ///
+    /// Host frames in between are skipped.
+    pub fn parent(&self) -> Option<&VMDebugFrame> {
+        // Frames are only handed out while all of their parents are live on
+        // the stack.
+        unsafe { self.parent.as_ref() }
+    }
+
+    /// Returns the `VMContext` of the instance the function belongs to.
+    pub fn vmctx(&self) -> *mut VMContext {
+        self.vmctx
+    }
+
+    /// Returns the index of the function in its module.
+    pub fn func_index(&self) -> FuncIndex {
+        FuncIndex::from_u32(self.func_index)
+    }
+
+    /// Returns the offset, from the start of the module, of the instruction
+    /// the frame is about to execute, or of the call it's in.
+    ///
+    /// This is only meaningful if the frame `is_valid`.
+    pub fn offset(&self) -> usize {
+        self.offset as usize
+    }
+
+    /// Returns whether the state of the frame has been recorded. This is
+    /// false for frames which haven't run an instruction since debugging was
+    /// armed.
+    pub fn is_valid(&self) -> bool {
+        self.valid.get() != 0
+    }
+
+    /// Marks the state of the frame as no longer recorded, for when the
+    /// function runs on without recording it. The state is recorded again
+    /// before the next instruction it executes while debugging is armed.
+    pub fn invalidate(&self) {
+        self.valid.set(0);
+    }
+
+    /// Returns the values of the locals of the function, starting with its
+    /// parameters, or nothing if the frame isn't valid.
+    pub fn locals(&self) -> &[VMDebugValue] {
+        &self.values()[..self.num_locals as usize]
+    }
+
+    /// Returns the operand stack of the function, with the bottom of the
+    /// stack first, or nothing if the frame isn't valid.
+    pub fn stack(&self) -> &[VMDebugValue] {
+        &self.values()[self.num_locals as usize..]
+    }
+
+    fn values(&self) -> &[VMDebugValue] {
+        if !self.is_valid() {
+            return &[];
+        }
+        unsafe {
+            let values = (self as *const Self as *const u8).add(Self::VALUES_OFFSET);
+            slice::from_raw_parts(
+                values as *const VMDebugValue,
+                (self.num_locals + self.stack_len) as usize,
+            )
+        }
+    }
+}
+
+/// A wasm value recorded in a `VMDebugFrame`.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct VMDebugValue {
+    ty: u32,
+    _padding: [u32; 3],
+    bits: [u8; 16],
+}
+
+impl VMDebugValue {
+    /// Returns the type of the value.
+    pub fn ty(&self) -> DebugValueType {
+        DebugValueType::from_u32(self.ty)
+    }
+
+    /// Returns the bits of the value. Only the low bits which fit its type
+    /// are meaningful.
+    pub fn bits(&self) -> u128 {
+        u128::from_ne_bytes(self.bits)
+    }
+}
+
+#[cfg(test)]
+mod test_vmdebug_frame {
+    use super::{VMDebugFrame, VMDebugValue};
+    use memoffset::offset_of;
+    use std::mem::size_of;
+    use wasmtime_environ::{Module, VMOffsets};
+
+    #[test]
+    fn check_vmdebug_frame_offsets() {
+        let module = Module::new();
+        let offsets = VMOffsets::new(size_of::<*mut u8>() as u8, &module.local);
+        assert_eq!(
+            offset_of!(VMDebugFrame, parent),
+            usize::from(offsets.vmdebug_frame_parent())
+        );
+        assert_eq!(
+            offset_of!(VMDebugFrame, vmctx),
+            usize::from(offsets.vmdebug_frame_vmctx())
+        );
+        assert_eq!(
+            offset_of!(VMDebugFrame, func_index),
+            usize::from(offsets.vmdebug_frame_func_index())
+        );
+        assert_eq!(
+            offset_of!(VMDebugFrame, offset),
+            usize::from(offsets.vmdebug_frame_offset())
+        );
+        assert_eq!(
+            offset_of!(VMDebugFrame, num_locals),
+            usize::from(offsets.vmdebug_frame_num_locals())
+        );
+        assert_eq!(
+            offset_of!(VMDebugFrame, stack_len),
+            usize::from(offsets.vmdebug_frame_stack_len())
+        );
+        assert_eq!(
+            offset_of!(VMDebugFrame, valid),
+            usize::from(offsets.vmdebug_frame_valid())
+        );
+        assert_eq!(
+            VMDebugFrame::VALUES_OFFSET,
+            usize::from(offsets.size_of_vmdebug_frame())
+        );
+    }
+
+    #[test]
+    fn check_vmdebug_value_offsets() {
+        let module = Module::new();
+        let offsets = VMOffsets::new(size_of::<*mut u8>() as u8, &module.local);
+        assert_eq!(
+            offset_of!(VMDebugValue, ty),
+            usize::from(offsets.vmdebug_value_ty())
+        );
+        assert_eq!(
+            offset_of!(VMDebugValue, bits),
+            usize::from(offsets.vmdebug_value_bits())
+        );
+        assert_eq!(
+            size_of::<VMDebugValue>(),
+            usize::from(offsets.size_of_vmdebug_value())
+        );
     }
 }
 
@@ -0,0 +1,172 @@
+//! Clocks for `WasiCtx`.
+//!
+//! Every clock a guest can observe, through `clock_time_get`, `clock_res_get`,
+//! or a timeout passed to `poll_oneoff`, is read through the `WasiClocks` of
+//! its `WasiCtx`. The host's clocks are used by default, but
+//! `FixedClocks` or `VirtualClocks` can be passed to
+//! `WasiCtxBuilder::clocks()` to make guests reproducible.
+use crate::sys::clock;
+use crate::wasi::{types, Errno, Result};
+use std::cell::Cell;
+use std::convert::TryInto;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+/// The clocks a guest can observe.
+///
+/// All timestamps are in nanoseconds.
+pub trait WasiClocks {
+    /// Returns the resolution of the clock `id`.
+    fn res_get(&self, id: types::Clockid) -> Result<types::Timestamp>;
+    /// Returns the current time of the clock `id`.
+    fn time_get(&self, id: types::Clockid) -> Result<types::Timestamp>;
+    /// Waits until `duration` has passed on these clocks.
+    ///
+    /// This is how `poll_oneoff` waits for a timeout when there are no file
+    /// descriptors to wait on as well. By default it sleeps the thread.
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// The host's clocks.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClocks;
+
+impl WasiClocks for SystemClocks {
+    fn res_get(&self, id: types::Clockid) -> Result<types::Timestamp> {
+        clock::res_get(id)
+    }
+    fn time_get(&self, id: types::Clockid) -> Result<types::Timestamp> {
+        clock::time_get(id)
+    }
+}
+
+/// Clocks which are all stopped at the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClocks {
+    time: types::Timestamp,
+}
+
+impl FixedClocks {
+    /// Creates clocks which always read `time`.
+    pub fn new(time: types::Timestamp) -> Self {
+        Self { time }
+    }
+}
+
+impl WasiClocks for FixedClocks {
+    fn res_get(&self, _id: types::Clockid) -> Result<types::Timestamp> {
+        Ok(1)
+    }
+    fn time_get(&self, _id: types::Clockid) -> Result<types::Timestamp> {
+        Ok(self.time)
+    }
+    fn sleep(&self, _duration: Duration) {
+        // Time never passes, so there's nothing to wait for.
+    }
+}
+
+/// Clocks which only move forward by a fixed tick each time they're read, or
+/// when they're advanced explicitly.
+///
+/// All the clocks read the same virtual time. Clones share it, so the embedder
+/// can keep a clone to advance the time the guest sees.
+#[derive(Debug, Clone)]
+pub struct VirtualClocks {
+    now: Rc<Cell<types::Timestamp>>,
+    tick: types::Timestamp,
+}
+
+impl VirtualClocks {
+    /// Creates clocks starting at `start`, which move forward by `tick` after
+    /// every read.
+    pub fn new(start: types::Timestamp, tick: Duration) -> Self {
+        Self {
+            now: Rc::new(Cell::new(start)),
+            tick: duration_as_timestamp(tick),
+        }
+    }
+
+    /// Moves the clocks forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let now = self.now.get();
+        self.now
+            .set(now.saturating_add(duration_as_timestamp(duration)));
+    }
+
+    /// Returns the current virtual time, without moving the clocks forward.
+    pub fn now(&self) -> types::Timestamp {
+        self.now.get()
+    }
+}
+
+impl WasiClocks for VirtualClocks {
+    fn res_get(&self, _id: types::Clockid) -> Result<types::Timestamp> {
+        Ok(self.tick.max(1))
+    }
+    fn time_get(&self, _id: types::Clockid) -> Result<types::Timestamp> {
+        let now = self.now.get();
+        let next = now.checked_add(self.tick).ok_or(Errno::Overflow)?;
+        self.now.set(next);
+        Ok(now)
+    }
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+fn duration_as_timestamp(duration: Duration) -> types::Timestamp {
+    duration
+        .as_nanos()
+        .try_into()
+        .unwrap_or(types::Timestamp::max_value())
+}
+
+/// Converts a delay in nanoseconds, as returned by `to_relative_ns_delay`, to
+/// a `Duration`.
+pub(crate) fn ns_delay_as_duration(delay: u128) -> Duration {
+    Duration::from_nanos(delay.try_into().unwrap_or(u64::max_value()))
+}
+
+/// Converts the timeout of `clock` into a delay relative to the current time
+/// of its clock in `clocks`, in nanoseconds.
+pub(crate) fn to_relative_ns_delay(
+    clocks: &dyn WasiClocks,
+    clock: &types::SubscriptionClock,
+) -> Result<u128> {
+    if clock.flags != types::Subclockflags::SUBSCRIPTION_CLOCK_ABSTIME {
+        return Ok(u128::from(clock.timeout));
+    }
+    let now = u128::from(clocks.time_get(clock.id)?);
+    let deadline = u128::from(clock.timeout);
+    Ok(deadline.saturating_sub(now))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FixedClocks, VirtualClocks, WasiClocks};
+    use crate::wasi::types::Clockid;
+    use std::time::Duration;
+
+    #[test]
+    fn fixed_clocks() {
+        let clocks = FixedClocks::new(42);
+        assert_eq!(clocks.time_get(Clockid::Realtime).unwrap(), 42);
+        assert_eq!(clocks.time_get(Clockid::Monotonic).unwrap(), 42);
+    }
+
+    #[test]
+    fn virtual_clocks() {
+        let clocks = VirtualClocks::new(100, Duration::from_nanos(10));
+        let handle = clocks.clone();
+        assert_eq!(clocks.time_get(Clockid::Monotonic).unwrap(), 100);
+        assert_eq!(clocks.time_get(Clockid::Realtime).unwrap(), 110);
+        handle.advance(Duration::from_micros(1));
+        assert_eq!(clocks.time_get(Clockid::Monotonic).unwrap(), 1120);
+        assert_eq!(handle.now(), 1130);
+        clocks.sleep(Duration::from_nanos(70));
+        assert_eq!(handle.now(), 1200);
+    }
+}
@@ -1,15 +1,22 @@
-use crate::entry::{Entry, EntryHandle};
+use crate::clocks::{SystemClocks, WasiClocks};
+use crate::entry::{Entry, EntryHandle, EntryRights};
 use crate::fdpool::FdPool;
 use crate::handle::Handle;
+use crate::limits::{IoStats, Quota, WasiLimits};
+use crate::overlayfs::OverlayDir;
 use crate::sys::oshandle::{OsHandle, OsHandleExt};
 use crate::virtfs::{VirtualDir, VirtualDirEntry};
 use crate::wasi::types;
 use crate::wasi::{Errno, Result};
+use rand_core::{OsRng, RngCore};
 use std::borrow::Borrow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::ffi::{self, CString, OsString};
 use std::fs::File;
+use std::net::{TcpListener, TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::{env, io, string};
@@ -38,6 +45,9 @@ pub enum WasiCtxBuilderError {
     /// `WasiCtx` has too many opened files.
     #[error("context object has too many opened files")]
     TooManyFilesOpen,
+    /// Provided file descriptor is already in use.
+    #[error("file descriptor {0} is already in use")]
+    FdInUse(u32),
 }
 
 type WasiCtxBuilderResult<T> = std::result::Result<T, WasiCtxBuilderError>;
@@ -45,6 +55,7 @@ type WasiCtxBuilderResult<T> = std::result::Result<T, WasiCtxBuilderError>;
 enum PendingEntry {
     Thunk(fn() -> io::Result<OsHandle>),
     File(File),
+    Handle(Box<dyn Handle>),
 }
 
 impl std::fmt::Debug for PendingEntry {
@@ -56,10 +67,55 @@ impl std::fmt::Debug for PendingEntry {
                 f as *const fn() -> io::Result<OsHandle>
             ),
             Self::File(f) => write!(fmt, "PendingEntry::File({:?})", f),
+            Self::Handle(h) => write!(fmt, "PendingEntry::Handle({:p})", &**h),
         }
     }
 }
 
+/// A host socket which can be preopened in a `WasiCtx`.
+#[derive(Debug)]
+pub enum HostSocket {
+    TcpListener(TcpListener),
+    TcpStream(TcpStream),
+    UdpSocket(UdpSocket),
+    #[cfg(unix)]
+    UnixListener(UnixListener),
+    #[cfg(unix)]
+    UnixStream(UnixStream),
+}
+
+impl From<TcpListener> for HostSocket {
+    fn from(socket: TcpListener) -> Self {
+        Self::TcpListener(socket)
+    }
+}
+
+impl From<TcpStream> for HostSocket {
+    fn from(socket: TcpStream) -> Self {
+        Self::TcpStream(socket)
+    }
+}
+
+impl From<UdpSocket> for HostSocket {
+    fn from(socket: UdpSocket) -> Self {
+        Self::UdpSocket(socket)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for HostSocket {
+    fn from(socket: UnixListener) -> Self {
+        Self::UnixListener(socket)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for HostSocket {
+    fn from(socket: UnixStream) -> Self {
+        Self::UnixStream(socket)
+    }
+}
+
 #[derive(Debug, Eq, Hash, PartialEq)]
 enum PendingCString {
     Bytes(Vec<u8>),
@@ -110,9 +166,13 @@ pub struct WasiCtxBuilder {
     stdin: Option<PendingEntry>,
     stdout: Option<PendingEntry>,
     stderr: Option<PendingEntry>,
-    preopens: Option<Vec<(PathBuf, Box<dyn Handle>)>>,
+    preopens: Option<Vec<(PathBuf, Box<dyn Handle>, Option<EntryRights>)>>,
+    fds: Option<Vec<(u32, Box<dyn Handle>)>>,
     args: Option<Vec<PendingCString>>,
     env: Option<HashMap<PendingCString, PendingCString>>,
+    clocks: Option<Box<dyn WasiClocks>>,
+    random: Option<Box<dyn RngCore>>,
+    limits: WasiLimits,
 }
 
 impl WasiCtxBuilder {
@@ -127,8 +187,12 @@ impl WasiCtxBuilder {
             stdout,
             stderr,
             preopens: Some(Vec::new()),
+            fds: Some(Vec::new()),
             args: Some(Vec::new()),
             env: Some(HashMap::new()),
+            clocks: Some(Box::new(SystemClocks)),
+            random: Some(Box::new(OsRng)),
+            limits: WasiLimits::default(),
         }
     }
 
@@ -247,11 +311,48 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Provide a `Handle` to use as stdin
+    pub fn stdin_handle(&mut self, handle: Box<dyn Handle>) -> &mut Self {
+        self.stdin = Some(PendingEntry::Handle(handle));
+        self
+    }
+
+    /// Provide a `Handle` to use as stdout
+    pub fn stdout_handle(&mut self, handle: Box<dyn Handle>) -> &mut Self {
+        self.stdout = Some(PendingEntry::Handle(handle));
+        self
+    }
+
+    /// Provide a `Handle` to use as stderr
+    pub fn stderr_handle(&mut self, handle: Box<dyn Handle>) -> &mut Self {
+        self.stderr = Some(PendingEntry::Handle(handle));
+        self
+    }
+
     /// Add a preopened directory.
     pub fn preopened_dir<P: AsRef<Path>>(&mut self, dir: File, guest_path: P) -> &mut Self {
         self.preopens.as_mut().unwrap().push((
             guest_path.as_ref().to_owned(),
             Box::new(OsHandle::from(dir)),
+            None,
+        ));
+        self
+    }
+
+    /// Add a preopened directory with restricted rights.
+    ///
+    /// The rights of the directory, and of anything opened through it, are limited to `rights`,
+    /// such as `EntryRights::read_only_dir()` or `EntryRights::no_create_dir()`.
+    pub fn preopened_dir_with_rights<P: AsRef<Path>>(
+        &mut self,
+        dir: File,
+        guest_path: P,
+        rights: EntryRights,
+    ) -> &mut Self {
+        self.preopens.as_mut().unwrap().push((
+            guest_path.as_ref().to_owned(),
+            Box::new(OsHandle::from(dir)),
+            Some(rights),
         ));
         self
     }
@@ -261,6 +362,28 @@ impl WasiCtxBuilder {
         &mut self,
         dir: VirtualDirEntry,
         guest_path: P,
+    ) -> &mut Self {
+        self.push_virt(dir, guest_path.as_ref(), None)
+    }
+
+    /// Add a preopened virtual directory with restricted rights.
+    ///
+    /// This is how read-only trees such as `VirtualDirEntry::from_tar` are mounted, with
+    /// `EntryRights::read_only_dir()`.
+    pub fn preopened_virt_with_rights<P: AsRef<Path>>(
+        &mut self,
+        dir: VirtualDirEntry,
+        guest_path: P,
+        rights: EntryRights,
+    ) -> &mut Self {
+        self.push_virt(dir, guest_path.as_ref(), Some(rights))
+    }
+
+    fn push_virt(
+        &mut self,
+        dir: VirtualDirEntry,
+        guest_path: &Path,
+        rights: Option<EntryRights>,
     ) -> &mut Self {
         fn populate_directory(virtentry: HashMap<String, VirtualDirEntry>, dir: &mut VirtualDir) {
             for (path, entry) in virtentry.into_iter() {
@@ -288,7 +411,68 @@ impl WasiCtxBuilder {
         self.preopens
             .as_mut()
             .unwrap()
-            .push((guest_path.as_ref().to_owned(), dir));
+            .push((guest_path.to_owned(), dir, rights));
+        self
+    }
+
+    /// Add a preopened copy-on-write overlay of a host directory.
+    ///
+    /// The guest can modify everything in the directory, but the host directory is never
+    /// changed: new and modified files are kept in memory, and deleted entries are only hidden,
+    /// until the `WasiCtx` is dropped.
+    pub fn preopened_overlay<P: AsRef<Path>>(&mut self, dir: File, guest_path: P) -> &mut Self {
+        let lower = Box::new(OsHandle::from(dir));
+        self.preopens.as_mut().unwrap().push((
+            guest_path.as_ref().to_owned(),
+            Box::new(OverlayDir::new(lower)),
+            None,
+        ));
+        self
+    }
+
+    /// Add a preopened socket at the raw WASI file descriptor `fd`.
+    ///
+    /// See `WasiCtxBuilder::preopened_handle()` for how `fd` is assigned.
+    pub fn preopened_socket(&mut self, fd: u32, socket: impl Into<HostSocket>) -> &mut Self {
+        #[cfg(unix)]
+        let handle = OsHandle::from(socket.into());
+        #[cfg(windows)]
+        let handle = crate::sys::oshandle::OsSocket::from(socket.into());
+        self.preopened_handle(fd, Box::new(handle))
+    }
+
+    /// Add a `Handle` at the raw WASI file descriptor `fd`.
+    ///
+    /// Preopened directories are assigned the lowest free file descriptors
+    /// after stdio first, since guests look for them by scanning upwards from
+    /// fd 3 until they find something which isn't a preopen. The handles are
+    /// then inserted, so `fd` should come after the preopened directories. If
+    /// `fd` is already in use, `WasiCtxBuilder::build()` will fail.
+    pub fn preopened_handle(&mut self, fd: u32, handle: Box<dyn Handle>) -> &mut Self {
+        self.fds.as_mut().unwrap().push((fd, handle));
+        self
+    }
+
+    /// Provide the clocks the guest observes, instead of the host's clocks.
+    pub fn clocks(&mut self, clocks: Box<dyn WasiClocks>) -> &mut Self {
+        self.clocks = Some(clocks);
+        self
+    }
+
+    /// Provide the source of `random_get`, instead of the host's random number
+    /// generator.
+    ///
+    /// Passing a seeded generator makes the random bytes a guest sees
+    /// reproducible.
+    pub fn random(&mut self, random: Box<dyn RngCore>) -> &mut Self {
+        self.random = Some(random);
+        self
+    }
+
+    /// Limit the resources the guest can use, such as the number of bytes it
+    /// can write or the number of file descriptors it can open.
+    pub fn limits(&mut self, limits: WasiLimits) -> &mut Self {
+        self.limits = limits;
         self
     }
 
@@ -349,11 +533,18 @@ impl WasiCtxBuilder {
                         .insert(entry)
                         .ok_or(WasiCtxBuilderError::TooManyFilesOpen)?
                 }
+                PendingEntry::Handle(h) => {
+                    let handle = EntryHandle::from(h);
+                    let entry = Entry::from(handle)?;
+                    entries
+                        .insert(entry)
+                        .ok_or(WasiCtxBuilderError::TooManyFilesOpen)?
+                }
             };
             log::debug!("WasiCtx inserted at {:?}", fd);
         }
         // Then add the preopen entries.
-        for (guest_path, dir) in self.preopens.take().unwrap() {
+        for (guest_path, dir, rights) in self.preopens.take().unwrap() {
             if !dir.is_directory() {
                 return Err(WasiCtxBuilderError::NotADirectory(guest_path));
             }
@@ -361,16 +552,39 @@ impl WasiCtxBuilder {
             let handle = EntryHandle::from(dir);
             let mut entry = Entry::from(handle)?;
             entry.preopen_path = Some(guest_path);
+            if let Some(rights) = rights {
+                let mut entry_rights = entry.rights.get();
+                entry_rights.base &= rights.base;
+                entry_rights.inheriting &= rights.inheriting;
+                entry.rights.set(entry_rights);
+            }
             let fd = entries
                 .insert(entry)
                 .ok_or(WasiCtxBuilderError::TooManyFilesOpen)?;
             log::debug!("WasiCtx inserted at {:?}", fd);
         }
+        // Then add the handles and sockets at their requested fds, after the preopens so
+        // that they don't end the scan of guests looking for preopened directories.
+        for (fd, handle) in self.fds.take().unwrap() {
+            let handle = EntryHandle::from(handle);
+            let entry = Entry::from(handle)?;
+            if !entries.insert_at_free(&fd.into(), entry) {
+                return Err(WasiCtxBuilderError::FdInUse(fd));
+            }
+            log::debug!("WasiCtx inserted at {:?}", fd);
+        }
+        // The entries above are never refused, but they count towards the limit on the guest
+        // opening more.
+        entries.fd_pool.set_max_open(self.limits.max_open_fds);
 
         Ok(WasiCtx {
             args,
             env,
             entries: RefCell::new(entries),
+            clocks: self.clocks.take().unwrap(),
+            random: RefCell::new(self.random.take().unwrap()),
+            quota: Quota::new(self.limits),
+            exit_status: Cell::new(None),
         })
     }
 }
@@ -398,7 +612,20 @@ impl EntryTable {
         Some(fd)
     }
 
+    fn insert_at_free(&mut self, fd: &types::Fd, entry: Entry) -> bool {
+        if !self.fd_pool.claim(*fd) {
+            return false;
+        }
+        self.entries.insert(*fd, Rc::new(entry));
+        true
+    }
+
     fn insert_at(&mut self, fd: &types::Fd, entry: Rc<Entry>) {
+        // An fd which isn't open yet has to be taken out of the pool, so that
+        // it counts towards the limit on open fds and isn't handed out again.
+        if !self.entries.contains_key(fd) {
+            self.fd_pool.claim(*fd);
+        }
         self.entries.insert(*fd, entry);
     }
 
@@ -417,6 +644,10 @@ pub struct WasiCtx {
     entries: RefCell<EntryTable>,
     pub(crate) args: Vec<CString>,
     pub(crate) env: Vec<CString>,
+    pub(crate) clocks: Box<dyn WasiClocks>,
+    pub(crate) random: RefCell<Box<dyn RngCore>>,
+    pub(crate) quota: Quota,
+    pub(crate) exit_status: Cell<Option<types::Exitcode>>,
 }
 
 impl WasiCtx {
@@ -435,6 +666,20 @@ impl WasiCtx {
             .build()
     }
 
+    /// The numbers of bytes the guest has read and written so far.
+    pub fn io_stats(&self) -> IoStats {
+        self.quota.stats()
+    }
+
+    /// The status the guest passed to `proc_exit`, if it has called it.
+    ///
+    /// Runtimes which don't override `proc_exit` should check this after every
+    /// call into the guest's imports and stop running the guest once it's set,
+    /// as `proc_exit` returns to the guest.
+    pub fn exit_status(&self) -> Option<types::Exitcode> {
+        self.exit_status.get()
+    }
+
     /// Check if `WasiCtx` contains the specified raw WASI `fd`.
     pub(crate) fn contains_entry(&self, fd: types::Fd) -> bool {
         self.entries.borrow().contains(&fd)
@@ -453,7 +698,14 @@ impl WasiCtx {
     /// The `Entry` will automatically get another free raw WASI `fd` assigned. Note that
     /// the two subsequent free raw WASI `fd`s do not have to be stored contiguously.
     pub(crate) fn insert_entry(&self, entry: Entry) -> Result<types::Fd> {
-        self.entries.borrow_mut().insert(entry).ok_or(Errno::Mfile)
+        let mut entries = self.entries.borrow_mut();
+        entries.insert(entry).ok_or_else(|| {
+            if entries.fd_pool.is_at_max_open() {
+                Errno::Nfile
+            } else {
+                Errno::Mfile
+            }
+        })
     }
 
     /// Insert the specified `Entry` with the specified raw WASI `fd` key into the `WasiCtx`
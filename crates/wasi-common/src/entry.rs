@@ -1,6 +1,6 @@
 use crate::handle::Handle;
 use crate::wasi::types::{Filetype, Rights};
-use crate::wasi::{Errno, Result};
+use crate::wasi::{Errno, Result, RightsExt};
 use std::cell::Cell;
 use std::ops::Deref;
 use std::path::PathBuf;
@@ -51,19 +51,22 @@ pub(crate) struct Entry {
 /// Represents rights of an `Entry` entity, either already held or
 /// required.
 #[derive(Debug, Copy, Clone)]
-pub(crate) struct EntryRights {
-    pub(crate) base: Rights,
-    pub(crate) inheriting: Rights,
+pub struct EntryRights {
+    /// The rights which apply to the file descriptor itself.
+    pub base: Rights,
+    /// The rights which apply to file descriptors derived from this one.
+    pub inheriting: Rights,
 }
 
 impl EntryRights {
-    pub(crate) fn new(base: Rights, inheriting: Rights) -> Self {
+    /// Create new `EntryRights` instance from `base` and `inheriting` rights.
+    pub fn new(base: Rights, inheriting: Rights) -> Self {
         Self { base, inheriting }
     }
 
     /// Create new `EntryRights` instance from `base` rights only, keeping
     /// `inheriting` set to none.
-    pub(crate) fn from_base(base: Rights) -> Self {
+    pub fn from_base(base: Rights) -> Self {
         Self {
             base,
             inheriting: Rights::empty(),
@@ -72,15 +75,60 @@ impl EntryRights {
 
     /// Create new `EntryRights` instance with both `base` and `inheriting`
     /// rights set to none.
-    pub(crate) fn empty() -> Self {
+    pub fn empty() -> Self {
         Self {
             base: Rights::empty(),
             inheriting: Rights::empty(),
         }
     }
 
+    /// Create new `EntryRights` instance for a preopened directory in which
+    /// existing files can be read and written, but nothing can be created,
+    /// linked, renamed or removed.
+    pub fn no_create_dir() -> Self {
+        let denied = !Self::create_rights();
+        Self {
+            base: Rights::directory_base() & denied,
+            inheriting: Rights::directory_inheriting() & denied,
+        }
+    }
+
+    /// Create new `EntryRights` instance for a preopened directory whose
+    /// files and subdirectories can only be read.
+    pub fn read_only_dir() -> Self {
+        let denied = !(Self::create_rights() | Self::write_rights());
+        Self {
+            base: Rights::directory_base() & denied,
+            inheriting: Rights::directory_inheriting() & denied,
+        }
+    }
+
+    /// The rights which add, move or remove directory entries.
+    fn create_rights() -> Rights {
+        Rights::PATH_CREATE_DIRECTORY
+            | Rights::PATH_CREATE_FILE
+            | Rights::PATH_LINK_SOURCE
+            | Rights::PATH_LINK_TARGET
+            | Rights::PATH_RENAME_SOURCE
+            | Rights::PATH_RENAME_TARGET
+            | Rights::PATH_SYMLINK
+            | Rights::PATH_UNLINK_FILE
+            | Rights::PATH_REMOVE_DIRECTORY
+    }
+
+    /// The rights which modify the contents or metadata of files.
+    pub(crate) fn write_rights() -> Rights {
+        Rights::FD_DATASYNC
+            | Rights::FD_WRITE
+            | Rights::FD_ALLOCATE
+            | Rights::FD_FILESTAT_SET_SIZE
+            | Rights::FD_FILESTAT_SET_TIMES
+            | Rights::PATH_FILESTAT_SET_SIZE
+            | Rights::PATH_FILESTAT_SET_TIMES
+    }
+
     /// Check if `other` is a subset of those rights.
-    pub(crate) fn contains(&self, other: &Self) -> bool {
+    pub fn contains(&self, other: &Self) -> bool {
         self.base.contains(&other.base) && self.inheriting.contains(&other.inheriting)
     }
 }
@@ -25,6 +25,10 @@ pub(crate) trait Fd {
 pub(crate) struct FdPool {
     next_alloc: Option<u32>,
     available: Vec<u32>,
+    /// The number of file descriptors currently allocated.
+    open: u32,
+    /// The maximum number of file descriptors `allocate` lets be open at once.
+    max_open: Option<u32>,
 }
 
 impl FdPool {
@@ -32,19 +36,41 @@ impl FdPool {
         Self {
             next_alloc: Some(0),
             available: Vec::new(),
+            open: 0,
+            max_open: None,
         }
     }
 
+    /// Limit the number of file descriptors which can be open at once.
+    ///
+    /// The limit only applies to `allocate`, and file descriptors which
+    /// are already open count towards it.
+    pub fn set_max_open(&mut self, max_open: Option<u32>) {
+        self.max_open = max_open;
+    }
+
+    /// Check if `allocate` fails because of the limit set with `set_max_open`.
+    pub fn is_at_max_open(&self) -> bool {
+        self.max_open
+            .map_or(false, |max_open| self.open >= max_open)
+    }
+
     /// Obtain another valid WASI file descriptor.
     ///
     /// If we've handed out the maximum possible amount of file
     /// descriptors (which would be equal to `2^32 + 1` accounting for `0`),
     /// then this method will return `None` to signal that case.
     /// Otherwise, a new file descriptor is return as `Some(fd)`.
+    /// This method also returns `None` if the limit set with
+    /// `set_max_open` has been reached.
     pub fn allocate<T: Fd>(&mut self) -> Option<T> {
+        if self.is_at_max_open() {
+            return None;
+        }
         if let Some(fd) = self.available.pop() {
             // Since we've had free, unclaimed handle in the pool,
             // simply claim it and return.
+            self.open += 1;
             return Some(T::from_raw(fd));
         }
         // There are no free handles available in the pool, so try
@@ -55,9 +81,32 @@ impl FdPool {
         // It's OK to not unpack the result of `fd.checked_add()` here which
         // can fail since we check for `None` in the snippet above.
         self.next_alloc = fd.checked_add(1);
+        self.open += 1;
         Some(T::from_raw(fd))
     }
 
+    /// Claim the specific file descriptor `fd`.
+    ///
+    /// Any unallocated file descriptors below `fd` are made available
+    /// to subsequent calls to `allocate`, lowest first. If `fd` has
+    /// already been allocated, this method will return `false`.
+    pub fn claim<T: Fd>(&mut self, fd: T) -> bool {
+        let fd = fd.as_raw();
+        if let Some(pos) = self.available.iter().position(|&x| x == fd) {
+            self.available.remove(pos);
+            self.open += 1;
+            return true;
+        }
+        let next_alloc = match self.next_alloc {
+            Some(next_alloc) if fd >= next_alloc => next_alloc,
+            _ => return false,
+        };
+        self.available.extend((next_alloc..fd).rev());
+        self.next_alloc = fd.checked_add(1);
+        self.open += 1;
+        true
+    }
+
     /// Return a file descriptor back to the pool.
     ///
     /// If the caller tries to return a file descriptor that was
@@ -70,6 +119,7 @@ impl FdPool {
         }
         debug_assert!(!self.available.contains(&fd));
         self.available.push(fd);
+        self.open = self.open.saturating_sub(1);
     }
 }
 
@@ -116,6 +166,21 @@ mod test {
         assert_eq!(*fd, 3);
     }
 
+    #[test]
+    fn claim() {
+        let mut fd_pool = FdPool::new();
+        let mut fd: Fd = fd_pool.allocate().expect("success allocating 0");
+        assert_eq!(*fd, 0);
+        assert!(!fd_pool.claim(0u32));
+        assert!(fd_pool.claim(3u32));
+        assert!(!fd_pool.claim(3u32));
+        fd = fd_pool.allocate().expect("success allocating 1");
+        assert_eq!(*fd, 1);
+        assert!(fd_pool.claim(2u32));
+        fd = fd_pool.allocate().expect("success allocating 4");
+        assert_eq!(*fd, 4);
+    }
+
     #[test]
     #[should_panic]
     fn deallocate_nonexistent() {
@@ -123,6 +188,21 @@ mod test {
         fd_pool.deallocate(0u32);
     }
 
+    #[test]
+    fn max_open() {
+        let mut fd_pool = FdPool::new();
+        assert!(fd_pool.claim(1u32));
+        fd_pool.set_max_open(Some(2));
+        let fd: Fd = fd_pool.allocate().expect("success allocating 0");
+        assert_eq!(*fd, 0);
+        assert!(fd_pool.is_at_max_open());
+        assert!(fd_pool.allocate::<Fd>().is_none());
+        fd_pool.deallocate(1u32);
+        assert!(!fd_pool.is_at_max_open());
+        let fd: Fd = fd_pool.allocate().expect("success reallocating 1");
+        assert_eq!(*fd, 1);
+    }
+
     #[test]
     fn max_allocation() {
         let mut fd_pool = FdPool::new();
@@ -3,13 +3,35 @@ use crate::wasi::{types, Errno, Result};
 use std::any::Any;
 use std::io::{self, SeekFrom};
 
-pub(crate) trait Handle {
+/// A resource which can be handed to a WASI guest as a file descriptor.
+///
+/// Embedders can implement this trait to back file descriptors with their own
+/// resources, such as in-memory pipes, and pass them to a `WasiCtxBuilder`.
+/// Every operation has a default implementation which fails the way an
+/// operation on the wrong kind of resource would, so only the operations which
+/// make sense for a resource need to be implemented.
+///
+/// The rights returned by `get_rights` are the rights the guest gets on the
+/// resulting file descriptor, and are checked before any operation is called,
+/// so operations don't have to check them again. Operations fail with the
+/// WASI errno they return, and are called with the `WasiCtx` borrowed, so they
+/// must not call back into the guest.
+pub trait Handle {
+    /// Returns `self` as `Any`, so that operations involving two handles, such
+    /// as `link` and `rename`, can downcast the other one to their own type.
     fn as_any(&self) -> &dyn Any;
+    /// Returns a new handle to the same resource, used when a file descriptor
+    /// is duplicated.
     fn try_clone(&self) -> io::Result<Box<dyn Handle>>;
+    /// Returns the type of the resource, which guests see in `fd_fdstat_get`
+    /// and `fd_filestat_get`.
     fn get_file_type(&self) -> io::Result<types::Filetype>;
+    /// Returns the rights the guest is granted on this resource, which are
+    /// none by default.
     fn get_rights(&self) -> io::Result<EntryRights> {
         Ok(EntryRights::empty())
     }
+    /// Whether the resource is a directory, according to `get_file_type`.
     fn is_directory(&self) -> bool {
         if let Ok(ft) = self.get_file_type() {
             return ft == types::Filetype::Directory;
@@ -18,6 +40,7 @@ pub(crate) trait Handle {
     }
     // TODO perhaps should be a separate trait?
     // FdOps
+    /// Implements `fd_advise`. Fails with `Errno::Badf` by default.
     fn advise(
         &self,
         _advice: types::Advice,
@@ -26,24 +49,37 @@ pub(crate) trait Handle {
     ) -> Result<()> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_allocate`, making sure `len` bytes from `offset` are
+    /// allocated. Fails with `Errno::Badf` by default.
     fn allocate(&self, _offset: types::Filesize, _len: types::Filesize) -> Result<()> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_datasync`. Fails with `Errno::Inval` by default, as for
+    /// resources which can't be synchronized.
     fn datasync(&self) -> Result<()> {
         Err(Errno::Inval)
     }
+    /// Returns the flags of the file descriptor for `fd_fdstat_get`, such as
+    /// `Fdflags::APPEND`. No flags are set by default.
     fn fdstat_get(&self) -> Result<types::Fdflags> {
         Ok(types::Fdflags::empty())
     }
+    /// Implements `fd_fdstat_set_flags`. Fails with `Errno::Badf` by default.
     fn fdstat_set_flags(&self, _fdflags: types::Fdflags) -> Result<()> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_filestat_get`. Fails with `Errno::Badf` by default.
     fn filestat_get(&self) -> Result<types::Filestat> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_filestat_set_size`, truncating or extending the file.
+    /// Fails with `Errno::Badf` by default.
     fn filestat_set_size(&self, _st_size: types::Filesize) -> Result<()> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_filestat_set_times`, with `fst_flags` saying which of
+    /// the times to set and whether to set them to now. Fails with
+    /// `Errno::Badf` by default.
     fn filestat_set_times(
         &self,
         _atim: types::Timestamp,
@@ -52,35 +88,57 @@ pub(crate) trait Handle {
     ) -> Result<()> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_pread`, reading at `offset` without moving the current
+    /// position, and returns the number of bytes read. Fails with
+    /// `Errno::Badf` by default.
     fn preadv(&self, _buf: &mut [io::IoSliceMut], _offset: u64) -> Result<usize> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_pwrite`, writing at `offset` without moving the current
+    /// position, and returns the number of bytes written. Fails with
+    /// `Errno::Badf` by default.
     fn pwritev(&self, _buf: &[io::IoSlice], _offset: u64) -> Result<usize> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_read`, returning the number of bytes read, which is 0 at
+    /// the end of the resource. Fails with `Errno::Badf` by default.
     fn read_vectored(&self, _iovs: &mut [io::IoSliceMut]) -> Result<usize> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_readdir`, returning the entries of a directory starting
+    /// at `cookie`, each with its name. The `d_next` of an entry is the cookie
+    /// to continue after it. Fails with `Errno::Badf` by default.
     fn readdir<'a>(
         &'a self,
         _cookie: types::Dircookie,
     ) -> Result<Box<dyn Iterator<Item = Result<(types::Dirent, String)>> + 'a>> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_seek` and `fd_tell`, returning the new position. Fails
+    /// with `Errno::Badf` by default, as for resources which can't seek.
     fn seek(&self, _offset: SeekFrom) -> Result<u64> {
         Err(Errno::Badf)
     }
+    /// Implements `fd_sync`. Succeeds without doing anything by default.
     fn sync(&self) -> Result<()> {
         Ok(())
     }
+    /// Implements `fd_write`, returning the number of bytes written. `isatty`
+    /// is set when the guest writes to a terminal, in which case control
+    /// sequences should be sanitized. Fails with `Errno::Badf` by default.
     fn write_vectored(&self, _iovs: &[io::IoSlice], _isatty: bool) -> Result<usize> {
         Err(Errno::Badf)
     }
     // TODO perhaps should be a separate trait?
     // PathOps
+    /// Implements `path_create_directory` for `path` relative to this
+    /// directory. This and the other path operations fail with `Errno::Acces`
+    /// by default.
     fn create_directory(&self, _path: &str) -> Result<()> {
         Err(Errno::Acces)
     }
+    /// Implements `path_open`, returning a handle to `path` relative to this
+    /// directory, opened for reading and/or writing.
     fn openat(
         &self,
         _path: &str,
@@ -91,6 +149,10 @@ pub(crate) trait Handle {
     ) -> Result<Box<dyn Handle>> {
         Err(Errno::Acces)
     }
+    /// Implements `path_link`, linking `old_path` relative to this directory
+    /// to `new_path` relative to `new_handle`, following a symlink at
+    /// `old_path` if `follow` is set. Implementations usually fail with
+    /// `Errno::Badf` if `new_handle` isn't of their own type.
     fn link(
         &self,
         _old_path: &str,
@@ -100,22 +162,53 @@ pub(crate) trait Handle {
     ) -> Result<()> {
         Err(Errno::Acces)
     }
+    /// Implements `path_readlink`, copying the target of the symlink at `path`
+    /// into `buf` and returning the number of bytes copied.
     fn readlink(&self, _path: &str, _buf: &mut [u8]) -> Result<usize> {
         Err(Errno::Acces)
     }
+    /// Returns the target of the symlink at `path`, which is used to resolve
+    /// symlinks in paths. Paths which aren't symlinks should fail with
+    /// `Errno::Inval`.
     fn readlinkat(&self, _path: &str) -> Result<String> {
         Err(Errno::Acces)
     }
+    /// Implements `path_remove_directory`, removing the empty directory at
+    /// `path`.
     fn remove_directory(&self, _path: &str) -> Result<()> {
         Err(Errno::Acces)
     }
+    /// Implements `path_rename`, moving `old_path` relative to this directory
+    /// to `new_path` relative to `new_handle`.
     fn rename(&self, _old_path: &str, _new_handle: Box<dyn Handle>, _new_path: &str) -> Result<()> {
         Err(Errno::Acces)
     }
+    /// Implements `path_symlink`, creating a symlink at `new_path` which points
+    /// to `old_path`.
     fn symlink(&self, _old_path: &str, _new_path: &str) -> Result<()> {
         Err(Errno::Acces)
     }
+    /// Implements `path_unlink_file`, removing the file or symlink at `path`.
     fn unlink_file(&self, _path: &str) -> Result<()> {
         Err(Errno::Acces)
     }
+    // SockOps
+    /// Implements `sock_recv`, returning the number of bytes received and the
+    /// flags of the message. This and the other socket operations fail with
+    /// `Errno::Notsock` by default.
+    fn sock_recv(
+        &self,
+        _iovs: &mut [io::IoSliceMut],
+        _flags: types::Riflags,
+    ) -> Result<(usize, types::Roflags)> {
+        Err(Errno::Notsock)
+    }
+    /// Implements `sock_send`, returning the number of bytes sent.
+    fn sock_send(&self, _iovs: &[io::IoSlice], _flags: types::Siflags) -> Result<usize> {
+        Err(Errno::Notsock)
+    }
+    /// Implements `sock_shutdown`, shutting down the directions in `how`.
+    fn sock_shutdown(&self, _how: types::Sdflags) -> Result<()> {
+        Err(Errno::Notsock)
+    }
 }
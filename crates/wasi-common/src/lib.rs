@@ -21,20 +21,30 @@
     )
 )]
 
+mod clocks;
 mod ctx;
 mod entry;
 mod fdpool;
 pub mod fs;
 mod handle;
+mod limits;
 pub mod old;
+mod overlayfs;
 mod path;
+mod pipe;
 mod poll;
 mod sandboxed_tty_writer;
 pub mod snapshots;
 mod sys;
+mod tarfs;
 mod virtfs;
 pub mod wasi;
 
-pub use ctx::{WasiCtx, WasiCtxBuilder, WasiCtxBuilderError};
+pub use clocks::{FixedClocks, SystemClocks, VirtualClocks, WasiClocks};
+pub use ctx::{HostSocket, WasiCtx, WasiCtxBuilder, WasiCtxBuilderError};
+pub use entry::EntryRights;
+pub use handle::Handle;
+pub use limits::{IoStats, WasiLimits};
+pub use pipe::{ReadPipe, WritePipe};
 pub use sys::preopen_dir;
 pub use virtfs::{FileContents, VirtualDirEntry};
@@ -0,0 +1,125 @@
+//! Resource limits and I/O accounting for `WasiCtx`.
+//!
+//! A guest with a writable preopen could otherwise fill the host's disk or
+//! exhaust its file descriptors. `WasiLimits` passed to
+//! `WasiCtxBuilder::limits()` caps what a single `WasiCtx` can do, and
+//! `WasiCtx::io_stats()` reports how much it has read and written so far.
+//!
+//! Both `wasi_snapshot_preview1` and `wasi_unstable` contexts take the same
+//! limits, through their own builders.
+use crate::wasi::Errno;
+use std::cell::Cell;
+
+/// Limits on the resources a guest can use. `None` means unlimited.
+///
+/// Exceeding a limit makes the call fail with `Errno::Dquot`, or with
+/// `Errno::Nfile` for `max_open_fds` (`EDQUOT` and `ENFILE` in
+/// `wasi_unstable`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WasiLimits {
+    /// The total number of bytes which can be written with `fd_write` and
+    /// `fd_pwrite`.
+    pub max_bytes_written: Option<u64>,
+    /// The size regular files can be grown to by writing, allocating or
+    /// resizing them.
+    pub max_file_size: Option<u64>,
+    /// The number of file descriptors which can be open at once, including
+    /// stdio and preopens.
+    pub max_open_fds: Option<u32>,
+    /// The number of files, directories and symlinks which can be created.
+    pub max_created_files: Option<u64>,
+}
+
+/// The numbers of bytes a guest has read and written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IoStats {
+    /// Bytes read with `fd_read` and `fd_pread`.
+    pub bytes_read: u64,
+    /// Bytes written with `fd_write` and `fd_pwrite`.
+    pub bytes_written: u64,
+}
+
+/// A `Quota` check failed. Each snapshot turns this into its own `EDQUOT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QuotaExceeded;
+
+impl From<QuotaExceeded> for Errno {
+    fn from(_: QuotaExceeded) -> Self {
+        Errno::Dquot
+    }
+}
+
+/// Enforces the `WasiLimits` of a `WasiCtx` and keeps its `IoStats`.
+pub(crate) struct Quota {
+    limits: WasiLimits,
+    stats: Cell<IoStats>,
+    created_files: Cell<u64>,
+}
+
+impl Quota {
+    pub(crate) fn new(limits: WasiLimits) -> Self {
+        Self {
+            limits,
+            stats: Cell::new(IoStats::default()),
+            created_files: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> IoStats {
+        self.stats.get()
+    }
+
+    /// Checks that `len` more bytes can be written.
+    pub(crate) fn check_write(&self, len: u64) -> Result<(), QuotaExceeded> {
+        match self.limits.max_bytes_written {
+            Some(max) if self.stats.get().bytes_written.saturating_add(len) > max => {
+                Err(QuotaExceeded)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a regular file can be grown to `size` bytes.
+    ///
+    /// `size` is only computed if the file size is limited, since that may
+    /// need calls into the host.
+    pub(crate) fn check_file_size<E: From<QuotaExceeded>>(
+        &self,
+        size: impl FnOnce() -> Result<u64, E>,
+    ) -> Result<(), E> {
+        match self.limits.max_file_size {
+            Some(max) if size()? > max => Err(QuotaExceeded.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether creating files is limited, and so whether callers have to find
+    /// out if a call creates a file.
+    pub(crate) fn limits_created_files(&self) -> bool {
+        self.limits.max_created_files.is_some()
+    }
+
+    /// Checks that one more file can be created.
+    pub(crate) fn check_create(&self) -> Result<(), QuotaExceeded> {
+        match self.limits.max_created_files {
+            Some(max) if self.created_files.get() >= max => Err(QuotaExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn record_create(&self) {
+        self.created_files.set(self.created_files.get() + 1);
+    }
+
+    pub(crate) fn record_read(&self, len: u64) {
+        let mut stats = self.stats.get();
+        stats.bytes_read = stats.bytes_read.saturating_add(len);
+        self.stats.set(stats);
+    }
+
+    pub(crate) fn record_write(&self, len: u64) {
+        let mut stats = self.stats.get();
+        stats.bytes_written = stats.bytes_written.saturating_add(len);
+        self.stats.set(stats);
+    }
+}
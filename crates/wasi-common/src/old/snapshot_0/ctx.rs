@@ -1,7 +1,12 @@
+use crate::clocks::{SystemClocks, WasiClocks};
+use crate::entry::EntryRights;
 use crate::fdpool::FdPool;
+use crate::limits::{IoStats, Quota, WasiLimits};
 use crate::old::snapshot_0::entry::Entry;
 use crate::old::snapshot_0::wasi::{self, WasiError, WasiResult};
+use rand_core::{OsRng, RngCore};
 use std::borrow::Borrow;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::ffi::{self, CString, OsString};
 use std::fs::File;
@@ -104,9 +109,12 @@ pub struct WasiCtxBuilder {
     stdin: Option<PendingEntry>,
     stdout: Option<PendingEntry>,
     stderr: Option<PendingEntry>,
-    preopens: Option<Vec<(PathBuf, File)>>,
+    preopens: Option<Vec<(PathBuf, File, Option<EntryRights>)>>,
     args: Option<Vec<PendingCString>>,
     env: Option<HashMap<PendingCString, PendingCString>>,
+    clocks: Option<Box<dyn WasiClocks>>,
+    random: Option<Box<dyn RngCore>>,
+    limits: WasiLimits,
 }
 
 impl WasiCtxBuilder {
@@ -119,6 +127,9 @@ impl WasiCtxBuilder {
             preopens: Some(Vec::new()),
             args: Some(Vec::new()),
             env: Some(HashMap::new()),
+            clocks: Some(Box::new(SystemClocks)),
+            random: Some(Box::new(OsRng)),
+            limits: WasiLimits::default(),
         }
     }
 
@@ -242,7 +253,44 @@ impl WasiCtxBuilder {
         self.preopens
             .as_mut()
             .unwrap()
-            .push((guest_path.as_ref().to_owned(), dir));
+            .push((guest_path.as_ref().to_owned(), dir, None));
+        self
+    }
+
+    /// Add a preopened directory with restricted rights.
+    ///
+    /// The rights of the directory, and of anything opened through it, are limited to `rights`,
+    /// such as `EntryRights::read_only_dir()` or `EntryRights::no_create_dir()`.
+    pub fn preopened_dir_with_rights<P: AsRef<Path>>(
+        &mut self,
+        dir: File,
+        guest_path: P,
+        rights: EntryRights,
+    ) -> &mut Self {
+        self.preopens
+            .as_mut()
+            .unwrap()
+            .push((guest_path.as_ref().to_owned(), dir, Some(rights)));
+        self
+    }
+
+    /// Provide the clocks the guest observes, instead of the host's clocks.
+    pub fn clocks(&mut self, clocks: Box<dyn WasiClocks>) -> &mut Self {
+        self.clocks = Some(clocks);
+        self
+    }
+
+    /// Provide the source of `random_get`, instead of the host's random number
+    /// generator.
+    pub fn random(&mut self, random: Box<dyn RngCore>) -> &mut Self {
+        self.random = Some(random);
+        self
+    }
+
+    /// Limit the resources the guest can use, such as the number of bytes it
+    /// can write or the number of file descriptors it can open.
+    pub fn limits(&mut self, limits: WasiLimits) -> &mut Self {
+        self.limits = limits;
         self
     }
 
@@ -298,7 +346,7 @@ impl WasiCtxBuilder {
             }
         }
         // Then add the preopen fds.
-        for (guest_path, dir) in self.preopens.take().unwrap() {
+        for (guest_path, dir, rights) in self.preopens.take().unwrap() {
             // We do the increment at the beginning of the loop body, so that we don't overflow
             // unnecessarily if we have exactly the maximum number of file descriptors.
             let preopen_fd = fd_pool
@@ -311,26 +359,51 @@ impl WasiCtxBuilder {
 
             let mut fe = Entry::from(dir)?;
             fe.preopen_path = Some(guest_path);
+            if let Some(rights) = rights {
+                fe.rights_base &= u64::from(rights.base);
+                fe.rights_inheriting &= u64::from(rights.inheriting);
+            }
             log::debug!("WasiCtx inserting ({:?}, {:?})", preopen_fd, fe);
             entries.insert(preopen_fd, fe);
             log::debug!("WasiCtx entries = {:?}", entries);
         }
+        // The entries above are never refused, but they count towards the limit on the guest
+        // opening more.
+        fd_pool.set_max_open(self.limits.max_open_fds);
 
         Ok(WasiCtx {
             args,
             env,
             fd_pool,
             entries,
+            clocks: self.clocks.take().unwrap(),
+            random: RefCell::new(self.random.take().unwrap()),
+            quota: Quota::new(self.limits),
+            exit_status: Cell::new(None),
         })
     }
 }
 
-#[derive(Debug)]
 pub struct WasiCtx {
     fd_pool: FdPool,
     entries: HashMap<wasi::__wasi_fd_t, Entry>,
     pub(crate) args: Vec<CString>,
     pub(crate) env: Vec<CString>,
+    pub(crate) clocks: Box<dyn WasiClocks>,
+    pub(crate) random: RefCell<Box<dyn RngCore>>,
+    pub(crate) quota: Quota,
+    pub(crate) exit_status: Cell<Option<wasi::__wasi_exitcode_t>>,
+}
+
+impl std::fmt::Debug for WasiCtx {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("WasiCtx")
+            .field("fd_pool", &self.fd_pool)
+            .field("entries", &self.entries)
+            .field("args", &self.args)
+            .field("env", &self.env)
+            .finish()
+    }
 }
 
 impl WasiCtx {
@@ -349,6 +422,20 @@ impl WasiCtx {
             .build()
     }
 
+    /// The numbers of bytes the guest has read and written so far.
+    pub fn io_stats(&self) -> IoStats {
+        self.quota.stats()
+    }
+
+    /// The status the guest passed to `proc_exit`, if it has called it.
+    ///
+    /// Runtimes which don't override `proc_exit` should check this after every
+    /// call into the guest's imports and stop running the guest once it's set,
+    /// as `proc_exit` returns to the guest.
+    pub fn exit_status(&self) -> Option<wasi::__wasi_exitcode_t> {
+        self.exit_status.get()
+    }
+
     /// Check if `WasiCtx` contains the specified raw WASI `fd`.
     pub(crate) unsafe fn contains_entry(&self, fd: wasi::__wasi_fd_t) -> bool {
         self.entries.contains_key(&fd)
@@ -369,7 +456,11 @@ impl WasiCtx {
     /// The `Entry` will automatically get another free raw WASI `fd` assigned. Note that
     /// the two subsequent free raw WASI `fd`s do not have to be stored contiguously.
     pub(crate) fn insert_entry(&mut self, fe: Entry) -> WasiResult<wasi::__wasi_fd_t> {
-        let fd = self.fd_pool.allocate().ok_or(WasiError::EMFILE)?;
+        let fd = match self.fd_pool.allocate() {
+            Some(fd) => fd,
+            None if self.fd_pool.is_at_max_open() => return Err(WasiError::ENFILE),
+            None => return Err(WasiError::EMFILE),
+        };
         self.entries.insert(fd, fe);
         Ok(fd)
     }
@@ -377,6 +468,11 @@ impl WasiCtx {
     /// Insert the specified `Entry` with the specified raw WASI `fd` key into the `WasiCtx`
     /// object.
     pub(crate) fn insert_entry_at(&mut self, fd: wasi::__wasi_fd_t, fe: Entry) -> Option<Entry> {
+        // An fd which isn't open yet has to be taken out of the pool, so that
+        // it counts towards the limit on open fds and isn't handed out again.
+        if !self.entries.contains_key(&fd) {
+            self.fd_pool.claim(fd);
+        }
         self.entries.insert(fd, fe)
     }
 
@@ -81,6 +81,7 @@ pub(crate) unsafe fn fd_pread(
     let buf_size = iovs.iter().map(|v| v.buf_len).sum();
     let mut buf = vec![0; buf_size];
     let host_nread = hostcalls_impl::fd_pread(fd, &mut buf, offset)?;
+    wasi_ctx.quota.record_read(host_nread as u64);
     let mut buf_offset = 0;
     let mut left = host_nread;
     for iov in &iovs {
@@ -117,8 +118,8 @@ pub(crate) unsafe fn fd_pwrite(
         nwritten
     );
 
-    let fd = wasi_ctx
-        .get_entry(fd)?
+    let entry = wasi_ctx.get_entry(fd)?;
+    let fd = entry
         .as_descriptor(
             wasi::__WASI_RIGHTS_FD_WRITE | wasi::__WASI_RIGHTS_FD_SEEK,
             0,
@@ -137,7 +138,15 @@ pub(crate) unsafe fn fd_pwrite(
             iov.buf_len,
         ));
     }
+    let len = buf.len() as u64;
+    wasi_ctx.quota.check_write(len)?;
+    if entry.file_type == wasi::__WASI_FILETYPE_REGULAR_FILE {
+        wasi_ctx
+            .quota
+            .check_file_size(|| offset.checked_add(len).ok_or(WasiError::EFBIG))?;
+    }
     let host_nwritten = hostcalls_impl::fd_pwrite(fd, &buf, offset)?;
+    wasi_ctx.quota.record_write(host_nwritten as u64);
 
     trace!("     | *nwritten={:?}", host_nwritten);
 
@@ -176,6 +185,7 @@ pub(crate) unsafe fn fd_read(
     };
 
     let host_nread = maybe_host_nread?;
+    wasi_ctx.quota.record_read(host_nread as u64);
 
     trace!("     | *nread={:?}", host_nread);
 
@@ -368,6 +378,24 @@ pub(crate) unsafe fn fd_write(
     let iovs = dec_ciovec_slice(memory, iovs_ptr, iovs_len)?;
     let iovs: Vec<io::IoSlice> = iovs.iter().map(|vec| host::ciovec_to_host(vec)).collect();
 
+    let len = iovs.iter().map(|iov| iov.len() as u64).sum::<u64>();
+    wasi_ctx.quota.check_write(len)?;
+    let entry = wasi_ctx.get_entry(fd)?;
+    if entry.file_type == wasi::__WASI_FILETYPE_REGULAR_FILE {
+        let file = entry
+            .as_descriptor(wasi::__WASI_RIGHTS_FD_WRITE, 0)?
+            .as_file()?;
+        wasi_ctx.quota.check_file_size(|| {
+            let position =
+                if hostcalls_impl::fd_fdstat_get(file)? & wasi::__WASI_FDFLAGS_APPEND != 0 {
+                    file.metadata()?.len()
+                } else {
+                    (&**file).seek(SeekFrom::Current(0))?
+                };
+            position.checked_add(len).ok_or(WasiError::EFBIG)
+        })?;
+    }
+
     // perform unbuffered writes
     let entry = wasi_ctx.get_entry_mut(fd)?;
     let isatty = entry.isatty();
@@ -399,6 +427,7 @@ pub(crate) unsafe fn fd_write(
         // on a tty later.
         Descriptor::Stderr => SandboxedTTYWriter::new(&mut io::stderr()).write_vectored(&iovs)?,
     };
+    wasi_ctx.quota.record_write(host_nwritten as u64);
 
     trace!("     | *nwritten={:?}", host_nwritten);
 
@@ -451,6 +480,9 @@ pub(crate) unsafe fn fd_allocate(
     if wanted_size > i64::max_value() as u64 {
         return Err(WasiError::E2BIG);
     }
+    wasi_ctx
+        .quota
+        .check_file_size(|| Ok::<_, WasiError>(wanted_size))?;
 
     if wanted_size > current_size {
         fd.set_len(wanted_size).map_err(Into::into)
@@ -481,7 +513,10 @@ pub(crate) unsafe fn path_create_directory(
     let fe = wasi_ctx.get_entry(dirfd)?;
     let resolved = path_get(fe, rights, 0, 0, path, false)?;
 
-    hostcalls_impl::path_create_directory(resolved)
+    wasi_ctx.quota.check_create()?;
+    hostcalls_impl::path_create_directory(resolved)?;
+    wasi_ctx.quota.record_create();
+    Ok(())
 }
 
 pub(crate) unsafe fn path_link(
@@ -588,7 +623,26 @@ pub(crate) unsafe fn path_open(
             | wasi::__WASI_RIGHTS_FD_FILESTAT_SET_SIZE)
         != 0;
 
+    // Only find out whether the file exists when it matters.
+    let creates = oflags & wasi::__WASI_OFLAGS_CREAT != 0
+        && wasi_ctx.quota.limits_created_files()
+        && match hostcalls_impl::path_open(
+            path_get(fe, needed_base, needed_inheriting, dirflags, path, true)?,
+            false,
+            false,
+            0,
+            0,
+        ) {
+            Err(WasiError::ENOENT) => true,
+            _ => false,
+        };
+    if creates {
+        wasi_ctx.quota.check_create()?;
+    }
     let fd = hostcalls_impl::path_open(resolved, read, write, oflags, fs_flags)?;
+    if creates {
+        wasi_ctx.quota.record_create();
+    }
 
     // Determine the type of the new file descriptor and which rights contradict with this type
     let (_ty, max_base, max_inheriting) = determine_type_rights(&fd)?;
@@ -789,6 +843,9 @@ pub(crate) unsafe fn fd_filestat_set_size(
     if st_size > i64::max_value() as u64 {
         return Err(WasiError::E2BIG);
     }
+    wasi_ctx
+        .quota
+        .check_file_size(|| Ok::<_, WasiError>(st_size))?;
     fd.set_len(st_size).map_err(Into::into)
 }
 
@@ -895,7 +952,10 @@ pub(crate) unsafe fn path_symlink(
     let fe = wasi_ctx.get_entry(dirfd)?;
     let resolved_new = path_get(fe, wasi::__WASI_RIGHTS_PATH_SYMLINK, 0, 0, new_path, true)?;
 
-    hostcalls_impl::path_symlink(old_path, resolved_new)
+    wasi_ctx.quota.check_create()?;
+    hostcalls_impl::path_symlink(old_path, resolved_new)?;
+    wasi_ctx.quota.record_create();
+    Ok(())
 }
 
 pub(crate) unsafe fn path_unlink_file(
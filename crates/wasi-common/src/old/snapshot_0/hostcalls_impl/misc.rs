@@ -1,11 +1,14 @@
 #![allow(non_camel_case_types)]
+use crate::clocks;
 use crate::old::snapshot_0::ctx::WasiCtx;
 use crate::old::snapshot_0::entry::Descriptor;
 use crate::old::snapshot_0::memory::*;
 use crate::old::snapshot_0::sys::hostcalls_impl;
 use crate::old::snapshot_0::wasi::{self, WasiError, WasiResult};
 use crate::old::snapshot_0::wasi32;
+use crate::wasi::{types, Errno};
 use log::{error, trace};
+use rand_core::RngCore;
 use std::convert::TryFrom;
 
 pub(crate) fn args_get(
@@ -131,7 +134,7 @@ pub(crate) fn environ_sizes_get(
 }
 
 pub(crate) fn random_get(
-    _wasi_ctx: &WasiCtx,
+    wasi_ctx: &WasiCtx,
     memory: &mut [u8],
     buf_ptr: wasi32::uintptr_t,
     buf_len: wasi32::size_t,
@@ -140,14 +143,18 @@ pub(crate) fn random_get(
 
     let buf = dec_slice_of_mut_u8(memory, buf_ptr, buf_len)?;
 
-    getrandom::getrandom(buf).map_err(|err| {
-        error!("getrandom failure: {:?}", err);
-        WasiError::EIO
-    })
+    wasi_ctx
+        .random
+        .borrow_mut()
+        .try_fill_bytes(buf)
+        .map_err(|err| {
+            error!("random_get failure: {:?}", err);
+            WasiError::EIO
+        })
 }
 
 pub(crate) fn clock_res_get(
-    _wasi_ctx: &WasiCtx,
+    wasi_ctx: &WasiCtx,
     memory: &mut [u8],
     clock_id: wasi::__wasi_clockid_t,
     resolution_ptr: wasi32::uintptr_t,
@@ -158,7 +165,10 @@ pub(crate) fn clock_res_get(
         resolution_ptr,
     );
 
-    let resolution = hostcalls_impl::clock_res_get(clock_id)?;
+    let resolution = wasi_ctx
+        .clocks
+        .res_get(wasi_clock_id(clock_id)?)
+        .map_err(clock_error)?;
 
     trace!("     | *resolution_ptr={:?}", resolution);
 
@@ -166,7 +176,7 @@ pub(crate) fn clock_res_get(
 }
 
 pub(crate) fn clock_time_get(
-    _wasi_ctx: &WasiCtx,
+    wasi_ctx: &WasiCtx,
     memory: &mut [u8],
     clock_id: wasi::__wasi_clockid_t,
     precision: wasi::__wasi_timestamp_t,
@@ -179,7 +189,10 @@ pub(crate) fn clock_time_get(
         time_ptr,
     );
 
-    let time = hostcalls_impl::clock_time_get(clock_id)?;
+    let time = wasi_ctx
+        .clocks
+        .time_get(wasi_clock_id(clock_id)?)
+        .map_err(clock_error)?;
 
     trace!("     | *time_ptr={:?}", time);
 
@@ -225,7 +238,7 @@ pub(crate) fn poll_oneoff(
         match subscription.u.tag {
             wasi::__WASI_EVENTTYPE_CLOCK => {
                 let clock = unsafe { subscription.u.u.clock };
-                let delay = wasi_clock_to_relative_ns_delay(clock)?;
+                let delay = wasi_clock_to_relative_ns_delay(wasi_ctx, clock)?;
 
                 log::debug!("poll_oneoff event.u.clock = {:?}", clock);
                 log::debug!("poll_oneoff delay = {:?}ns", delay);
@@ -302,7 +315,25 @@ pub(crate) fn poll_oneoff(
     log::debug!("poll_oneoff timeout = {:?}", timeout);
     log::debug!("poll_oneoff fd_events = {:?}", fd_events);
 
-    hostcalls_impl::poll_oneoff(timeout, fd_events, &mut events)?;
+    match timeout {
+        // With only a timeout to wait for, leave the waiting to the context's
+        // clocks, so that virtual clocks skip ahead instead.
+        Some(timeout) if fd_events.is_empty() => {
+            wasi_ctx
+                .clocks
+                .sleep(clocks::ns_delay_as_duration(timeout.delay));
+            events.push(wasi::__wasi_event_t {
+                userdata: timeout.userdata,
+                error: wasi::__WASI_ERRNO_SUCCESS,
+                r#type: wasi::__WASI_EVENTTYPE_CLOCK,
+                fd_readwrite: wasi::__wasi_event_fd_readwrite_t {
+                    nbytes: 0,
+                    flags: 0,
+                },
+            });
+        }
+        _ => hostcalls_impl::poll_oneoff(timeout, fd_events, &mut events)?,
+    }
 
     let events_count = u32::try_from(events.len()).map_err(|_| WasiError::EOVERFLOW)?;
 
@@ -314,19 +345,33 @@ pub(crate) fn poll_oneoff(
 }
 
 fn wasi_clock_to_relative_ns_delay(
+    wasi_ctx: &WasiCtx,
     wasi_clock: wasi::__wasi_subscription_clock_t,
 ) -> WasiResult<u128> {
-    use std::time::SystemTime;
-
     if wasi_clock.flags != wasi::__WASI_SUBCLOCKFLAGS_SUBSCRIPTION_CLOCK_ABSTIME {
         return Ok(u128::from(wasi_clock.timeout));
     }
-    let now: u128 = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .map_err(|_| WasiError::ENOTCAPABLE)?
-        .as_nanos();
+    let now = wasi_ctx
+        .clocks
+        .time_get(wasi_clock_id(wasi_clock.id)?)
+        .map_err(clock_error)?;
     let deadline = u128::from(wasi_clock.timeout);
-    Ok(deadline.saturating_sub(now))
+    Ok(deadline.saturating_sub(u128::from(now)))
+}
+
+fn wasi_clock_id(clock_id: wasi::__wasi_clockid_t) -> WasiResult<types::Clockid> {
+    types::Clockid::try_from(clock_id).map_err(|_| WasiError::EINVAL)
+}
+
+/// Converts an error of the context's `WasiClocks` into this snapshot's errors.
+fn clock_error(errno: Errno) -> WasiError {
+    match errno {
+        Errno::Inval => WasiError::EINVAL,
+        Errno::Notcapable => WasiError::ENOTCAPABLE,
+        Errno::Notsup => WasiError::ENOTSUP,
+        Errno::Overflow => WasiError::EOVERFLOW,
+        _ => WasiError::EIO,
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -342,11 +387,13 @@ pub(crate) struct FdEventData<'a> {
     pub(crate) userdata: wasi::__wasi_userdata_t,
 }
 
-pub(crate) fn proc_exit(_wasi_ctx: &WasiCtx, _memory: &mut [u8], rval: wasi::__wasi_exitcode_t) {
+pub(crate) fn proc_exit(wasi_ctx: &WasiCtx, _memory: &mut [u8], rval: wasi::__wasi_exitcode_t) {
     trace!("proc_exit(rval={:?})", rval);
-    // TODO: Rather than call std::process::exit here, we should trigger a
-    // stack unwind similar to a trap.
-    std::process::exit(rval as i32);
+    // proc_exit is special in that it's expected to unwind the stack, which
+    // typically requires runtime-specific logic, so runtimes are expected to
+    // override this implementation as `wasmtime-wasi` does. Otherwise the
+    // status is left for the embedder to find in `WasiCtx::exit_status`.
+    wasi_ctx.exit_status.set(Some(rval));
 }
 
 pub(crate) fn proc_raise(
@@ -1,4 +1,6 @@
-use crate::old::snapshot_0::wasi::{self, WasiResult};
+//! Sockets are only supported by `wasi_snapshot_preview1`, so guests of this
+//! snapshot can't use any.
+use crate::old::snapshot_0::wasi::{self, WasiError, WasiResult};
 use crate::old::snapshot_0::{wasi32, WasiCtx};
 
 pub fn sock_recv(
@@ -11,7 +13,7 @@ pub fn sock_recv(
     _ro_datalen: wasi32::uintptr_t,
     _ro_flags: wasi32::uintptr_t,
 ) -> WasiResult<()> {
-    unimplemented!("sock_recv")
+    Err(WasiError::ENOTSUP)
 }
 
 pub fn sock_send(
@@ -23,7 +25,7 @@ pub fn sock_send(
     _si_flags: wasi::__wasi_siflags_t,
     _so_datalen: wasi32::uintptr_t,
 ) -> WasiResult<()> {
-    unimplemented!("sock_send")
+    Err(WasiError::ENOTSUP)
 }
 
 pub fn sock_shutdown(
@@ -32,5 +34,5 @@ pub fn sock_shutdown(
     _sock: wasi::__wasi_fd_t,
     _how: wasi::__wasi_sdflags_t,
 ) -> WasiResult<()> {
-    unimplemented!("sock_shutdown")
+    Err(WasiError::ENOTSUP)
 }
@@ -3,55 +3,6 @@
 use crate::old::snapshot_0::hostcalls_impl::{ClockEventData, FdEventData};
 use crate::old::snapshot_0::wasi::{self, WasiError, WasiResult};
 use std::io;
-use yanix::clock::{clock_getres, clock_gettime, ClockId};
-
-fn wasi_clock_id_to_unix(clock_id: wasi::__wasi_clockid_t) -> WasiResult<ClockId> {
-    // convert the supported clocks to libc types, or return EINVAL
-    match clock_id {
-        wasi::__WASI_CLOCKID_REALTIME => Ok(ClockId::Realtime),
-        wasi::__WASI_CLOCKID_MONOTONIC => Ok(ClockId::Monotonic),
-        wasi::__WASI_CLOCKID_PROCESS_CPUTIME_ID => Ok(ClockId::ProcessCPUTime),
-        wasi::__WASI_CLOCKID_THREAD_CPUTIME_ID => Ok(ClockId::ThreadCPUTime),
-        _ => Err(WasiError::EINVAL),
-    }
-}
-
-pub(crate) fn clock_res_get(
-    clock_id: wasi::__wasi_clockid_t,
-) -> WasiResult<wasi::__wasi_timestamp_t> {
-    let clock_id = wasi_clock_id_to_unix(clock_id)?;
-    let timespec = clock_getres(clock_id)?;
-
-    // convert to nanoseconds, returning EOVERFLOW in case of overflow;
-    // this is freelancing a bit from the spec but seems like it'll
-    // be an unusual situation to hit
-    (timespec.tv_sec as wasi::__wasi_timestamp_t)
-        .checked_mul(1_000_000_000)
-        .and_then(|sec_ns| sec_ns.checked_add(timespec.tv_nsec as wasi::__wasi_timestamp_t))
-        .map_or(Err(WasiError::EOVERFLOW), |resolution| {
-            // a supported clock can never return zero; this case will probably never get hit, but
-            // make sure we follow the spec
-            if resolution == 0 {
-                Err(WasiError::EINVAL)
-            } else {
-                Ok(resolution)
-            }
-        })
-}
-
-pub(crate) fn clock_time_get(
-    clock_id: wasi::__wasi_clockid_t,
-) -> WasiResult<wasi::__wasi_timestamp_t> {
-    let clock_id = wasi_clock_id_to_unix(clock_id)?;
-    let timespec = clock_gettime(clock_id)?;
-
-    // convert to nanoseconds, returning EOVERFLOW in case of overflow; this is freelancing a bit
-    // from the spec but seems like it'll be an unusual situation to hit
-    (timespec.tv_sec as wasi::__wasi_timestamp_t)
-        .checked_mul(1_000_000_000)
-        .and_then(|sec_ns| sec_ns.checked_add(timespec.tv_nsec as wasi::__wasi_timestamp_t))
-        .map_or(Err(WasiError::EOVERFLOW), Ok)
-}
 
 pub(crate) fn poll_oneoff(
     timeout: Option<ClockEventData>,
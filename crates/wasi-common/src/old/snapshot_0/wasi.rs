@@ -119,6 +119,12 @@ impl From<std::str::Utf8Error> for WasiError {
     }
 }
 
+impl From<crate::limits::QuotaExceeded> for WasiError {
+    fn from(_err: crate::limits::QuotaExceeded) -> Self {
+        Self::EDQUOT
+    }
+}
+
 pub(crate) const RIGHTS_ALL: __wasi_rights_t = __WASI_RIGHTS_FD_DATASYNC
     | __WASI_RIGHTS_FD_READ
     | __WASI_RIGHTS_FD_SEEK
@@ -0,0 +1,481 @@
+//! A copy-on-write overlay of a host directory.
+//!
+//! An `OverlayDir` shows the guest a host directory, the lower layer, as if it were writable, but
+//! every change lands in an in-memory upper layer instead, which is thrown away along with the
+//! `WasiCtx`:
+//!
+//! * a file of the lower layer is copied up into an `InMemoryFile` the first time it's opened for
+//!   writing, and opening it read-only gives a handle which can't modify the host file,
+//! * new files and directories are only created in the upper layer,
+//! * deleting an entry of the lower layer records a whiteout, which hides it from then on,
+//! * `readdir` merges both layers.
+//!
+//! Directories and symlinks can't be renamed, and links and symlinks can't be created.
+use crate::entry::EntryRights;
+use crate::handle::Handle;
+use crate::virtfs::InMemoryFile;
+use crate::wasi::{self, types, Errno, Result, RightsExt};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::{self, SeekFrom};
+use std::rc::{Rc, Weak};
+
+/// A directory of an overlay, shared by all the handles to it.
+struct Node {
+    /// The directory in the lower layer, unless this directory only exists in the upper layer.
+    lower: Option<Box<dyn Handle>>,
+    /// Entries of the upper layer, which hide entries of the lower layer with the same name.
+    upper: RefCell<HashMap<String, Upper>>,
+    /// Names which were deleted, and which no longer show entries of the lower layer.
+    whiteouts: RefCell<HashSet<String>>,
+    parent: Weak<Node>,
+}
+
+enum Upper {
+    File(InMemoryFile),
+    /// A directory which was created in the upper layer, or a directory of the lower layer which
+    /// was looked up and which keeps its own upper layer from then on.
+    Dir(Rc<Node>),
+}
+
+/// An entry of the lower layer.
+enum Lower {
+    Dir(Box<dyn Handle>),
+    /// A regular file, or another kind of file which can only be read, opened read-only.
+    File(Box<dyn Handle>),
+    Symlink,
+}
+
+impl Node {
+    fn new(lower: Option<Box<dyn Handle>>, parent: Weak<Node>) -> Rc<Self> {
+        Rc::new(Self {
+            lower,
+            upper: RefCell::new(HashMap::new()),
+            whiteouts: RefCell::new(HashSet::new()),
+            parent,
+        })
+    }
+
+    /// Looks `name` up in the lower layer, unless it was deleted.
+    fn lookup_lower(&self, name: &str) -> Result<Option<Lower>> {
+        let lower = match &self.lower {
+            Some(lower) if !self.whiteouts.borrow().contains(name) => lower,
+            _ => return Ok(None),
+        };
+        match lower.openat(
+            name,
+            true,
+            false,
+            types::Oflags::empty(),
+            types::Fdflags::empty(),
+        ) {
+            Ok(handle) if handle.is_directory() => Ok(Some(Lower::Dir(handle))),
+            Ok(handle) => Ok(Some(Lower::File(handle))),
+            Err(Errno::Noent) => Ok(None),
+            // Symlinks aren't followed when opening, so check whether `name` is one.
+            Err(e) => match lower.readlinkat(name) {
+                Ok(_) => Ok(Some(Lower::Symlink)),
+                Err(_) => Err(e),
+            },
+        }
+    }
+
+    /// Lists the names and types of the entries of both layers.
+    fn entries(&self) -> Result<Vec<(String, types::Filetype)>> {
+        let upper = self.upper.borrow();
+        let mut entries = Vec::new();
+        if let Some(lower) = &self.lower {
+            let whiteouts = self.whiteouts.borrow();
+            for entry in lower.readdir(wasi::DIRCOOKIE_START)? {
+                let (dirent, name) = entry?;
+                if name == "." || name == ".." || whiteouts.contains(&name) {
+                    continue;
+                }
+                if !upper.contains_key(&name) {
+                    entries.push((name, dirent.d_type));
+                }
+            }
+        }
+        for (name, entry) in upper.iter() {
+            let file_type = match entry {
+                Upper::File(_) => types::Filetype::RegularFile,
+                Upper::Dir(_) => types::Filetype::Directory,
+            };
+            entries.push((name.clone(), file_type));
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+
+    /// Removes `name` from the upper layer and hides it in the lower layer.
+    fn remove(&self, name: &str) -> Option<Upper> {
+        self.whiteouts.borrow_mut().insert(name.to_owned());
+        self.upper.borrow_mut().remove(name)
+    }
+}
+
+/// A copy-on-write overlay of a host directory. See the module documentation.
+pub(crate) struct OverlayDir {
+    node: Rc<Node>,
+}
+
+impl OverlayDir {
+    /// Creates an overlay of the host directory `lower`.
+    pub(crate) fn new(lower: Box<dyn Handle>) -> Self {
+        Self {
+            node: Node::new(Some(lower), Weak::new()),
+        }
+    }
+
+    fn from_node(node: Rc<Node>) -> Box<dyn Handle> {
+        Box::new(Self { node })
+    }
+}
+
+/// Copies the contents of the lower layer file `lower` into a new `InMemoryFile`.
+fn copy_up(lower: &dyn Handle) -> Result<InMemoryFile> {
+    let file = InMemoryFile::memory_backed();
+    let mut buf = vec![0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let nread = lower.preadv(&mut [io::IoSliceMut::new(&mut buf)], offset)?;
+        if nread == 0 {
+            break;
+        }
+        file.pwritev(&[io::IoSlice::new(&buf[..nread])], offset)?;
+        offset += nread as u64;
+    }
+    Ok(file)
+}
+
+/// Opens a new handle to the upper layer file `file`.
+fn open_upper(
+    file: &InMemoryFile,
+    oflags: types::Oflags,
+    fd_flags: types::Fdflags,
+) -> Result<Box<dyn Handle>> {
+    let handle = file.try_clone()?;
+    if oflags.contains(&types::Oflags::TRUNC) {
+        handle.filestat_set_size(0)?;
+    }
+    handle.fdstat_set_flags(fd_flags)?;
+    Ok(handle)
+}
+
+impl Handle for OverlayDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
+        Ok(Self::from_node(Rc::clone(&self.node)))
+    }
+    fn get_file_type(&self) -> io::Result<types::Filetype> {
+        // Reporting the type of the lower layer makes `WasiCtxBuilder` reject overlays of files.
+        match &self.node.lower {
+            Some(lower) => lower.get_file_type(),
+            None => Ok(types::Filetype::Directory),
+        }
+    }
+    fn get_rights(&self) -> io::Result<EntryRights> {
+        Ok(EntryRights::new(
+            types::Rights::directory_base(),
+            types::Rights::directory_inheriting(),
+        ))
+    }
+    // FdOps
+    fn filestat_get(&self) -> Result<types::Filestat> {
+        match &self.node.lower {
+            Some(lower) => lower.filestat_get(),
+            None => Ok(types::Filestat {
+                dev: 0,
+                ino: 0,
+                nlink: 0,
+                size: 0,
+                atim: 0,
+                ctim: 0,
+                mtim: 0,
+                filetype: types::Filetype::Directory,
+            }),
+        }
+    }
+    fn readdir<'a>(
+        &'a self,
+        cookie: types::Dircookie,
+    ) -> Result<Box<dyn Iterator<Item = Result<(types::Dirent, String)>> + 'a>> {
+        let mut entries = vec![
+            (".".to_owned(), types::Filetype::Directory),
+            ("..".to_owned(), types::Filetype::Directory),
+        ];
+        entries.extend(self.node.entries()?);
+        // Cookies are indices into the merged entries.
+        let start: usize = cookie.try_into().unwrap_or(std::usize::MAX);
+        Ok(Box::new(entries.into_iter().enumerate().skip(start).map(
+            |(i, (name, d_type))| {
+                let dirent = types::Dirent {
+                    d_next: (i + 1) as u64,
+                    d_ino: 0,
+                    d_namlen: name.len().try_into()?,
+                    d_type,
+                };
+                Ok((dirent, name))
+            },
+        )))
+    }
+    // PathOps
+    fn create_directory(&self, path: &str) -> Result<()> {
+        let name = path.trim_end_matches('/');
+        if self.node.upper.borrow().contains_key(name) || self.node.lookup_lower(name)?.is_some() {
+            return Err(Errno::Exist);
+        }
+        // A whiteout of `name` stays in place, so the new directory doesn't show the contents of
+        // a deleted directory of the lower layer.
+        let node = Node::new(None, Rc::downgrade(&self.node));
+        self.node
+            .upper
+            .borrow_mut()
+            .insert(name.to_owned(), Upper::Dir(node));
+        Ok(())
+    }
+    fn openat(
+        &self,
+        path: &str,
+        read: bool,
+        write: bool,
+        oflags: types::Oflags,
+        fd_flags: types::Fdflags,
+    ) -> Result<Box<dyn Handle>> {
+        log::trace!(
+            "OverlayDir::openat(path={:?}, read={:?}, write={:?}, oflags={:?}, fd_flags={:?}",
+            path,
+            read,
+            write,
+            oflags,
+            fd_flags
+        );
+
+        if path == "." {
+            return self.try_clone().map_err(Into::into);
+        } else if path == ".." {
+            return match self.node.parent.upgrade() {
+                Some(parent) => Ok(Self::from_node(parent)),
+                None => self.try_clone().map_err(Into::into),
+            };
+        }
+
+        let name = path.trim_end_matches('/');
+        let creat_excl_mask = types::Oflags::CREAT | types::Oflags::EXCL;
+        let exclusive = (oflags & creat_excl_mask) == creat_excl_mask;
+        let directory = oflags.contains(&types::Oflags::DIRECTORY);
+
+        if let Some(entry) = self.node.upper.borrow().get(name) {
+            if exclusive {
+                return Err(Errno::Exist);
+            }
+            return match entry {
+                Upper::Dir(node) => Ok(Self::from_node(Rc::clone(node))),
+                Upper::File(_) if directory => Err(Errno::Notdir),
+                Upper::File(file) => open_upper(file, oflags, fd_flags),
+            };
+        }
+
+        match self.node.lookup_lower(name)? {
+            Some(_) if exclusive => Err(Errno::Exist),
+            Some(Lower::Dir(lower)) => {
+                // Keep the directory in the upper layer, so that changes to it are kept too.
+                let node = Node::new(Some(lower), Rc::downgrade(&self.node));
+                self.node
+                    .upper
+                    .borrow_mut()
+                    .insert(name.to_owned(), Upper::Dir(Rc::clone(&node)));
+                Ok(Self::from_node(node))
+            }
+            // Like opening a symlink on the host, which lets `path::get` expand it.
+            Some(Lower::Symlink) => Err(Errno::Loop),
+            Some(Lower::File(_)) if directory => Err(Errno::Notdir),
+            Some(Lower::File(lower)) => {
+                if !write && !oflags.contains(&types::Oflags::TRUNC) {
+                    return Ok(Box::new(LowerFile(lower)));
+                }
+                if lower.get_file_type()? != types::Filetype::RegularFile {
+                    return Err(Errno::Acces);
+                }
+                log::trace!("OverlayDir::openat copying up {}", name);
+                let file = copy_up(&*lower)?;
+                let handle = open_upper(&file, oflags, fd_flags)?;
+                self.node
+                    .upper
+                    .borrow_mut()
+                    .insert(name.to_owned(), Upper::File(file));
+                Ok(handle)
+            }
+            None if oflags.contains(&types::Oflags::CREAT) && !directory => {
+                log::trace!("OverlayDir::openat creating an InMemoryFile named {}", name);
+                let file = InMemoryFile::memory_backed();
+                let handle = open_upper(&file, oflags, fd_flags)?;
+                self.node
+                    .upper
+                    .borrow_mut()
+                    .insert(name.to_owned(), Upper::File(file));
+                Ok(handle)
+            }
+            None => Err(Errno::Noent),
+        }
+    }
+    fn readlink(&self, path: &str, buf: &mut [u8]) -> Result<usize> {
+        let name = path.trim_end_matches('/');
+        if self.node.upper.borrow().contains_key(name) {
+            // Nothing in the upper layer is a symlink.
+            return Err(Errno::Inval);
+        }
+        match &self.node.lower {
+            Some(lower) if !self.node.whiteouts.borrow().contains(name) => {
+                lower.readlink(path, buf)
+            }
+            _ => Err(Errno::Noent),
+        }
+    }
+    fn readlinkat(&self, path: &str) -> Result<String> {
+        let name = path.trim_end_matches('/');
+        if self.node.upper.borrow().contains_key(name) {
+            return Err(Errno::Inval);
+        }
+        match &self.node.lower {
+            Some(lower) if !self.node.whiteouts.borrow().contains(name) => lower.readlinkat(path),
+            _ => Err(Errno::Noent),
+        }
+    }
+    fn remove_directory(&self, path: &str) -> Result<()> {
+        let name = path.trim_end_matches('/');
+        let upper = match self.node.upper.borrow().get(name) {
+            Some(Upper::Dir(node)) => Some(Rc::clone(node)),
+            Some(Upper::File(_)) => return Err(Errno::Notdir),
+            None => None,
+        };
+        let node = match upper {
+            Some(node) => node,
+            None => match self.node.lookup_lower(name)? {
+                Some(Lower::Dir(lower)) => Node::new(Some(lower), Weak::new()),
+                Some(_) => return Err(Errno::Notdir),
+                None => return Err(Errno::Noent),
+            },
+        };
+        if !node.entries()?.is_empty() {
+            return Err(Errno::Notempty);
+        }
+        self.node.remove(name);
+        Ok(())
+    }
+    fn rename(&self, old_path: &str, new_handle: Box<dyn Handle>, new_path: &str) -> Result<()> {
+        let new_dir = match new_handle.as_any().downcast_ref::<Self>() {
+            Some(dir) => dir,
+            None => return Err(Errno::Xdev),
+        };
+        let old_name = old_path.trim_end_matches('/');
+        let new_name = new_path.trim_end_matches('/');
+
+        // Only files can be renamed, and only over other files.
+        let target_is_dir = match new_dir.node.upper.borrow().get(new_name) {
+            Some(Upper::Dir(_)) => true,
+            Some(Upper::File(_)) => false,
+            None => match new_dir.node.lookup_lower(new_name)? {
+                Some(Lower::Dir(_)) => true,
+                _ => false,
+            },
+        };
+        if target_is_dir {
+            return Err(Errno::Isdir);
+        }
+        let source_in_upper = match self.node.upper.borrow().get(old_name) {
+            Some(Upper::Dir(_)) => return Err(Errno::Xdev),
+            Some(Upper::File(_)) => true,
+            None => false,
+        };
+        let file = if source_in_upper {
+            match self.node.remove(old_name) {
+                Some(Upper::File(file)) => file,
+                _ => unreachable!("the source was checked to be a file"),
+            }
+        } else {
+            let file = match self.node.lookup_lower(old_name)? {
+                Some(Lower::File(lower))
+                    if lower.get_file_type()? == types::Filetype::RegularFile =>
+                {
+                    copy_up(&*lower)?
+                }
+                Some(_) => return Err(Errno::Xdev),
+                None => return Err(Errno::Noent),
+            };
+            self.node.remove(old_name);
+            file
+        };
+        new_dir
+            .node
+            .upper
+            .borrow_mut()
+            .insert(new_name.to_owned(), Upper::File(file));
+        Ok(())
+    }
+    fn unlink_file(&self, path: &str) -> Result<()> {
+        let name = path.trim_end_matches('/');
+        if name == "." || name == ".." {
+            return Err(Errno::Isdir);
+        }
+        match self.node.upper.borrow().get(name) {
+            Some(Upper::Dir(_)) => return Err(Errno::Isdir),
+            Some(Upper::File(_)) => {}
+            None => match self.node.lookup_lower(name)? {
+                Some(Lower::Dir(_)) => return Err(Errno::Isdir),
+                Some(_) => {}
+                None => return Err(Errno::Noent),
+            },
+        }
+        self.node.remove(name);
+        Ok(())
+    }
+}
+
+/// A file of the lower layer which was opened read-only, and which can't be modified.
+struct LowerFile(Box<dyn Handle>);
+
+impl Handle for LowerFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
+        Ok(Box::new(Self(self.0.try_clone()?)))
+    }
+    fn get_file_type(&self) -> io::Result<types::Filetype> {
+        self.0.get_file_type()
+    }
+    fn get_rights(&self) -> io::Result<EntryRights> {
+        let mut rights = self.0.get_rights()?;
+        rights.base &= !EntryRights::write_rights();
+        Ok(rights)
+    }
+    // FdOps
+    fn advise(
+        &self,
+        advice: types::Advice,
+        offset: types::Filesize,
+        len: types::Filesize,
+    ) -> Result<()> {
+        self.0.advise(advice, offset, len)
+    }
+    fn fdstat_get(&self) -> Result<types::Fdflags> {
+        self.0.fdstat_get()
+    }
+    fn filestat_get(&self) -> Result<types::Filestat> {
+        self.0.filestat_get()
+    }
+    fn preadv(&self, buf: &mut [io::IoSliceMut], offset: u64) -> Result<usize> {
+        self.0.preadv(buf, offset)
+    }
+    fn read_vectored(&self, iovs: &mut [io::IoSliceMut]) -> Result<usize> {
+        self.0.read_vectored(iovs)
+    }
+    fn seek(&self, offset: SeekFrom) -> Result<u64> {
+        self.0.seek(offset)
+    }
+}
@@ -0,0 +1,220 @@
+//! Virtual pipes.
+//!
+//! These types provide easy implementations of `Handle` that mimic much of the behavior of Unix
+//! pipes. These are particularly helpful for redirecting WASI stdio handles to destinations other
+//! than OS files.
+//!
+//! Some convenience constructors are included for common backing types like `Vec<u8>` and `String`,
+//! but the virtual pipes can be instantiated with any `Read` or `Write` type.
+//!
+//! Note that `poll_oneoff` is not supported for these types, so they do not match the behavior of
+//! real pipes exactly.
+use crate::entry::EntryRights;
+use crate::handle::Handle;
+use crate::wasi::{types, Result};
+use std::any::Any;
+use std::cell::{Ref, RefCell};
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+/// A virtual pipe read end.
+///
+/// A variety of `From` impls are provided so that common pipe types are easy to create. For example:
+///
+/// ```
+/// # use wasi_common::{ReadPipe, WasiCtxBuilder};
+/// let stdin = ReadPipe::from("hello from stdin!");
+/// let mut builder = WasiCtxBuilder::new();
+/// builder.stdin_handle(Box::new(stdin));
+/// ```
+#[derive(Debug)]
+pub struct ReadPipe<R: Read + Any> {
+    reader: Rc<RefCell<R>>,
+}
+
+impl<R: Read + Any> Clone for ReadPipe<R> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: Rc::clone(&self.reader),
+        }
+    }
+}
+
+impl<R: Read + Any> ReadPipe<R> {
+    /// Create a new pipe from a `Read` type.
+    ///
+    /// All `Handle` operations that read from stdin, such as `fd_read`, will be forwarded to the
+    /// wrapped `Read` type.
+    pub fn new(r: R) -> Self {
+        Self {
+            reader: Rc::new(RefCell::new(r)),
+        }
+    }
+
+    /// Try to convert this `ReadPipe<R>` back to the underlying `R` type.
+    ///
+    /// This will fail with `Err(self)` if multiple references to the underlying `R` exist.
+    pub fn try_into_inner(self) -> std::result::Result<R, Self> {
+        match Rc::try_unwrap(self.reader) {
+            Ok(reader) => Ok(reader.into_inner()),
+            Err(reader) => Err(Self { reader }),
+        }
+    }
+}
+
+impl From<Vec<u8>> for ReadPipe<io::Cursor<Vec<u8>>> {
+    fn from(r: Vec<u8>) -> Self {
+        Self::new(io::Cursor::new(r))
+    }
+}
+
+impl From<&[u8]> for ReadPipe<io::Cursor<Vec<u8>>> {
+    fn from(r: &[u8]) -> Self {
+        Self::from(r.to_vec())
+    }
+}
+
+impl From<String> for ReadPipe<io::Cursor<String>> {
+    fn from(r: String) -> Self {
+        Self::new(io::Cursor::new(r))
+    }
+}
+
+impl From<&str> for ReadPipe<io::Cursor<String>> {
+    fn from(r: &str) -> Self {
+        Self::from(r.to_string())
+    }
+}
+
+impl<R: Read + Any> Handle for ReadPipe<R> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
+        Ok(Box::new(self.clone()))
+    }
+    fn get_file_type(&self) -> io::Result<types::Filetype> {
+        Ok(types::Filetype::Unknown)
+    }
+    fn get_rights(&self) -> io::Result<EntryRights> {
+        Ok(EntryRights::from_base(types::Rights::FD_READ))
+    }
+    fn read_vectored(&self, iovs: &mut [io::IoSliceMut]) -> Result<usize> {
+        let nread = self.reader.borrow_mut().read_vectored(iovs)?;
+        Ok(nread)
+    }
+}
+
+/// A virtual pipe write end.
+///
+/// ```
+/// # use wasi_common::{WasiCtxBuilder, WritePipe};
+/// let stdout = WritePipe::new_in_memory();
+/// let mut builder = WasiCtxBuilder::new();
+/// builder.stdout_handle(Box::new(stdout.clone()));
+/// // use builder in a `Wasi` instance and run the guest, and then:
+/// let contents = stdout.borrow().clone();
+/// assert!(contents.is_empty());
+/// ```
+#[derive(Debug)]
+pub struct WritePipe<W: Write + Any> {
+    writer: Rc<RefCell<W>>,
+}
+
+impl<W: Write + Any> Clone for WritePipe<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: Rc::clone(&self.writer),
+        }
+    }
+}
+
+impl<W: Write + Any> WritePipe<W> {
+    /// Create a new pipe from a `Write` type.
+    ///
+    /// All `Handle` operations that write to stdout, such as `fd_write`, will be forwarded to the
+    /// wrapped `Write` type.
+    pub fn new(w: W) -> Self {
+        Self {
+            writer: Rc::new(RefCell::new(w)),
+        }
+    }
+
+    /// Borrow the underlying `W` type, for example to inspect what has been written so far.
+    pub fn borrow(&self) -> Ref<'_, W> {
+        self.writer.borrow()
+    }
+
+    /// Try to convert this `WritePipe<W>` back to the underlying `W` type.
+    ///
+    /// This will fail with `Err(self)` if multiple references to the underlying `W` exist.
+    pub fn try_into_inner(self) -> std::result::Result<W, Self> {
+        match Rc::try_unwrap(self.writer) {
+            Ok(writer) => Ok(writer.into_inner()),
+            Err(writer) => Err(Self { writer }),
+        }
+    }
+}
+
+impl WritePipe<Vec<u8>> {
+    /// Create a new writable virtual pipe backed by a `Vec<u8>` buffer.
+    pub fn new_in_memory() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<W: Write + Any> Handle for WritePipe<W> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
+        Ok(Box::new(self.clone()))
+    }
+    fn get_file_type(&self) -> io::Result<types::Filetype> {
+        Ok(types::Filetype::Unknown)
+    }
+    fn get_rights(&self) -> io::Result<EntryRights> {
+        Ok(EntryRights::from_base(types::Rights::FD_WRITE))
+    }
+    fn sync(&self) -> Result<()> {
+        self.writer.borrow_mut().flush()?;
+        Ok(())
+    }
+    fn write_vectored(&self, iovs: &[io::IoSlice], _isatty: bool) -> Result<usize> {
+        let nwritten = self.writer.borrow_mut().write_vectored(iovs)?;
+        Ok(nwritten)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ReadPipe, WritePipe};
+    use crate::handle::Handle;
+    use std::io;
+
+    #[test]
+    fn read_pipe() {
+        let pipe = ReadPipe::from("hello");
+        let mut buf = [0; 8];
+        let nread = pipe
+            .read_vectored(&mut [io::IoSliceMut::new(&mut buf)])
+            .unwrap();
+        assert_eq!(&buf[..nread], b"hello");
+        assert!(pipe
+            .write_vectored(&[io::IoSlice::new(b"x")], false)
+            .is_err());
+    }
+
+    #[test]
+    fn write_pipe_is_shared_between_clones() {
+        let pipe = WritePipe::new_in_memory();
+        let handle = pipe.try_clone().unwrap();
+        let nwritten = handle
+            .write_vectored(&[io::IoSlice::new(b"hel"), io::IoSlice::new(b"lo")], false)
+            .unwrap();
+        assert_eq!(nwritten, 5);
+        assert_eq!(&*pipe.borrow(), b"hello");
+        drop(handle);
+        assert_eq!(pipe.try_into_inner().unwrap(), b"hello");
+    }
+}
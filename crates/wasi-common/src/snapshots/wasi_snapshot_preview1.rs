@@ -1,10 +1,11 @@
+use crate::clocks;
 use crate::entry::{Entry, EntryHandle, EntryRights};
-use crate::sys::clock;
 use crate::wasi::wasi_snapshot_preview1::WasiSnapshotPreview1;
 use crate::wasi::{types, AsBytes, Errno, Result};
 use crate::WasiCtx;
 use crate::{path, poll};
 use log::{debug, error, trace};
+use rand_core::RngCore;
 use std::convert::TryInto;
 use std::io::{self, SeekFrom};
 use wiggle::{GuestBorrows, GuestPtr};
@@ -71,7 +72,7 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
     }
 
     fn clock_res_get(&self, id: types::Clockid) -> Result<types::Timestamp> {
-        let resolution = clock::res_get(id)?;
+        let resolution = self.clocks.res_get(id)?;
         Ok(resolution)
     }
 
@@ -80,7 +81,7 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         id: types::Clockid,
         _precision: types::Timestamp,
     ) -> Result<types::Timestamp> {
-        let time = clock::time_get(id)?;
+        let time = self.clocks.time_get(id)?;
         Ok(time)
     }
 
@@ -106,7 +107,10 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
     ) -> Result<()> {
         let required_rights = EntryRights::from_base(types::Rights::FD_ALLOCATE);
         let entry = self.get_entry(fd)?;
-        entry.as_handle(&required_rights)?.allocate(offset, len)
+        let handle = entry.as_handle(&required_rights)?;
+        self.quota
+            .check_file_size(|| offset.checked_add(len).ok_or(Errno::Fbig))?;
+        handle.allocate(offset, len)
     }
 
     fn fd_close(&self, fd: types::Fd) -> Result<()> {
@@ -175,7 +179,9 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         if size > i64::max_value() as u64 {
             return Err(Errno::TooBig);
         }
-        entry.as_handle(&required_rights)?.filestat_set_size(size)
+        let handle = entry.as_handle(&required_rights)?;
+        self.quota.check_file_size(|| Ok::<_, Errno>(size))?;
+        handle.filestat_set_size(size)
     }
 
     fn fd_filestat_set_times(
@@ -220,9 +226,9 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         }
         let host_nread = entry
             .as_handle(&required_rights)?
-            .preadv(&mut buf, offset)?
-            .try_into()?;
-        Ok(host_nread)
+            .preadv(&mut buf, offset)?;
+        self.quota.record_read(host_nread as u64);
+        Ok(host_nread.try_into()?)
     }
 
     fn fd_prestat_get(&self, fd: types::Fd) -> Result<types::Prestat> {
@@ -296,11 +302,16 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
             return Err(Errno::Io);
         }
 
-        let host_nwritten = entry
-            .as_handle(&required_rights)?
-            .pwritev(&buf, offset)?
-            .try_into()?;
-        Ok(host_nwritten)
+        let handle = entry.as_handle(&required_rights)?;
+        let len = buf.iter().map(|b| b.len() as u64).sum::<u64>();
+        self.quota.check_write(len)?;
+        if entry.file_type == types::Filetype::RegularFile {
+            self.quota
+                .check_file_size(|| offset.checked_add(len).ok_or(Errno::Fbig))?;
+        }
+        let host_nwritten = handle.pwritev(&buf, offset)?;
+        self.quota.record_write(host_nwritten as u64);
+        Ok(host_nwritten.try_into()?)
     }
 
     fn fd_read(&self, fd: types::Fd, iovs: &types::IovecArray<'_>) -> Result<types::Size> {
@@ -322,10 +333,10 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         let entry = self.get_entry(fd)?;
         let host_nread = entry
             .as_handle(&required_rights)?
-            .read_vectored(&mut slices)?
-            .try_into()?;
+            .read_vectored(&mut slices)?;
+        self.quota.record_read(host_nread as u64);
 
-        Ok(host_nread)
+        Ok(host_nread.try_into()?)
     }
 
     fn fd_readdir(
@@ -438,11 +449,22 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         let required_rights = EntryRights::from_base(types::Rights::FD_WRITE);
         let entry = self.get_entry(fd)?;
         let isatty = entry.isatty();
-        let host_nwritten = entry
-            .as_handle(&required_rights)?
-            .write_vectored(&slices, isatty)?
-            .try_into()?;
-        Ok(host_nwritten)
+        let handle = entry.as_handle(&required_rights)?;
+        let len = slices.iter().map(|s| s.len() as u64).sum::<u64>();
+        self.quota.check_write(len)?;
+        if entry.file_type == types::Filetype::RegularFile {
+            self.quota.check_file_size(|| {
+                let position = if handle.fdstat_get()?.contains(&types::Fdflags::APPEND) {
+                    handle.filestat_get()?.size
+                } else {
+                    handle.seek(SeekFrom::Current(0))?
+                };
+                position.checked_add(len).ok_or(Errno::Fbig)
+            })?;
+        }
+        let host_nwritten = handle.write_vectored(&slices, isatty)?;
+        self.quota.record_write(host_nwritten as u64);
+        Ok(host_nwritten.try_into()?)
     }
 
     fn path_create_directory(&self, dirfd: types::Fd, path: &GuestPtr<'_, str>) -> Result<()> {
@@ -456,7 +478,10 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
             path,
             false,
         )?;
-        dirfd.create_directory(&path)
+        self.quota.check_create()?;
+        dirfd.create_directory(&path)?;
+        self.quota.record_create();
+        Ok(())
     }
 
     fn path_filestat_get(
@@ -576,7 +601,26 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
             read,
             write
         );
+        // Only find out whether the file exists when it matters.
+        let creates = oflags.contains(&types::Oflags::CREAT)
+            && self.quota.limits_created_files()
+            && match dirfd.openat(
+                &path,
+                false,
+                false,
+                types::Oflags::empty(),
+                types::Fdflags::empty(),
+            ) {
+                Err(Errno::Noent) => true,
+                _ => false,
+            };
+        if creates {
+            self.quota.check_create()?;
+        }
         let fd = dirfd.openat(&path, read, write, oflags, fdflags)?;
+        if creates {
+            self.quota.record_create();
+        }
         let fe = Entry::from(EntryHandle::from(fd))?;
         // We need to manually deny the rights which are not explicitly requested
         // because Entry::from will assign maximal consistent rights.
@@ -676,7 +720,10 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
             &*raw
         };
         trace!("     | old_path='{}'", old_path);
-        new_fd.symlink(&old_path, &new_path)
+        self.quota.check_create()?;
+        new_fd.symlink(&old_path, &new_path)?;
+        self.quota.record_create();
+        Ok(())
     }
 
     fn path_unlink_file(&self, dirfd: types::Fd, path: &GuestPtr<'_, str>) -> Result<()> {
@@ -726,7 +773,7 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         for subscription in subscriptions {
             match subscription.u {
                 types::SubscriptionU::Clock(clock) => {
-                    let delay = clock::to_relative_ns_delay(&clock)?;
+                    let delay = clocks::to_relative_ns_delay(&*self.clocks, &clock)?;
                     debug!("poll_oneoff event.u.clock = {:?}", clock);
                     debug!("poll_oneoff delay = {:?}ns", delay);
                     let current = poll::ClockEventData {
@@ -797,7 +844,24 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         // The underlying implementation should successfully and immediately return
         // if no events have been passed. Such situation may occur if all provided
         // events have been filtered out as errors in the code above.
-        poll::oneoff(timeout, fd_events, &mut events)?;
+        match timeout {
+            // With only a timeout to wait for, leave the waiting to the
+            // context's clocks, so that virtual clocks skip ahead instead.
+            Some(timeout) if fd_events.is_empty() => {
+                self.clocks
+                    .sleep(clocks::ns_delay_as_duration(timeout.delay));
+                events.push(types::Event {
+                    userdata: timeout.userdata,
+                    error: Errno::Success,
+                    type_: types::Eventtype::Clock,
+                    fd_readwrite: types::EventFdReadwrite {
+                        nbytes: 0,
+                        flags: types::Eventrwflags::empty(),
+                    },
+                });
+            }
+            _ => poll::oneoff(timeout, fd_events, &mut events)?,
+        }
         let nevents = events.len().try_into()?;
 
         let out_events = out.as_array(nevents);
@@ -812,13 +876,13 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         Ok(nevents)
     }
 
-    // This is just a temporary to ignore the warning which becomes a hard error
-    // in the CI. Once we figure out non-returns in `wiggle`, this should be gone.
-    #[allow(unreachable_code)]
     fn proc_exit(&self, rval: types::Exitcode) -> std::result::Result<(), ()> {
-        // TODO: Rather than call std::process::exit here, we should trigger a
-        // stack unwind similar to a trap.
-        std::process::exit(rval as i32);
+        // proc_exit is special in that it's expected to unwind the stack, which
+        // typically requires runtime-specific logic, so runtimes are expected to
+        // override this implementation as `wasmtime-wasi` does. Otherwise the
+        // status is left for the embedder to find in `WasiCtx::exit_status`.
+        self.exit_status.set(Some(rval));
+        Ok(())
     }
 
     fn proc_raise(&self, _sig: types::Signal) -> Result<()> {
@@ -837,31 +901,77 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
             let raw = buf.as_raw(&mut bc)?;
             &mut *raw
         };
-        getrandom::getrandom(slice).map_err(|err| {
-            error!("getrandom failure: {:?}", err);
-            Errno::Io
-        })
+        self.random
+            .borrow_mut()
+            .try_fill_bytes(slice)
+            .map_err(|err| {
+                error!("random_get failure: {:?}", err);
+                Errno::Io
+            })
     }
 
     fn sock_recv(
         &self,
-        _fd: types::Fd,
-        _ri_data: &types::IovecArray<'_>,
-        _ri_flags: types::Riflags,
+        fd: types::Fd,
+        ri_data: &types::IovecArray<'_>,
+        ri_flags: types::Riflags,
     ) -> Result<(types::Size, types::Roflags)> {
-        unimplemented!("sock_recv")
+        let mut bc = GuestBorrows::new();
+        let mut slices = Vec::new();
+        bc.borrow_slice(&ri_data)?;
+        for iov_ptr in ri_data.iter() {
+            let iov_ptr = iov_ptr?;
+            let iov: types::Iovec = iov_ptr.read()?;
+            let slice = unsafe {
+                let buf = iov.buf.as_array(iov.buf_len);
+                let raw = buf.as_raw(&mut bc)?;
+                &mut *raw
+            };
+            slices.push(io::IoSliceMut::new(slice));
+        }
+
+        let required_rights = EntryRights::from_base(types::Rights::FD_READ);
+        let entry = self.get_entry(fd)?;
+        let (host_nread, ro_flags) = entry
+            .as_handle(&required_rights)?
+            .sock_recv(&mut slices, ri_flags)?;
+
+        Ok((host_nread.try_into()?, ro_flags))
     }
 
     fn sock_send(
         &self,
-        _fd: types::Fd,
-        _si_data: &types::CiovecArray<'_>,
-        _si_flags: types::Siflags,
+        fd: types::Fd,
+        si_data: &types::CiovecArray<'_>,
+        si_flags: types::Siflags,
     ) -> Result<types::Size> {
-        unimplemented!("sock_send")
+        let mut bc = GuestBorrows::new();
+        let mut slices = Vec::new();
+        bc.borrow_slice(&si_data)?;
+        for ciov_ptr in si_data.iter() {
+            let ciov_ptr = ciov_ptr?;
+            let ciov: types::Ciovec = ciov_ptr.read()?;
+            let slice = unsafe {
+                let buf = ciov.buf.as_array(ciov.buf_len);
+                let raw = buf.as_raw(&mut bc)?;
+                &*raw
+            };
+            slices.push(io::IoSlice::new(slice));
+        }
+
+        let required_rights = EntryRights::from_base(types::Rights::FD_WRITE);
+        let entry = self.get_entry(fd)?;
+        let host_nwritten = entry
+            .as_handle(&required_rights)?
+            .sock_send(&slices, si_flags)?
+            .try_into()?;
+
+        Ok(host_nwritten)
     }
 
-    fn sock_shutdown(&self, _fd: types::Fd, _how: types::Sdflags) -> Result<()> {
-        unimplemented!("sock_shutdown")
+    fn sock_shutdown(&self, fd: types::Fd, how: types::Sdflags) -> Result<()> {
+        let required_rights = EntryRights::from_base(types::Rights::SOCK_SHUTDOWN);
+        let entry = self.get_entry(fd)?;
+        entry.as_handle(&required_rights)?.sock_shutdown(how)
     }
 }
@@ -20,3 +20,5 @@ cfg_if! {
 
 pub(crate) use sys_impl::path;
 pub(crate) use sys_impl::poll;
+#[cfg(unix)]
+pub(crate) use sys_impl::sock;
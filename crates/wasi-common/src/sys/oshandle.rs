@@ -1,3 +1,5 @@
+#[cfg(unix)]
+use super::sock;
 use super::{fd, path};
 use crate::entry::EntryRights;
 use crate::handle::Handle;
@@ -274,4 +276,24 @@ impl Handle for OsHandle {
     fn unlink_file(&self, path: &str) -> Result<()> {
         path::unlink_file(self.as_os_file()?, path)
     }
+    // SockOps
+    //
+    // On Windows, sockets are `OsSocket`s rather than `OsHandle`s, so these
+    // are left to fail with `Errno::Notsock` there.
+    #[cfg(unix)]
+    fn sock_recv(
+        &self,
+        iovs: &mut [io::IoSliceMut],
+        flags: types::Riflags,
+    ) -> Result<(usize, types::Roflags)> {
+        sock::recv(self, iovs, flags)
+    }
+    #[cfg(unix)]
+    fn sock_send(&self, iovs: &[io::IoSlice], flags: types::Siflags) -> Result<usize> {
+        sock::send(self, iovs, flags)
+    }
+    #[cfg(unix)]
+    fn sock_shutdown(&self, how: types::Sdflags) -> Result<()> {
+        sock::shutdown(self, how)
+    }
 }
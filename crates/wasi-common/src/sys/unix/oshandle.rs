@@ -1,3 +1,4 @@
+use crate::ctx::HostSocket;
 use crate::entry::EntryRights;
 use crate::sys::oshandle::{AsFile, OsHandle, OsHandleExt};
 use crate::wasi::{types, RightsExt};
@@ -32,6 +33,24 @@ impl From<File> for OsHandle {
     }
 }
 
+impl From<HostSocket> for OsHandle {
+    fn from(socket: HostSocket) -> Self {
+        let fd = match socket {
+            HostSocket::TcpListener(socket) => socket.into_raw_fd(),
+            HostSocket::TcpStream(socket) => socket.into_raw_fd(),
+            HostSocket::UdpSocket(socket) => socket.into_raw_fd(),
+            HostSocket::UnixListener(socket) => socket.into_raw_fd(),
+            HostSocket::UnixStream(socket) => socket.into_raw_fd(),
+        };
+        // A guest sending to a socket whose peer has gone away must get an
+        // error back rather than have `SIGPIPE` kill the host.
+        if let Err(e) = unsafe { yanix::socket::set_nosigpipe(fd) } {
+            log::warn!("unable to disable SIGPIPE on host fd {:?}: {}", fd, e);
+        }
+        Self::from(unsafe { OsFile::from_raw_fd(fd) })
+    }
+}
+
 impl OsHandleExt for OsHandle {
     fn get_file_type(&self) -> io::Result<types::Filetype> {
         let file = self.as_file();
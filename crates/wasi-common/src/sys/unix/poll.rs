@@ -12,6 +12,22 @@ pub(crate) fn oneoff(
     fd_events: Vec<FdEventData>,
     events: &mut Vec<types::Event>,
 ) -> Result<()> {
+    // Only OS resources can be polled.
+    let (fd_events, unsupported): (Vec<_>, Vec<_>) = fd_events
+        .into_iter()
+        .partition(|event| event.handle.as_any().is::<OsHandle>());
+    for event in unsupported {
+        events.push(types::Event {
+            userdata: event.userdata,
+            error: Errno::Notsup,
+            type_: event.r#type,
+            fd_readwrite: types::EventFdReadwrite {
+                nbytes: 0,
+                flags: types::Eventrwflags::empty(),
+            },
+        });
+    }
+
     if fd_events.is_empty() && timeout.is_none() {
         return Ok(());
     }
@@ -32,7 +48,7 @@ pub(crate) fn oneoff(
                 .handle
                 .as_any()
                 .downcast_ref::<OsHandle>()
-                .expect("can poll FdEvent for OS resources only");
+                .expect("non-OS resources were filtered out above");
             unsafe { PollFd::new(handle.as_raw_fd(), flags) }
         })
         .collect();
@@ -91,7 +107,13 @@ fn handle_fd_event(
                 return Ok(len - host_offset);
             }
         }
-        unsafe { Ok(fionread(handle.as_raw_fd())?.into()) }
+        match unsafe { fionread(handle.as_raw_fd()) } {
+            Ok(nbytes) => Ok(nbytes.into()),
+            // Listening sockets don't support `FIONREAD`; they're ready to read
+            // when there's a connection waiting to be accepted.
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => Ok(0),
+            Err(err) => Err(err.into()),
+        }
     }
 
     for (fd_event, poll_fd) in ready_events {
@@ -110,7 +132,7 @@ fn handle_fd_event(
                 .handle
                 .as_any()
                 .downcast_ref::<OsHandle>()
-                .expect("can poll FdEvent for OS resources only");
+                .expect("non-OS resources were filtered out above");
             query_nbytes(handle)?
         } else {
             0
@@ -0,0 +1,53 @@
+use super::super::oshandle::OsHandle;
+use crate::wasi::{types, Errno, Result};
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::prelude::AsRawFd;
+use yanix::socket::MsgFlags;
+
+pub(crate) fn recv(
+    handle: &OsHandle,
+    iovs: &mut [io::IoSliceMut],
+    flags: types::Riflags,
+) -> Result<(usize, types::Roflags)> {
+    let mut host_flags = MsgFlags::empty();
+    if flags.contains(&types::Riflags::RECV_PEEK) {
+        host_flags |= MsgFlags::PEEK;
+    }
+    if flags.contains(&types::Riflags::RECV_WAITALL) {
+        host_flags |= MsgFlags::WAITALL;
+    }
+    let (nread, msg_flags) =
+        unsafe { yanix::socket::recv_vectored(handle.as_raw_fd(), iovs, host_flags)? };
+    let mut roflags = types::Roflags::empty();
+    if msg_flags.contains(MsgFlags::TRUNC) {
+        roflags |= types::Roflags::RECV_DATA_TRUNCATED;
+    }
+    Ok((nread, roflags))
+}
+
+pub(crate) fn send(
+    handle: &OsHandle,
+    iovs: &[io::IoSlice],
+    _flags: types::Siflags,
+) -> Result<usize> {
+    // There are no `siflags` defined yet. `send_vectored` makes sure that
+    // sending to a peer which has gone away fails instead of raising `SIGPIPE`.
+    let nwritten =
+        unsafe { yanix::socket::send_vectored(handle.as_raw_fd(), iovs, MsgFlags::empty())? };
+    Ok(nwritten)
+}
+
+pub(crate) fn shutdown(handle: &OsHandle, how: types::Sdflags) -> Result<()> {
+    let how = if how == types::Sdflags::RD | types::Sdflags::WR {
+        Shutdown::Both
+    } else if how == types::Sdflags::RD {
+        Shutdown::Read
+    } else if how == types::Sdflags::WR {
+        Shutdown::Write
+    } else {
+        return Err(Errno::Inval);
+    };
+    unsafe { yanix::socket::shutdown(handle.as_raw_fd(), how)? };
+    Ok(())
+}
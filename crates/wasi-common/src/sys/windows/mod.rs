@@ -3,6 +3,7 @@ pub(crate) mod fd;
 pub(crate) mod oshandle;
 pub(crate) mod path;
 pub(crate) mod poll;
+pub(crate) mod sock;
 
 use crate::wasi::{types, Errno, Result};
 use std::convert::{TryFrom, TryInto};
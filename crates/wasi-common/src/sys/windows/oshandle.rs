@@ -1,11 +1,19 @@
+use super::sock;
+use crate::ctx::HostSocket;
 use crate::entry::EntryRights;
+use crate::handle::Handle;
 use crate::sys::oshandle::{AsFile, OsHandle, OsHandleExt};
-use crate::wasi::{types, RightsExt};
+use crate::wasi::{types, Result, RightsExt};
+use std::any::Any;
 use std::cell::Cell;
 use std::fs::{File, OpenOptions};
-use std::io;
+use std::io::{self, Read, Write};
 use std::mem::ManuallyDrop;
-use std::os::windows::prelude::{AsRawHandle, FromRawHandle, IntoRawHandle, RawHandle};
+use std::net::TcpStream;
+use std::os::windows::prelude::{
+    AsRawHandle, AsRawSocket, FromRawHandle, FromRawSocket, IntoRawHandle, IntoRawSocket,
+    RawHandle, RawSocket,
+};
 
 #[derive(Debug)]
 pub(crate) struct OsFile(Cell<RawHandle>);
@@ -88,6 +96,111 @@ impl From<File> for OsHandle {
     }
 }
 
+/// A socket handed to the guest.
+///
+/// Unlike on Unix, sockets can't be kept in an `OsFile`: a `SOCKET` has to be
+/// closed with `closesocket` rather than `CloseHandle`, and `GetFileType`
+/// can't tell sockets apart from pipes. So the socket type is remembered when
+/// the socket is preopened.
+#[derive(Debug)]
+pub(crate) struct OsSocket {
+    socket: RawSocket,
+    file_type: types::Filetype,
+}
+
+impl OsSocket {
+    /// Views the socket as a `TcpStream`.
+    ///
+    /// Only the socket-generic calls of the returned stream may be used, since
+    /// it may actually be a datagram socket.
+    pub(crate) fn as_stream(&self) -> ManuallyDrop<TcpStream> {
+        let stream = unsafe { TcpStream::from_raw_socket(self.socket) };
+        ManuallyDrop::new(stream)
+    }
+}
+
+impl Drop for OsSocket {
+    fn drop(&mut self) {
+        // Closes the socket with `closesocket`.
+        unsafe {
+            TcpStream::from_raw_socket(self.socket);
+        }
+    }
+}
+
+impl AsRawSocket for OsSocket {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket
+    }
+}
+
+impl From<HostSocket> for OsSocket {
+    fn from(socket: HostSocket) -> Self {
+        let (socket, file_type) = match socket {
+            HostSocket::TcpListener(socket) => {
+                (socket.into_raw_socket(), types::Filetype::SocketStream)
+            }
+            HostSocket::TcpStream(socket) => {
+                (socket.into_raw_socket(), types::Filetype::SocketStream)
+            }
+            HostSocket::UdpSocket(socket) => {
+                (socket.into_raw_socket(), types::Filetype::SocketDgram)
+            }
+        };
+        Self { socket, file_type }
+    }
+}
+
+impl Handle for OsSocket {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
+        let socket = self.as_stream().try_clone()?.into_raw_socket();
+        Ok(Box::new(Self {
+            socket,
+            file_type: self.file_type,
+        }))
+    }
+    fn get_file_type(&self) -> io::Result<types::Filetype> {
+        Ok(self.file_type)
+    }
+    fn get_rights(&self) -> io::Result<EntryRights> {
+        Ok(EntryRights::new(
+            types::Rights::socket_base(),
+            types::Rights::socket_inheriting(),
+        ))
+    }
+    // FdOps
+    fn fdstat_get(&self) -> Result<types::Fdflags> {
+        Ok(types::Fdflags::empty())
+    }
+    fn read_vectored(&self, iovs: &mut [io::IoSliceMut]) -> Result<usize> {
+        let mut stream: &TcpStream = &self.as_stream();
+        let nread = stream.read_vectored(iovs)?;
+        Ok(nread)
+    }
+    fn write_vectored(&self, iovs: &[io::IoSlice], _isatty: bool) -> Result<usize> {
+        let mut stream: &TcpStream = &self.as_stream();
+        let nwritten = stream.write_vectored(iovs)?;
+        Ok(nwritten)
+    }
+    // SockOps
+    fn sock_recv(
+        &self,
+        iovs: &mut [io::IoSliceMut],
+        flags: types::Riflags,
+    ) -> Result<(usize, types::Roflags)> {
+        sock::recv(self, iovs, flags)
+    }
+    fn sock_send(&self, iovs: &[io::IoSlice], flags: types::Siflags) -> Result<usize> {
+        sock::send(self, iovs, flags)
+    }
+    fn sock_shutdown(&self, how: types::Sdflags) -> Result<()> {
+        sock::shutdown(self, how)
+    }
+}
+
 impl OsHandleExt for OsHandle {
     fn get_file_type(&self) -> io::Result<types::Filetype> {
         let file_type = unsafe { winx::file::get_file_type(self.as_raw_handle())? };
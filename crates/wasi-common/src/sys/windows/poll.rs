@@ -1,11 +1,12 @@
-use super::super::oshandle::OsHandle;
+use super::super::oshandle::{OsHandle, OsSocket};
 use crate::poll::{ClockEventData, FdEventData};
 use crate::sys::oshandle::AsFile;
 use crate::wasi::{types, Errno, Result};
 use lazy_static::lazy_static;
 use log::{debug, error, trace, warn};
 use std::convert::TryInto;
-use std::os::windows::io::AsRawHandle;
+use std::io;
+use std::os::windows::io::{AsRawHandle, AsRawSocket, RawSocket};
 use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use std::sync::Mutex;
 use std::thread;
@@ -171,6 +172,64 @@ fn handle_rw_event(event: FdEventData, out_events: &mut Vec<types::Event>) {
     out_events.push(new_event);
 }
 
+/// Polls sockets with `WSAPoll`, waiting for at most `wait`, or indefinitely
+/// if it's `None`. Returns the number of sockets which were ready.
+fn poll_sockets(
+    socket_events: Vec<(RawSocket, FdEventData)>,
+    wait: Option<Duration>,
+    out_events: &mut Vec<types::Event>,
+) -> Result<usize> {
+    use winapi::um::winsock2::{
+        ioctlsocket, WSAGetLastError, WSAPoll, FIONREAD, POLLNVAL, POLLRDNORM, POLLWRNORM, SOCKET,
+        SOCKET_ERROR, WSAPOLLFD,
+    };
+
+    let mut poll_fds: Vec<_> = socket_events
+        .iter()
+        .map(|(socket, event)| WSAPOLLFD {
+            fd: *socket as SOCKET,
+            events: match event.r#type {
+                types::Eventtype::FdRead => POLLRDNORM,
+                _ => POLLWRNORM,
+            },
+            revents: 0,
+        })
+        .collect();
+    let timeout = wait.map_or(-1, |wait| {
+        wait.as_millis()
+            .try_into()
+            .unwrap_or(libc::c_int::max_value())
+    });
+    let ready = unsafe { WSAPoll(poll_fds.as_mut_ptr(), poll_fds.len().try_into()?, timeout) };
+    if ready == SOCKET_ERROR {
+        let err = unsafe { WSAGetLastError() };
+        return Err(io::Error::from_raw_os_error(err).into());
+    }
+
+    for ((socket, event), poll_fd) in socket_events.into_iter().zip(poll_fds) {
+        if poll_fd.revents == 0 {
+            continue;
+        }
+        if poll_fd.revents & POLLNVAL != 0 {
+            handle_error_event(event, Errno::Badf, out_events);
+            continue;
+        }
+        let nbytes = if event.r#type == types::Eventtype::FdRead {
+            let mut nbytes = 0;
+            if unsafe { ioctlsocket(socket as SOCKET, FIONREAD, &mut nbytes) } == SOCKET_ERROR {
+                let err = unsafe { WSAGetLastError() };
+                Err(io::Error::from_raw_os_error(err).into())
+            } else {
+                Ok(u64::from(nbytes))
+            }
+        } else {
+            Ok(0)
+        };
+        out_events.push(make_rw_event(&event, nbytes));
+    }
+    Ok(ready.try_into()?)
+}
+
 fn handle_error_event(event: FdEventData, error: Errno, out_events: &mut Vec<types::Event>) {
     let new_event = make_rw_event(&event, Err(error));
     out_events.push(new_event);
@@ -204,13 +263,21 @@ pub(crate) fn oneoff(
     let mut stdin_events = vec![];
     let mut immediate_events = vec![];
     let mut pipe_events = vec![];
+    let mut socket_events = vec![];
 
     for event in fd_events {
-        let handle = event
-            .handle
-            .as_any()
-            .downcast_ref::<OsHandle>()
-            .expect("can poll FdEvent for OS resources only");
+        if let Some(socket) = event.handle.as_any().downcast_ref::<OsSocket>() {
+            socket_events.push((socket.as_raw_socket(), event));
+            continue;
+        }
+        let handle = match event.handle.as_any().downcast_ref::<OsHandle>() {
+            Some(handle) => handle,
+            None => {
+                debug!("poll_oneoff: can poll FdEvent for OS resources only");
+                handle_error_event(event, Errno::Notsup, events);
+                continue;
+            }
+        };
         match handle {
             OsHandle::Stdin if event.r#type == types::Eventtype::FdRead => stdin_events.push(event),
             // stdout/stderr are always considered ready to write because there seems to
@@ -235,7 +302,7 @@ pub(crate) fn oneoff(
         };
     }
 
-    let immediate = !immediate_events.is_empty();
+    let mut immediate = !immediate_events.is_empty();
     // Process all the events that do not require waiting.
     if immediate {
         trace!("    | have immediate events, will return immediately");
@@ -243,6 +310,24 @@ pub(crate) fn oneoff(
             handle_rw_event(event, events);
         }
     }
+    let mut timed_out = false;
+    if !socket_events.is_empty() {
+        // Sockets are only waited on if nothing else is, since waiting for
+        // stdin happens on another thread.
+        let wait = if immediate || !stdin_events.is_empty() {
+            trace!("     | checking sockets");
+            Some(Duration::from_secs(0))
+        } else {
+            trace!("     | waiting on sockets");
+            timeout.map(|(_event, dur)| dur)
+        };
+        if poll_sockets(socket_events, wait, events)? > 0 {
+            immediate = true;
+        } else if !immediate && stdin_events.is_empty() {
+            handle_timeout_event(timeout.expect("timeout should not be None").0, events);
+            timed_out = true;
+        }
+    }
     if !stdin_events.is_empty() {
         // waiting for data to arrive on stdin. This thread will not terminate.
         //
@@ -284,7 +369,7 @@ pub(crate) fn oneoff(
         }
     }
 
-    if !immediate && !pipe_events.is_empty() {
+    if !immediate && !timed_out && !pipe_events.is_empty() {
         trace!("     | actively polling pipes");
         match timeout {
             Some((event, dur)) => {
@@ -0,0 +1,50 @@
+use super::oshandle::OsSocket;
+use crate::wasi::{types, Errno, Result};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+
+pub(crate) fn recv(
+    socket: &OsSocket,
+    iovs: &mut [io::IoSliceMut],
+    flags: types::Riflags,
+) -> Result<(usize, types::Roflags)> {
+    if flags.contains(&types::Riflags::RECV_WAITALL) {
+        return Err(Errno::Notsup);
+    }
+    let mut stream: &TcpStream = &socket.as_stream();
+    let nread = if flags.contains(&types::Riflags::RECV_PEEK) {
+        // Windows can't peek into multiple buffers at once.
+        match iovs.iter_mut().find(|iov| !iov.is_empty()) {
+            Some(iov) => stream.peek(iov)?,
+            None => 0,
+        }
+    } else {
+        stream.read_vectored(iovs)?
+    };
+    Ok((nread, types::Roflags::empty()))
+}
+
+pub(crate) fn send(
+    socket: &OsSocket,
+    iovs: &[io::IoSlice],
+    _flags: types::Siflags,
+) -> Result<usize> {
+    // There are no `siflags` defined yet.
+    let mut stream: &TcpStream = &socket.as_stream();
+    let nwritten = stream.write_vectored(iovs)?;
+    Ok(nwritten)
+}
+
+pub(crate) fn shutdown(socket: &OsSocket, how: types::Sdflags) -> Result<()> {
+    let how = if how == types::Sdflags::RD | types::Sdflags::WR {
+        Shutdown::Both
+    } else if how == types::Sdflags::RD {
+        Shutdown::Read
+    } else if how == types::Sdflags::WR {
+        Shutdown::Write
+    } else {
+        return Err(Errno::Inval);
+    };
+    socket.as_stream().shutdown(how)?;
+    Ok(())
+}
@@ -0,0 +1,390 @@
+//! Virtual directories backed by tar archives.
+//!
+//! An archive is indexed once, when it's loaded, and the contents of its files are then read
+//! straight from the archive's bytes. ustar archives are supported, along with the GNU long name
+//! and PAX extended headers used for long paths, large files and precise times.
+use crate::virtfs::{FileContents, VirtualDirEntry};
+use crate::wasi::{types, Errno, Result};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::rc::Rc;
+use std::str;
+
+const BLOCK_SIZE: usize = 512;
+
+impl VirtualDirEntry {
+    /// Indexes the tar archive `archive` as a tree of directories and files.
+    ///
+    /// Files are read directly from `archive`, which is kept alive by the tree, so it can be
+    /// memory mapped. They report the size and modification time recorded in the archive, while
+    /// directories report no times. Only regular files, hard links and directories are supported,
+    /// except that symbolic links are skipped, since virtual directories can't hold them.
+    ///
+    /// The tree is meant to be mounted read-only, with `WasiCtxBuilder::preopened_virt_with_rights`
+    /// and `EntryRights::read_only_dir()`. Writing to its files fails with `Errno::Rofs` either way.
+    pub fn from_tar<T: AsRef<[u8]> + 'static>(archive: T) -> io::Result<Self> {
+        let archive: Rc<dyn AsRef<[u8]>> = Rc::new(archive);
+        let data = (*archive).as_ref();
+        let mut root = HashMap::new();
+        // Files by path, to resolve hard links.
+        let mut files: HashMap<String, TarFileContents> = HashMap::new();
+        // Metadata from GNU long name and PAX headers, which applies to the next entry.
+        let mut ext = Extensions::default();
+        let mut offset = 0;
+
+        while offset + BLOCK_SIZE <= data.len() {
+            let header = &data[offset..offset + BLOCK_SIZE];
+            if header.iter().all(|&b| b == 0) {
+                break;
+            }
+            verify_checksum(header)?;
+
+            let typeflag = header[156];
+            let is_extension = match typeflag {
+                b'L' | b'K' | b'x' | b'g' => true,
+                _ => false,
+            };
+            let size = match ext.size {
+                Some(size) if !is_extension => size,
+                _ => number(&header[124..136])?,
+            };
+            let start = offset + BLOCK_SIZE;
+            let end = size
+                .try_into()
+                .ok()
+                .and_then(|size: usize| start.checked_add(size))
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| invalid("the archive is truncated"))?;
+            offset = end + (BLOCK_SIZE - end % BLOCK_SIZE) % BLOCK_SIZE;
+            let contents = &data[start..end];
+
+            match typeflag {
+                b'L' => ext.path = Some(utf8(c_str(contents))?.to_owned()),
+                b'K' => ext.link_path = Some(utf8(c_str(contents))?.to_owned()),
+                b'x' => ext.parse_pax(contents)?,
+                // Global PAX headers only hold metadata which doesn't apply to virtual files.
+                b'g' => {}
+                _ => {
+                    let path = match ext.path.take() {
+                        Some(path) => path,
+                        None => ustar_path(header)?,
+                    };
+                    let mtime = match ext.mtime.take() {
+                        Some(mtime) => mtime,
+                        None => number(&header[136..148])?.saturating_mul(1_000_000_000),
+                    };
+                    let link_path = ext.link_path.take();
+                    ext.size = None;
+                    let path = normalize(&path)?;
+                    match typeflag {
+                        b'0' | b'\0' | b'7' => {
+                            let file = TarFileContents {
+                                archive: Rc::clone(&archive),
+                                start,
+                                size: end - start,
+                                mtime,
+                            };
+                            insert(&mut root, &path, Self::File(Box::new(file.clone())))?;
+                            files.insert(path, file);
+                        }
+                        b'1' => {
+                            let target = match link_path {
+                                Some(target) => target,
+                                None => utf8(c_str(&header[157..257]))?.to_owned(),
+                            };
+                            let file =
+                                files.get(&normalize(&target)?).cloned().ok_or_else(|| {
+                                    invalid(format!("{} links to a missing file {}", path, target))
+                                })?;
+                            insert(&mut root, &path, Self::File(Box::new(file.clone())))?;
+                            files.insert(path, file);
+                        }
+                        b'5' => insert(&mut root, &path, Self::empty_directory())?,
+                        b'2' => log::debug!("skipping the symlink {} in a tar archive", path),
+                        _ => {
+                            return Err(invalid(format!(
+                                "{} has the unsupported type {:?}",
+                                path, typeflag as char
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self::Directory(root))
+    }
+}
+
+/// The contents of a file in a tar archive.
+#[derive(Clone)]
+struct TarFileContents {
+    archive: Rc<dyn AsRef<[u8]>>,
+    start: usize,
+    size: usize,
+    mtime: types::Timestamp,
+}
+
+impl TarFileContents {
+    fn bytes(&self) -> &[u8] {
+        &(*self.archive).as_ref()[self.start..self.start + self.size]
+    }
+}
+
+impl FileContents for TarFileContents {
+    fn max_size(&self) -> types::Filesize {
+        self.size as types::Filesize
+    }
+
+    fn size(&self) -> types::Filesize {
+        self.size as types::Filesize
+    }
+
+    fn resize(&mut self, _new_size: types::Filesize) -> Result<()> {
+        Err(Errno::Rofs)
+    }
+
+    fn preadv(&self, iovs: &mut [io::IoSliceMut], offset: types::Filesize) -> Result<usize> {
+        let mut read_total = 0usize;
+        for iov in iovs.iter_mut() {
+            let read = self.pread(iov, offset + read_total as types::Filesize)?;
+            read_total += read;
+            if read < iov.len() {
+                break;
+            }
+        }
+        Ok(read_total)
+    }
+
+    fn pwritev(&mut self, _iovs: &[io::IoSlice], _offset: types::Filesize) -> Result<usize> {
+        Err(Errno::Rofs)
+    }
+
+    fn pread(&self, buf: &mut [u8], offset: types::Filesize) -> Result<usize> {
+        let bytes = self.bytes();
+        let offset: usize = offset.try_into().map_err(|_| Errno::Inval)?;
+        let remaining = bytes.get(offset..).unwrap_or(&[]);
+        let read_count = std::cmp::min(buf.len(), remaining.len());
+        buf[..read_count].copy_from_slice(&remaining[..read_count]);
+        Ok(read_count)
+    }
+
+    fn pwrite(&mut self, _buf: &[u8], _offset: types::Filesize) -> Result<usize> {
+        Err(Errno::Rofs)
+    }
+
+    fn mtime(&self) -> types::Timestamp {
+        self.mtime
+    }
+}
+
+/// Metadata of the next entry from extension headers, which overrides its ustar header.
+#[derive(Default)]
+struct Extensions {
+    path: Option<String>,
+    link_path: Option<String>,
+    size: Option<u64>,
+    mtime: Option<types::Timestamp>,
+}
+
+impl Extensions {
+    /// Parses PAX records, which look like `"<length> <key>=<value>\n"`.
+    fn parse_pax(&mut self, mut records: &[u8]) -> io::Result<()> {
+        let bad_record = || invalid("bad PAX extended header");
+        while !records.is_empty() {
+            let space = records
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(bad_record)?;
+            let len: usize = str::from_utf8(&records[..space])
+                .ok()
+                .and_then(|len| len.parse().ok())
+                .filter(|&len| len > space && len <= records.len())
+                .ok_or_else(bad_record)?;
+            let mut record = &records[space + 1..len];
+            if record.last() == Some(&b'\n') {
+                record = &record[..record.len() - 1];
+            }
+            let eq = record
+                .iter()
+                .position(|&b| b == b'=')
+                .ok_or_else(bad_record)?;
+            let value = utf8(&record[eq + 1..])?;
+            match &record[..eq] {
+                b"path" => self.path = Some(value.to_owned()),
+                b"linkpath" => self.link_path = Some(value.to_owned()),
+                b"size" => self.size = Some(value.parse().map_err(|_| bad_record())?),
+                b"mtime" => self.mtime = Some(pax_time(value).ok_or_else(bad_record)?),
+                _ => {}
+            }
+            records = &records[len..];
+        }
+        Ok(())
+    }
+}
+
+/// Parses a PAX time, which is in seconds with an optional fraction, to nanoseconds. Times before
+/// the epoch are clamped to it.
+fn pax_time(value: &str) -> Option<types::Timestamp> {
+    let (secs, fraction) = match value.find('.') {
+        Some(dot) => (&value[..dot], &value[dot + 1..]),
+        None => (value, ""),
+    };
+    if secs.starts_with('-') {
+        return Some(0);
+    }
+    let secs: u64 = secs.parse().ok()?;
+    let mut nanos = 0;
+    for (i, digit) in fraction.bytes().enumerate() {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        if i < 9 {
+            nanos += u64::from(digit - b'0') * 10u64.pow(8 - i as u32);
+        }
+    }
+    Some(secs.saturating_mul(1_000_000_000).saturating_add(nanos))
+}
+
+/// Checks the checksum of a header, which is the sum of its bytes with the checksum field itself
+/// counted as spaces. Some old archivers summed signed bytes, so that's accepted too.
+fn verify_checksum(header: &[u8]) -> io::Result<()> {
+    let expected = number(&header[148..156])?;
+    let (mut unsigned, mut signed) = (0u64, 0i64);
+    for (i, &b) in header.iter().enumerate() {
+        let b = if (148..156).contains(&i) { b' ' } else { b };
+        unsigned += u64::from(b);
+        signed += i64::from(b as i8);
+    }
+    if expected != unsigned && expected as i64 != signed {
+        return Err(invalid(
+            "bad header checksum, this may not be a tar archive",
+        ));
+    }
+    Ok(())
+}
+
+/// Parses a numeric header field, which is octal text, or big-endian binary with the high bit of
+/// the first byte set for values which don't fit (a GNU extension).
+fn number(field: &[u8]) -> io::Result<u64> {
+    let bad_number = || invalid("bad number in a header");
+    if field[0] & 0x80 != 0 {
+        let mut n = u64::from(field[0] & 0x7f);
+        for &b in &field[1..] {
+            n = n
+                .checked_mul(256)
+                .and_then(|n| n.checked_add(u64::from(b)))
+                .ok_or_else(bad_number)?;
+        }
+        return Ok(n);
+    }
+    let text = utf8(field)
+        .map_err(|_| bad_number())?
+        .trim_matches(|c| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| bad_number())
+}
+
+/// The path of a ustar header, which may be split into a prefix and a name.
+///
+/// Old GNU headers, whose magic is `"ustar  \0"`, keep other fields where the prefix would be.
+fn ustar_path(header: &[u8]) -> io::Result<String> {
+    let name = utf8(c_str(&header[..100]))?;
+    let prefix = if &header[257..263] == b"ustar\0" {
+        utf8(c_str(&header[345..500]))?
+    } else {
+        ""
+    };
+    Ok(if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", prefix, name)
+    })
+}
+
+/// Makes a path relative to the root of the archive, without `.` components or trailing slashes.
+fn normalize(path: &str) -> io::Result<String> {
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return Err(invalid(format!("{} is outside of the archive", path))),
+            _ => components.push(component),
+        }
+    }
+    Ok(components.join("/"))
+}
+
+/// Adds `entry` at the normalized `path`, creating its parent directories as needed.
+fn insert(
+    root: &mut HashMap<String, VirtualDirEntry>,
+    path: &str,
+    entry: VirtualDirEntry,
+) -> io::Result<()> {
+    let is_dir = |entry: &VirtualDirEntry| match entry {
+        VirtualDirEntry::Directory(_) => true,
+        VirtualDirEntry::File(_) => false,
+    };
+    let not_a_dir = || invalid(format!("{} is inside of a file", path));
+
+    if path.is_empty() {
+        // The root directory itself.
+        return if is_dir(&entry) {
+            Ok(())
+        } else {
+            Err(not_a_dir())
+        };
+    }
+    let mut components = path.rsplitn(2, '/');
+    let name = components.next().unwrap();
+    let mut dir = root;
+    for component in components
+        .next()
+        .into_iter()
+        .flat_map(|parent| parent.split('/'))
+    {
+        dir = match dir
+            .entry(component.to_owned())
+            .or_insert_with(VirtualDirEntry::empty_directory)
+        {
+            VirtualDirEntry::Directory(entries) => entries,
+            VirtualDirEntry::File(_) => return Err(not_a_dir()),
+        };
+    }
+    match dir.entry(name.to_owned()) {
+        Entry::Vacant(v) => {
+            v.insert(entry);
+        }
+        Entry::Occupied(mut o) => match (is_dir(o.get()), is_dir(&entry)) {
+            // Later entries replace earlier files, like when extracting the archive.
+            (false, false) => {
+                o.insert(entry);
+            }
+            // Directories may be listed after their contents, or more than once.
+            (true, true) => {}
+            _ => return Err(invalid(format!("{} is both a file and a directory", path))),
+        },
+    }
+    Ok(())
+}
+
+/// The bytes of `field` up to its first NUL.
+fn c_str(field: &[u8]) -> &[u8] {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..len]
+}
+
+fn utf8(bytes: &[u8]) -> io::Result<&str> {
+    str::from_utf8(bytes).map_err(|_| invalid("a path in the archive isn't valid UTF-8"))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid tar archive: {}", message.into()),
+    )
+}
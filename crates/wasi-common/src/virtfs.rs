@@ -4,8 +4,8 @@ use crate::wasi::{self, types, Errno, Result, RightsExt};
 use log::trace;
 use std::any::Any;
 use std::cell::{Cell, RefCell};
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::io;
 use std::io::SeekFrom;
@@ -53,6 +53,11 @@ pub trait FileContents {
     /// Read from the file at `offset`, filling `buf`. The returned size must not be more than the
     /// capacity of `buf`, and `offset` plus the returned size must not exceed `self.max_size()`.
     fn pread(&self, buf: &mut [u8], offset: types::Filesize) -> Result<usize>;
+    /// The time of the last modification of the contents, in nanoseconds since the Unix epoch, or
+    /// 0 if it isn't known.
+    fn mtime(&self) -> types::Timestamp {
+        0
+    }
 }
 
 impl FileContents for VecFileContents {
@@ -219,14 +224,15 @@ impl Handle for InMemoryFile {
         Ok(())
     }
     fn filestat_get(&self) -> Result<types::Filestat> {
+        let data = self.data.borrow();
         let stat = types::Filestat {
             dev: 0,
             ino: 0,
             nlink: 0,
-            size: self.data.borrow().size(),
-            atim: 0,
-            ctim: 0,
-            mtim: 0,
+            size: data.size(),
+            atim: data.mtime(),
+            ctim: data.mtime(),
+            mtim: data.mtime(),
             filetype: self.get_file_type()?,
         };
         Ok(stat)
@@ -401,12 +407,15 @@ impl Handle for InMemoryFile {
 }
 
 /// A clonable read/write directory.
+///
+/// Entries are kept sorted by name, so `readdir` lists them in the same order every time and
+/// cookies stay valid as long as no entry is added or removed.
 pub struct VirtualDir {
     writable: bool,
     // All copies of this `VirtualDir` must share `parent`, and changes in one copy's `parent`
     // must be reflected in all handles, so they share `Rc` of an underlying `parent`.
     parent: Rc<RefCell<Option<Box<dyn Handle>>>>,
-    entries: Rc<RefCell<HashMap<PathBuf, Box<dyn Handle>>>>,
+    entries: Rc<RefCell<BTreeMap<PathBuf, Box<dyn Handle>>>>,
 }
 
 impl VirtualDir {
@@ -414,7 +423,7 @@ impl VirtualDir {
         Self {
             writable,
             parent: Rc::new(RefCell::new(None)),
-            entries: Rc::new(RefCell::new(HashMap::new())),
+            entries: Rc::new(RefCell::new(BTreeMap::new())),
         }
     }
 
@@ -502,7 +511,7 @@ impl Handle for VirtualDir {
     ) -> Result<Box<dyn Iterator<Item = Result<(types::Dirent, String)>>>> {
         struct VirtualDirIter {
             start: u32,
-            entries: Rc<RefCell<HashMap<PathBuf, Box<dyn Handle>>>>,
+            entries: Rc<RefCell<BTreeMap<PathBuf, Box<dyn Handle>>>>,
         }
         impl Iterator for VirtualDirIter {
             type Item = Result<(types::Dirent, String)>;
@@ -534,7 +543,7 @@ impl Handle for VirtualDir {
 
                 let entries = self.entries.borrow();
 
-                // Adjust `start` to be an appropriate number of HashMap entries.
+                // Adjust `start` to be an appropriate number of entries.
                 let start = self.start - RESERVED_ENTRY_COUNT;
                 if start as usize >= entries.len() {
                     return None;
@@ -47,6 +47,16 @@ pub fn define_struct(args: TokenStream) -> TokenStream {
                 linker.define(#module_name, #name, self.#name_ident.clone())?;
             });
 
+            // `proc_exit` unwinds the stack with a trap instead of returning,
+            // which wasi-common can't do itself, so it's implemented in the
+            // `wasmtime-wasi` crate.
+            if name == "proc_exit" {
+                ctor_externs.push(quote! {
+                    let #name_ident = wasmtime::Func::wrap(store, crate::wasi_proc_exit);
+                });
+                continue;
+            }
+
             let mut shim_arg_decls = Vec::new();
             let mut params = Vec::new();
             let mut formats = Vec::new();
@@ -226,6 +236,7 @@ pub fn define_struct(args: TokenStream) -> TokenStream {
         /// can be used to do name-based resolution.
         pub struct Wasi {
             #(#fields,)*
+            cx: std::rc::Rc<std::cell::RefCell<WasiCtx>>,
         }
 
         impl Wasi {
@@ -240,9 +251,19 @@ pub fn define_struct(args: TokenStream) -> TokenStream {
 
                 Wasi {
                     #(#ctor_fields,)*
+                    cx,
                 }
             }
 
+            /// Returns the `WasiCtx` the guest uses, for example to read its
+            /// `WasiCtx::io_stats()`.
+            ///
+            /// This panics if called from within a call to one of the wasi
+            /// functions.
+            pub fn ctx(&self) -> std::cell::Ref<'_, WasiCtx> {
+                self.cx.borrow()
+            }
+
             /// Looks up a field called `name` in this structure, returning it
             /// if found.
             ///
@@ -292,14 +313,51 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
                 linker.define(#module_name, #name, self.#name_ident.clone())?;
             });
 
+            // `proc_exit` unwinds the stack with a trap instead of returning,
+            // which wasi-common can't do itself, so it's implemented in the
+            // `wasmtime-wasi` crate.
+            if name == "proc_exit" {
+                let status = utils::param_name(&func.params[0]);
+                let strace_arg = strace_arg(&func.params[0]);
+                ctor_externs.push(quote! {
+                    let my_trace = trace.clone();
+                    let my_strace = strace.as_ref().filter(|s| s.traces(#name)).cloned();
+                    let #name_ident = wasmtime::Func::wrap(
+                        store,
+                        move |#status: i32| -> Result<(), wasmtime::Trap> {
+                            let exit = || {
+                                if let Some(trace) = &my_trace {
+                                    unsafe {
+                                        trace.hostcall(#name, &[i64::from(#status)], None, |_| 0)?;
+                                    }
+                                }
+                                crate::wasi_proc_exit(#status).map(|()| 0)
+                            };
+                            let result = match &my_strace {
+                                Some(strace) => unsafe {
+                                    strace.call(#name, &[], &[#strace_arg], &[], None, exit)
+                                },
+                                None => exit(),
+                            };
+                            result.map(drop)
+                        }
+                    );
+                });
+                continue;
+            }
+
             let mut shim_arg_decls = Vec::new();
             let mut params = Vec::new();
             let mut formats = Vec::new();
             let mut format_args = Vec::new();
             let mut hostcall_args = Vec::new();
+            let mut trace_args = Vec::new();
+            let mut strace_args = Vec::new();
+            let mut strace_outs = Vec::new();
 
             for param in func.params.iter() {
                 let name = utils::param_name(param);
+                strace_args.push(strace_arg(param));
 
                 // Registers a new parameter to the shim we're making with the
                 // given `name`, the `abi_ty` wasm type and `hex` defines
@@ -312,23 +370,28 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
                 // * How to log the parameter value in a call to `trace!`
                 // * How to actually pass this argument to the host
                 //   implementation, converting as necessary.
+                // * How to record the argument in a trace, as an `i64`.
                 let mut add_param = |name: &Ident, abi_ty: Abi, hex: bool| {
                     match abi_ty {
                         Abi::I32 => {
                             params.push(quote! { types::I32 });
                             shim_arg_decls.push(quote! { #name: i32 });
+                            trace_args.push(quote! { i64::from(#name) });
                         }
                         Abi::I64 => {
                             params.push(quote! { types::I64 });
                             shim_arg_decls.push(quote! { #name: i64 });
+                            trace_args.push(quote! { #name });
                         }
                         Abi::F32 => {
                             params.push(quote! { types::F32 });
                             shim_arg_decls.push(quote! { #name: f32 });
+                            trace_args.push(quote! { i64::from(#name.to_bits()) });
                         }
                         Abi::F64 => {
                             params.push(quote! { types::F64 });
                             shim_arg_decls.push(quote! { #name: f64 });
+                            trace_args.push(quote! { #name.to_bits() as i64 });
                         }
                     }
                     formats.push(format!("{}={}", name, if hex { "{:#x}" } else { "{}" },));
@@ -396,30 +459,24 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
                 }
             }
 
+            // The first result is returned bare right now, and it's always an
+            // errno since traces record results as an `i32`...
             let mut results = func.results.iter();
-            let mut ret_ty = quote! { () };
-            let mut cvt_ret = quote! {};
-            let mut returns = Vec::new();
-            let mut handle_early_error = quote! { panic!("error: {:?}", e) };
-
-            // The first result is returned bare right now...
-            if let Some(ret) = results.next() {
-                handle_early_error = quote! { return e.into() };
-                match &*ret.tref.type_() {
-                    // Eventually we'll want to add support for more returned
-                    // types, but for now let's just conform to what `*.witx`
-                    // definitions currently use.
-                    witx::Type::Enum(e) => match e.repr {
-                        witx::IntRepr::U16 => {
-                            returns.push(quote! { types::I32 });
-                            ret_ty = quote! { i32 };
-                            cvt_ret = quote! { .into() }
-                        }
-                        other => panic!("unsupported ret enum repr {:?}", other),
-                    },
-                    other => panic!("unsupported first return {:?}", other),
-                }
-            }
+            let errnos = match results.next().map(|ret| ret.tref.type_()).as_deref() {
+                // Eventually we'll want to add support for more returned
+                // types, but for now let's just conform to what `*.witx`
+                // definitions currently use.
+                Some(witx::Type::Enum(e)) => match e.repr {
+                    witx::IntRepr::U16 => e
+                        .variants
+                        .iter()
+                        .map(|v| v.name.as_str().to_string())
+                        .collect::<Vec<_>>(),
+                    other => panic!("unsupported ret enum repr {:?}", other),
+                },
+                Some(other) => panic!("unsupported first return {:?}", other),
+                None => panic!("`{}` doesn't return an errno", name),
+            };
 
             // ... and all remaining results are returned via out-poiners
             for result in results {
@@ -429,14 +486,18 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
                 formats.push(format!("{}={{:#x}}", name));
                 format_args.push(name.clone());
                 hostcall_args.push(quote! { #name });
+                trace_args.push(quote! { i64::from(#name) });
+                strace_outs.push(strace_out(result));
             }
 
             let format_str = format!("{}({})", name, formats.join(", "));
             ctor_externs.push(quote! {
                 let my_cx = cx.clone();
+                let my_trace = trace.clone();
+                let my_strace = strace.as_ref().filter(|s| s.traces(#name)).cloned();
                 let #name_ident = wasmtime::Func::wrap(
                     store,
-                    move |caller: wasmtime::Caller<'_> #(,#shim_arg_decls)*| -> #ret_ty {
+                    move |caller: wasmtime::Caller<'_> #(,#shim_arg_decls)*| -> Result<i32, wasmtime::Trap> {
                         log::trace!(
                             #format_str,
                             #(#format_args),*
@@ -446,15 +507,38 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
                                 Some(wasmtime::Extern::Memory(m)) => m,
                                 _ => {
                                     let e = wasi_common::wasi::Errno::Inval;
-                                    #handle_early_error
+                                    return Ok(e.into());
                                 }
                             };
-                            let mem: WasiMemory = mem.into();
-                            wasi_common::wasi::#module_id::#name_ident(
-                                &mut my_cx.borrow_mut(),
-                                &mem,
-                                #(#hostcall_args),*
-                            ) #cvt_ret
+                            // Traces give the hostcall a guest memory which
+                            // records what it accesses.
+                            let hostcall = |guest: Option<&dyn wiggle::GuestMemory>| {
+                                wasi_common::wasi::#module_id::#name_ident(
+                                    &mut my_cx.borrow_mut(),
+                                    guest.unwrap_or(&mem),
+                                    #(#hostcall_args),*
+                                ).into()
+                            };
+                            let call = || match &my_trace {
+                                Some(trace) => trace.hostcall(
+                                    #name,
+                                    &[#(#trace_args),*],
+                                    Some(&mem),
+                                    hostcall,
+                                ),
+                                None => Ok(hostcall(None)),
+                            };
+                            match &my_strace {
+                                Some(strace) => strace.call(
+                                    #name,
+                                    &[#(#errnos),*],
+                                    &[#(#strace_args),*],
+                                    &[#(#strace_outs),*],
+                                    Some(&mem),
+                                    call,
+                                ),
+                                None => call(),
+                            }
                         }
                     }
                 );
@@ -463,23 +547,6 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
     }
 
     quote! {
-        /// Lightweight `wasmtime::Memory` wrapper so that we can
-        /// implement `wiggle::GuestMemory` trait on it which is
-        /// now required to interface with `wasi-common`.
-        struct WasiMemory(wasmtime::Memory);
-
-        impl From<wasmtime::Memory> for WasiMemory {
-            fn from(mem: wasmtime::Memory) -> Self {
-                Self(mem)
-            }
-        }
-
-        unsafe impl wiggle::GuestMemory for WasiMemory {
-            fn base(&self) -> (*mut u8, u32) {
-                (self.0.data_ptr(), self.0.data_size() as _)
-            }
-        }
-
         /// An instantiated instance of the wasi exports.
         ///
         /// This represents a wasi module which can be used to instantiate other
@@ -489,6 +556,7 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
         /// can be used to do name-based resolution.
         pub struct Wasi {
             #(#fields,)*
+            cx: std::rc::Rc<std::cell::RefCell<WasiCtx>>,
         }
 
         impl Wasi {
@@ -498,14 +566,43 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
             /// configuration of the wasi instance itself should be all
             /// contained in the `cx` parameter.
             pub fn new(store: &wasmtime::Store, cx: WasiCtx) -> Wasi {
+                Wasi::with_tracing(store, cx, None, None)
+            }
+
+            /// Creates a new [`Wasi`] instance which records all the calls
+            /// made to it into `trace`, or replays them from `trace` without
+            /// using `cx` at all.
+            pub fn with_trace(store: &wasmtime::Store, cx: WasiCtx, trace: crate::Trace) -> Wasi {
+                Wasi::with_tracing(store, cx, Some(trace), None)
+            }
+
+            /// Creates a new [`Wasi`] instance which optionally records or
+            /// replays its calls with `trace`, like [`Wasi::with_trace`], and
+            /// prints them with `strace`.
+            pub fn with_tracing(
+                store: &wasmtime::Store,
+                cx: WasiCtx,
+                trace: Option<crate::Trace>,
+                strace: Option<crate::Strace>,
+            ) -> Wasi {
                 let cx = std::rc::Rc::new(std::cell::RefCell::new(cx));
                 #(#ctor_externs)*
 
                 Wasi {
                     #(#ctor_fields,)*
+                    cx,
                 }
             }
 
+            /// Returns the `WasiCtx` the guest uses, for example to read its
+            /// `WasiCtx::io_stats()`.
+            ///
+            /// This panics if called from within a call to one of the wasi
+            /// functions.
+            pub fn ctx(&self) -> std::cell::Ref<'_, WasiCtx> {
+                self.cx.borrow()
+            }
+
             /// Looks up a field called `name` in this structure, returning it
             /// if found.
             ///
@@ -526,3 +623,113 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
         }
     }
 }
+
+/// Builds the `Arg` which `wasmtime_wasi::Strace` decodes the shim argument
+/// for `param` with.
+fn strace_arg(param: &witx::InterfaceFuncParam) -> TokenStream {
+    let name = utils::param_name(param);
+    let display = param.name.as_str();
+    let uint = quote! { i64::from(#name as u32) };
+    let int = |repr: &witx::IntRepr| match repr {
+        witx::IntRepr::U64 => quote! { #name },
+        _ => uint.clone(),
+    };
+    let mut len = quote! { 0 };
+    let (value, show) = match &*param.tref.type_() {
+        witx::Type::Int(e) => (int(&e.repr), quote! { UInt }),
+        witx::Type::Enum(e) => {
+            let names = e.variants.iter().map(|v| v.name.as_str());
+            (int(&e.repr), quote! { Enum(&[#(#names),*]) })
+        }
+        witx::Type::Flags(f) => {
+            let names = f.flags.iter().map(|f| f.name.as_str());
+            (int(&f.repr), quote! { Flags(&[#(#names),*]) })
+        }
+        witx::Type::Builtin(witx::BuiltinType::S8)
+        | witx::Type::Builtin(witx::BuiltinType::S16)
+        | witx::Type::Builtin(witx::BuiltinType::S32) => {
+            (quote! { i64::from(#name) }, quote! { Int })
+        }
+        witx::Type::Builtin(witx::BuiltinType::S64) => (quote! { #name }, quote! { Int }),
+        witx::Type::Builtin(witx::BuiltinType::U64) => (quote! { #name }, quote! { UInt }),
+        witx::Type::Builtin(witx::BuiltinType::F32) => {
+            (quote! { i64::from(#name.to_bits()) }, quote! { Hex })
+        }
+        witx::Type::Builtin(witx::BuiltinType::F64) => {
+            (quote! { #name.to_bits() as i64 }, quote! { Hex })
+        }
+        witx::Type::Builtin(witx::BuiltinType::String) => {
+            let len_name = format_ident!("{}_len", name);
+            len = quote! { #len_name as u32 };
+            (uint, quote! { Str })
+        }
+        witx::Type::Builtin(_) | witx::Type::Handle(_) => (uint, quote! { UInt }),
+        witx::Type::Array(tref) => {
+            let len_name = format_ident!("{}_len", name);
+            len = quote! { #len_name as u32 };
+            let show = match tref {
+                witx::TypeRef::Name(nt) => match nt.name.as_str() {
+                    "iovec" | "ciovec" => quote! { Iovecs },
+                    _ => quote! { Array },
+                },
+                _ => quote! { Array },
+            };
+            (uint, show)
+        }
+        witx::Type::ConstPointer(_) | witx::Type::Pointer(_) => (uint, quote! { Hex }),
+        witx::Type::Struct(_) | witx::Type::Union(_) => panic!("unsupported argument type"),
+    };
+    quote! {
+        crate::strace::Arg {
+            name: #display,
+            value: #value,
+            len: #len,
+            show: crate::strace::Show::#show,
+        }
+    }
+}
+
+/// Builds the `Out` which `wasmtime_wasi::Strace` decodes the out-pointer
+/// `result` from.
+fn strace_out(result: &witx::InterfaceFuncParam) -> TokenStream {
+    let name = format_ident!("{}", result.name.as_str());
+    let display = result.name.as_str();
+    let size = |repr: &witx::IntRepr| match repr {
+        witx::IntRepr::U8 => 1u32,
+        witx::IntRepr::U16 => 2,
+        witx::IntRepr::U32 => 4,
+        witx::IntRepr::U64 => 8,
+    };
+    let (size, show) = match &*result.tref.type_() {
+        witx::Type::Int(e) => (size(&e.repr), quote! { UInt }),
+        witx::Type::Enum(e) => {
+            let names = e.variants.iter().map(|v| v.name.as_str());
+            (size(&e.repr), quote! { Enum(&[#(#names),*]) })
+        }
+        witx::Type::Flags(f) => {
+            let names = f.flags.iter().map(|f| f.name.as_str());
+            (size(&f.repr), quote! { Flags(&[#(#names),*]) })
+        }
+        witx::Type::Builtin(witx::BuiltinType::Char8)
+        | witx::Type::Builtin(witx::BuiltinType::S8)
+        | witx::Type::Builtin(witx::BuiltinType::U8) => (1, quote! { UInt }),
+        witx::Type::Builtin(witx::BuiltinType::S16)
+        | witx::Type::Builtin(witx::BuiltinType::U16) => (2, quote! { UInt }),
+        witx::Type::Builtin(witx::BuiltinType::S32)
+        | witx::Type::Builtin(witx::BuiltinType::U32)
+        | witx::Type::Builtin(witx::BuiltinType::USize)
+        | witx::Type::Handle(_) => (4, quote! { UInt }),
+        witx::Type::Builtin(witx::BuiltinType::S64)
+        | witx::Type::Builtin(witx::BuiltinType::U64) => (8, quote! { UInt }),
+        // Structs and such are only printed as a pointer.
+        _ => (0, quote! { Hex }),
+    };
+    quote! {
+        crate::strace::Out {
+            name: #display,
+            ptr: #name as u32,
+            size: #size,
+            show: crate::strace::Show::#show,
+        }
+    }
+}
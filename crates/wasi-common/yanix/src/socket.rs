@@ -1,6 +1,10 @@
-use crate::from_success_code;
-use std::io::Result;
+use crate::{from_result, from_success_code};
+use bitflags::bitflags;
+use cfg_if::cfg_if;
+use std::io::{IoSlice, IoSliceMut, Result};
+use std::net::Shutdown;
 use std::os::unix::prelude::*;
+use std::{convert::TryInto, mem};
 
 #[derive(Debug, Clone, Copy)]
 #[repr(i32)]
@@ -13,7 +17,7 @@ pub enum SockType {
 }
 
 pub unsafe fn get_socket_type(fd: RawFd) -> Result<SockType> {
-    use std::mem::{self, MaybeUninit};
+    use std::mem::MaybeUninit;
     let mut buffer = MaybeUninit::<SockType>::zeroed().assume_init();
     let mut out_len = mem::size_of::<SockType>() as libc::socklen_t;
     from_success_code(libc::getsockopt(
@@ -30,3 +34,90 @@ pub unsafe fn get_socket_type(fd: RawFd) -> Result<SockType> {
     );
     Ok(buffer)
 }
+
+bitflags! {
+    pub struct MsgFlags: libc::c_int {
+        const PEEK = libc::MSG_PEEK;
+        const TRUNC = libc::MSG_TRUNC;
+        const WAITALL = libc::MSG_WAITALL;
+    }
+}
+
+/// Receives a message from the socket into the given buffers, returning the
+/// number of bytes received and the flags set on the received message.
+pub unsafe fn recv_vectored(
+    fd: RawFd,
+    iovs: &mut [IoSliceMut],
+    flags: MsgFlags,
+) -> Result<(usize, MsgFlags)> {
+    // `IoSliceMut` is guaranteed to be ABI compatible with `iovec` on Unix.
+    let mut msg: libc::msghdr = mem::zeroed();
+    msg.msg_iov = iovs.as_mut_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = iovs.len() as _;
+    let nread = from_result(libc::recvmsg(fd, &mut msg, flags.bits()))?;
+    // When recvmsg doesn't fail, its return value is non-negative, so we can
+    // unwrap() here.
+    Ok((
+        nread.try_into().unwrap(),
+        MsgFlags::from_bits_truncate(msg.msg_flags),
+    ))
+}
+
+/// Sends the contents of the given buffers as a single message on the socket,
+/// returning the number of bytes sent.
+///
+/// Sending on a socket whose peer has gone away fails with `EPIPE` rather than
+/// raising `SIGPIPE`, provided that `set_nosigpipe` has been called on the
+/// socket on platforms where that's needed.
+pub unsafe fn send_vectored(fd: RawFd, iovs: &[IoSlice], flags: MsgFlags) -> Result<usize> {
+    // `IoSlice` is guaranteed to be ABI compatible with `iovec` on Unix.
+    let mut msg: libc::msghdr = mem::zeroed();
+    msg.msg_iov = iovs.as_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = iovs.len() as _;
+    let nwritten = from_result(libc::sendmsg(fd, &msg, flags.bits() | NOSIGNAL))?;
+    // When sendmsg doesn't fail, its return value is non-negative, so we can
+    // unwrap() here.
+    Ok(nwritten.try_into().unwrap())
+}
+
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "openbsd"))]
+const NOSIGNAL: libc::c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(any(target_os = "android", target_os = "linux", target_os = "openbsd")))]
+const NOSIGNAL: libc::c_int = 0;
+
+/// Stops writes to the socket from raising `SIGPIPE` once its peer has gone
+/// away, so that they fail with `EPIPE` instead.
+///
+/// This is a no-op where `send_vectored` passes `MSG_NOSIGNAL` instead.
+pub unsafe fn set_nosigpipe(fd: RawFd) -> Result<()> {
+    cfg_if! {
+        if #[cfg(any(
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "macos",
+            target_os = "netbsd"
+        ))] {
+            let on: libc::c_int = 1;
+            from_success_code(libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_NOSIGPIPE,
+                &on as *const libc::c_int as *const _,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            ))
+        } else {
+            let _ = fd;
+            Ok(())
+        }
+    }
+}
+
+pub unsafe fn shutdown(fd: RawFd, how: Shutdown) -> Result<()> {
+    let how = match how {
+        Shutdown::Read => libc::SHUT_RD,
+        Shutdown::Write => libc::SHUT_WR,
+        Shutdown::Both => libc::SHUT_RDWR,
+    };
+    from_success_code(libc::shutdown(fd, how))
+}
@@ -1,5 +1,11 @@
+use wasmtime::Trap;
+
 pub mod old;
+mod strace;
+mod trace;
 
+pub use strace::{CallClass, Strace};
+pub use trace::Trace;
 pub use wasi_common::{WasiCtx, WasiCtxBuilder};
 
 // Defines a `struct Wasi` with member fields and appropriate APIs for dealing
@@ -12,3 +18,15 @@ pub fn is_wasi_module(name: &str) -> bool {
     // trick.
     name.starts_with("wasi")
 }
+
+/// Implements the WASI `proc_exit` function.
+///
+/// This is implemented here instead of in wasi-common so that it can unwind
+/// back to the embedder with a trap, rather than returning to the guest.
+///
+/// Every status unwinds with an exit trap, so embedders can always find it with
+/// [`Trap::i32_exit_status`]. WASI only defines statuses in `0..126`, and
+/// it's up to the embedder what to do with any others.
+fn wasi_proc_exit(status: i32) -> Result<(), Trap> {
+    Err(Trap::i32_exit(status))
+}
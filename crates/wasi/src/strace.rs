@@ -0,0 +1,423 @@
+//! strace-like logging of WASI calls.
+//!
+//! A [`Strace`] prints one line for every call a guest makes to
+//! `wasi_snapshot_preview1`, along with its errno. Arguments are decoded from
+//! their witx types: strings such as paths are printed as strings, flags and
+//! enums by the names of their values, and iovecs as the sizes of their
+//! buffers. Values which a successful call wrote through its out-pointers are
+//! printed after its errno, for example:
+//!
+//! ```text
+//! fd_write(fd=1, iovs=[14]) = success nwritten=14
+//! ```
+//!
+//! Calls can be filtered by their [`CallClass`], and printed as JSON lines
+//! instead of text.
+
+use anyhow::bail;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+use std::str::FromStr;
+use wasmtime::{Memory, Trap};
+
+/// The most bytes of a string argument which are printed.
+const MAX_STR: usize = 256;
+
+/// The most iovecs of an argument whose sizes are printed.
+const MAX_IOVECS: usize = 16;
+
+/// Prints the WASI calls a guest makes.
+///
+/// Clones share the same output.
+#[derive(Clone)]
+pub struct Strace {
+    out: Rc<RefCell<BufWriter<Box<dyn Write>>>>,
+    classes: Option<Vec<CallClass>>,
+    json: bool,
+}
+
+/// A class of related WASI calls, which [`Strace::classes`] filters by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallClass {
+    /// `fd_*` and `path_*` calls.
+    Fs,
+    /// `clock_*` calls.
+    Clock,
+    /// `poll_oneoff` and `sched_yield`.
+    Poll,
+    /// `sock_*` calls.
+    Sock,
+    /// `random_get`.
+    Random,
+    /// `proc_exit` and `proc_raise`.
+    Proc,
+    /// `args_*` and `environ_*` calls.
+    Env,
+}
+
+impl CallClass {
+    /// Returns the class of the WASI function called `name`, if it's a known
+    /// one.
+    pub fn of(name: &str) -> Option<CallClass> {
+        match name.split('_').next()? {
+            "fd" | "path" => Some(CallClass::Fs),
+            "clock" => Some(CallClass::Clock),
+            "poll" | "sched" => Some(CallClass::Poll),
+            "sock" => Some(CallClass::Sock),
+            "random" => Some(CallClass::Random),
+            "proc" => Some(CallClass::Proc),
+            "args" | "environ" => Some(CallClass::Env),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for CallClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<CallClass> {
+        Ok(match s {
+            "fs" => CallClass::Fs,
+            "clock" => CallClass::Clock,
+            "poll" => CallClass::Poll,
+            "sock" => CallClass::Sock,
+            "random" => CallClass::Random,
+            "proc" => CallClass::Proc,
+            "env" => CallClass::Env,
+            _ => bail!(
+                "unknown class of WASI calls `{}`, expected one of \
+                 fs, clock, poll, sock, random, proc or env",
+                s
+            ),
+        })
+    }
+}
+
+impl Strace {
+    /// Creates a `Strace` which prints all calls into `writer` as text.
+    ///
+    /// Every call is flushed to `writer` as soon as it returns.
+    pub fn new(writer: impl Write + 'static) -> Strace {
+        Strace {
+            out: Rc::new(RefCell::new(BufWriter::new(Box::new(writer)))),
+            classes: None,
+            json: false,
+        }
+    }
+
+    /// Only prints calls of the given classes.
+    pub fn classes(mut self, classes: &[CallClass]) -> Strace {
+        self.classes = Some(classes.to_vec());
+        self
+    }
+
+    /// Prints every call as a line of JSON instead of text, as in:
+    ///
+    /// ```json
+    /// {"call":"fd_write","args":{"fd":1,"iovs":[14]},"errno":"success","outs":{"nwritten":14}}
+    /// ```
+    ///
+    /// Calls which trap have a `"trap"` message instead of an `"errno"`, and
+    /// `proc_exit` has the `"exit"` status.
+    pub fn json(mut self, json: bool) -> Strace {
+        self.json = json;
+        self
+    }
+
+    /// Whether calls to the WASI function called `name` are printed.
+    pub fn traces(&self, name: &str) -> bool {
+        match (&self.classes, CallClass::of(name)) {
+            (None, _) => true,
+            (Some(classes), Some(class)) => classes.contains(&class),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Performs a call to `name` with `call`, and prints it.
+    ///
+    /// `args` are decoded before the call and `outs` after it, when it
+    /// succeeds. The errno it returns is printed as its name in `errnos`.
+    ///
+    /// # Unsafety
+    ///
+    /// The contents of `mem` are read, so they must not be borrowed mutably
+    /// elsewhere.
+    pub(crate) unsafe fn call(
+        &self,
+        name: &str,
+        errnos: &[&str],
+        args: &[Arg],
+        outs: &[Out],
+        mem: Option<&Memory>,
+        call: impl FnOnce() -> Result<i32, Trap>,
+    ) -> Result<i32, Trap> {
+        let data = mem.map(|mem| mem.data_unchecked()).unwrap_or(&[]);
+        let args = args
+            .iter()
+            .map(|arg| (arg.name, arg.decode(data)))
+            .collect::<Vec<_>>();
+        let result = call();
+        let outs = match result {
+            Ok(0) => {
+                let data = mem.map(|mem| mem.data_unchecked()).unwrap_or(&[]);
+                outs.iter()
+                    .map(|out| (out.name, out.decode(data)))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        let ret = match &result {
+            Ok(errno) => Ret::Errno(errnos.get(*errno as usize).copied(), *errno),
+            Err(trap) => match trap.i32_exit_status() {
+                Some(status) => Ret::Exit(status),
+                None => Ret::Trap(trap.message()),
+            },
+        };
+
+        let line = if self.json {
+            to_json(name, &args, &ret, &outs).to_string()
+        } else {
+            Line(name, &args, &ret, &outs).to_string()
+        };
+        // Failing to print a call shouldn't fail the call itself.
+        let mut out = self.out.borrow_mut();
+        let _ = writeln!(out, "{}", line).and_then(|()| out.flush());
+        result
+    }
+}
+
+/// An argument of a call, as it's passed to the host.
+pub(crate) struct Arg {
+    pub name: &'static str,
+    pub value: i64,
+    /// The length of strings and arrays.
+    pub len: u32,
+    pub show: Show,
+}
+
+/// A value a call writes through the out-pointer `ptr` if it succeeds.
+pub(crate) struct Out {
+    pub name: &'static str,
+    pub ptr: u32,
+    /// The size of the little-endian integer at `ptr`, or 0 if it isn't
+    /// one, in which case only `ptr` is printed.
+    pub size: u32,
+    pub show: Show,
+}
+
+/// How to print an argument, according to its witx type.
+pub(crate) enum Show {
+    Int,
+    UInt,
+    Hex,
+    Enum(&'static [&'static str]),
+    Flags(&'static [&'static str]),
+    /// A string at `value` of `len` bytes.
+    Str,
+    /// An array at `value` of `len` iovecs or ciovecs.
+    Iovecs,
+    /// Any other array at `value` of `len` elements.
+    Array,
+}
+
+/// A decoded argument or out value.
+enum Decoded {
+    Int(i64),
+    UInt(u64),
+    Hex(u64),
+    Name(String),
+    Str(String),
+    Sizes(Vec<u32>, bool),
+    Array(u32, u32),
+}
+
+/// The result of a call.
+enum Ret<'a> {
+    Errno(Option<&'a str>, i32),
+    Exit(i32),
+    Trap(&'a str),
+}
+
+impl Arg {
+    fn decode(&self, data: &[u8]) -> Decoded {
+        let ptr = self.value as u32;
+        match self.show {
+            Show::Str => match slice(data, ptr, self.len) {
+                Some(bytes) => {
+                    let mut s = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_STR)]);
+                    if bytes.len() > MAX_STR {
+                        s.to_mut().push_str("...");
+                    }
+                    Decoded::Str(s.into_owned())
+                }
+                None => Decoded::Hex(u64::from(ptr)),
+            },
+            Show::Iovecs => {
+                // Each iovec is a pointer to a buffer followed by its size.
+                let count = self.len.min(MAX_IOVECS as u32);
+                match slice(data, ptr, count * 8) {
+                    Some(bytes) => Decoded::Sizes(
+                        bytes
+                            .chunks(8)
+                            .map(|iovec| read_le(&iovec[4..]) as u32)
+                            .collect(),
+                        self.len > count,
+                    ),
+                    None => Decoded::Array(ptr, self.len),
+                }
+            }
+            Show::Array => Decoded::Array(ptr, self.len),
+            _ => decode_int(self.value, &self.show),
+        }
+    }
+}
+
+impl Out {
+    fn decode(&self, data: &[u8]) -> Decoded {
+        match slice(data, self.ptr, self.size) {
+            Some(bytes) if self.size > 0 => decode_int(read_le(bytes) as i64, &self.show),
+            _ => Decoded::Hex(u64::from(self.ptr)),
+        }
+    }
+}
+
+fn decode_int(value: i64, show: &Show) -> Decoded {
+    match show {
+        Show::Int => Decoded::Int(value),
+        Show::Enum(names) => match names.get(value as usize) {
+            Some(name) => Decoded::Name(name.to_string()),
+            None => Decoded::UInt(value as u64),
+        },
+        Show::Flags(names) => Decoded::Name(flags(value as u64, names)),
+        Show::Hex => Decoded::Hex(value as u64),
+        _ => Decoded::UInt(value as u64),
+    }
+}
+
+/// Names the set bits of `value` as `a|b`, and any unknown bits in hex.
+fn flags(value: u64, names: &[&str]) -> String {
+    let mut set = Vec::new();
+    let mut unknown = value;
+    for (i, name) in names.iter().enumerate().take(64) {
+        if value & (1 << i) != 0 {
+            set.push(name.to_string());
+            unknown &= !(1 << i);
+        }
+    }
+    if unknown != 0 {
+        set.push(format!("{:#x}", unknown));
+    }
+    if set.is_empty() {
+        "0".to_string()
+    } else {
+        set.join("|")
+    }
+}
+
+fn slice(data: &[u8], ptr: u32, len: u32) -> Option<&[u8]> {
+    let start = ptr as usize;
+    data.get(start..start.checked_add(len as usize)?)
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | u64::from(byte))
+}
+
+/// Formats a call as `name(arg=value, ...) = errno out=value ...`.
+struct Line<'a>(
+    &'a str,
+    &'a [(&'a str, Decoded)],
+    &'a Ret<'a>,
+    &'a [(&'a str, Decoded)],
+);
+
+impl fmt::Display for Line<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Line(name, args, ret, outs) = self;
+        write!(f, "{}(", name)?;
+        for (i, (name, value)) in args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        match ret {
+            Ret::Errno(Some(name), _) => write!(f, ") = {}", name)?,
+            Ret::Errno(None, errno) => write!(f, ") = {}", errno)?,
+            Ret::Exit(status) => write!(f, ") = <exit {}>", status)?,
+            Ret::Trap(message) => write!(f, ") = <trap: {}>", message)?,
+        }
+        for (name, value) in outs.iter() {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoded::Int(value) => write!(f, "{}", value),
+            Decoded::UInt(value) => write!(f, "{}", value),
+            Decoded::Hex(value) => write!(f, "{:#x}", value),
+            Decoded::Name(name) => write!(f, "{}", name),
+            Decoded::Str(s) => write!(f, "{:?}", s),
+            Decoded::Sizes(sizes, truncated) => {
+                write!(f, "[")?;
+                for (i, size) in sizes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", size)?;
+                }
+                if *truncated {
+                    write!(f, ", ...")?;
+                }
+                write!(f, "]")
+            }
+            Decoded::Array(ptr, len) => write!(f, "{:#x}[{}]", ptr, len),
+        }
+    }
+}
+
+impl Decoded {
+    fn to_json(&self) -> Value {
+        match self {
+            Decoded::Int(value) => json!(value),
+            Decoded::UInt(value) | Decoded::Hex(value) => json!(value),
+            Decoded::Name(s) | Decoded::Str(s) => json!(s),
+            Decoded::Sizes(sizes, _) => json!(sizes),
+            Decoded::Array(ptr, len) => json!({ "ptr": ptr, "len": len }),
+        }
+    }
+}
+
+fn to_json(name: &str, args: &[(&str, Decoded)], ret: &Ret, outs: &[(&str, Decoded)]) -> Value {
+    let object = |values: &[(&str, Decoded)]| {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_json()))
+            .collect::<Map<_, _>>()
+    };
+    let mut call = json!({
+        "call": name,
+        "args": object(args),
+    });
+    let (key, value) = match ret {
+        Ret::Errno(Some(name), _) => ("errno", json!(name)),
+        Ret::Errno(None, errno) => ("errno", json!(errno)),
+        Ret::Exit(status) => ("exit", json!(status)),
+        Ret::Trap(message) => ("trap", json!(message)),
+    };
+    call[key] = value;
+    if !outs.is_empty() {
+        call["outs"] = Value::Object(object(outs));
+    }
+    call
+}
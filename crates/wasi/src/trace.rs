@@ -0,0 +1,334 @@
+//! Recording and replaying of WASI calls.
+//!
+//! A [`Trace`] in recording mode writes down every call a guest makes to
+//! `wasi_snapshot_preview1`: its arguments, a hash of each region of the
+//! guest's memory it accessed, its result and all the bytes it wrote into the
+//! guest's memory. In replay mode the recorded results and writes are fed back
+//! to the guest without calling into the host at all, so a run can be
+//! reproduced exactly as long as the guest makes the same calls. A guest which
+//! makes a different call than the one recorded, or passes it different data,
+//! traps.
+//!
+//! Recording only copies the regions of memory which a call accesses through
+//! `GuestPtr`, when it first accesses them, and looks for writes in just those
+//! regions afterwards.
+
+use anyhow::{bail, Context as _};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::rc::Rc;
+use std::slice;
+use wasmtime::{Memory, Trap};
+use wiggle::{GuestError, GuestMemory};
+
+/// The header of trace files, which includes the version of the format.
+const MAGIC: &[u8; 8] = b"wasitrc\x02";
+
+/// A recording of WASI calls, or a replay of a recording.
+///
+/// Clones share the same underlying file.
+#[derive(Clone)]
+pub struct Trace {
+    mode: Rc<RefCell<Mode>>,
+}
+
+enum Mode {
+    Record(BufWriter<Box<dyn Write>>),
+    Replay(BufReader<Box<dyn Read>>),
+}
+
+/// A recorded call.
+#[derive(Serialize, Deserialize)]
+struct Call {
+    name: String,
+    args: Vec<i64>,
+    reads: Vec<MemoryRead>,
+    result: i32,
+    writes: Vec<MemoryWrite>,
+}
+
+/// A region of the guest's memory which a call accessed, and a hash of its
+/// contents before the call, so that replays can check that the guest passed
+/// the same data.
+#[derive(Serialize, Deserialize)]
+struct MemoryRead {
+    offset: u32,
+    len: u32,
+    hash: u64,
+}
+
+/// Bytes which a call wrote into the guest's memory.
+#[derive(Serialize, Deserialize)]
+struct MemoryWrite {
+    offset: u32,
+    bytes: Vec<u8>,
+}
+
+impl Trace {
+    /// Creates a trace which records calls into `writer`.
+    ///
+    /// Every call is flushed to `writer` as soon as it returns, so that the
+    /// recording is complete even if the host process dies.
+    pub fn record(writer: impl Write + 'static) -> anyhow::Result<Trace> {
+        let mut writer = BufWriter::new(Box::new(writer) as Box<dyn Write>);
+        writer.write_all(MAGIC)?;
+        writer.flush()?;
+        Ok(Trace::new(Mode::Record(writer)))
+    }
+
+    /// Creates a trace which replays the calls recorded in `reader`.
+    pub fn replay(reader: impl Read + 'static) -> anyhow::Result<Trace> {
+        let mut reader = BufReader::new(Box::new(reader) as Box<dyn Read>);
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .context("failed to read the WASI trace header")?;
+        if &magic != MAGIC {
+            bail!("not a WASI trace, or a trace from an incompatible version");
+        }
+        Ok(Trace::new(Mode::Replay(reader)))
+    }
+
+    fn new(mode: Mode) -> Trace {
+        Trace {
+            mode: Rc::new(RefCell::new(mode)),
+        }
+    }
+
+    /// Finishes the trace after the guest is done.
+    ///
+    /// When replaying, this fails if the guest didn't make all the recorded
+    /// calls.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match &mut *self.mode.borrow_mut() {
+            Mode::Record(writer) => writer.flush()?,
+            Mode::Replay(reader) => {
+                if let Some(call) = read_call(reader)? {
+                    bail!(
+                        "WASI trace diverged: the guest finished without calling `{}`",
+                        Describe(&call.name, &call.args)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Records or replays a call to `name` with `args`.
+    ///
+    /// When recording, `hostcall` performs the call, and the regions of `mem`
+    /// it accesses through the guest memory it's given and its writes to them
+    /// are recorded. When replaying, `hostcall` is never called.
+    ///
+    /// # Unsafety
+    ///
+    /// The contents of `mem` are read and written, so they must not be
+    /// borrowed elsewhere.
+    pub(crate) unsafe fn hostcall(
+        &self,
+        name: &str,
+        args: &[i64],
+        mem: Option<&Memory>,
+        hostcall: impl FnOnce(Option<&dyn GuestMemory>) -> i32,
+    ) -> Result<i32, Trap> {
+        match &mut *self.mode.borrow_mut() {
+            Mode::Record(writer) => {
+                let (result, reads, writes) = match mem {
+                    Some(mem) => {
+                        let recording = RecordingMemory {
+                            memory: mem,
+                            accessed: RefCell::new(Vec::new()),
+                        };
+                        let result = hostcall(Some(&recording));
+                        let (reads, writes) = recording.finish();
+                        (result, reads, writes)
+                    }
+                    None => (hostcall(None), Vec::new(), Vec::new()),
+                };
+                let call = Call {
+                    name: name.to_string(),
+                    args: args.to_vec(),
+                    reads,
+                    result,
+                    writes,
+                };
+                bincode::serialize_into(&mut *writer, &call)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                    .and_then(|()| writer.flush())
+                    .map_err(|e| Trap::new(format!("failed to write the WASI trace: {}", e)))?;
+                Ok(result)
+            }
+            Mode::Replay(reader) => {
+                let call = match read_call(reader) {
+                    Ok(Some(call)) => call,
+                    Ok(None) => {
+                        return Err(Trap::new(format!(
+                            "WASI trace diverged: the guest called `{}` after the end of the trace",
+                            Describe(name, args)
+                        )))
+                    }
+                    Err(e) => {
+                        return Err(Trap::new(format!("failed to read the WASI trace: {}", e)))
+                    }
+                };
+                if call.name != name || call.args != args {
+                    return Err(Trap::new(format!(
+                        "WASI trace diverged: expected a call to `{}`, but the guest called `{}`",
+                        Describe(&call.name, &call.args),
+                        Describe(name, args)
+                    )));
+                }
+                let data = mem.map(|mem| mem.data_unchecked_mut()).unwrap_or(&mut []);
+                for read in &call.reads {
+                    let contents = region(data, read.offset, read.len as usize);
+                    if contents.map(|contents| hash(contents)) != Some(read.hash) {
+                        return Err(Trap::new(format!(
+                            "WASI trace diverged: the guest passed different data at {:#x} to `{}`",
+                            read.offset,
+                            Describe(name, args)
+                        )));
+                    }
+                }
+                for write in call.writes {
+                    let dst = region(data, write.offset, write.bytes.len()).ok_or_else(|| {
+                        Trap::new(format!(
+                            "WASI trace diverged: `{}` wrote out of bounds",
+                            Describe(name, args)
+                        ))
+                    })?;
+                    dst.copy_from_slice(&write.bytes);
+                }
+                Ok(call.result)
+            }
+        }
+    }
+}
+
+/// A guest memory which remembers the contents of each region the hostcall
+/// accesses as it's validated. Hostcalls validate regions before they read or
+/// write them, so that's their contents before the call.
+struct RecordingMemory<'a> {
+    memory: &'a Memory,
+    /// The offset and earlier contents of each validated region, in the order
+    /// they were validated.
+    accessed: RefCell<Vec<(u32, Vec<u8>)>>,
+}
+
+unsafe impl GuestMemory for RecordingMemory<'_> {
+    fn base(&self) -> (*mut u8, u32) {
+        self.memory.base()
+    }
+
+    fn validate_size_align(
+        &self,
+        offset: u32,
+        align: usize,
+        len: u32,
+    ) -> Result<*mut u8, GuestError> {
+        let ptr = self.memory.validate_size_align(offset, align, len)?;
+        let contents = unsafe { slice::from_raw_parts(ptr, len as usize) };
+        self.accessed.borrow_mut().push((offset, contents.to_vec()));
+        Ok(ptr)
+    }
+}
+
+impl RecordingMemory<'_> {
+    /// Returns the regions which were accessed, and what was written to them.
+    fn finish(self) -> (Vec<MemoryRead>, Vec<MemoryWrite>) {
+        let data = unsafe { self.memory.data_unchecked() };
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        // The ranges of bytes whose contents from before the call are known.
+        // A region which is validated again after the call wrote to it only
+        // has the earlier contents of the bytes outside of these.
+        let mut known: Vec<(usize, usize)> = Vec::new();
+        for (offset, before) in self.accessed.into_inner() {
+            let start = offset as usize;
+            let end = start + before.len();
+            let mut unknown = vec![(start, end)];
+            for &(known_start, known_end) in &known {
+                unknown = unknown
+                    .into_iter()
+                    .flat_map(|(s, e)| {
+                        vec![(s, e.min(known_start)), (s.max(known_end), e)]
+                            .into_iter()
+                            .filter(|(s, e)| s < e)
+                    })
+                    .collect();
+            }
+            for (s, e) in unknown {
+                let before = &before[s - start..e - start];
+                reads.push(MemoryRead {
+                    offset: s as u32,
+                    len: (e - s) as u32,
+                    hash: hash(before),
+                });
+                writes.extend(diff(s, before, &data[s..e]));
+                known.push((s, e));
+            }
+        }
+        (reads, writes)
+    }
+}
+/// Reads the next call, or `None` at the end of the trace.
+fn read_call(reader: &mut BufReader<Box<dyn Read>>) -> anyhow::Result<Option<Call>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize_from(reader)?))
+}
+
+/// Returns the `len` bytes of `data` at `offset`, if they're in bounds.
+fn region(data: &mut [u8], offset: u32, len: usize) -> Option<&mut [u8]> {
+    let start = offset as usize;
+    data.get_mut(start..start.checked_add(len)?)
+}
+
+/// Finds the bytes of `after` which differ from `before`, both of which start
+/// at `offset`.
+fn diff(offset: usize, before: &[u8], after: &[u8]) -> Vec<MemoryWrite> {
+    let mut writes = Vec::new();
+    let mut i = 0;
+    while i < after.len() {
+        if before[i] == after[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < after.len() && before[i] != after[i] {
+            i += 1;
+        }
+        writes.push(MemoryWrite {
+            offset: (offset + start) as u32,
+            bytes: after[start..i].to_vec(),
+        });
+    }
+    writes
+}
+
+/// Hashes `bytes` with 64-bit FNV-1a, which unlike the hashers of `std` is
+/// guaranteed to stay the same, so that traces can be replayed by later
+/// builds.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Formats a call as `name(arg, ...)`.
+struct Describe<'a>(&'a str, &'a [i64]);
+
+impl fmt::Display for Describe<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}(", self.0)?;
+        for (i, arg) in self.1.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        write!(f, ")")
+    }
+}
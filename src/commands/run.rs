@@ -1,19 +1,22 @@
 //! The module that implements the `wasmtime run` command.
 
+use crate::gdb::GdbServer;
 use crate::{init_file_per_thread_logger, CommonOptions};
 use anyhow::{bail, Context as _, Result};
 use std::thread;
 use std::time::Duration;
 use std::{
     ffi::{OsStr, OsString},
-    fs::File,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    net::TcpListener,
     path::{Component, Path, PathBuf},
     process,
 };
 use structopt::{clap::AppSettings, StructOpt};
-use wasi_common::preopen_dir;
-use wasmtime::{Engine, Instance, Module, Store, Trap, Val, ValType};
-use wasmtime_wasi::{old::snapshot_0::Wasi as WasiSnapshot0, Wasi};
+use wasi_common::{preopen_dir, EntryRights};
+use wasmtime::{Engine, Instance, Module, ProfilingStrategy, Store, Trap, Val, ValType};
+use wasmtime_wasi::{old::snapshot_0::Wasi as WasiSnapshot0, CallClass, Strace, Trace, Wasi};
 
 fn parse_module(s: &OsStr) -> Result<PathBuf, OsString> {
     // Do not accept wasmtime subcommand names as the module name
@@ -33,12 +36,60 @@ fn parse_env_var(s: &str) -> Result<(String, String)> {
     Ok((parts[0].to_owned(), parts[1].to_owned()))
 }
 
-fn parse_map_dirs(s: &str) -> Result<(String, String)> {
+/// How a preopened directory may be accessed.
+#[derive(Debug, Clone, Copy)]
+enum DirAccess {
+    /// Everything in the directory can be read, written, created and removed.
+    ReadWrite,
+    /// The directory's files and subdirectories can only be read.
+    ReadOnly,
+}
+
+fn parse_dir_access(s: &str) -> Result<DirAccess> {
+    match s {
+        "rw" => Ok(DirAccess::ReadWrite),
+        "ro" => Ok(DirAccess::ReadOnly),
+        other => bail!(
+            "unknown access `{}`, only `ro` and `rw` are accepted",
+            other
+        ),
+    }
+}
+
+fn parse_dirs(s: &str) -> Result<(String, DirAccess)> {
     let parts: Vec<&str> = s.split("::").collect();
-    if parts.len() != 2 {
-        bail!("must contain exactly one double colon ('::')");
+    match parts.as_slice() {
+        [dir] => Ok((dir.to_string(), DirAccess::ReadWrite)),
+        [dir, access] => Ok((dir.to_string(), parse_dir_access(access)?)),
+        _ => bail!("must contain at most one double colon ('::')"),
+    }
+}
+
+fn parse_map_dirs(s: &str) -> Result<(String, String, DirAccess)> {
+    let parts: Vec<&str> = s.split("::").collect();
+    match parts.as_slice() {
+        [guest, host] => Ok((guest.to_string(), host.to_string(), DirAccess::ReadWrite)),
+        [guest, host, access] => Ok((
+            guest.to_string(),
+            host.to_string(),
+            parse_dir_access(access)?,
+        )),
+        _ => bail!("must contain one or two double colons ('::')"),
+    }
+}
+
+/// What `--profile` should profile.
+#[derive(Debug, Clone, Copy)]
+enum Profile {
+    /// Sample the stacks of the wasm guest.
+    Guest,
+}
+
+fn parse_profile(s: &str) -> Result<Profile> {
+    match s {
+        "guest" => Ok(Profile::Guest),
+        other => bail!("unknown profile `{}`, only `guest` is accepted", other),
     }
-    Ok((parts[0].into(), parts[1].into()))
 }
 
 fn parse_dur(s: &str) -> Result<Duration> {
@@ -58,9 +109,10 @@ pub struct RunCommand {
     #[structopt(flatten)]
     common: CommonOptions,
 
-    /// Grant access to the given host directory
-    #[structopt(long = "dir", number_of_values = 1, value_name = "DIRECTORY")]
-    dirs: Vec<String>,
+    /// Grant access to the given host directory, read-only with a `::ro`
+    /// suffix
+    #[structopt(long = "dir", number_of_values = 1, value_name = "DIRECTORY[::ro|::rw]", parse(try_from_str = parse_dirs))]
+    dirs: Vec<(String, DirAccess)>,
 
     /// Pass an environment variable to the program
     #[structopt(long = "env", number_of_values = 1, value_name = "NAME=VAL", parse(try_from_str = parse_env_var))]
@@ -70,9 +122,19 @@ pub struct RunCommand {
     #[structopt(long, value_name = "FUNCTION")]
     invoke: Option<String>,
 
-    /// Grant access to a guest directory mapped as a host directory
-    #[structopt(long = "mapdir", number_of_values = 1, value_name = "GUEST_DIR::HOST_DIR", parse(try_from_str = parse_map_dirs))]
-    map_dirs: Vec<(String, String)>,
+    /// Grant access to a guest directory mapped as a host directory,
+    /// read-only with a `::ro` suffix
+    #[structopt(long = "mapdir", number_of_values = 1, value_name = "GUEST_DIR::HOST_DIR[::ro|::rw]", parse(try_from_str = parse_map_dirs))]
+    map_dirs: Vec<(String, String, DirAccess)>,
+
+    /// Grant access to a TCP socket listening on the given address, passed
+    /// to the program as a file descriptor after any preopened directories
+    #[structopt(
+        long = "tcplisten",
+        number_of_values = 1,
+        value_name = "SOCKET ADDRESS"
+    )]
+    tcplisten: Vec<String>,
 
     /// The path of the WebAssembly module to run
     #[structopt(
@@ -100,6 +162,79 @@ pub struct RunCommand {
     )]
     wasm_timeout: Option<Duration>,
 
+    /// Profile the program with the built-in sampling profiler, writing
+    /// `wasmtime-guest-profile.json` (for the Firefox profiler) and
+    /// `wasmtime-guest-profile.folded` (collapsed stacks, for flamegraphs)
+    #[structopt(
+        long,
+        value_name = "KIND",
+        parse(try_from_str = parse_profile),
+    )]
+    profile: Option<Profile>,
+
+    /// Write the profile's JSON into the given file instead, and its
+    /// collapsed stacks next to it with a `.folded` extension
+    #[structopt(long, value_name = "FILE", parse(from_os_str), requires = "profile")]
+    profile_output: Option<PathBuf>,
+
+    /// How much CPU time to let pass between samples (1ms, 100us, etc),
+    /// 1ms by default
+    #[structopt(
+        long,
+        value_name = "TIME",
+        parse(try_from_str = parse_dur),
+        requires = "profile"
+    )]
+    profile_interval: Option<Duration>,
+
+    /// Wait for a debugger, such as lldb, to connect to the given port with
+    /// the gdb remote protocol and debug the program in terms of wasm
+    #[structopt(long, value_name = "PORT")]
+    gdb_port: Option<u16>,
+
+    /// Collect code coverage of the main module and write it to the given
+    /// file in the LCOV format, which requires DWARF debug information
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    coverage: Option<PathBuf>,
+
+    /// Record every WASI call the program makes, with its results, into the
+    /// given file
+    #[structopt(
+        long,
+        value_name = "FILE",
+        parse(from_os_str),
+        conflicts_with = "replay"
+    )]
+    record: Option<PathBuf>,
+
+    /// Replay the WASI calls recorded in the given file with `--record`
+    /// instead of calling into the host
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    replay: Option<PathBuf>,
+
+    /// Print every WASI call the program makes, with its decoded arguments
+    /// and its result, to stderr
+    #[structopt(long)]
+    trace_wasi: bool,
+
+    /// Only print the WASI calls of the given classes: fs, clock, poll, sock,
+    /// random, proc or env
+    #[structopt(
+        long,
+        value_name = "CLASS,...",
+        use_delimiter = true,
+        requires = "trace-wasi"
+    )]
+    trace_wasi_filter: Vec<CallClass>,
+
+    /// Print the WASI calls as JSON lines
+    #[structopt(long, requires = "trace-wasi")]
+    trace_wasi_json: bool,
+
+    /// Print the WASI calls into the given file instead of stderr
+    #[structopt(long, value_name = "FILE", parse(from_os_str), requires = "trace-wasi")]
+    trace_wasi_output: Option<PathBuf>,
+
     // NOTE: this must come last for trailing varargs
     /// The arguments to pass to the module
     #[structopt(value_name = "ARGS")]
@@ -120,26 +255,86 @@ impl RunCommand {
         if self.wasm_timeout.is_some() {
             config.interruptable(true);
         }
+        if let Some(Profile::Guest) = self.profile {
+            if self.common.jitdump || self.common.vtune || self.common.perfmap {
+                bail!("can't use `--profile` together with `--jitdump`, `--vtune` or `--perfmap`");
+            }
+            match self.profile_interval {
+                Some(interval) => config.sampling_profiler(interval)?,
+                None => config.profiler(ProfilingStrategy::Sampling)?,
+            };
+        }
+        if self.gdb_port.is_some() {
+            config.debug_instrumentation(true);
+        }
+        if self.coverage.is_some() {
+            config.coverage(true);
+        }
         let engine = Engine::new(&config);
         let store = Store::new(&engine);
+        let gdb = match self.gdb_port {
+            Some(port) => Some(GdbServer::accept(&store, port)?),
+            None => None,
+        };
 
         // Make wasi available by default.
         let preopen_dirs = self.compute_preopen_dirs()?;
+        let listeners = self.compute_listeners()?;
         let argv = self.compute_argv();
-
-        let module_registry = ModuleRegistry::new(&store, &preopen_dirs, &argv, &self.vars)?;
+        let trace = self.compute_trace()?;
+        let strace = self.compute_strace()?;
+
+        let module_registry = ModuleRegistry::new(
+            &store,
+            &preopen_dirs,
+            &listeners,
+            &argv,
+            &self.vars,
+            trace.clone(),
+            strace,
+        )?;
 
         // Load the preload wasm modules.
         for preload in self.preloads.iter() {
-            Self::instantiate_module(&store, &module_registry, preload)
+            Self::instantiate_module(&store, &module_registry, gdb.as_ref(), preload)
                 .with_context(|| format!("failed to process preload at `{}`", preload.display()))?;
         }
 
         // Load the main wasm module.
-        match self
-            .handle_module(&store, &module_registry)
-            .with_context(|| format!("failed to run main module `{}`", self.module.display()))
-        {
+        let result = self
+            .handle_module(&store, &module_registry, gdb.as_ref())
+            .with_context(|| format!("failed to run main module `{}`", self.module.display()));
+        // A guest which calls WASI's `proc_exit` unwinds with a trap carrying
+        // its exit status, which becomes the exit status of this process.
+        // WASI only defines statuses in `0..126`, larger values are reserved
+        // for signals and other uses by the host, so any other status is
+        // reported like any other trap.
+        let exit_status = match &result {
+            Ok(()) => None,
+            Err(e) => e
+                .downcast_ref::<Trap>()
+                .and_then(|trap| trap.i32_exit_status())
+                .filter(|status| (0..126).contains(status)),
+        };
+        // A replay must have made all the recorded calls by the time the
+        // program is done.
+        if let Some(trace) = &trace {
+            if result.is_ok() || exit_status.is_some() {
+                trace.finish()?;
+            }
+        }
+        if let Some(gdb) = &gdb {
+            match (&result, exit_status) {
+                (_, Some(status)) => gdb.exited(status as u8)?,
+                (Ok(()), None) => gdb.exited(0)?,
+                (Err(e), None) if e.is::<Trap>() => gdb.trapped()?,
+                (Err(_), None) => gdb.exited(1)?,
+            }
+        }
+        if let Some(status) = exit_status {
+            process::exit(status);
+        }
+        match result {
             Ok(()) => (),
             Err(e) => {
                 // If the program exited because of a trap, return an error code
@@ -165,27 +360,72 @@ impl RunCommand {
         Ok(())
     }
 
-    fn compute_preopen_dirs(&self) -> Result<Vec<(String, File)>> {
+    fn compute_preopen_dirs(&self) -> Result<Vec<(String, File, DirAccess)>> {
         let mut preopen_dirs = Vec::new();
 
-        for dir in self.dirs.iter() {
+        for (dir, access) in self.dirs.iter() {
             preopen_dirs.push((
                 dir.clone(),
                 preopen_dir(dir).with_context(|| format!("failed to open directory '{}'", dir))?,
+                *access,
             ));
         }
 
-        for (guest, host) in self.map_dirs.iter() {
+        for (guest, host, access) in self.map_dirs.iter() {
             preopen_dirs.push((
                 guest.clone(),
                 preopen_dir(host)
                     .with_context(|| format!("failed to open directory '{}'", host))?,
+                *access,
             ));
         }
 
         Ok(preopen_dirs)
     }
 
+    fn compute_listeners(&self) -> Result<Vec<TcpListener>> {
+        self.tcplisten
+            .iter()
+            .map(|addr| {
+                TcpListener::bind(addr)
+                    .with_context(|| format!("failed to listen on address '{}'", addr))
+            })
+            .collect()
+    }
+
+    fn compute_trace(&self) -> Result<Option<Trace>> {
+        if let Some(path) = &self.record {
+            let file = File::create(path)
+                .with_context(|| format!("failed to create `{}`", path.display()))?;
+            return Ok(Some(Trace::record(file)?));
+        }
+        if let Some(path) = &self.replay {
+            let file =
+                File::open(path).with_context(|| format!("failed to open `{}`", path.display()))?;
+            let trace = Trace::replay(file)
+                .with_context(|| format!("failed to replay `{}`", path.display()))?;
+            return Ok(Some(trace));
+        }
+        Ok(None)
+    }
+
+    fn compute_strace(&self) -> Result<Option<Strace>> {
+        if !self.trace_wasi {
+            return Ok(None);
+        }
+        let mut strace = match &self.trace_wasi_output {
+            Some(path) => Strace::new(
+                File::create(path)
+                    .with_context(|| format!("failed to create `{}`", path.display()))?,
+            ),
+            None => Strace::new(io::stderr()),
+        };
+        if !self.trace_wasi_filter.is_empty() {
+            strace = strace.classes(&self.trace_wasi_filter);
+        }
+        Ok(Some(strace.json(self.trace_wasi_json)))
+    }
+
     fn compute_argv(&self) -> Vec<String> {
         let mut result = Vec::new();
 
@@ -212,12 +452,16 @@ impl RunCommand {
     fn instantiate_module(
         store: &Store,
         module_registry: &ModuleRegistry,
+        gdb: Option<&GdbServer>,
         path: &Path,
     ) -> Result<Instance> {
         // Read the wasm module binary either as `*.wat` or a raw binary
         let data = wat::parse_file(path)?;
 
         let module = Module::new(store, &data)?;
+        if let Some(gdb) = gdb {
+            gdb.add_module(path, &module, data);
+        }
 
         // Resolve import using module_registry.
         let imports = module
@@ -227,6 +471,12 @@ impl RunCommand {
                     "wasi_snapshot_preview1" => {
                         module_registry.wasi_snapshot_preview1.get_export(i.name())
                     }
+                    "wasi_unstable" if module_registry.traced => {
+                        bail!("`--record` and `--replay` only support `wasi_snapshot_preview1`")
+                    }
+                    "wasi_unstable" if module_registry.straced => {
+                        bail!("`--trace-wasi` only supports `wasi_snapshot_preview1`")
+                    }
                     "wasi_unstable" => module_registry.wasi_unstable.get_export(i.name()),
                     other => bail!("import module `{}` was not found", other),
                 };
@@ -247,7 +497,12 @@ impl RunCommand {
         Ok(instance)
     }
 
-    fn handle_module(&self, store: &Store, module_registry: &ModuleRegistry) -> Result<()> {
+    fn handle_module(
+        &self,
+        store: &Store,
+        module_registry: &ModuleRegistry,
+        gdb: Option<&GdbServer>,
+    ) -> Result<()> {
         if let Some(timeout) = self.wasm_timeout {
             let handle = store.interrupt_handle()?;
             thread::spawn(move || {
@@ -255,20 +510,69 @@ impl RunCommand {
                 handle.interrupt();
             });
         }
-        let instance = Self::instantiate_module(store, module_registry, &self.module)?;
+        let instance = Self::instantiate_module(store, module_registry, gdb, &self.module)?;
 
         // If a function to invoke was given, invoke it.
-        if let Some(name) = self.invoke.as_ref() {
-            self.invoke_export(instance, name)?;
+        let result = if let Some(name) = self.invoke.as_ref() {
+            self.invoke_export(instance.clone(), name)
         } else if instance.exports().any(|export| export.name().is_empty()) {
             // Launch the default command export.
-            self.invoke_export(instance, "")?;
+            self.invoke_export(instance.clone(), "")
         } else {
             // If the module doesn't have a default command export, launch the
             // _start function if one is present, as a compatibility measure.
-            self.invoke_export(instance, "_start")?;
+            self.invoke_export(instance.clone(), "_start")
+        };
+
+        // The profile is symbolized using the instance's code, so write it
+        // out before the instance goes away, even if the program failed.
+        self.write_guest_profile(store.engine())?;
+        if let Some(path) = &self.coverage {
+            let report = instance.coverage_report()?;
+            fs::write(path, report)
+                .with_context(|| format!("failed to write `{}`", path.display()))?;
         }
 
+        result
+    }
+
+    fn write_guest_profile(&self, engine: &Engine) -> Result<()> {
+        let profile = match engine.take_guest_profile() {
+            Some(profile) => profile,
+            None => return Ok(()),
+        };
+
+        let json = match &self.profile_output {
+            Some(path) => path.clone(),
+            None => PathBuf::from("wasmtime-guest-profile.json"),
+        };
+        let mut out = BufWriter::new(
+            File::create(&json)
+                .with_context(|| format!("failed to create `{}`", json.display()))?,
+        );
+        profile.write_firefox_json(&mut out)?;
+        out.flush()?;
+
+        let folded = json.with_extension("folded");
+        let mut out = BufWriter::new(
+            File::create(&folded)
+                .with_context(|| format!("failed to create `{}`", folded.display()))?,
+        );
+        profile.write_collapsed(&mut out)?;
+        out.flush()?;
+
+        eprintln!(
+            "wrote {} samples to `{}` and `{}`",
+            profile.samples(),
+            json.display(),
+            folded.display()
+        );
+        if profile.dropped_samples() > 0 {
+            eprintln!(
+                "warning: {} samples were dropped because the profile buffer was full",
+                profile.dropped_samples()
+            );
+        }
         Ok(())
     }
 
@@ -339,21 +643,40 @@ impl RunCommand {
 struct ModuleRegistry {
     wasi_snapshot_preview1: Wasi,
     wasi_unstable: WasiSnapshot0,
+    traced: bool,
+    straced: bool,
 }
 
 impl ModuleRegistry {
     fn new(
         store: &Store,
-        preopen_dirs: &[(String, File)],
+        preopen_dirs: &[(String, File, DirAccess)],
+        listeners: &[TcpListener],
         argv: &[String],
         vars: &[(String, String)],
+        trace: Option<Trace>,
+        strace: Option<Strace>,
     ) -> Result<ModuleRegistry> {
         let mut cx1 = wasi_common::WasiCtxBuilder::new();
 
         cx1.inherit_stdio().args(argv).envs(vars);
 
-        for (name, file) in preopen_dirs {
-            cx1.preopened_dir(file.try_clone()?, name);
+        for (name, file, access) in preopen_dirs {
+            match access {
+                DirAccess::ReadWrite => cx1.preopened_dir(file.try_clone()?, name),
+                DirAccess::ReadOnly => cx1.preopened_dir_with_rights(
+                    file.try_clone()?,
+                    name,
+                    EntryRights::read_only_dir(),
+                ),
+            };
+        }
+
+        // Sockets go after the preopened directories, which start right
+        // after stdio, so that guests scanning for preopens find them all.
+        let first_socket_fd = 3 + preopen_dirs.len() as u32;
+        for (fd, listener) in (first_socket_fd..).zip(listeners) {
+            cx1.preopened_socket(fd, listener.try_clone()?);
         }
 
         let cx1 = cx1.build()?;
@@ -362,15 +685,28 @@ impl ModuleRegistry {
 
         cx2.inherit_stdio().args(argv).envs(vars);
 
-        for (name, file) in preopen_dirs {
-            cx2.preopened_dir(file.try_clone()?, name);
+        for (name, file, access) in preopen_dirs {
+            match access {
+                DirAccess::ReadWrite => cx2.preopened_dir(file.try_clone()?, name),
+                DirAccess::ReadOnly => cx2.preopened_dir_with_rights(
+                    file.try_clone()?,
+                    name,
+                    EntryRights::read_only_dir(),
+                ),
+            };
         }
 
         let cx2 = cx2.build()?;
 
+        let traced = trace.is_some();
+        let straced = strace.is_some();
+        let wasi_snapshot_preview1 = Wasi::with_tracing(store, cx1, trace, strace);
+
         Ok(ModuleRegistry {
-            wasi_snapshot_preview1: Wasi::new(store, cx1),
+            wasi_snapshot_preview1,
             wasi_unstable: WasiSnapshot0::new(store, cx2),
+            traced,
+            straced,
         })
     }
 }
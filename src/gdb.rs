@@ -0,0 +1,449 @@
+//! A server for the gdb remote serial protocol which debugs wasm guests in
+//! terms of wasm, rather than native, addresses.
+//!
+//! This speaks the dialect lldb uses for wasm targets: each loaded module is
+//! reported as a library whose code lives at its own range of the 64-bit
+//! address space, so debuggers can load the module, including its DWARF,
+//! straight from the original binary. Breakpoints and single-stepping are
+//! implemented with the `wasmtime::Debugger` of the store, and the locals,
+//! globals and memories of paused frames are read with `qWasm*` packets.
+//!
+//! Addresses are encoded as follows:
+//!
+//! * bits 0-31: the offset within the module binary or linear memory
+//! * bits 32-61: the index of the module, in the order they were loaded
+//! * bits 62-63: `1` for module code and `0` for linear memory
+
+use anyhow::{Context as _, Result};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::rc::Rc;
+use wasmtime::{DebugAction, DebugEvent, DebugFrame, Debugger, Module, Store, Trap, Val};
+
+const CODE_SPACE: u64 = 1 << 62;
+
+/// The one thread of the debugged process.
+const THREAD_ID: u32 = 1;
+
+/// gdb's number for `SIGABRT`, which is the same on every host.
+const SIGNAL_ABRT: u8 = 6;
+
+/// A gdb remote protocol connection debugging the wasm code of a `Store`.
+pub(crate) struct GdbServer {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    conn: Connection,
+    debugger: Debugger,
+    modules: Vec<LoadedModule>,
+    /// Whether the client resumed execution and is waiting for it to stop.
+    running: bool,
+    detached: bool,
+}
+
+struct LoadedModule {
+    name: String,
+    module: Module,
+    bytes: Vec<u8>,
+}
+
+/// What to do after a packet was handled.
+enum Response {
+    Reply(String),
+    Resume(DebugAction),
+    Kill,
+}
+
+impl GdbServer {
+    /// Waits for a debugger to connect to `port` on the loopback interface.
+    ///
+    /// Execution is paused at the first wasm instruction which runs in
+    /// `store`, which is where the debugger gets control.
+    pub(crate) fn accept(store: &Store, port: u16) -> Result<GdbServer> {
+        let debugger = store.debugger()?;
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .with_context(|| format!("failed to listen on port {}", port))?;
+        eprintln!(
+            "waiting for a debugger to connect to {}",
+            listener.local_addr()?
+        );
+        let (stream, _) = listener.accept()?;
+        let state = Rc::new(RefCell::new(State {
+            conn: Connection::new(stream)?,
+            debugger: debugger.clone(),
+            modules: Vec::new(),
+            running: false,
+            detached: false,
+        }));
+
+        let events = state.clone();
+        debugger.on_event(move |event| {
+            events
+                .borrow_mut()
+                .stopped(event)
+                .map_err(|e| Trap::new(format!("debugger connection failed: {}", e)))?
+                .ok_or_else(|| Trap::new("killed by the debugger"))
+        });
+        debugger.step();
+        Ok(GdbServer { state })
+    }
+
+    /// Reports `module`, compiled from `bytes` read from `path`, to the
+    /// debugger. This must be called before the module is instantiated so
+    /// that breakpoints can be set in its start function.
+    pub(crate) fn add_module(&self, path: &Path, module: &Module, bytes: Vec<u8>) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "module.wasm".to_string());
+        self.state.borrow_mut().modules.push(LoadedModule {
+            name,
+            module: module.clone(),
+            bytes,
+        });
+    }
+
+    /// Tells the debugger that the program exited with `code`.
+    pub(crate) fn exited(&self, code: u8) -> Result<()> {
+        self.finished(&format!("W{:02x}", code))
+    }
+
+    /// Tells the debugger that the program was terminated by a trap, which
+    /// is reported as an abort, like the exit status of the process.
+    pub(crate) fn trapped(&self) -> Result<()> {
+        self.finished(&format!("X{:02x}", SIGNAL_ABRT))
+    }
+
+    fn finished(&self, packet: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.detached {
+            return Ok(());
+        }
+        state.conn.send(packet)?;
+        Ok(())
+    }
+}
+
+impl State {
+    /// Serves packets while execution is paused, returning how to resume, or
+    /// `None` if the program should be killed.
+    fn stopped(&mut self, event: &DebugEvent) -> io::Result<Option<DebugAction>> {
+        if self.running {
+            self.running = false;
+            self.conn.send(&stop_reply())?;
+        }
+        loop {
+            let packet = self.conn.recv()?;
+            match self.handle(&packet, event) {
+                Response::Reply(reply) => {
+                    self.conn.send(&reply)?;
+                    // The request itself is still acknowledged.
+                    if packet == "QStartNoAckMode" {
+                        self.conn.no_ack = true;
+                    }
+                }
+                Response::Resume(action) => {
+                    self.running = !self.detached;
+                    return Ok(Some(action));
+                }
+                Response::Kill => return Ok(None),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str, event: &DebugEvent) -> Response {
+        let reply = |s: &str| Response::Reply(s.to_string());
+        let (name, args) = match packet.find(|c| c == ':' || c == ',' || c == ';') {
+            Some(i) if packet.starts_with('q') || packet.starts_with('Q') => {
+                (&packet[..i], &packet[i + 1..])
+            }
+            _ => (packet, ""),
+        };
+        match name {
+            "qSupported" => reply("PacketSize=4000;qXfer:libraries:read+;QStartNoAckMode+"),
+            "QStartNoAckMode" => reply("OK"),
+            "?" => Response::Reply(stop_reply()),
+            "qHostInfo" => Response::Reply(format!(
+                "triple:{};endian:little;ptrsize:4;",
+                hex(b"wasm32-unknown-unknown-wasm")
+            )),
+            "qProcessInfo" => Response::Reply(format!(
+                "pid:{:x};parent-pid:{:x};arch:wasm32;triple:{};endian:little;ptrsize:4;",
+                std::process::id(),
+                std::process::id(),
+                hex(b"wasm32-unknown-unknown-wasm")
+            )),
+            "qfThreadInfo" => Response::Reply(format!("m{:x}", THREAD_ID)),
+            "qsThreadInfo" => reply("l"),
+            "qC" => Response::Reply(format!("QC{:x}", THREAD_ID)),
+            "qAttached" => reply("1"),
+            "qRegisterInfo0" => reply(
+                "name:pc;alt-name:pc;bitsize:64;offset:0;encoding:uint;format:hex;\
+                 set:General Purpose Registers;gcc:16;dwarf:16;generic:pc;",
+            ),
+            "qXfer" => Response::Reply(self.libraries(args).unwrap_or_else(error)),
+            "qWasmCallStack" => {
+                let mut pcs = Vec::new();
+                for frame in event.frames() {
+                    pcs.extend_from_slice(&self.pc(&frame).to_le_bytes());
+                }
+                Response::Reply(hex(&pcs))
+            }
+            "qWasmLocal" => Response::Reply(
+                frame_value(event, args, |frame, i| frame.locals().get(i).cloned())
+                    .unwrap_or_else(error),
+            ),
+            "qWasmStackValue" => Response::Reply(
+                frame_value(event, args, |frame, i| frame.stack().get(i).cloned())
+                    .unwrap_or_else(error),
+            ),
+            "qWasmGlobal" => Response::Reply(
+                frame_value(event, args, |frame, i| {
+                    Some(Some(frame.global(i as u32)?.get()))
+                })
+                .unwrap_or_else(error),
+            ),
+            "qWasmMem" => Response::Reply(read_memory(event, args).unwrap_or_else(error)),
+            _ if name.starts_with('H') => reply("OK"),
+            _ if name.starts_with('p') || name == "g" => {
+                let pc = event.frames().next().map_or(0, |frame| self.pc(&frame));
+                Response::Reply(hex(&pc.to_le_bytes()))
+            }
+            _ if name.starts_with('m') => {
+                Response::Reply(self.read_code(&name[1..]).unwrap_or_else(error))
+            }
+            _ if name.starts_with("Z0,") => {
+                Response::Reply(self.breakpoint(&name[3..], true).unwrap_or_else(error))
+            }
+            _ if name.starts_with("z0,") => {
+                Response::Reply(self.breakpoint(&name[3..], false).unwrap_or_else(error))
+            }
+            "vCont?" => reply("vCont;c;C;s;S"),
+            _ if name.starts_with("vCont;c") || name.starts_with("vCont;C") || name == "c" => {
+                Response::Resume(DebugAction::Continue)
+            }
+            _ if name.starts_with("vCont;s") || name.starts_with("vCont;S") || name == "s" => {
+                Response::Resume(DebugAction::Step)
+            }
+            "D" => {
+                self.debugger.clear_breakpoints();
+                self.detached = true;
+                // The client expects a reply before the connection goes away,
+                // and nothing after it.
+                let _ = self.conn.send("OK");
+                Response::Resume(DebugAction::Continue)
+            }
+            "k" => Response::Kill,
+            _ => reply(""),
+        }
+    }
+
+    /// Returns the address of the instruction `frame` is paused at.
+    fn pc(&self, frame: &DebugFrame) -> u64 {
+        let id = self
+            .modules
+            .iter()
+            .position(|m| frame.is_in(&m.module))
+            .unwrap_or(0);
+        CODE_SPACE | ((id as u64) << 32) | frame.offset().unwrap_or(0) as u64
+    }
+
+    /// Handles `qXfer:libraries:read::<offset>,<length>`.
+    fn libraries(&self, args: &str) -> Option<String> {
+        let prefix = "libraries:read::";
+        if !args.starts_with(prefix) {
+            return None;
+        }
+        let (offset, length) = parse_pair(&args[prefix.len()..], ',')?;
+        let mut xml = String::from("<library-list>");
+        for (id, module) in self.modules.iter().enumerate() {
+            write!(
+                xml,
+                "<library name=\"{}\"><section address=\"0x{:x}\"/></library>",
+                module.name.replace('&', "&amp;").replace('"', "&quot;"),
+                CODE_SPACE | ((id as u64) << 32)
+            )
+            .unwrap();
+        }
+        xml.push_str("</library-list>");
+        let end = offset.checked_add(length)?.min(xml.len() as u64);
+        let start = offset.min(end) as usize;
+        let end = end as usize;
+        let more = if end < xml.len() { 'm' } else { 'l' };
+        Some(format!("{}{}", more, &xml[start..end]))
+    }
+
+    /// Handles `m<address>,<length>` for addresses of module code.
+    fn read_code(&self, args: &str) -> Option<String> {
+        let (addr, len) = parse_pair(args, ',')?;
+        let (id, offset) = split_code_address(addr)?;
+        let bytes = &self.modules.get(id)?.bytes;
+        if offset >= bytes.len() {
+            return None;
+        }
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| offset.checked_add(len))?
+            .min(bytes.len());
+        Some(hex(&bytes[offset..end]))
+    }
+
+    /// Handles `Z0` and `z0` packets, whose `kind` is ignored.
+    fn breakpoint(&self, args: &str, insert: bool) -> Option<String> {
+        let addr = u64::from_str_radix(args.split(',').next()?, 16).ok()?;
+        let (id, offset) = split_code_address(addr)?;
+        let module = &self.modules.get(id)?.module;
+        if insert {
+            self.debugger.set_breakpoint(module, offset);
+        } else {
+            self.debugger.remove_breakpoint(module, offset);
+        }
+        Some("OK".to_string())
+    }
+}
+
+/// Handles packets of the form `<name>:<frame>;<index>`, replying with the
+/// bytes of the value `get` returns.
+fn frame_value(
+    event: &DebugEvent,
+    args: &str,
+    get: impl Fn(&DebugFrame, usize) -> Option<Option<Val>>,
+) -> Option<String> {
+    let (frame, index) = parse_pair(args, ';')?;
+    let frame = event.frames().nth(frame as usize)?;
+    let bytes = match get(&frame, index as usize)?? {
+        Val::I32(i) => i.to_le_bytes().to_vec(),
+        Val::I64(i) => i.to_le_bytes().to_vec(),
+        Val::F32(f) => f.to_le_bytes().to_vec(),
+        Val::F64(f) => f.to_le_bytes().to_vec(),
+        Val::V128(v) => v.to_le_bytes().to_vec(),
+        _ => return None,
+    };
+    Some(hex(&bytes))
+}
+
+/// Handles `qWasmMem:<frame>;<address>;<length>`, reading the first memory of
+/// the frame's instance.
+fn read_memory(event: &DebugEvent, args: &str) -> Option<String> {
+    let mut parts = args.split(';');
+    let frame = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let addr = u64::from_str_radix(parts.next()?, 16).ok()? as u32 as usize;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let memory = event.frames().nth(frame)?.memory(0)?;
+    // Safety: the memory can't change while it's read since wasm is paused.
+    let data = unsafe { memory.data_unchecked() };
+    let start = addr.min(data.len());
+    let end = addr.saturating_add(len).min(data.len());
+    Some(hex(&data[start..end]))
+}
+
+fn split_code_address(addr: u64) -> Option<(usize, usize)> {
+    if addr >> 62 != CODE_SPACE >> 62 {
+        return None;
+    }
+    Some((
+        ((addr >> 32) & 0x3fff_ffff) as usize,
+        (addr & 0xffff_ffff) as usize,
+    ))
+}
+
+fn parse_pair(s: &str, separator: char) -> Option<(u64, u64)> {
+    let mut parts = s.splitn(2, separator);
+    let a = u64::from_str_radix(parts.next()?, 16).ok()?;
+    let b = u64::from_str_radix(parts.next()?, 16).ok()?;
+    Some((a, b))
+}
+
+fn stop_reply() -> String {
+    // Execution always stops with a trap, as if a breakpoint instruction was
+    // hit.
+    format!("T05thread:{:x};", THREAD_ID)
+}
+
+fn error() -> String {
+    "E03".to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{:02x}", b).unwrap();
+    }
+    s
+}
+
+/// The framing layer of the protocol.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Set by `QStartNoAckMode` once acknowledgements are no longer sent.
+    no_ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            no_ack: false,
+        })
+    }
+
+    /// Receives the next packet, skipping acknowledgements and interrupt
+    /// requests, which are meaningless while execution is paused.
+    fn recv(&mut self) -> io::Result<String> {
+        loop {
+            let mut byte = [0];
+            self.reader.read_exact(&mut byte)?;
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut data = Vec::new();
+        self.reader.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+        if !self.no_ack {
+            self.writer.write_all(b"+")?;
+        }
+
+        let mut packet = Vec::with_capacity(data.len());
+        let mut escaped = false;
+        for b in data {
+            if escaped {
+                packet.push(b ^ 0x20);
+                escaped = false;
+            } else if b == b'}' {
+                escaped = true;
+            } else {
+                packet.push(b);
+            }
+        }
+        Ok(String::from_utf8_lossy(&packet).into_owned())
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        for &b in data.as_bytes() {
+            if b == b'$' || b == b'#' || b == b'}' || b == b'*' {
+                packet.push(b'}');
+                packet.push(b ^ 0x20);
+            } else {
+                packet.push(b);
+            }
+        }
+        let checksum = packet[1..].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        write!(packet, "#{:02x}", checksum)?;
+        self.writer.write_all(&packet)
+    }
+}
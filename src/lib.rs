@@ -25,6 +25,7 @@
 )]
 
 pub mod commands;
+mod gdb;
 mod obj;
 
 use anyhow::{bail, Result};
@@ -43,15 +44,19 @@ fn pick_compilation_strategy(cranelift: bool, lightbeam: bool) -> Result<Strateg
     })
 }
 
-fn pick_profiling_strategy(jitdump: bool, vtune: bool) -> Result<ProfilingStrategy> {
-    Ok(match (jitdump, vtune) {
-        (true, false) => ProfilingStrategy::JitDump,
-        (false, true) => ProfilingStrategy::VTune,
-        (true, true) => {
-            println!("Can't enable --jitdump and --vtune at the same time. Profiling not enabled.");
+fn pick_profiling_strategy(jitdump: bool, vtune: bool, perfmap: bool) -> Result<ProfilingStrategy> {
+    Ok(match (jitdump, vtune, perfmap) {
+        (true, false, false) => ProfilingStrategy::JitDump,
+        (false, true, false) => ProfilingStrategy::VTune,
+        (false, false, true) => ProfilingStrategy::PerfMap,
+        (false, false, false) => ProfilingStrategy::None,
+        _ => {
+            println!(
+                "Can't enable more than one of --jitdump, --vtune and --perfmap at the same \
+                 time. Profiling not enabled."
+            );
             ProfilingStrategy::None
         }
-        _ => ProfilingStrategy::None,
     })
 }
 
@@ -133,13 +138,17 @@ struct CommonOptions {
     lightbeam: bool,
 
     /// Generate jitdump file (supported on --features=profiling build)
-    #[structopt(long, conflicts_with = "vtune")]
+    #[structopt(long, conflicts_with_all = &["vtune", "perfmap"])]
     jitdump: bool,
 
     /// Generate vtune (supported on --features=vtune build)
-    #[structopt(long, conflicts_with = "jitdump")]
+    #[structopt(long, conflicts_with_all = &["jitdump", "perfmap"])]
     vtune: bool,
 
+    /// Generate a /tmp/perf-<pid>.map file for `perf` (supported on Linux)
+    #[structopt(long, conflicts_with_all = &["jitdump", "vtune"])]
+    perfmap: bool,
+
     /// Run optimization passes on translated functions, on by default
     #[structopt(short = "O", long)]
     optimize: bool,
@@ -167,7 +176,11 @@ impl CommonOptions {
             .wasm_threads(self.enable_threads || self.enable_all)
             .cranelift_opt_level(self.opt_level())
             .strategy(pick_compilation_strategy(self.cranelift, self.lightbeam)?)?
-            .profiler(pick_profiling_strategy(self.jitdump, self.vtune)?)?;
+            .profiler(pick_profiling_strategy(
+                self.jitdump,
+                self.vtune,
+                self.perfmap,
+            )?)?;
         if !self.disable_cache {
             match &self.config {
                 Some(path) => {
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (import "" "host" (func $host))
+        (func (export "run")
+            call $host
+            call $host))
+"#;
+
+fn record_hooks(store: &Store) -> Rc<RefCell<Vec<CallHook>>> {
+    let hooks = Rc::new(RefCell::new(Vec::new()));
+    let hooks2 = hooks.clone();
+    store.call_hook(move |hook| {
+        hooks2.borrow_mut().push(hook);
+        Ok(())
+    });
+    hooks
+}
+
+#[test]
+fn hooks_fire_on_every_transition() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let hooks = record_hooks(&store);
+
+    let expected = vec![
+        CallHook::CallingWasm,
+        CallHook::CallingHost,
+        CallHook::ReturningFromHost,
+        CallHook::CallingHost,
+        CallHook::ReturningFromHost,
+        CallHook::ReturningFromWasm,
+    ];
+
+    // Host functions defined with `Func::wrap`, called with typed wrappers.
+    let host = Func::wrap(&store, || {});
+    let instance = Instance::new(&module, &[host.into()])?;
+    instance.get_func("run").unwrap().get0::<()>()?()?;
+    assert_eq!(*hooks.borrow(), expected);
+
+    // Host functions defined with `Func::new`, called with `Func::call`.
+    hooks.borrow_mut().clear();
+    let host = Func::new(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        |_, _, _| Ok(()),
+    );
+    let instance = Instance::new(&module, &[host.into()])?;
+    instance.get_func("run").unwrap().call(&[])?;
+    assert_eq!(*hooks.borrow(), expected);
+    Ok(())
+}
+
+#[test]
+fn hook_errors_trap() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let calls = Rc::new(Cell::new(0));
+    let calls2 = calls.clone();
+    let host = Func::wrap(&store, move || calls2.set(calls2.get() + 1));
+    let instance = Instance::new(&module, &[host.into()])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+
+    // Refusing to enter the host traps the guest before the host function
+    // runs.
+    store.call_hook(|hook| match hook {
+        CallHook::CallingHost => Err(Trap::new("out of fuel")),
+        _ => Ok(()),
+    });
+    let trap = run().unwrap_err();
+    assert!(trap.message().contains("out of fuel"), "{}", trap.message());
+    assert_eq!(calls.get(), 0);
+
+    // Refusing to return from the host traps after it ran once.
+    store.call_hook(|hook| match hook {
+        CallHook::ReturningFromHost => Err(Trap::new("out of fuel")),
+        _ => Ok(()),
+    });
+    assert!(run().is_err());
+    assert_eq!(calls.get(), 1);
+
+    // Refusing to enter wasm doesn't run anything.
+    store.call_hook(|hook| match hook {
+        CallHook::CallingWasm => Err(Trap::new("out of fuel")),
+        _ => Ok(()),
+    });
+    assert!(instance.get_func("run").unwrap().call(&[]).is_err());
+    assert!(run().is_err());
+    assert_eq!(calls.get(), 1);
+    Ok(())
+}
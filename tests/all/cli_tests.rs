@@ -1,11 +1,13 @@
 use anyhow::{bail, Result};
-use std::io::Write;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::path::Path;
-use std::process::{Command, Output};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
 use tempfile::NamedTempFile;
 
-// Run the wasmtime CLI with the provided args and return the `Output`.
-fn run_wasmtime_for_output(args: &[&str]) -> Result<Output> {
+// Build a `Command` which runs the wasmtime CLI with the provided args.
+fn wasmtime_command(args: &[&str]) -> Result<Command> {
     let runner = std::env::vars()
         .filter(|(k, _v)| k.starts_with("CARGO_TARGET") && k.ends_with("RUNNER"))
         .next();
@@ -28,7 +30,13 @@ fn run_wasmtime_for_output(args: &[&str]) -> Result<Output> {
     } else {
         Command::new(&me)
     };
-    cmd.args(args).output().map_err(Into::into)
+    cmd.args(args);
+    Ok(cmd)
+}
+
+// Run the wasmtime CLI with the provided args and return the `Output`.
+fn run_wasmtime_for_output(args: &[&str]) -> Result<Output> {
+    wasmtime_command(args)?.output().map_err(Into::into)
 }
 
 // Run the wasmtime CLI with the provided args and, if it succeeds, return
@@ -147,6 +155,40 @@ fn hello_wasi_snapshot1() -> Result<()> {
     Ok(())
 }
 
+// Exit with a valid non-zero exit code, snapshot0 edition.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn exit125_wasi_snapshot0() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/exit125_wasi_snapshot0.wat")?;
+    let output = run_wasmtime_for_output(&[wasm.path().to_str().unwrap(), "--disable-cache"])?;
+    assert_eq!(output.status.code().unwrap(), 125);
+    Ok(())
+}
+
+// Exit with a valid non-zero exit code, snapshot1 edition.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn exit125_wasi_snapshot1() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/exit125_wasi_snapshot1.wat")?;
+    let output = run_wasmtime_for_output(&[wasm.path().to_str().unwrap(), "--disable-cache"])?;
+    assert_eq!(output.status.code().unwrap(), 125);
+    Ok(())
+}
+
+// Hand a listening socket to a WASI program.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn run_wasmtime_tcplisten() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/tcplisten.wat")?;
+    run_wasmtime(&[
+        wasm.path().to_str().unwrap(),
+        "--tcplisten",
+        "127.0.0.1:0",
+        "--disable-cache",
+    ])?;
+    Ok(())
+}
+
 #[test]
 fn timeout_in_start() -> Result<()> {
     let wasm = build_wasm("tests/wasm/iloop-start.wat")?;
@@ -188,3 +230,272 @@ fn timeout_in_invoke() -> Result<()> {
     );
     Ok(())
 }
+
+// A minimal gdb remote protocol client, which doesn't send acknowledgements
+// since it switches to no-ack mode first.
+struct GdbClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl GdbClient {
+    fn connect(port: u16) -> Result<GdbClient> {
+        // Retry for a while since the server may not be listening yet.
+        let mut attempts = 0;
+        let stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) if attempts < 200 => {
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        Ok(GdbClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    fn send(&mut self, packet: &str) -> Result<()> {
+        let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.writer, "${}#{:02x}", packet, checksum)?;
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<String> {
+        loop {
+            let mut byte = [0];
+            self.reader.read_exact(&mut byte)?;
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut data = Vec::new();
+        self.reader.read_until(b'#', &mut data)?;
+        data.pop();
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+        Ok(String::from_utf8(data)?)
+    }
+
+    fn request(&mut self, packet: &str) -> Result<String> {
+        self.send(packet)?;
+        self.recv()
+    }
+}
+
+// Debug a module over the gdb remote protocol: step into a function, set a
+// breakpoint there and read the locals when it's hit.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn gdb_breakpoints_and_locals() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/gdb.wat")?;
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let mut child = wasmtime_command(&[
+        "run",
+        "--gdb-port",
+        &port.to_string(),
+        wasm.path().to_str().unwrap(),
+        "--invoke",
+        "run",
+        "--disable-cache",
+    ])?
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()?;
+
+    let mut gdb = GdbClient::connect(port)?;
+    assert!(gdb.request("qSupported")?.contains("qXfer:libraries:read+"));
+    assert_eq!(gdb.request("QStartNoAckMode")?, "OK");
+    assert_eq!(gdb.request("?")?, "T05thread:1;");
+    let libraries = gdb.request("qXfer:libraries:read::0,1000")?;
+    assert!(libraries.starts_with("l<library-list>"), "{}", libraries);
+    assert!(libraries.contains("address=\"0x4000000000000000\""));
+
+    // Paused at the first instruction of `run`; step into `add`.
+    let stack = gdb.request("qWasmCallStack")?;
+    assert_eq!(stack.len(), 16);
+    for _ in 0..3 {
+        assert_eq!(gdb.request("s")?, "T05thread:1;");
+    }
+    let stack = gdb.request("qWasmCallStack")?;
+    assert_eq!(stack.len(), 32);
+    let pc = u64::from_str_radix(&gdb.request("p0")?, 16)?.swap_bytes();
+    assert_eq!(pc >> 32, 0x4000_0000);
+    assert_eq!(gdb.request("qWasmLocal:0;0")?, "05000000");
+    assert_eq!(gdb.request("qWasmLocal:0;1")?, "07000000");
+
+    // The breakpoint is hit by the second call.
+    assert_eq!(gdb.request(&format!("Z0,{:x},1", pc))?, "OK");
+    assert_eq!(gdb.request("c")?, "T05thread:1;");
+    assert_eq!(gdb.request("qWasmLocal:0;0")?, "0c000000");
+    assert_eq!(gdb.request("qWasmLocal:0;1")?, "01000000");
+    assert_eq!(gdb.request(&format!("z0,{:x},1", pc))?, "OK");
+    assert_eq!(gdb.request("c")?, "W00");
+
+    let mut stdout = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut stdout)?;
+    assert!(child.wait()?.success());
+    assert_eq!(stdout, "13\n");
+    Ok(())
+}
+
+// Out of range reads get an error reply, and the exit status of the guest is
+// reported when it exits.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn gdb_bad_reads_and_exit_status() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/gdb_exit.wat")?;
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let mut child = wasmtime_command(&[
+        "run",
+        "--gdb-port",
+        &port.to_string(),
+        wasm.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()?;
+
+    let mut gdb = GdbClient::connect(port)?;
+    assert_eq!(gdb.request("QStartNoAckMode")?, "OK");
+    assert_eq!(gdb.request("?")?, "T05thread:1;");
+    assert!(gdb.request("m4000000000000000,4")?.starts_with("0061736d"));
+    assert!(gdb
+        .request("m4000000000000010,ffffffffffffffff")?
+        .starts_with('E'));
+    assert!(gdb.request("m40000000ffffff00,10")?.starts_with('E'));
+    let libraries = gdb.request("qXfer:libraries:read::10,ffffffffffffffff")?;
+    assert!(libraries.starts_with('E'), "{}", libraries);
+    assert_eq!(gdb.request("qXfer:libraries:read::100000,10")?, "l");
+
+    assert_eq!(gdb.request("c")?, "W07");
+    assert_eq!(child.wait()?.code(), Some(7));
+    Ok(())
+}
+
+// Write the coverage of a module with DWARF line information.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn run_wasmtime_coverage() -> Result<()> {
+    let lcov = NamedTempFile::new()?;
+    let stdout = run_wasmtime(&[
+        "run",
+        "--coverage",
+        lcov.path().to_str().unwrap(),
+        "tests/all/debug/testsuite/fib-wasm.wasm",
+        "--invoke",
+        "fib",
+        "--disable-cache",
+        "3",
+    ])?;
+    assert_eq!(stdout, "3\n");
+    let report = std::fs::read_to_string(lcov.path())?;
+    assert!(report.contains("DA:8,3\n"), "{}", report);
+    Ok(())
+}
+
+// Record the WASI calls of a program and replay them without touching the host.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn run_wasmtime_record_replay() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/hello_wasi_snapshot1.wat")?;
+    let trace = NamedTempFile::new()?;
+    let stdout = run_wasmtime(&[
+        wasm.path().to_str().unwrap(),
+        "--record",
+        trace.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?;
+    assert_eq!(stdout, "Hello, world!\n");
+
+    // The recorded `fd_write` succeeds, but doesn't print anything again.
+    let stdout = run_wasmtime(&[
+        wasm.path().to_str().unwrap(),
+        "--replay",
+        trace.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?;
+    assert_eq!(stdout, "");
+
+    // A different program diverges from the trace.
+    let wasm = build_wasm("tests/wasm/exit125_wasi_snapshot1.wat")?;
+    let output = run_wasmtime_for_output(&[
+        wasm.path().to_str().unwrap(),
+        "--replay",
+        trace.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("WASI trace diverged"),
+        "bad stderr: {}",
+        stderr
+    );
+    Ok(())
+}
+
+// Print the WASI calls of a program.
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn run_wasmtime_trace_wasi() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/hello_wasi_snapshot1.wat")?;
+    let strace = NamedTempFile::new()?;
+    let stdout = run_wasmtime(&[
+        wasm.path().to_str().unwrap(),
+        "--trace-wasi",
+        "--trace-wasi-output",
+        strace.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?;
+    assert_eq!(stdout, "Hello, world!\n");
+    assert_eq!(
+        std::fs::read_to_string(strace.path())?,
+        "fd_write(fd=1, iovs=[14]) = success nwritten=14\n"
+    );
+
+    // Filtered calls go to stderr, as JSON if asked to.
+    let wasm = build_wasm("tests/wasm/exit125_wasi_snapshot1.wat")?;
+    let output = run_wasmtime_for_output(&[
+        wasm.path().to_str().unwrap(),
+        "--trace-wasi",
+        "--trace-wasi-filter",
+        "proc",
+        "--trace-wasi-json",
+        "--disable-cache",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 125);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "{\"call\":\"proc_exit\",\"args\":{\"rval\":125},\"exit\":125}\n"
+    );
+    Ok(())
+}
+
+// Write the guest profile where asked to, sampling at the given interval.
+#[test]
+#[cfg_attr(not(all(target_os = "linux", target_arch = "x86_64")), ignore)]
+fn run_wasmtime_profile_output() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/hello_wasi_snapshot1.wat")?;
+    let dir = tempfile::tempdir()?;
+    let json = dir.path().join("hello.json");
+    let output = run_wasmtime_for_output(&[
+        wasm.path().to_str().unwrap(),
+        "--profile",
+        "guest",
+        "--profile-output",
+        json.to_str().unwrap(),
+        "--profile-interval",
+        "100us",
+        "--disable-cache",
+    ])?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, world!\n");
+    assert!(std::fs::read_to_string(&json)?.starts_with("{\"meta\":"));
+    assert!(dir.path().join("hello.folded").exists());
+    Ok(())
+}
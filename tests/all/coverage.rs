@@ -0,0 +1,52 @@
+use anyhow::Result;
+use wasmtime::*;
+
+fn fib_instance(store: &Store) -> Result<(Instance, Module)> {
+    // See `fib-wasm.c` for the source lines reported below.
+    let module = Module::new(
+        store,
+        std::fs::read("tests/all/debug/testsuite/fib-wasm.wasm")?,
+    )?;
+    Ok((Instance::new(&module, &[])?, module))
+}
+
+#[test]
+fn lcov_counts_executed_lines() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coverage(true)));
+    let (instance, module) = fib_instance(&store)?;
+    let fib = instance.get_func("fib").unwrap().get1::<i32, i32>()?;
+    assert_eq!(fib(3)?, 3);
+
+    let report = instance.coverage_report()?;
+    assert!(report.starts_with("TN:\nSF:"), "{}", report);
+    assert!(report.contains("fib-wasm.c\n"), "{}", report);
+    assert!(report.contains("FN:5,fib\n"), "{}", report);
+    assert!(report.contains("FNDA:1,fib\n"), "{}", report);
+    // The loop condition runs once more than the loop body.
+    assert!(report.contains("DA:7,4\n"), "{}", report);
+    assert!(report.contains("DA:8,3\n"), "{}", report);
+    assert!(report.contains("DA:12,1\n"), "{}", report);
+    assert!(report.ends_with("end_of_record\n"), "{}", report);
+
+    // Counts accumulate across calls, but not across instances.
+    fib(2)?;
+    assert!(instance.coverage_report()?.contains("DA:8,5\n"));
+    let other = Instance::new(&module, &[])?;
+    assert!(other.coverage_report()?.contains("DA:8,0\n"));
+    Ok(())
+}
+
+#[test]
+fn coverage_report_requirements() -> Result<()> {
+    // Coverage must be enabled...
+    let (instance, _) = fib_instance(&Store::default())?;
+    assert!(instance.coverage_report().is_err());
+
+    // ... and the module must have DWARF line information.
+    let store = Store::new(&Engine::new(Config::new().coverage(true)));
+    let module = Module::new(&store, r#"(func (export "f"))"#)?;
+    let instance = Instance::new(&module, &[])?;
+    instance.get_func("f").unwrap().call(&[])?;
+    assert!(instance.coverage_report().is_err());
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasmtime::*;
+
+fn debug_store() -> Store {
+    let engine = Engine::new(Config::new().debug_instrumentation(true));
+    Store::new(&engine)
+}
+
+fn i32s(values: Vec<Option<Val>>) -> Vec<i32> {
+    values
+        .into_iter()
+        .map(|v| v.unwrap().unwrap_i32())
+        .collect()
+}
+
+#[test]
+fn debugger_requires_instrumentation() {
+    assert!(Store::default().debugger().is_err());
+    assert!(debug_store().debugger().is_ok());
+}
+
+#[test]
+fn step_reports_locals_and_stack() -> Result<()> {
+    let store = debug_store();
+    let debugger = store.debugger()?;
+    let module = Module::new(
+        &store,
+        r#"
+            (func (export "add") (param i32 i32) (result i32) (local i32)
+                local.get 0
+                local.get 1
+                i32.add)
+        "#,
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let add = instance.get_func("add").unwrap().get2::<i32, i32, i32>()?;
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events2 = events.clone();
+    debugger.on_event(move |event| {
+        assert_eq!(event.kind(), DebugEventKind::Step);
+        let frame = event.frames().next().unwrap();
+        events2.borrow_mut().push((
+            frame.offset().unwrap(),
+            i32s(frame.locals()),
+            i32s(frame.stack()),
+        ));
+        // Stop stepping at the function's `end`.
+        if events2.borrow().len() == 4 {
+            Ok(DebugAction::Continue)
+        } else {
+            Ok(DebugAction::Step)
+        }
+    });
+    debugger.step();
+    assert_eq!(add(1, 2)?, 3);
+
+    let events = events.borrow();
+    let states = events
+        .iter()
+        .map(|(_, locals, stack)| (locals.clone(), stack.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        states,
+        vec![
+            (vec![1, 2, 0], vec![]),
+            (vec![1, 2, 0], vec![1]),
+            (vec![1, 2, 0], vec![1, 2]),
+            (vec![1, 2, 0], vec![3]),
+        ]
+    );
+    // Each instruction is reported at its own, increasing offset.
+    assert!(events.windows(2).all(|w| w[0].0 < w[1].0));
+    Ok(())
+}
+
+#[test]
+fn breakpoints_pause_with_caller_frames() -> Result<()> {
+    let store = debug_store();
+    let debugger = store.debugger()?;
+    let module = Module::new(
+        &store,
+        r#"
+            (global (mut i32) (i32.const 7))
+            (memory 1)
+            (data (i32.const 0) "\2a")
+            (func $callee (param i32) (result i32)
+                local.get 0
+                global.get 0
+                i32.add)
+            (func (export "run") (result i32)
+                i32.const 10
+                call $callee
+                i32.const 10
+                call $callee
+                i32.add)
+        "#,
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let run = instance.get_func("run").unwrap().get0::<i32>()?;
+
+    // Find the offset of `global.get` by stepping into the first call.
+    let offsets = Rc::new(RefCell::new(Vec::new()));
+    let offsets2 = offsets.clone();
+    debugger.on_event(move |event| {
+        let frame = event.frames().next().unwrap();
+        let mut offsets = offsets2.borrow_mut();
+        offsets.push((frame.func_index(), frame.offset().unwrap()));
+        if offsets.len() == 4 {
+            Ok(DebugAction::Continue)
+        } else {
+            Ok(DebugAction::Step)
+        }
+    });
+    debugger.step();
+    assert_eq!(run()?, 34);
+    let (func, global_get) = offsets.borrow()[3];
+    assert_eq!(func, 0);
+
+    let hits = Rc::new(RefCell::new(0));
+    let hits2 = hits.clone();
+    debugger.on_event(move |event| {
+        assert_eq!(event.kind(), DebugEventKind::Breakpoint);
+        let frames = event.frames().collect::<Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].func_index(), 0);
+        assert_eq!(frames[0].offset(), Some(global_get));
+        assert_eq!(i32s(frames[0].locals()), vec![10]);
+        assert_eq!(frames[1].func_index(), 1);
+        assert_eq!(i32s(frames[1].stack()).last(), Some(&10));
+
+        // Globals and memory of the paused instance can be modified.
+        let global = frames[0].global(0).unwrap();
+        let expected = if *hits2.borrow() == 0 { 7 } else { 100 };
+        assert_eq!(global.get().unwrap_i32(), expected);
+        global.set(Val::I32(100)).unwrap();
+        assert!(frames[0].global(1).is_none());
+        let memory = frames[0].memory(0).unwrap();
+        assert_eq!(unsafe { memory.data_unchecked()[0] }, 42);
+
+        *hits2.borrow_mut() += 1;
+        Ok(DebugAction::Continue)
+    });
+    debugger.set_breakpoint(&module, global_get);
+    assert_eq!(run()?, 220);
+    assert_eq!(*hits.borrow(), 2);
+
+    assert!(debugger.remove_breakpoint(&module, global_get));
+    assert!(!debugger.remove_breakpoint(&module, global_get));
+    assert_eq!(run()?, 220);
+    assert_eq!(*hits.borrow(), 2);
+    Ok(())
+}
+
+#[test]
+fn callback_errors_trap() -> Result<()> {
+    let store = debug_store();
+    let debugger = store.debugger()?;
+    let module = Module::new(&store, r#"(func (export "run") (loop br 0))"#)?;
+    let instance = Instance::new(&module, &[])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+
+    debugger.on_event(|_| Err(Trap::new("stopped by debugger")));
+    debugger.step();
+    let trap = run().unwrap_err();
+    assert!(trap.message().contains("stopped by debugger"));
+    Ok(())
+}
+
+#[test]
+fn frames_which_ran_disarmed_have_no_state() -> Result<()> {
+    let store = debug_store();
+    let debugger = store.debugger()?;
+    let module = Module::new(
+        &store,
+        r#"
+            (import "" "step" (func $step))
+            (func $inner (result i32)
+                call $step
+                i32.const 1)
+            (func (export "run") (param i32) (result i32) (local i32)
+                i32.const 5
+                local.set 1
+                call $inner)
+        "#,
+    )?;
+    let debugger2 = debugger.clone();
+    let step = Func::wrap(&store, move || debugger2.step());
+    let instance = Instance::new(&module, &[step.into()])?;
+    let run = instance.get_func("run").unwrap().get1::<i32, i32>()?;
+
+    let states = Rc::new(RefCell::new(Vec::new()));
+    let states2 = states.clone();
+    debugger.on_event(move |event| {
+        let frames = event
+            .frames()
+            .map(|frame| (frame.offset().is_some(), frame.locals().len()))
+            .collect::<Vec<_>>();
+        states2.borrow_mut().push(frames);
+        // Resuming with nothing to pause at disarms the debugger, until
+        // `$inner` steps again.
+        Ok(DebugAction::Continue)
+    });
+    debugger.step();
+    assert_eq!(run(3)?, 1);
+
+    // `run` kept running while disarmed, so the state it recorded when it
+    // was first paused is stale by the time `$inner` pauses.
+    assert_eq!(
+        *states.borrow(),
+        vec![vec![(true, 2)], vec![(true, 0), (false, 0)]]
+    );
+    Ok(())
+}
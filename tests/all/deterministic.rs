@@ -0,0 +1,191 @@
+use anyhow::Result;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use wasmtime::*;
+
+fn deterministic_store(config: &mut Config) -> Store {
+    Store::new(&Engine::new(config.deterministic(true)))
+}
+
+#[test]
+fn nans_are_canonical() -> Result<()> {
+    let store = deterministic_store(&mut Config::new());
+    let module = Module::new(
+        &store,
+        r#"
+            (func (export "nan") (result i32)
+                f32.const -nan:0x1234
+                f32.const 0
+                f32.add
+                i32.reinterpret_f32)
+        "#,
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let nan = instance.get_func("nan").unwrap().get0::<i32>()?;
+    assert_eq!(nan()? as u32, 0x7fc0_0000);
+    Ok(())
+}
+
+#[test]
+fn nondeterministic_settings_are_rejected() {
+    let mut config = Config::new();
+    config.deterministic(true).wasm_simd(true);
+    let store = Store::new(&Engine::new(&config));
+    let err = Module::new(&store, "(module)").unwrap_err();
+    assert!(err.to_string().contains("SIMD"), "{}", err);
+
+    let mut config = Config::new();
+    config.deterministic(true).interruptable(true);
+    let store = Store::new(&Engine::new(&config));
+    assert!(Module::new(&store, "(module)").is_err());
+
+    let mut config = Config::new();
+    config.deterministic(true).consume_fuel(false);
+    let store = Store::new(&Engine::new(&config));
+    let err = Module::new(&store, "(module)").unwrap_err();
+    assert!(err.to_string().contains("fuel"), "{}", err);
+}
+
+#[test]
+fn nondeterministic_wasi_imports_are_rejected() -> Result<()> {
+    let wat = r#"
+        (module
+            (import "wasi_snapshot_preview1" "fd_write"
+                (func (param i32 i32 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "random_get"
+                (func (param i32 i32) (result i32))))
+    "#;
+
+    let store = deterministic_store(&mut Config::new());
+    let err = Module::new(&store, wat).unwrap_err();
+    assert!(err.to_string().contains("random_get"), "{}", err);
+
+    let mut config = Config::new();
+    config.override_nondeterministic_import("wasi_snapshot_preview1", "random_get");
+    let store = deterministic_store(&mut config);
+    Module::new(&store, wat)?;
+
+    // Without deterministic mode any import is fine.
+    Module::new(&Store::default(), wat)?;
+    Ok(())
+}
+
+/// A memory which can never be grown, like a host out of memory.
+struct ExhaustedMemory;
+
+unsafe impl LinearMemory for ExhaustedMemory {
+    fn size(&self) -> u32 {
+        0
+    }
+
+    fn grow(&self, _delta: u32) -> Option<u32> {
+        None
+    }
+
+    fn as_ptr(&self) -> *mut u8 {
+        NonNull::dangling().as_ptr()
+    }
+}
+
+struct ExhaustedMemoryCreator;
+
+unsafe impl MemoryCreator for ExhaustedMemoryCreator {
+    fn new_memory(&self, _ty: MemoryType) -> Result<Box<dyn LinearMemory>, String> {
+        Ok(Box::new(ExhaustedMemory))
+    }
+}
+
+#[test]
+fn memory_grow_failure_doesnt_depend_on_host() -> Result<()> {
+    let wat = r#"
+        (memory 0 10)
+        (func (export "grow") (param i32) (result i32)
+            local.get 0
+            memory.grow)
+    "#;
+    let mut config = Config::new();
+    config.with_host_memory(Arc::new(ExhaustedMemoryCreator));
+
+    let store = Store::new(&Engine::new(&config));
+    let instance = Instance::new(&Module::new(&store, wat)?, &[])?;
+    let grow = instance.get_func("grow").unwrap().get1::<i32, i32>()?;
+    assert_eq!(grow(1)?, -1);
+
+    let store = deterministic_store(&mut config);
+    let instance = Instance::new(&Module::new(&store, wat)?, &[])?;
+    let grow = instance.get_func("grow").unwrap().get1::<i32, i32>()?;
+    // Exceeding the maximum fails the same way on every host...
+    assert_eq!(grow(11)?, -1);
+    // ... but the host failing to provide memory traps.
+    let trap = grow(1).unwrap_err();
+    assert!(trap.message().contains("out of memory"), "{}", trap);
+    Ok(())
+}
+
+#[test]
+fn memory_can_grow_to_the_max_pages() -> Result<()> {
+    let wat = r#"
+        (memory 0)
+        (func (export "grow") (param i32) (result i32)
+            local.get 0
+            memory.grow)
+    "#;
+    let mut config = Config::new();
+    config.with_host_memory(Arc::new(ExhaustedMemoryCreator));
+    let store = deterministic_store(&mut config);
+    let instance = Instance::new(&Module::new(&store, wat)?, &[])?;
+    let grow = instance.get_func("grow").unwrap().get1::<i32, i32>()?;
+    // Growing past 4GiB always fails...
+    assert_eq!(grow(0x1_0001)?, -1);
+    // ... while growing to exactly 4GiB is within the limits, so the host
+    // failing to provide it traps.
+    let trap = grow(0x1_0000).unwrap_err();
+    assert!(trap.message().contains("out of memory"), "{}", trap);
+    Ok(())
+}
+
+#[test]
+fn running_out_of_fuel_interrupts() -> Result<()> {
+    let store = deterministic_store(&mut Config::new());
+    let module = Module::new(
+        &store,
+        r#"
+            (func (export "spin") (loop br 0))
+            (func (export "count") (param i32) (result i32)
+                (loop
+                    (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                    (br_if 0 (local.get 0)))
+                (local.get 0))
+        "#,
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let spin = instance.get_func("spin").unwrap().get0::<()>()?;
+    let count = instance.get_func("count").unwrap().get1::<i32, i32>()?;
+
+    store.set_fuel(10_000)?;
+    let trap = spin().unwrap_err();
+    assert!(trap.message().contains("wasm trap: interrupt"), "{}", trap);
+    assert_eq!(store.fuel_remaining(), Some(0));
+
+    // The same code consumes the same amount of fuel every time.
+    let mut consumed = Vec::new();
+    for _ in 0..2 {
+        store.set_fuel(10_000)?;
+        assert_eq!(count(100)?, 0);
+        consumed.push(10_000 - store.fuel_remaining().unwrap());
+    }
+    assert!(consumed[0] >= 100 * 6, "{:?}", consumed);
+    assert_eq!(consumed[0], consumed[1]);
+
+    // Running out partway through the loop traps too.
+    store.set_fuel(consumed[0] / 2)?;
+    assert!(count(100).is_err());
+    Ok(())
+}
+
+#[test]
+fn fuel_needs_to_be_enabled() {
+    let store = Store::default();
+    assert!(store.set_fuel(1).is_err());
+    assert_eq!(store.fuel_remaining(), None);
+}
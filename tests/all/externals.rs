@@ -110,3 +110,57 @@ fn cross_store() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn memory_read_write() -> anyhow::Result<()> {
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)));
+
+    memory.write(10, b"hello")?;
+    let mut buf = [0; 5];
+    memory.read(10, &mut buf)?;
+    assert_eq!(&buf, b"hello");
+
+    memory.write_u32(0, 0x0403_0201)?;
+    assert_eq!(memory.read_u8(0)?, 1);
+    assert_eq!(memory.read_u16(1)?, 0x0302);
+    memory.write_u64(65528, u64::max_value())?;
+    assert_eq!(memory.read_u64(65528)?, u64::max_value());
+
+    // Accesses must be entirely in bounds.
+    assert!(memory.read(65532, &mut buf).is_err());
+    assert!(memory.write(65536, &[]).is_ok());
+    assert!(memory.write(usize::max_value(), b"x").is_err());
+    assert!(memory.read_u64(65529).is_err());
+    assert!(memory.write_u16(65535, 0).is_err());
+    assert_eq!(memory.read_u8(65535)?, 0xff);
+
+    // After growing, the new pages are accessible.
+    memory.grow(1)?;
+    memory.write_u32(65536, 7)?;
+    assert_eq!(memory.read_u32(65536)?, 7);
+    Ok(())
+}
+
+#[test]
+fn memory_is_guest_memory() -> anyhow::Result<()> {
+    use wiggle::{GuestError, GuestMemory};
+
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)));
+    memory.write_u32(8, 42)?;
+
+    assert_eq!(memory.ptr::<u32>(8).read()?, 42);
+    memory.ptr::<u32>(12).write(43)?;
+    assert_eq!(memory.read_u32(12)?, 43);
+
+    match memory.ptr::<u32>(9).read() {
+        Err(GuestError::PtrNotAligned(_, 4)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match memory.ptr::<u32>(65534).read() {
+        Err(GuestError::PtrOutOfBounds(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    Ok(())
+}
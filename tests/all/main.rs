@@ -1,6 +1,10 @@
+mod call_hook;
 mod cli_tests;
+mod coverage;
 mod custom_signal_handler;
 mod debug;
+mod debugger;
+mod deterministic;
 mod externals;
 mod func;
 mod fuzzing;
@@ -11,8 +15,22 @@ mod import_indexes;
 mod instance;
 mod invoke_func_via_table;
 mod linker;
+mod memory_config;
 mod memory_creator;
 mod name;
+mod profile;
+mod snapshot;
 mod stack_overflow;
+mod stats;
 mod traps;
+mod wasi_clocks;
+mod wasi_exit;
+mod wasi_limits;
+mod wasi_overlay;
+mod wasi_pipes;
+mod wasi_preopens;
+mod wasi_sockets;
+mod wasi_strace;
+mod wasi_tar;
+mod wasi_trace;
 mod wast;
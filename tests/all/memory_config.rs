@@ -0,0 +1,126 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (memory (export "memory") 1 4)
+        (data (i32.const 65532) "\01\02\03\04")
+        (func (export "load32") (param i32) (result i32)
+            local.get 0
+            i32.load)
+        (func (export "load32_offset") (param i32) (result i32)
+            local.get 0
+            i32.load offset=4)
+        (func (export "load64") (param i32) (result i64)
+            local.get 0
+            i64.load)
+        (func (export "store16") (param i32 i32)
+            local.get 0
+            local.get 1
+            i32.store16 offset=2)
+        (func (export "sum") (param i32) (result i32)
+            local.get 0
+            i32.load8_u
+            local.get 0
+            i32.load8_u offset=1
+            i32.add
+            local.get 0
+            i32.load8_u offset=2
+            i32.add
+            local.get 0
+            i32.load8_u offset=3
+            i32.add))
+"#;
+
+fn check_bounds(config: &Config) -> Result<()> {
+    let store = Store::new(&Engine::new(config));
+    let instance = Instance::new(&Module::new(&store, WAT)?, &[])?;
+    let load32 = instance.get_func("load32").unwrap().get1::<i32, i32>()?;
+    let load32_offset = instance
+        .get_func("load32_offset")
+        .unwrap()
+        .get1::<i32, i32>()?;
+    let load64 = instance.get_func("load64").unwrap().get1::<i32, i64>()?;
+    let store16 = instance
+        .get_func("store16")
+        .unwrap()
+        .get2::<i32, i32, ()>()?;
+    let sum = instance.get_func("sum").unwrap().get1::<i32, i32>()?;
+
+    let assert_oob = |result: Result<i64, Trap>| {
+        let trap = result.unwrap_err();
+        assert!(trap.message().contains("out of bounds"), "{}", trap);
+    };
+
+    assert_eq!(load32(65532)?, 0x0403_0201);
+    assert_eq!(load32_offset(65528)?, 0x0403_0201);
+    assert_eq!(sum(65532)?, 10);
+    assert_oob(load32(65533).map(i64::from));
+    assert_oob(load32_offset(65529).map(i64::from));
+    assert_oob(load64(65532));
+    assert_oob(sum(65533).map(i64::from));
+    assert_oob(store16(65533, 0).map(|()| 0));
+    store16(65532, 0x0605)?;
+    assert_eq!(load32(65532)?, 0x0605_0201);
+
+    // Growing the memory, which moves dynamic memories, keeps the contents
+    // and moves the bounds.
+    let memory = instance.get_memory("memory").unwrap();
+    memory.grow(1)?;
+    assert_eq!(load32(65532)?, 0x0605_0201);
+    assert_eq!(load64(65532)?, 0x0605_0201);
+    assert_oob(load32(2 * 65536 - 3).map(i64::from));
+    Ok(())
+}
+
+#[test]
+fn default_memories() -> Result<()> {
+    check_bounds(&Config::new())
+}
+
+#[test]
+fn dynamic_memories_without_guards() -> Result<()> {
+    let mut config = Config::new();
+    config
+        .static_memory_maximum_size(0)
+        .dynamic_memory_guard_size(0);
+    check_bounds(&config)
+}
+
+#[test]
+fn dynamic_memories_with_guards() -> Result<()> {
+    let mut config = Config::new();
+    config
+        .static_memory_maximum_size(0)
+        .dynamic_memory_guard_size(0x1_0000);
+    check_bounds(&config)
+}
+
+#[test]
+fn small_static_memories() -> Result<()> {
+    let mut config = Config::new();
+    config
+        .static_memory_maximum_size(4 * 0x1_0000)
+        .static_memory_guard_size(0);
+    check_bounds(&config)?;
+
+    config.static_memory_guard_size(1);
+    check_bounds(&config)
+}
+
+#[test]
+fn many_instances_fit_in_limited_address_space() -> Result<()> {
+    let mut config = Config::new();
+    config
+        .static_memory_maximum_size(0)
+        .dynamic_memory_guard_size(0);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(&store, "(module (memory 1))")?;
+    // With the default 6 GiB reservations these would need 6 TiB of address
+    // space.
+    let instances = (0..1000)
+        .map(|_| Instance::new(&module, &[]))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(instances.len(), 1000);
+    Ok(())
+}
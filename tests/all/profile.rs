@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::time::{Duration, Instant};
+use wasmtime::*;
+
+#[test]
+#[cfg_attr(not(all(target_os = "linux", target_arch = "x86_64")), ignore)]
+fn sampling_profiler_symbolizes_guest_stacks() -> Result<()> {
+    let mut config = Config::new();
+    config.profiler(ProfilingStrategy::Sampling)?;
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    let module = Module::new_with_name(
+        &store,
+        r#"
+            (module
+                (func $spin (param i32)
+                    loop
+                        local.get 0
+                        i32.const 1
+                        i32.sub
+                        local.tee 0
+                        br_if 0
+                    end)
+                (func (export "run")
+                    i32.const 10000000
+                    call $spin))
+        "#,
+        "busy",
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+
+    // The timer measures CPU time, so keep the CPU busy until a few samples
+    // have been recorded.
+    let start = Instant::now();
+    let mut samples = 0;
+    let mut collapsed = Vec::new();
+    while samples < 10 && start.elapsed() < Duration::from_secs(10) {
+        run()?;
+        let profile = engine.take_guest_profile().unwrap();
+        samples += profile.samples();
+        profile.write_collapsed(&mut collapsed)?;
+    }
+    assert!(samples >= 10, "only got {} samples", samples);
+
+    let collapsed = String::from_utf8(collapsed)?;
+    assert!(
+        collapsed.contains("busy!<wasm function 1>;busy!spin "),
+        "unexpected stacks:\n{}",
+        collapsed
+    );
+
+    let mut json = Vec::new();
+    engine
+        .take_guest_profile()
+        .unwrap()
+        .write_firefox_json(&mut json)?;
+    assert!(String::from_utf8(json)?.starts_with("{\"meta\":"));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(all(target_os = "linux", target_arch = "x86_64")), ignore)]
+fn sampling_profiler_interval() -> Result<()> {
+    let mut config = Config::new();
+    assert!(config.sampling_profiler(Duration::from_secs(0)).is_err());
+    config.sampling_profiler(Duration::from_millis(500))?;
+    let engine = Engine::new(&config);
+
+    // The Firefox profiler format records the interval in milliseconds.
+    let mut json = Vec::new();
+    engine
+        .take_guest_profile()
+        .unwrap()
+        .write_firefox_json(&mut json)?;
+    let json = String::from_utf8(json)?;
+    assert!(json.contains("\"interval\":500,"), "{}", json);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn perf_map_lists_compiled_functions() -> Result<()> {
+    let mut config = Config::new();
+    config.profiler(ProfilingStrategy::PerfMap)?;
+    let store = Store::new(&Engine::new(&config));
+    Module::new(
+        &store,
+        r#"
+            (module
+                (func $perf_map_named)
+                (func (export "run")))
+        "#,
+    )?;
+
+    // Each line is `START SIZE NAME`, with the address and size in hex.
+    let map = std::fs::read_to_string(format!("/tmp/perf-{}.map", std::process::id()))?;
+    for name in &["perf_map_named", "wasm::wasm-function[1]"] {
+        let line = map
+            .lines()
+            .find(|line| line.ends_with(&format!(" {}", name)))
+            .unwrap_or_else(|| panic!("{} isn't in the perf map:\n{}", name, map));
+        let fields: Vec<_> = line.splitn(3, ' ').collect();
+        assert_eq!(fields.len(), 3, "{}", line);
+        assert!(u64::from_str_radix(fields[0], 16)? > 0, "{}", line);
+        assert!(u64::from_str_radix(fields[1], 16)? > 0, "{}", line);
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (memory (export "memory") 3)
+        (data (i32.const 0) "hello")
+        (data (i32.const 65534) "abcd")
+        (global (export "counter") (mut i32) (i32.const 7))
+        (table (export "table") 2 funcref)
+        (elem (i32.const 0) $one)
+        (type $t (func (result i32)))
+        (func $one (result i32) i32.const 1)
+        (func (export "call") (param i32) (result i32)
+            local.get 0
+            call_indirect (type $t))
+        (func (export "store") (param i32 i32)
+            local.get 0
+            local.get 1
+            i32.store8)
+        (func (export "bump")
+            global.get 0
+            i32.const 1
+            i32.add
+            global.set 0))
+"#;
+
+fn bytes(memory: &Memory, offset: usize, len: usize) -> Vec<u8> {
+    unsafe { memory.data_unchecked()[offset..offset + len].to_vec() }
+}
+
+#[test]
+fn restore_rolls_back_memory_globals_and_tables() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let instance = Instance::new(&module, &[])?;
+    let memory = instance.get_memory("memory").unwrap();
+    let counter = instance.get_global("counter").unwrap();
+    let table = instance.get_table("table").unwrap();
+    let store8 = instance.get_func("store").unwrap().get2::<i32, i32, ()>()?;
+    let bump = instance.get_func("bump").unwrap().get0::<()>()?;
+    let call = instance.get_func("call").unwrap().get1::<i32, i32>()?;
+
+    // Modify the state before taking the snapshot, so that it differs from
+    // the initial image.
+    store8(1, b'a' as i32)?;
+    store8(2 * 65536 + 5, 9)?;
+    bump()?;
+    let snapshot = instance.snapshot();
+
+    store8(0, b'j' as i32)?;
+    store8(65535, 0)?;
+    store8(2 * 65536 + 5, 10)?;
+    store8(65536 + 100, 1)?;
+    bump()?;
+    bump()?;
+    table.set(0, Val::FuncRef(Func::wrap(&store, || 2)))?;
+    table.set(1, Val::FuncRef(Func::wrap(&store, || 3)))?;
+    assert_eq!(counter.get().unwrap_i32(), 10);
+    assert_eq!(call(0)?, 2);
+
+    instance.restore(&snapshot)?;
+    assert_eq!(bytes(&memory, 0, 5), b"hallo");
+    assert_eq!(bytes(&memory, 65534, 4), b"abcd");
+    assert_eq!(bytes(&memory, 65536 + 100, 1), [0]);
+    assert_eq!(bytes(&memory, 2 * 65536 + 5, 1), [9]);
+    assert_eq!(counter.get().unwrap_i32(), 8);
+    assert_eq!(call(0)?, 1);
+    let trap = call(1).unwrap_err();
+    assert!(trap.message().contains("uninitialized element"), "{}", trap);
+
+    // The same snapshot can be restored repeatedly.
+    store8(3, 0)?;
+    instance.restore(&snapshot)?;
+    assert_eq!(bytes(&memory, 0, 5), b"hallo");
+    Ok(())
+}
+
+#[test]
+fn restore_requires_same_instance() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let instance = Instance::new(&module, &[])?;
+    let other = Instance::new(&module, &[])?;
+    let snapshot = instance.snapshot();
+
+    let err = other.restore(&snapshot).unwrap_err();
+    assert!(err.to_string().contains("different instance"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn restore_shrinks_grown_memories_and_tables() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, WAT)?;
+    let instance = Instance::new(&module, &[])?;
+    let memory = instance.get_memory("memory").unwrap();
+    let table = instance.get_table("table").unwrap();
+    let store8 = instance.get_func("store").unwrap().get2::<i32, i32, ()>()?;
+    let call = instance.get_func("call").unwrap().get1::<i32, i32>()?;
+    let snapshot = instance.snapshot();
+
+    memory.grow(2)?;
+    store8(3 * 65536 + 7, 1)?;
+    store8(0, b'j' as i32)?;
+    table.grow(1, Val::FuncRef(Func::wrap(&store, || 3)))?;
+    assert_eq!(call(2)?, 3);
+
+    instance.restore(&snapshot)?;
+    assert_eq!(memory.size(), 3);
+    assert_eq!(bytes(&memory, 0, 5), b"hello");
+    assert!(store8(3 * 65536 + 7, 1).is_err());
+    assert_eq!(table.size(), 2);
+    assert!(call(2).is_err());
+
+    // Pages which come back after growing again are zeroed.
+    memory.grow(1)?;
+    assert_eq!(bytes(&memory, 3 * 65536, 65536), vec![0; 65536]);
+    instance.restore(&snapshot)?;
+    assert_eq!(memory.size(), 3);
+    Ok(())
+}
+
+#[test]
+fn restore_lazily_initialized_memory() -> Result<()> {
+    let mut config = Config::new();
+    config.memory_lazy_init(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(&store, WAT)?;
+    let instance = Instance::new(&module, &[])?;
+    let lazy = store.memory_usage().resident_linear_memory == 0;
+    let memory = instance.get_memory("memory").unwrap();
+    let store8 = instance.get_func("store").unwrap().get2::<i32, i32, ()>()?;
+
+    store8(2 * 65536 + 5, 9)?;
+    let snapshot = instance.snapshot();
+    store8(0, b'j' as i32)?;
+    store8(65536 + 100, 1)?;
+    store8(2 * 65536 + 5, 10)?;
+
+    // Where userfaultfd is available the memory is discarded, and only the
+    // page the snapshot changed is populated again.
+    instance.restore(&snapshot)?;
+    if lazy {
+        assert_eq!(store.memory_usage().resident_linear_memory, 65536);
+    }
+    assert_eq!(bytes(&memory, 0, 5), b"hello");
+    assert_eq!(bytes(&memory, 65534, 4), b"abcd");
+    assert_eq!(bytes(&memory, 65536 + 100, 1), [0]);
+    assert_eq!(bytes(&memory, 2 * 65536 + 5, 1), [9]);
+    Ok(())
+}
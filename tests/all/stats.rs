@@ -0,0 +1,126 @@
+use anyhow::Result;
+use wasmtime::*;
+
+#[test]
+fn engine_counts_compilations_and_instances() -> Result<()> {
+    let engine = Engine::default();
+    let store = Store::new(&engine);
+    assert_eq!(engine.stats().modules_compiled, 0);
+
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (func (export "a") (param i32) (result i32) local.get 0)
+                (func (export "b")))
+        "#,
+    )?;
+    let stats = engine.stats();
+    assert_eq!(stats.modules_compiled, 1);
+    assert_eq!(stats.functions_compiled, 2);
+    assert_eq!(stats.compiled_bytes, module.compile_stats().compiled_bytes);
+    assert_eq!(stats.trampolines, 2);
+    assert_eq!(stats.instances, 0);
+
+    Instance::new(&module, &[])?;
+    Instance::new(&module, &[])?;
+    assert_eq!(engine.stats().instances, 2);
+    Ok(())
+}
+
+#[test]
+fn module_compile_stats() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (import "" "" (func))
+                (func)
+                (func (call 0) (call 1)))
+        "#,
+    )?;
+    let stats = module.compile_stats();
+    let indices = stats
+        .function_sizes
+        .iter()
+        .map(|(index, _)| *index)
+        .collect::<Vec<_>>();
+    assert_eq!(indices, [1, 2]);
+    assert!(stats.function_sizes.iter().all(|(_, size)| *size > 0));
+    assert_eq!(
+        stats.compiled_bytes,
+        stats.function_sizes.iter().map(|(_, size)| size).sum()
+    );
+    Ok(())
+}
+
+#[test]
+fn store_memory_usage() -> Result<()> {
+    let store = Store::default();
+    assert_eq!(store.memory_usage().linear_memory, 0);
+
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (table 10 funcref))
+        "#,
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let usage = store.memory_usage();
+    assert_eq!(usage.linear_memory, 0x10000);
+    assert!(usage.tables > 0);
+
+    let memory = instance.get_memory("memory").unwrap();
+    memory.grow(2)?;
+    assert_eq!(store.memory_usage().linear_memory, 3 * 0x10000);
+
+    let host_memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)));
+    assert_eq!(store.memory_usage().linear_memory, 4 * 0x10000);
+
+    drop((instance, memory));
+    assert_eq!(store.memory_usage().linear_memory, 0x10000);
+    drop(host_memory);
+    assert_eq!(store.memory_usage().linear_memory, 0);
+    Ok(())
+}
+
+#[test]
+fn lazily_initialized_memory_usage() -> Result<()> {
+    let mut config = Config::new();
+    config.memory_lazy_init(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (memory (export "memory") 100 100)
+                (data (i32.const 0) "hello")
+                (data (i32.const 3276800) "world")
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load8_u))
+        "#,
+    )?;
+    let instance = Instance::new(&module, &[])?;
+    let load = instance.get_func("load").unwrap().get1::<i32, i32>()?;
+    let usage = store.memory_usage();
+    assert_eq!(usage.linear_memory, 100 * 0x10000);
+    assert!(usage.resident_linear_memory <= usage.linear_memory);
+
+    // The contents are the same whether or not userfaultfd is available.
+    assert_eq!(load(0)?, b'h' as i32);
+    assert_eq!(load(50 * 0x10000 + 4)?, b'd' as i32);
+    assert_eq!(load(99 * 0x10000)?, 0);
+
+    // Where userfaultfd is available nothing is populated until it's accessed,
+    // and each access above populated one wasm page.
+    if usage.resident_linear_memory == 0 {
+        assert_eq!(store.memory_usage().resident_linear_memory, 3 * 0x10000);
+        assert_eq!(load(99 * 0x10000 + 1)?, 0);
+        assert_eq!(store.memory_usage().resident_linear_memory, 3 * 0x10000);
+    }
+    Ok(())
+}
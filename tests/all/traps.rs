@@ -1,5 +1,7 @@
 use anyhow::Result;
+use std::cell::RefCell;
 use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
 use wasmtime::*;
 
 #[test]
@@ -30,6 +32,38 @@ fn test_trap_return() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_i32_exit() -> Result<()> {
+    let store = Store::default();
+    let wat = r#"
+        (module
+        (func $exit (import "" "exit") (param i32))
+        (func (export "run")
+            (call $exit (i32.const 42))
+            unreachable)
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let exit_func = Func::wrap(&store, |status: i32| -> Result<(), Trap> {
+        Err(Trap::i32_exit(status))
+    });
+
+    let instance = Instance::new(&module, &[exit_func.into()])?;
+    let run_func = instance.get_func("run").expect("expected function export");
+
+    let e = run_func
+        .call(&[])
+        .err()
+        .expect("error calling function")
+        .downcast::<Trap>()?;
+
+    assert_eq!(e.i32_exit_status(), Some(42));
+    assert_eq!(Trap::new("test 123").i32_exit_status(), None);
+
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
 fn test_trap_trace() -> Result<()> {
@@ -108,6 +142,51 @@ fn test_trap_trace_cb() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
+fn caller_backtrace() -> Result<()> {
+    let store = Store::default();
+    let wat = r#"
+        (module $hello_mod
+            (import "" "log" (func $log (param i32)))
+            (func (export "run") (call $hello))
+            (func $hello (call $log (i32.const 1)))
+        )
+    "#;
+
+    let traces = Rc::new(RefCell::new(Vec::new()));
+    let traces2 = traces.clone();
+    let log = Func::wrap(&store, move |caller: Caller<'_>, _: i32| {
+        traces2.borrow_mut().push(caller.backtrace());
+    });
+
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &[log.into()])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+    run()?;
+    run()?;
+
+    let traces = traces.borrow();
+    assert_eq!(traces.len(), 2);
+    for trace in traces.iter() {
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].module_name().unwrap(), "hello_mod");
+        assert_eq!(trace[0].func_index(), 2);
+        assert_eq!(trace[1].module_name().unwrap(), "hello_mod");
+        assert_eq!(trace[1].func_index(), 1);
+    }
+
+    // Outside of wasm there are no frames to report.
+    let trace = Rc::new(RefCell::new(None));
+    let trace2 = trace.clone();
+    let direct = Func::wrap(&store, move |caller: Caller<'_>| {
+        *trace2.borrow_mut() = Some(caller.backtrace());
+    });
+    direct.call(&[])?;
+    assert_eq!(trace.borrow().as_ref().unwrap().len(), 0);
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1521)
 fn test_trap_stack_overflow() -> Result<()> {
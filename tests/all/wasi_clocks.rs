@@ -0,0 +1,121 @@
+use anyhow::Result;
+use rand_core::{impls, Error, RngCore};
+use std::time::{Duration, Instant};
+use wasi_common::{FixedClocks, VirtualClocks, WasiCtxBuilder};
+use wasmtime::*;
+use wasmtime_wasi::Wasi;
+
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "clock_time_get"
+            (func $clock_time_get (param i32 i64 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "random_get"
+            (func $random_get (param i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "poll_oneoff"
+            (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+
+        (func (export "now") (param $id i32) (result i64)
+            (if (call $clock_time_get (local.get $id) (i64.const 1) (i32.const 0))
+                (then unreachable))
+            (i64.load (i32.const 0)))
+
+        (func (export "random") (result i64)
+            (if (call $random_get (i32.const 8) (i32.const 8))
+                (then unreachable))
+            (i64.load (i32.const 8)))
+
+        ;; Waits for `$ns` nanoseconds on the monotonic clock, and returns the
+        ;; userdata of the event which fired.
+        (func (export "sleep") (param $ns i64) (result i64)
+            ;; The subscription, at 64: userdata, a clock tag, the monotonic
+            ;; clock id and a relative timeout.
+            (i64.store (i32.const 64) (i64.const 42))
+            (i32.store8 (i32.const 72) (i32.const 0))
+            (i32.store (i32.const 80) (i32.const 1))
+            (i64.store (i32.const 88) (local.get $ns))
+            (i64.store (i32.const 96) (i64.const 0))
+            (i32.store16 (i32.const 104) (i32.const 0))
+            (if (call $poll_oneoff (i32.const 64) (i32.const 128) (i32.const 1) (i32.const 16))
+                (then unreachable))
+            (if (i32.ne (i32.load (i32.const 16)) (i32.const 1))
+                (then unreachable))
+            (i64.load (i32.const 128)))
+
+        (memory (export "memory") 1)
+    )
+"#;
+
+const REALTIME: i32 = 0;
+const MONOTONIC: i32 = 1;
+
+/// An "rng" which counts up from zero.
+struct Counter(u64);
+
+impl RngCore for Counter {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.0 += 1;
+        self.0
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+fn instantiate(builder: &mut WasiCtxBuilder) -> Result<Instance> {
+    let store = Store::default();
+    let wasi = Wasi::new(&store, builder.build()?);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    linker.instantiate(&Module::new(&store, WAT)?)
+}
+
+#[test]
+fn fixed_clocks_and_random() -> Result<()> {
+    let instance = instantiate(
+        WasiCtxBuilder::new()
+            .clocks(Box::new(FixedClocks::new(1_000)))
+            .random(Box::new(Counter(0))),
+    )?;
+    let now = instance.get_func("now").unwrap().get1::<i32, i64>()?;
+    let random = instance.get_func("random").unwrap().get0::<i64>()?;
+
+    assert_eq!(now(REALTIME)?, 1_000);
+    assert_eq!(now(MONOTONIC)?, 1_000);
+    assert_eq!(random()?, 1);
+    assert_eq!(random()?, 2);
+    Ok(())
+}
+
+#[test]
+fn virtual_clocks() -> Result<()> {
+    let clocks = VirtualClocks::new(0, Duration::from_nanos(1));
+    let instance = instantiate(WasiCtxBuilder::new().clocks(Box::new(clocks.clone())))?;
+    let now = instance.get_func("now").unwrap().get1::<i32, i64>()?;
+
+    assert_eq!(now(MONOTONIC)?, 0);
+    assert_eq!(now(MONOTONIC)?, 1);
+    clocks.advance(Duration::from_secs(1));
+    assert_eq!(now(REALTIME)?, 1_000_000_002);
+    Ok(())
+}
+
+#[test]
+fn poll_oneoff_advances_virtual_clocks() -> Result<()> {
+    let clocks = VirtualClocks::new(0, Duration::from_nanos(0));
+    let instance = instantiate(WasiCtxBuilder::new().clocks(Box::new(clocks.clone())))?;
+    let sleep = instance.get_func("sleep").unwrap().get1::<i64, i64>()?;
+
+    // An hour passes on the virtual clocks, but not on the host's.
+    let start = Instant::now();
+    assert_eq!(sleep(3_600_000_000_000)?, 42);
+    assert!(start.elapsed() < Duration::from_secs(60));
+    assert_eq!(clocks.now(), 3_600_000_000_000);
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use wasi_common::wasi::wasi_snapshot_preview1::WasiSnapshotPreview1;
+use wasi_common::WasiCtxBuilder;
+use wasmtime::*;
+use wasmtime_wasi::Wasi;
+
+#[test]
+fn wasi_common_proc_exit_returns_to_the_host() -> Result<()> {
+    let ctx = WasiCtxBuilder::new().build()?;
+    assert_eq!(ctx.exit_status(), None);
+    // Getting past the call at all means the host is still running.
+    assert_eq!(WasiSnapshotPreview1::proc_exit(&ctx, 3), Ok(()));
+    assert_eq!(ctx.exit_status(), Some(3));
+    Ok(())
+}
+
+#[test]
+fn old_wasi_common_proc_exit_returns_to_the_host() -> Result<()> {
+    use wasi_common::old::snapshot_0::{hostcalls, WasiCtxBuilder};
+
+    let mut ctx = WasiCtxBuilder::new().build()?;
+    assert_eq!(ctx.exit_status(), None);
+    unsafe { hostcalls::proc_exit(&mut ctx, &mut [], 3) };
+    assert_eq!(ctx.exit_status(), Some(3));
+    Ok(())
+}
+
+#[test]
+fn every_exit_status_is_an_exit_trap() -> Result<()> {
+    let store = Store::default();
+    let wasi = Wasi::new(&store, WasiCtxBuilder::new().build()?);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit"
+                    (func $proc_exit (param i32)))
+                (func (export "exit") (param $status i32)
+                    (call $proc_exit (local.get $status))
+                    unreachable)
+                (memory (export "memory") 1)
+            )
+        "#,
+    )?;
+    let instance = linker.instantiate(&module)?;
+    let exit = instance.get_func("exit").unwrap().get1::<i32, ()>()?;
+
+    // Statuses outside of the `0..126` that WASI defines still carry them.
+    for &status in &[0, 125, 126, 255, -1] {
+        let trap = exit(status).unwrap_err();
+        assert_eq!(trap.i32_exit_status(), Some(status));
+    }
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+use wasi_common::{preopen_dir, IoStats, WasiCtxBuilder, WasiLimits};
+use wasmtime::*;
+use wasmtime_wasi::{old::snapshot_0, Wasi};
+
+const ERRNO_DQUOT: i32 = 19;
+const ERRNO_NFILE: i32 = 41;
+const OFLAGS_CREAT: i32 = 1;
+const RIGHTS_FD_READ: i64 = 1 << 1;
+const RIGHTS_FD_WRITE: i64 = 1 << 6;
+const RIGHTS_FD_FILESTAT_SET_SIZE: i64 = 1 << 22;
+
+/// Every test runs against both snapshots, which take the same limits.
+const SNAPSHOTS: &[&str] = &["wasi_snapshot_preview1", "wasi_unstable"];
+
+/// Imports from `wasi_snapshot_preview1`, which `Guest::new` replaces with the
+/// snapshot under test. The imports used have the same signatures in both.
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open
+                (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "path_create_directory"
+            (func $path_create_directory (param i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_filestat_set_size"
+            (func $fd_filestat_set_size (param i32 i64) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_renumber"
+            (func $fd_renumber (param i32 i32) (result i32)))
+
+        ;; Opens the path in the preopen at fd 3, storing the new fd at 0.
+        (func (export "open") (param $ptr i32) (param $len i32) (param $oflags i32)
+            (param $rights i64) (result i32)
+            (call $path_open
+                (i32.const 3) (i32.const 0) (local.get $ptr) (local.get $len)
+                (local.get $oflags) (local.get $rights) (i64.const 0)
+                (i32.const 0) (i32.const 0)))
+
+        (func (export "mkdir") (param $ptr i32) (param $len i32) (result i32)
+            (call $path_create_directory (i32.const 3) (local.get $ptr) (local.get $len)))
+
+        ;; Reads up to `$len` bytes into 1024.
+        (func (export "read") (param $fd i32) (param $len i32) (result i32)
+            (i32.store (i32.const 8) (i32.const 1024))
+            (i32.store (i32.const 12) (local.get $len))
+            (call $fd_read (local.get $fd) (i32.const 8) (i32.const 1) (i32.const 4)))
+
+        ;; Writes `$len` bytes from 1024.
+        (func (export "write") (param $fd i32) (param $len i32) (result i32)
+            (i32.store (i32.const 8) (i32.const 1024))
+            (i32.store (i32.const 12) (local.get $len))
+            (call $fd_write (local.get $fd) (i32.const 8) (i32.const 1) (i32.const 4)))
+
+        (func (export "set_size") (param $fd i32) (param $size i64) (result i32)
+            (call $fd_filestat_set_size (local.get $fd) (local.get $size)))
+
+        (func (export "renumber") (param $from i32) (param $to i32) (result i32)
+            (call $fd_renumber (local.get $from) (local.get $to)))
+
+        (memory (export "memory") 1)
+    )
+"#;
+
+/// The address the guest finds paths at.
+const PATH: usize = 512;
+
+struct Guest {
+    // Kept alive while the guest uses it.
+    dir: TempDir,
+    io_stats: Box<dyn Fn() -> IoStats>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Guest {
+    fn new(snapshot: &str, limits: WasiLimits) -> Result<Guest> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("existing"), b"hello")?;
+        let store = Store::default();
+        let mut linker = Linker::new(&store);
+        let io_stats: Box<dyn Fn() -> IoStats> = if snapshot == "wasi_unstable" {
+            let ctx = snapshot_0::WasiCtxBuilder::new()
+                .preopened_dir(preopen_dir(dir.path())?, ".")
+                .limits(limits)
+                .build()?;
+            let wasi = snapshot_0::Wasi::new(&store, ctx);
+            wasi.add_to_linker(&mut linker)?;
+            Box::new(move || wasi.ctx().io_stats())
+        } else {
+            let ctx = WasiCtxBuilder::new()
+                .preopened_dir(preopen_dir(dir.path())?, ".")
+                .limits(limits)
+                .build()?;
+            let wasi = Wasi::new(&store, ctx);
+            wasi.add_to_linker(&mut linker)?;
+            Box::new(move || wasi.ctx().io_stats())
+        };
+        let wat = WAT.replace("wasi_snapshot_preview1", snapshot);
+        let instance = linker.instantiate(&Module::new(&store, &wat)?)?;
+        let memory = instance.get_memory("memory").unwrap();
+        Ok(Guest {
+            dir,
+            io_stats,
+            instance,
+            memory,
+        })
+    }
+
+    /// Opens `path`, returning the new fd.
+    fn open(&self, path: &str, oflags: i32, rights: i64) -> Result<std::result::Result<i32, i32>> {
+        self.memory.write(PATH, path.as_bytes())?;
+        let open = self.instance.get_func("open").unwrap();
+        let open = open.get4::<i32, i32, i32, i64, i32>()?;
+        match open(PATH as i32, path.len() as i32, oflags, rights)? {
+            0 => Ok(Ok(self.read_u32(0)? as i32)),
+            errno => Ok(Err(errno)),
+        }
+    }
+
+    fn mkdir(&self, path: &str) -> Result<i32> {
+        self.memory.write(PATH, path.as_bytes())?;
+        let mkdir = self.instance.get_func("mkdir").unwrap();
+        let mkdir = mkdir.get2::<i32, i32, i32>()?;
+        Ok(mkdir(PATH as i32, path.len() as i32)?)
+    }
+
+    fn call(&self, func: &str, fd: i32, len: i32) -> Result<i32> {
+        let func = self.instance.get_func(func).unwrap();
+        Ok(func.get2::<i32, i32, i32>()?(fd, len)?)
+    }
+
+    fn set_size(&self, fd: i32, size: i64) -> Result<i32> {
+        let set_size = self.instance.get_func("set_size").unwrap();
+        Ok(set_size.get2::<i32, i64, i32>()?(fd, size)?)
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.memory.read(offset, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn host_file_len(&self, name: &str) -> Result<u64> {
+        Ok(fs::metadata(self.dir.path().join(name))?.len())
+    }
+}
+
+#[test]
+fn max_bytes_written_and_io_stats() -> Result<()> {
+    for &snapshot in SNAPSHOTS {
+        let guest = Guest::new(
+            snapshot,
+            WasiLimits {
+                max_bytes_written: Some(10),
+                ..WasiLimits::default()
+            },
+        )?;
+        let fd = guest.open("file", OFLAGS_CREAT, RIGHTS_FD_WRITE)?.unwrap();
+        assert_eq!(guest.call("write", fd, 6)?, 0);
+        assert_eq!(guest.call("write", fd, 6)?, ERRNO_DQUOT);
+        assert_eq!(guest.call("write", fd, 4)?, 0);
+        assert_eq!(guest.host_file_len("file")?, 10);
+
+        let fd = guest.open("existing", 0, RIGHTS_FD_READ)?.unwrap();
+        assert_eq!(guest.call("read", fd, 64)?, 0);
+        assert_eq!(
+            (guest.io_stats)(),
+            IoStats {
+                bytes_read: 5,
+                bytes_written: 10,
+            }
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn max_file_size() -> Result<()> {
+    for &snapshot in SNAPSHOTS {
+        let guest = Guest::new(
+            snapshot,
+            WasiLimits {
+                max_file_size: Some(8),
+                ..WasiLimits::default()
+            },
+        )?;
+        let rights = RIGHTS_FD_WRITE | RIGHTS_FD_FILESTAT_SET_SIZE;
+        let fd = guest.open("file", OFLAGS_CREAT, rights)?.unwrap();
+        assert_eq!(guest.call("write", fd, 8)?, 0);
+        assert_eq!(guest.call("write", fd, 1)?, ERRNO_DQUOT);
+        assert_eq!(guest.set_size(fd, 9)?, ERRNO_DQUOT);
+        assert_eq!(guest.set_size(fd, 4)?, 0);
+        assert_eq!(guest.host_file_len("file")?, 4);
+    }
+    Ok(())
+}
+
+#[test]
+fn max_open_fds() -> Result<()> {
+    for &snapshot in SNAPSHOTS {
+        // stdio and the preopen are already open.
+        let guest = Guest::new(
+            snapshot,
+            WasiLimits {
+                max_open_fds: Some(5),
+                ..WasiLimits::default()
+            },
+        )?;
+        assert!(guest.open("existing", 0, RIGHTS_FD_READ)?.is_ok());
+        assert_eq!(guest.open("existing", 0, RIGHTS_FD_READ)?, Err(ERRNO_NFILE));
+    }
+    Ok(())
+}
+
+#[test]
+fn max_open_fds_with_renumber() -> Result<()> {
+    for &snapshot in SNAPSHOTS {
+        let guest = Guest::new(
+            snapshot,
+            WasiLimits {
+                max_open_fds: Some(6),
+                ..WasiLimits::default()
+            },
+        )?;
+        let a = guest.open("existing", 0, RIGHTS_FD_READ)?.unwrap();
+        let b = guest.open("existing", 0, RIGHTS_FD_READ)?.unwrap();
+        assert_eq!(guest.open("existing", 0, RIGHTS_FD_READ)?, Err(ERRNO_NFILE));
+
+        // Renumbering to an fd which isn't open moves the fd there, without
+        // making room for another one.
+        assert_eq!(guest.call("renumber", a, 100)?, 0);
+        assert_eq!(guest.open("existing", 0, RIGHTS_FD_READ)?, Err(ERRNO_NFILE));
+
+        // Renumbering over an open fd closes it, which makes room for one more.
+        assert_eq!(guest.call("renumber", 100, b)?, 0);
+        assert!(guest.open("existing", 0, RIGHTS_FD_READ)?.is_ok());
+        assert_eq!(guest.open("existing", 0, RIGHTS_FD_READ)?, Err(ERRNO_NFILE));
+    }
+    Ok(())
+}
+
+#[test]
+fn max_created_files() -> Result<()> {
+    for &snapshot in SNAPSHOTS {
+        let guest = Guest::new(
+            snapshot,
+            WasiLimits {
+                max_created_files: Some(2),
+                ..WasiLimits::default()
+            },
+        )?;
+        // Opening existing files doesn't count.
+        assert!(guest
+            .open("existing", OFLAGS_CREAT, RIGHTS_FD_WRITE)?
+            .is_ok());
+        assert!(guest.open("a", OFLAGS_CREAT, RIGHTS_FD_WRITE)?.is_ok());
+        assert!(guest.open("a", OFLAGS_CREAT, RIGHTS_FD_WRITE)?.is_ok());
+        assert_eq!(guest.mkdir("dir")?, 0);
+        assert_eq!(
+            guest.open("b", OFLAGS_CREAT, RIGHTS_FD_WRITE)?,
+            Err(ERRNO_DQUOT)
+        );
+        assert_eq!(guest.mkdir("dir2")?, ERRNO_DQUOT);
+    }
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+use wasi_common::{preopen_dir, WasiCtxBuilder};
+use wasmtime::*;
+use wasmtime_wasi::Wasi;
+
+const ERRNO_NOENT: i32 = 44;
+
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open
+                (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_close"
+            (func $fd_close (param i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_readdir"
+            (func $fd_readdir (param i32 i32 i32 i64 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "path_unlink_file"
+            (func $path_unlink_file (param i32 i32 i32) (result i32)))
+
+        ;; Opens the path in the preopen at fd 3, storing the new fd at 0.
+        (func $open (param $ptr i32) (param $len i32) (param $oflags i32) (param $rights i64)
+            (result i32)
+            (call $path_open
+                (i32.const 3) (i32.const 0) (local.get $ptr) (local.get $len)
+                (local.get $oflags) (local.get $rights) (i64.const 0)
+                (i32.const 0) (i32.const 0)))
+
+        ;; Reads up to 64 bytes of the file into 256, storing their count at 4.
+        (func (export "read") (param $ptr i32) (param $len i32) (result i32)
+            (local $errno i32)
+            (local.set $errno
+                (call $open (local.get $ptr) (local.get $len) (i32.const 0) (i64.const 2)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (i32.store (i32.const 8) (i32.const 256))
+            (i32.store (i32.const 12) (i32.const 64))
+            (local.set $errno
+                (call $fd_read (i32.load (i32.const 0)) (i32.const 8) (i32.const 1) (i32.const 4)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (call $fd_close (i32.load (i32.const 0))))
+
+        ;; Writes "changed" to the file, creating or truncating it.
+        (func (export "write") (param $ptr i32) (param $len i32) (result i32)
+            (local $errno i32)
+            (local.set $errno
+                (call $open (local.get $ptr) (local.get $len) (i32.const 9) (i64.const 64)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (i32.store (i32.const 8) (i32.const 128))
+            (i32.store (i32.const 12) (i32.const 7))
+            (local.set $errno
+                (call $fd_write (i32.load (i32.const 0)) (i32.const 8) (i32.const 1) (i32.const 4)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (call $fd_close (i32.load (i32.const 0))))
+
+        (func (export "unlink") (param $ptr i32) (param $len i32) (result i32)
+            (call $path_unlink_file (i32.const 3) (local.get $ptr) (local.get $len)))
+
+        ;; Reads the entries of the preopen into 1024, storing their size at 4.
+        (func (export "readdir") (result i32)
+            (call $fd_readdir (i32.const 3) (i32.const 1024) (i32.const 4096) (i64.const 0)
+                (i32.const 4)))
+
+        (memory (export "memory") 1)
+        (data (i32.const 128) "changed")
+    )
+"#;
+
+/// The address the guest finds paths at.
+const PATH: usize = 512;
+
+struct Guest {
+    // Kept alive while the guest uses it.
+    dir: TempDir,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Guest {
+    fn new() -> Result<Guest> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a"), b"lower a")?;
+        fs::write(dir.path().join("b"), b"lower b")?;
+        let store = Store::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.preopened_overlay(preopen_dir(dir.path())?, ".");
+        let wasi = Wasi::new(&store, builder.build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+        let memory = instance.get_memory("memory").unwrap();
+        Ok(Guest {
+            dir,
+            instance,
+            memory,
+        })
+    }
+
+    fn call_with_path(&self, func: &str, path: &str) -> Result<i32> {
+        self.memory.write(PATH, path.as_bytes())?;
+        let func = self.instance.get_func(func).unwrap();
+        let func = func.get2::<i32, i32, i32>()?;
+        Ok(func(PATH as i32, path.len() as i32)?)
+    }
+
+    fn read(&self, path: &str) -> Result<Result<String, i32>> {
+        let errno = self.call_with_path("read", path)?;
+        if errno != 0 {
+            return Ok(Err(errno));
+        }
+        let mut buf = vec![0; self.read_u32(4)? as usize];
+        self.memory.read(256, &mut buf)?;
+        Ok(Ok(String::from_utf8(buf)?))
+    }
+
+    fn readdir(&self) -> Result<Vec<String>> {
+        let readdir = self.instance.get_func("readdir").unwrap().get0::<i32>()?;
+        assert_eq!(readdir()?, 0);
+        let end = 1024 + self.read_u32(4)? as usize;
+        let mut names = Vec::new();
+        let mut offset = 1024;
+        // Each dirent is 24 bytes, with the length of the name at 16, and followed by the name.
+        while offset < end {
+            let mut name = vec![0; self.read_u32(offset + 16)? as usize];
+            self.memory.read(offset + 24, &mut name)?;
+            names.push(String::from_utf8(name)?);
+            offset += 24 + names.last().unwrap().len();
+        }
+        Ok(names)
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.memory.read(offset, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn host_file(&self, name: &str) -> Option<String> {
+        fs::read_to_string(self.dir.path().join(name)).ok()
+    }
+}
+
+#[test]
+fn writes_stay_in_the_overlay() -> Result<()> {
+    let guest = Guest::new()?;
+    assert_eq!(guest.read("a")?, Ok("lower a".to_owned()));
+
+    assert_eq!(guest.call_with_path("write", "a")?, 0);
+    assert_eq!(guest.read("a")?, Ok("changed".to_owned()));
+    assert_eq!(guest.host_file("a"), Some("lower a".to_owned()));
+
+    assert_eq!(guest.call_with_path("write", "c")?, 0);
+    assert_eq!(guest.read("c")?, Ok("changed".to_owned()));
+    assert_eq!(guest.host_file("c"), None);
+    Ok(())
+}
+
+#[test]
+fn deletes_stay_in_the_overlay() -> Result<()> {
+    let guest = Guest::new()?;
+    assert_eq!(guest.call_with_path("unlink", "b")?, 0);
+    assert_eq!(guest.read("b")?, Err(ERRNO_NOENT));
+    assert_eq!(guest.readdir()?, [".", "..", "a"]);
+    assert_eq!(guest.host_file("b"), Some("lower b".to_owned()));
+    Ok(())
+}
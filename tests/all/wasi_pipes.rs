@@ -0,0 +1,91 @@
+use anyhow::Result;
+use wasi_common::{preopen_dir, ReadPipe, WasiCtxBuilder, WritePipe};
+use wasmtime::*;
+use wasmtime_wasi::Wasi;
+
+const ERRNO_NOTSUP: i32 = 58;
+
+// Copies up to 64 bytes from stdin to stdout and then to fd 3.
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+
+        (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 64))
+            (if (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8))
+                (then unreachable))
+            (i32.store (i32.const 4) (i32.load (i32.const 8)))
+            (if (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))
+                (then unreachable))
+            (if (call $fd_write (i32.const 3) (i32.const 0) (i32.const 1) (i32.const 8))
+                (then unreachable)))
+
+        (memory (export "memory") 1)
+    )
+"#;
+
+#[test]
+fn capture_output_with_pipes() -> Result<()> {
+    let stdout = WritePipe::new_in_memory();
+    let extra = WritePipe::new_in_memory();
+    let store = Store::default();
+    let mut builder = WasiCtxBuilder::new();
+    builder
+        .stdin_handle(Box::new(ReadPipe::from("hello from stdin")))
+        .stdout_handle(Box::new(stdout.clone()))
+        .preopened_handle(3, Box::new(extra.clone()));
+    let wasi = Wasi::new(&store, builder.build()?);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+    instance.get_func("_start").unwrap().get0::<()>()?()?;
+
+    assert_eq!(&*stdout.borrow(), b"hello from stdin");
+    assert_eq!(&*extra.borrow(), b"hello from stdin");
+    Ok(())
+}
+
+#[test]
+fn handles_need_a_free_fd() {
+    let mut builder = WasiCtxBuilder::new();
+    builder.preopened_handle(1, Box::new(WritePipe::new_in_memory()));
+    assert!(builder.build().is_err());
+}
+
+#[test]
+fn only_preopened_dirs_have_a_prestat() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = Store::default();
+    let mut builder = WasiCtxBuilder::new();
+    builder
+        .preopened_handle(4, Box::new(WritePipe::new_in_memory()))
+        .preopened_dir(preopen_dir(dir.path())?, ".");
+    let wasi = Wasi::new(&store, builder.build()?);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_prestat_get"
+                    (func $fd_prestat_get (param i32 i32) (result i32)))
+                (func (export "prestat") (param $fd i32) (result i32)
+                    (call $fd_prestat_get (local.get $fd) (i32.const 0)))
+                (memory (export "memory") 1)
+            )
+        "#,
+    )?;
+    let instance = linker.instantiate(&module)?;
+    let prestat = instance.get_func("prestat").unwrap().get1::<i32, i32>()?;
+
+    // The directory comes first, before the handle, and neither stdio nor the
+    // handle look like a preopen.
+    assert_eq!(prestat(0)?, ERRNO_NOTSUP);
+    assert_eq!(prestat(3)?, 0);
+    assert_eq!(prestat(4)?, ERRNO_NOTSUP);
+    Ok(())
+}
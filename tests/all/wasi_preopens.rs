@@ -0,0 +1,64 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+use wasi_common::{preopen_dir, EntryRights, WasiCtxBuilder};
+use wasmtime::*;
+use wasmtime_wasi::Wasi;
+
+const ERRNO_NOTCAPABLE: i32 = 76;
+const OFLAGS_CREAT: i32 = 1;
+const RIGHTS_FD_READ: i64 = 1 << 1;
+const RIGHTS_FD_WRITE: i64 = 1 << 6;
+
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open
+                (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+
+        ;; Opens `file` (or `new` if `$new` is set) in the preopen at fd 3.
+        (func (export "open") (param $new i32) (param $oflags i32) (param $rights i64) (result i32)
+            (call $path_open
+                (i32.const 3) (i32.const 0)
+                (select (i32.const 72) (i32.const 64) (local.get $new)) (i32.const 4)
+                (local.get $oflags) (local.get $rights) (i64.const 0)
+                (i32.const 0) (i32.const 0)))
+
+        (memory (export "memory") 1)
+        (data (i32.const 64) "file")
+        (data (i32.const 72) "new!")
+    )
+"#;
+
+// The directory is returned to keep it alive while the guest uses it.
+fn open_with_rights(rights: EntryRights) -> Result<(TempDir, Func)> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("file"), b"hello")?;
+    let store = Store::default();
+    let mut builder = WasiCtxBuilder::new();
+    builder.preopened_dir_with_rights(preopen_dir(dir.path())?, ".", rights);
+    let wasi = Wasi::new(&store, builder.build()?);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+    Ok((dir, instance.get_func("open").unwrap()))
+}
+
+#[test]
+fn read_only_dir() -> Result<()> {
+    let (_dir, open) = open_with_rights(EntryRights::read_only_dir())?;
+    let open = open.get3::<i32, i32, i64, i32>()?;
+    assert_eq!(open(0, 0, RIGHTS_FD_READ)?, 0);
+    assert_eq!(open(0, 0, RIGHTS_FD_WRITE)?, ERRNO_NOTCAPABLE);
+    assert_eq!(open(1, OFLAGS_CREAT, RIGHTS_FD_READ)?, ERRNO_NOTCAPABLE);
+    Ok(())
+}
+
+#[test]
+fn no_create_dir() -> Result<()> {
+    let (_dir, open) = open_with_rights(EntryRights::no_create_dir())?;
+    let open = open.get3::<i32, i32, i64, i32>()?;
+    assert_eq!(open(0, 0, RIGHTS_FD_READ | RIGHTS_FD_WRITE)?, 0);
+    assert_eq!(open(1, OFLAGS_CREAT, RIGHTS_FD_WRITE)?, ERRNO_NOTCAPABLE);
+    Ok(())
+}
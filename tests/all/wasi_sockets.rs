@@ -0,0 +1,313 @@
+#[cfg(not(target_os = "windows"))]
+mod not_for_windows {
+    use anyhow::Result;
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use wasi_common::{preopen_dir, WasiCtxBuilder};
+    use wasmtime::*;
+    use wasmtime_wasi::Wasi;
+
+    const ERRNO_NOTSOCK: i32 = 57;
+    const ERRNO_NOTSUP: i32 = 58;
+    const ERRNO_PIPE: i32 = 64;
+    const SDFLAGS_WR: i32 = 2;
+    const EVENTTYPE_CLOCK: u8 = 0;
+    const EVENTTYPE_FD_READ: u8 = 1;
+
+    const WAT: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "sock_recv"
+                (func $sock_recv (param i32 i32 i32 i32 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "sock_send"
+                (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "sock_shutdown"
+                (func $sock_shutdown (param i32 i32) (result i32)))
+
+            ;; Sends the 5 bytes at 64 and stores the count sent at 8.
+            (func (export "send") (param $fd i32) (result i32)
+                (i32.store (i32.const 0) (i32.const 64))
+                (i32.store (i32.const 4) (i32.const 5))
+                (call $sock_send (local.get $fd) (i32.const 0) (i32.const 1)
+                    (i32.const 0) (i32.const 8)))
+
+            ;; Receives up to 16 bytes at 128 and stores the count received
+            ;; at 24.
+            (func (export "recv") (param $fd i32) (result i32)
+                (i32.store (i32.const 16) (i32.const 128))
+                (i32.store (i32.const 20) (i32.const 16))
+                (call $sock_recv (local.get $fd) (i32.const 16) (i32.const 1)
+                    (i32.const 0) (i32.const 24) (i32.const 28)))
+
+            (func (export "shutdown") (param $fd i32) (param $how i32) (result i32)
+                (call $sock_shutdown (local.get $fd) (local.get $how)))
+
+            (memory (export "memory") 1)
+            (data (i32.const 64) "hello")
+        )
+    "#;
+
+    fn loopback() -> Result<(TcpStream, TcpStream)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let client = TcpStream::connect(listener.local_addr()?)?;
+        let (server, _) = listener.accept()?;
+        Ok((client, server))
+    }
+
+    #[test]
+    fn send_recv_shutdown() -> Result<()> {
+        let (guest, mut host) = loopback()?;
+        let store = Store::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.preopened_socket(3, guest);
+        let wasi = Wasi::new(&store, builder.build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+        let memory = instance.get_memory("memory").unwrap();
+        let send = instance.get_func("send").unwrap().get1::<i32, i32>()?;
+        let recv = instance.get_func("recv").unwrap().get1::<i32, i32>()?;
+        let shutdown = instance
+            .get_func("shutdown")
+            .unwrap()
+            .get2::<i32, i32, i32>()?;
+
+        assert_eq!(send(3)?, 0);
+        assert_eq!(memory.read_u32(8)?, 5);
+        let mut buf = [0; 5];
+        host.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"hello");
+
+        host.write_all(b"world")?;
+        host.shutdown(Shutdown::Write)?;
+        assert_eq!(recv(3)?, 0);
+        assert_eq!(memory.read_u32(24)?, 5);
+        let mut buf = [0; 5];
+        memory.read(128, &mut buf)?;
+        assert_eq!(&buf, b"world");
+
+        assert_eq!(shutdown(3, SDFLAGS_WR)?, 0);
+        assert_eq!(host.read(&mut buf)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn send_after_peer_is_gone() -> Result<()> {
+        let (guest, host) = loopback()?;
+        let store = Store::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.preopened_socket(3, guest);
+        let wasi = Wasi::new(&store, builder.build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+        let send = instance.get_func("send").unwrap().get1::<i32, i32>()?;
+
+        // The test harness ignores `SIGPIPE`, which would hide it being
+        // raised, but a blocked signal is kept pending instead.
+        let mut sigpipe: libc::sigset_t = unsafe { std::mem::zeroed() };
+        let mut old_mask: libc::sigset_t = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::sigemptyset(&mut sigpipe);
+            libc::sigaddset(&mut sigpipe, libc::SIGPIPE);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &sigpipe, &mut old_mask);
+        }
+
+        // The first send after the peer closes its end may still succeed,
+        // and the peer answers it with a reset.
+        drop(host);
+        let mut errno = 0;
+        for _ in 0..100 {
+            errno = send(3)?;
+            if errno != 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let mut pending: libc::sigset_t = unsafe { std::mem::zeroed() };
+        let raised = unsafe {
+            libc::sigpending(&mut pending);
+            let raised = libc::sigismember(&pending, libc::SIGPIPE) == 1;
+            if raised {
+                let mut signum = 0;
+                libc::sigwait(&sigpipe, &mut signum);
+            }
+            libc::pthread_sigmask(libc::SIG_SETMASK, &old_mask, std::ptr::null_mut());
+            raised
+        };
+        assert!(!raised, "sending raised SIGPIPE");
+        assert_eq!(errno, ERRNO_PIPE);
+        Ok(())
+    }
+
+    #[test]
+    fn sock_calls_need_a_socket() -> Result<()> {
+        let store = Store::default();
+        let wasi = Wasi::new(&store, WasiCtxBuilder::new().build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+        let send = instance.get_func("send").unwrap().get1::<i32, i32>()?;
+        let shutdown = instance
+            .get_func("shutdown")
+            .unwrap()
+            .get2::<i32, i32, i32>()?;
+
+        // Stdout is `/dev/null` here.
+        assert_eq!(send(1)?, ERRNO_NOTSOCK);
+        assert_eq!(shutdown(1, SDFLAGS_WR)?, ERRNO_NOTSOCK);
+        Ok(())
+    }
+
+    #[test]
+    fn preopened_dirs_come_before_sockets() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (guest, _host) = loopback()?;
+        let store = Store::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .preopened_socket(4, guest)
+            .preopened_dir(preopen_dir(dir.path())?, "/sandbox");
+        let wasi = Wasi::new(&store, builder.build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let module = Module::new(
+            &store,
+            r#"
+                (module
+                    (import "wasi_snapshot_preview1" "fd_prestat_get"
+                        (func $fd_prestat_get (param i32 i32) (result i32)))
+                    (import "wasi_snapshot_preview1" "fd_prestat_dir_name"
+                        (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+
+                    ;; Stores the prestat of the fd at 0 and the name of the directory at 64.
+                    (func (export "prestat") (param $fd i32) (result i32)
+                        (local $errno i32)
+                        (local.set $errno (call $fd_prestat_get (local.get $fd) (i32.const 0)))
+                        (if (local.get $errno) (then (return (local.get $errno))))
+                        (call $fd_prestat_dir_name
+                            (local.get $fd) (i32.const 64) (i32.load (i32.const 4))))
+
+                    (memory (export "memory") 1)
+                )
+            "#,
+        )?;
+        let instance = linker.instantiate(&module)?;
+        let memory = instance.get_memory("memory").unwrap();
+        let prestat = instance.get_func("prestat").unwrap().get1::<i32, i32>()?;
+
+        // The directory is found at fd 3, and the scan stops at the socket.
+        assert_eq!(prestat(3)?, 0);
+        let mut name = vec![0; memory.read_u32(4)? as usize];
+        memory.read(64, &mut name)?;
+        assert_eq!(name, b"/sandbox");
+        assert_eq!(prestat(4)?, ERRNO_NOTSUP);
+        Ok(())
+    }
+
+    const POLL_WAT: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "poll_oneoff"
+                (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+
+            ;; Waits for `$fd` to be readable, for at most 10ms. The events
+            ;; are stored at 512, and their count at 32.
+            (func (export "poll") (param $fd i32) (result i32)
+                ;; An fd_read subscription with userdata 1.
+                (i64.store (i32.const 256) (i64.const 1))
+                (i32.store8 (i32.const 264) (i32.const 1))
+                (i32.store (i32.const 272) (local.get $fd))
+                ;; A relative timeout on the monotonic clock with userdata 2.
+                (i64.store (i32.const 304) (i64.const 2))
+                (i32.store8 (i32.const 312) (i32.const 0))
+                (i32.store (i32.const 320) (i32.const 1))
+                (i64.store (i32.const 328) (i64.const 10000000))
+                (i64.store (i32.const 336) (i64.const 0))
+                (i32.store16 (i32.const 344) (i32.const 0))
+                (call $poll_oneoff (i32.const 256) (i32.const 512) (i32.const 2) (i32.const 32)))
+
+            (memory (export "memory") 1)
+        )
+    "#;
+
+    #[test]
+    fn poll_socket() -> Result<()> {
+        let (guest, mut host) = loopback()?;
+        let store = Store::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.preopened_socket(3, guest);
+        let wasi = Wasi::new(&store, builder.build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&Module::new(&store, POLL_WAT)?)?;
+        let memory = instance.get_memory("memory").unwrap();
+        let poll = instance.get_func("poll").unwrap().get1::<i32, i32>()?;
+        let event = |memory: &Memory| -> Result<(u64, u8, u64)> {
+            let mut userdata = [0; 8];
+            memory.read(512, &mut userdata)?;
+            let mut type_ = [0; 1];
+            memory.read(522, &mut type_)?;
+            let mut nbytes = [0; 8];
+            memory.read(528, &mut nbytes)?;
+            Ok((
+                u64::from_le_bytes(userdata),
+                type_[0],
+                u64::from_le_bytes(nbytes),
+            ))
+        };
+
+        // Nothing has been sent yet, so the poll times out.
+        assert_eq!(poll(3)?, 0);
+        assert_eq!(memory.read_u32(32)?, 1);
+        assert_eq!(event(&memory)?, (2, EVENTTYPE_CLOCK, 0));
+
+        host.write_all(b"hello")?;
+        assert_eq!(poll(3)?, 0);
+        assert_eq!(memory.read_u32(32)?, 1);
+        assert_eq!(event(&memory)?, (1, EVENTTYPE_FD_READ, 5));
+        Ok(())
+    }
+}
+
+#[test]
+fn old_snapshot_sock_calls_are_not_supported() -> anyhow::Result<()> {
+    use wasmtime::*;
+    use wasmtime_wasi::old::snapshot_0::{Wasi, WasiCtxBuilder};
+
+    const ERRNO_NOTSUP: i32 = 58;
+
+    let store = Store::default();
+    let wasi = Wasi::new(&store, WasiCtxBuilder::new().build()?);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let module = Module::new(
+        &store,
+        r#"
+            (module
+                (import "wasi_unstable" "sock_recv"
+                    (func $sock_recv (param i32 i32 i32 i32 i32 i32) (result i32)))
+                (import "wasi_unstable" "sock_send"
+                    (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+                (import "wasi_unstable" "sock_shutdown"
+                    (func $sock_shutdown (param i32 i32) (result i32)))
+
+                (func (export "recv") (result i32)
+                    (call $sock_recv (i32.const 1) (i32.const 0) (i32.const 0)
+                        (i32.const 0) (i32.const 8) (i32.const 12)))
+                (func (export "send") (result i32)
+                    (call $sock_send (i32.const 1) (i32.const 0) (i32.const 0)
+                        (i32.const 0) (i32.const 8)))
+                (func (export "shutdown") (result i32)
+                    (call $sock_shutdown (i32.const 1) (i32.const 2)))
+
+                (memory (export "memory") 1)
+            )
+        "#,
+    )?;
+    let instance = linker.instantiate(&module)?;
+    for name in &["recv", "send", "shutdown"] {
+        let call = instance.get_func(name).unwrap().get0::<i32>()?;
+        assert_eq!(call()?, ERRNO_NOTSUP, "{}", name);
+    }
+    Ok(())
+}
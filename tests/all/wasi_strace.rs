@@ -0,0 +1,137 @@
+use anyhow::Result;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+use wasi_common::preopen_dir;
+use wasmtime::*;
+use wasmtime_wasi::{CallClass, Strace, Wasi, WasiCtxBuilder};
+
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open
+                (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "clock_time_get"
+            (func $clock_time_get (param i32 i64 i32) (result i32)))
+
+        ;; Opens "hello.txt" in the preopen at fd 3 for reading, and reads it into two buffers
+        ;; of 64 and 16 bytes at 256.
+        (func (export "read") (param $fd i32)
+            (drop
+                (call $path_open
+                    (i32.const 3) (i32.const 1) (i32.const 128) (i32.const 9)
+                    (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 0)))
+            (i32.store (i32.const 8) (i32.const 256))
+            (i32.store (i32.const 12) (i32.const 64))
+            (i32.store (i32.const 16) (i32.const 320))
+            (i32.store (i32.const 20) (i32.const 16))
+            (drop
+                (call $fd_read (local.get $fd) (i32.const 8) (i32.const 2) (i32.const 4))))
+
+        (func (export "clock")
+            (drop (call $clock_time_get (i32.const 1) (i64.const 1000) (i32.const 32))))
+
+        (memory (export "memory") 1)
+        (data (i32.const 128) "hello.txt")
+    )
+"#;
+
+/// A writer into a buffer which the test can look at.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+/// Reads "hello.txt" from the given fd and calls `clock_time_get` with `strace`.
+fn run(strace: Strace, fd: i32) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("hello.txt"), b"hello")?;
+    let ctx = WasiCtxBuilder::new()
+        .preopened_dir(preopen_dir(dir.path())?, ".")
+        .build()?;
+    let store = Store::default();
+    let wasi = Wasi::with_tracing(&store, ctx, None, Some(strace));
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+    instance.get_func("read").unwrap().get1::<i32, ()>()?(fd)?;
+    instance.get_func("clock").unwrap().get0::<()>()?()?;
+    Ok(())
+}
+
+#[test]
+fn decodes_arguments_and_results() -> Result<()> {
+    let output = Output::default();
+    run(Strace::new(output.clone()), 4)?;
+    let lines = output.lines();
+    assert_eq!(lines.len(), 3, "{:?}", lines);
+    assert!(
+        lines[0].starts_with(
+            "path_open(fd=3, dirflags=symlink_follow, path=\"hello.txt\", oflags=0, \
+             fs_rights_base=fd_read, "
+        ),
+        "{}",
+        lines[0]
+    );
+    assert!(
+        lines[0].ends_with(") = success opened_fd=4"),
+        "{}",
+        lines[0]
+    );
+    assert_eq!(lines[1], "fd_read(fd=4, iovs=[64, 16]) = success nread=5");
+    assert!(
+        lines[2].starts_with("clock_time_get(id=monotonic, precision=1000) = success time="),
+        "{}",
+        lines[2]
+    );
+    Ok(())
+}
+
+#[test]
+fn filters_by_class() -> Result<()> {
+    let output = Output::default();
+    run(Strace::new(output.clone()).classes(&[CallClass::Clock]), 4)?;
+    let lines = output.lines();
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    assert!(lines[0].starts_with("clock_time_get("), "{}", lines[0]);
+    Ok(())
+}
+
+#[test]
+fn json_lines() -> Result<()> {
+    let output = Output::default();
+    let strace = Strace::new(output.clone())
+        .classes(&[CallClass::Fs])
+        .json(true);
+    run(strace, 42)?;
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+    // Outs are only printed by calls which succeed.
+    assert_eq!(
+        lines[1],
+        r#"{"call":"fd_read","args":{"fd":42,"iovs":[64,16]},"errno":"badf"}"#
+    );
+    Ok(())
+}
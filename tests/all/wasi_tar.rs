@@ -0,0 +1,248 @@
+use anyhow::Result;
+use wasi_common::{EntryRights, VirtualDirEntry, WasiCtxBuilder};
+use wasmtime::*;
+use wasmtime_wasi::Wasi;
+
+const ERRNO_NOTCAPABLE: i32 = 76;
+const SECOND: u64 = 1_000_000_000;
+
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open
+                (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_filestat_get"
+            (func $fd_filestat_get (param i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_close"
+            (func $fd_close (param i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_readdir"
+            (func $fd_readdir (param i32 i32 i32 i64 i32) (result i32)))
+
+        ;; Opens the path in the preopen at fd 3, storing the new fd at 0.
+        (func $open (param $ptr i32) (param $len i32) (param $rights i64) (result i32)
+            (call $path_open
+                (i32.const 3) (i32.const 0) (local.get $ptr) (local.get $len)
+                (i32.const 0) (local.get $rights) (i64.const 0)
+                (i32.const 0) (i32.const 0)))
+
+        ;; Reads up to 64 bytes of the file into 256, storing their count at 4, and its filestat
+        ;; at 384.
+        (func (export "read") (param $ptr i32) (param $len i32) (result i32)
+            (local $errno i32)
+            (local.set $errno
+                ;; FD_READ | FD_FILESTAT_GET
+                (call $open (local.get $ptr) (local.get $len) (i64.const 0x200002)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (i32.store (i32.const 8) (i32.const 256))
+            (i32.store (i32.const 12) (i32.const 64))
+            (local.set $errno
+                (call $fd_read (i32.load (i32.const 0)) (i32.const 8) (i32.const 1) (i32.const 4)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (local.set $errno (call $fd_filestat_get (i32.load (i32.const 0)) (i32.const 384)))
+            (if (local.get $errno) (then (return (local.get $errno))))
+            (call $fd_close (i32.load (i32.const 0))))
+
+        ;; Opens the file for writing.
+        (func (export "open_write") (param $ptr i32) (param $len i32) (result i32)
+            (call $open (local.get $ptr) (local.get $len) (i64.const 64)))
+
+        ;; Reads the entries of the preopen from `$cookie` into 1024, storing their size at 4.
+        (func (export "readdir") (param $cookie i64) (result i32)
+            (call $fd_readdir (i32.const 3) (i32.const 1024) (i32.const 4096) (local.get $cookie)
+                (i32.const 4)))
+
+        (memory (export "memory") 1)
+    )
+"#;
+
+/// The address the guest finds paths at.
+const PATH: usize = 512;
+
+/// Builds a ustar header with a valid checksum.
+fn header(path: &str, typeflag: u8, size: usize, mtime: u64, link: &str) -> Vec<u8> {
+    let mut header = vec![0; 512];
+    header[..path.len()].copy_from_slice(path.as_bytes());
+    header[100..107].copy_from_slice(b"0000644");
+    header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+    header[136..147].copy_from_slice(format!("{:011o}", mtime).as_bytes());
+    header[156] = typeflag;
+    header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+    header[257..265].copy_from_slice(b"ustar\x0000");
+    set_checksum(&mut header);
+    header
+}
+
+/// Computes the checksum of `header`, with its checksum field counted as spaces.
+fn set_checksum(header: &mut [u8]) {
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+}
+
+/// Appends an entry to `archive`, padding its contents to a whole block.
+fn append(archive: &mut Vec<u8>, path: &str, typeflag: u8, contents: &[u8], mtime: u64) {
+    archive.extend(header(path, typeflag, contents.len(), mtime, ""));
+    archive.extend(contents);
+    archive.resize((archive.len() + 511) / 512 * 512, 0);
+}
+
+/// A PAX record, whose length includes itself.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {}={}\n", key, value);
+    let mut len = rest.len() + 1;
+    while len.to_string().len() + rest.len() != len {
+        len += 1;
+    }
+    format!("{}{}", len, rest)
+}
+
+struct Guest {
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Guest {
+    fn new(archive: Vec<u8>) -> Result<Guest> {
+        let store = Store::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.preopened_virt_with_rights(
+            VirtualDirEntry::from_tar(archive)?,
+            ".",
+            EntryRights::read_only_dir(),
+        );
+        let wasi = Wasi::new(&store, builder.build()?);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+        let memory = instance.get_memory("memory").unwrap();
+        Ok(Guest { instance, memory })
+    }
+
+    fn call_with_path(&self, func: &str, path: &str) -> Result<i32> {
+        self.memory.write(PATH, path.as_bytes())?;
+        let func = self.instance.get_func(func).unwrap();
+        let func = func.get2::<i32, i32, i32>()?;
+        Ok(func(PATH as i32, path.len() as i32)?)
+    }
+
+    /// Reads a file, returning its contents, size and modification time.
+    fn read(&self, path: &str) -> Result<(String, u64, u64)> {
+        assert_eq!(self.call_with_path("read", path)?, 0);
+        let mut buf = vec![0; self.read_u32(4)? as usize];
+        self.memory.read(256, &mut buf)?;
+        Ok((
+            String::from_utf8(buf)?,
+            self.read_u64(384 + 32)?,
+            self.read_u64(384 + 48)?,
+        ))
+    }
+
+    /// Lists the names and cookies of the entries of the preopen, starting from `cookie`.
+    fn readdir(&self, cookie: u64) -> Result<Vec<(String, u64)>> {
+        let readdir = self.instance.get_func("readdir").unwrap();
+        assert_eq!(readdir.get1::<i64, i32>()?(cookie as i64)?, 0);
+        let end = 1024 + self.read_u32(4)? as usize;
+        let mut entries = Vec::new();
+        let mut offset = 1024;
+        // Each dirent is 24 bytes, with the next cookie at 0 and the length of the name at 16, and
+        // it's followed by the name.
+        while offset < end {
+            let mut name = vec![0; self.read_u32(offset + 16)? as usize];
+            self.memory.read(offset + 24, &mut name)?;
+            let next = self.read_u64(offset)?;
+            offset += 24 + name.len();
+            entries.push((String::from_utf8(name)?, next));
+        }
+        Ok(entries)
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.memory.read(offset, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&self, offset: usize) -> Result<u64> {
+        let mut buf = [0; 8];
+        self.memory.read(offset, &mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+#[test]
+fn read_only_tar_mount() -> Result<()> {
+    let mut archive = Vec::new();
+    append(&mut archive, "./b.txt", b'0', b"bee", 1_000);
+    append(&mut archive, "./dir/", b'5', b"", 2_000);
+    append(&mut archive, "./dir/hello.txt", b'0', b"hello, tar", 3_000);
+    archive.extend(header("./a.txt", b'1', 0, 4_000, "./b.txt"));
+    archive.resize(archive.len() + 1024, 0);
+
+    let guest = Guest::new(archive)?;
+    assert_eq!(
+        guest.read("dir/hello.txt")?,
+        ("hello, tar".to_owned(), 10, 3_000 * SECOND)
+    );
+    assert_eq!(guest.read("a.txt")?, ("bee".to_owned(), 3, 1_000 * SECOND));
+    assert_eq!(
+        guest.call_with_path("open_write", "b.txt")?,
+        ERRNO_NOTCAPABLE
+    );
+
+    let entries = guest.readdir(0)?;
+    let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, [".", "..", "a.txt", "b.txt", "dir"]);
+    // Resuming from a cookie continues where the listing left off.
+    let rest = guest.readdir(entries[2].1)?;
+    assert_eq!(rest, entries[3..]);
+    Ok(())
+}
+
+#[test]
+fn pax_headers() -> Result<()> {
+    let path = format!("{}/file", "long".repeat(40));
+    let pax = pax_record("path", &path) + &pax_record("mtime", "1234.5");
+    let mut archive = Vec::new();
+    append(&mut archive, "PaxHeader", b'x', pax.as_bytes(), 0);
+    append(&mut archive, "truncated", b'0', b"pax", 0);
+    archive.resize(archive.len() + 1024, 0);
+
+    let guest = Guest::new(archive)?;
+    assert_eq!(
+        guest.read(&path)?,
+        ("pax".to_owned(), 3, 1234 * SECOND + SECOND / 2)
+    );
+    Ok(())
+}
+
+#[test]
+fn old_gnu_headers_and_symlinks() -> Result<()> {
+    let mut archive = Vec::new();
+    // Old GNU headers keep the access and change times where ustar keeps the path prefix.
+    let mut gnu = header("gnu.txt", b'0', 3, 5_000, "");
+    gnu[257..265].copy_from_slice(b"ustar  \0");
+    gnu[345..357].copy_from_slice(b"00000011610\0");
+    gnu[357..369].copy_from_slice(b"00000011610\0");
+    set_checksum(&mut gnu);
+    archive.extend(gnu);
+    archive.extend(b"gnu");
+    archive.resize(archive.len() + 509, 0);
+    archive.extend(header("link", b'2', 0, 0, "gnu.txt"));
+    archive.resize(archive.len() + 1024, 0);
+
+    // The symlink is left out rather than failing the whole archive.
+    let guest = Guest::new(archive)?;
+    assert_eq!(
+        guest.read("gnu.txt")?,
+        ("gnu".to_owned(), 3, 5_000 * SECOND)
+    );
+    let names: Vec<_> = guest
+        .readdir(0)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, [".", "..", "gnu.txt"]);
+    Ok(())
+}
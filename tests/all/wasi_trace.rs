@@ -0,0 +1,152 @@
+use anyhow::Result;
+use std::fs::File;
+use tempfile::NamedTempFile;
+use wasmtime::*;
+use wasmtime_wasi::{Trace, Wasi, WasiCtxBuilder};
+
+const WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "random_get"
+            (func $random_get (param i32 i32) (result i32)))
+
+        (func (export "random") (result i64)
+            (if (call $random_get (i32.const 8) (i32.const 8))
+                (then unreachable))
+            (i64.load (i32.const 8)))
+
+        (memory (export "memory") 1)
+    )
+"#;
+
+fn random(trace: Trace, calls: usize) -> Result<Vec<i64>> {
+    let store = Store::default();
+    let wasi = Wasi::with_trace(&store, WasiCtxBuilder::new().build()?, trace);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let instance = linker.instantiate(&Module::new(&store, WAT)?)?;
+    let random = instance.get_func("random").unwrap().get0::<i64>()?;
+    (0..calls).map(|_| Ok(random()?)).collect()
+}
+
+#[test]
+fn replay_reproduces_random() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    let trace = Trace::record(File::create(file.path())?)?;
+    let recorded = random(trace.clone(), 3)?;
+    trace.finish()?;
+
+    let trace = Trace::replay(File::open(file.path())?)?;
+    assert_eq!(random(trace.clone(), 3)?, recorded);
+    trace.finish()?;
+    Ok(())
+}
+
+#[test]
+fn replay_detects_divergence() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    let trace = Trace::record(File::create(file.path())?)?;
+    random(trace.clone(), 2)?;
+    trace.finish()?;
+
+    // Making fewer calls than were recorded...
+    let trace = Trace::replay(File::open(file.path())?)?;
+    random(trace.clone(), 1)?;
+    assert!(trace.finish().is_err());
+
+    // ... or more both fail.
+    let trace = Trace::replay(File::open(file.path())?)?;
+    let err = random(trace, 3).unwrap_err();
+    assert!(
+        err.to_string().contains("WASI trace diverged"),
+        "bad error: {}",
+        err
+    );
+    Ok(())
+}
+
+const WRITE_WAT: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+
+        ;; Writes the byte `$byte` to stdout.
+        (func (export "write") (param $byte i32)
+            (i32.store8 (i32.const 64) (local.get $byte))
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 1))
+            (if (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 16))
+                (then unreachable)))
+
+        (memory (export "memory") 1)
+    )
+"#;
+
+fn write(trace: Trace, byte: i32) -> Result<()> {
+    let store = Store::default();
+    let wasi = Wasi::with_trace(&store, WasiCtxBuilder::new().build()?, trace);
+    let mut linker = Linker::new(&store);
+    wasi.add_to_linker(&mut linker)?;
+    let instance = linker.instantiate(&Module::new(&store, WRITE_WAT)?)?;
+    instance.get_func("write").unwrap().get1::<i32, ()>()?(byte)?;
+    Ok(())
+}
+
+#[test]
+fn replay_detects_different_input_data() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    let trace = Trace::record(File::create(file.path())?)?;
+    write(trace.clone(), b'a' as i32)?;
+    trace.finish()?;
+
+    let trace = Trace::replay(File::open(file.path())?)?;
+    write(trace.clone(), b'a' as i32)?;
+    trace.finish()?;
+
+    // The arguments are the same, but the buffer being written isn't.
+    let trace = Trace::replay(File::open(file.path())?)?;
+    let err = write(trace, b'b' as i32).unwrap_err();
+    assert!(err.to_string().contains("different data"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn replay_checks_bounds() -> Result<()> {
+    let random_at = |pages: u32, trace: Trace| -> Result<i64> {
+        let store = Store::default();
+        let wasi = Wasi::with_trace(&store, WasiCtxBuilder::new().build()?, trace);
+        let mut linker = Linker::new(&store);
+        wasi.add_to_linker(&mut linker)?;
+        let module = Module::new(
+            &store,
+            format!(
+                r#"
+                    (module
+                        (import "wasi_snapshot_preview1" "random_get"
+                            (func $random_get (param i32 i32) (result i32)))
+                        (func (export "random") (result i32)
+                            (call $random_get (i32.const 65532) (i32.const 8)))
+                        (memory (export "memory") {})
+                    )
+                "#,
+                pages
+            ),
+        )?;
+        let instance = linker.instantiate(&module)?;
+        Ok(i64::from(instance
+            .get_func("random")
+            .unwrap()
+            .get0::<i32>()?()?))
+    };
+
+    let file = NamedTempFile::new()?;
+    let trace = Trace::record(File::create(file.path())?)?;
+    assert_eq!(random_at(2, trace.clone())?, 0);
+    trace.finish()?;
+
+    // Replaying into a smaller memory, where the buffer is out of bounds,
+    // traps instead of panicking.
+    let trace = Trace::replay(File::open(file.path())?)?;
+    let err = random_at(1, trace).unwrap_err();
+    assert!(err.to_string().contains("WASI trace diverged"), "{}", err);
+    Ok(())
+}
@@ -0,0 +1,11 @@
+(module
+  (func $add (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+  (func (export "run") (result i32)
+    i32.const 5
+    i32.const 7
+    call $add
+    i32.const 1
+    call $add))
@@ -0,0 +1,6 @@
+(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (func (export "_start")
+    i32.const 7
+    call $proc_exit)
+  (memory (export "memory") 1))
@@ -0,0 +1,24 @@
+(module
+  (import "wasi_snapshot_preview1" "fd_prestat_get"
+    (func $__wasi_fd_prestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_fdstat_get"
+    (func $__wasi_fd_fdstat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_shutdown"
+    (func $__wasi_sock_shutdown (param i32 i32) (result i32)))
+  (func $_start
+    ;; The listening socket isn't a preopened directory.
+    (if (i32.ne (call $__wasi_fd_prestat_get (i32.const 3) (i32.const 0)) (i32.const 8))
+      (then unreachable))
+    ;; It's a stream socket.
+    (if (i32.ne (call $__wasi_fd_fdstat_get (i32.const 3) (i32.const 16)) (i32.const 0))
+      (then unreachable))
+    (if (i32.ne (i32.load8_u (i32.const 16)) (i32.const 6))
+      (then unreachable))
+    ;; It isn't connected, so it can't be shut down.
+    (if (i32.ne (call $__wasi_sock_shutdown (i32.const 3) (i32.const 3)) (i32.const 53))
+      (then unreachable))
+  )
+  (memory 1)
+  (export "memory" (memory 0))
+  (export "_start" (func $_start))
+)